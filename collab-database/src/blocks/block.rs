@@ -12,6 +12,7 @@ use collab_plugins::CollabKVDB;
 use lru::LruCache;
 use parking_lot::Mutex;
 use tokio::sync::broadcast;
+use tracing::error;
 use uuid::Uuid;
 
 use crate::blocks::task_controller::{BlockTask, BlockTaskController};
@@ -136,15 +137,20 @@ impl Block {
     };
 
     let collab = self.collab_for_row(&row_id);
-    let database_row = MutexDatabaseRow::new(DatabaseRow::create(
+    match DatabaseRow::create(
       row,
       self.uid,
       row_id.clone(),
       self.collab_db.clone(),
       collab,
       self.row_change_tx.clone(),
-    ));
-    self.cache.lock().put(row_id, Arc::new(database_row));
+    ) {
+      Ok(database_row) => {
+        let database_row = MutexDatabaseRow::new(database_row);
+        self.cache.lock().put(row_id, Arc::new(database_row));
+      },
+      Err(err) => error!("Failed to create the row:{}. {}", row_id, err),
+    }
     row_order
   }
 
@@ -247,13 +253,20 @@ impl Block {
         }
 
         let collab = self.collab_for_row(row_id);
-        let row = Arc::new(MutexDatabaseRow::new(DatabaseRow::new(
+        let database_row = DatabaseRow::new(
           self.uid,
           row_id.clone(),
           self.collab_db.clone(),
           collab,
           self.row_change_tx.clone(),
-        )));
+        );
+        let row = match database_row {
+          Ok(database_row) => Arc::new(MutexDatabaseRow::new(database_row)),
+          Err(err) => {
+            error!("Failed to open the row:{}. {}", row_id, err);
+            return None;
+          },
+        };
         self.cache.lock().put(row_id.clone(), row.clone());
         Some(row)
       },
@@ -300,14 +313,13 @@ async fn async_create_row<T: Into<Row>>(
   })
   .await
   {
-    let database_row = MutexDatabaseRow::new(DatabaseRow::create(
-      row,
-      uid,
-      row_id.clone(),
-      collab_db,
-      collab,
-      row_change_tx,
-    ));
-    cache.lock().put(row_id, Arc::new(database_row));
+    match DatabaseRow::create(row, uid, row_id.clone(), collab_db, collab, row_change_tx) {
+      Ok(database_row) => {
+        cache
+          .lock()
+          .put(row_id, Arc::new(MutexDatabaseRow::new(database_row)));
+      },
+      Err(err) => error!("Failed to create the row:{}. {}", row_id, err),
+    }
   }
 }
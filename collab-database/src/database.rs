@@ -6,6 +6,8 @@ use std::sync::{Arc, Weak};
 use collab::core::any_map::AnyMapExtension;
 use collab::core::collab::MutexCollab;
 use collab::core::collab_state::{SnapshotState, SyncState};
+use collab::core::read_only::ReadOnlyMode;
+use collab::error::CollabError;
 use collab::preclude::{
   Collab, JsonValue, MapRefExtension, MapRefWrapper, ReadTxn, TransactionMut,
 };
@@ -34,7 +36,6 @@ use crate::views::{
 };
 
 pub struct Database {
-  #[allow(dead_code)]
   inner: Arc<MutexCollab>,
   pub(crate) root: MapRefWrapper,
   pub views: Rc<ViewMap>,
@@ -77,7 +78,7 @@ impl Database {
 
     let row_orders = this.block.create_rows(rows);
     let field_orders = fields.iter().map(FieldOrder::from).collect();
-    this.root.with_transact_mut(|txn| {
+    this.root.try_with_transact_mut(|txn| {
       // Set the inline view id. The inline view id should not be
       // empty if the current database exists.
       this.set_inline_view_with_txn(txn, &params.view_id);
@@ -89,7 +90,7 @@ impl Database {
       // Create a inline view
       this.create_view_with_txn(txn, params, field_orders, row_orders)?;
       Ok::<(), DatabaseError>(())
-    })?;
+    })??;
     Ok(this)
   }
 
@@ -159,24 +160,22 @@ impl Database {
       None => Self::create(database_id, context),
       Some(database) => {
         let collab_guard = context.collab.lock();
-        let (fields, views, metas) = collab_guard.with_origin_transact_mut(|txn| {
-          // { DATABASE: { FIELDS: {:} } }
-          let fields = collab_guard
-            .get_map_with_txn(txn, vec![DATABASE, FIELDS])
-            .unwrap();
-
-          // { DATABASE: { FIELDS: {:}, VIEWS: {:} } }
-          let views = collab_guard
-            .get_map_with_txn(txn, vec![DATABASE, VIEWS])
-            .unwrap();
-
-          // { DATABASE: { FIELDS: {:},  VIEWS: {:}, METAS: {:} } }
-          let metas = collab_guard
-            .get_map_with_txn(txn, vec![DATABASE, METAS])
-            .unwrap();
-
-          (fields, views, metas)
-        });
+        let txn = collab_guard.transact();
+        // { DATABASE: { FIELDS: {:} } }
+        let fields = collab_guard
+          .get_map_with_txn(&txn, vec![DATABASE, FIELDS])
+          .unwrap();
+
+        // { DATABASE: { FIELDS: {:}, VIEWS: {:} } }
+        let views = collab_guard
+          .get_map_with_txn(&txn, vec![DATABASE, VIEWS])
+          .unwrap();
+
+        // { DATABASE: { FIELDS: {:},  VIEWS: {:}, METAS: {:} } }
+        let metas = collab_guard
+          .get_map_with_txn(&txn, vec![DATABASE, METAS])
+          .unwrap();
+        drop(txn);
         let views = ViewMap::new(
           views,
           context
@@ -223,7 +222,7 @@ impl Database {
       return Err(DatabaseError::InvalidDatabaseID("database_id is empty"));
     }
     let collab_guard = context.collab.lock();
-    let (database, fields, views, metas) = collab_guard.with_origin_transact_mut(|txn| {
+    let (database, fields, views, metas) = collab_guard.try_with_origin_transact_mut(|txn| {
       // { DATABASE: {:} }
       let database = collab_guard
         .get_map_with_txn(txn, vec![DATABASE])
@@ -247,7 +246,7 @@ impl Database {
         .unwrap_or_else(|| database.create_map_with_txn(txn, METAS));
 
       (database, fields, views, metas)
-    });
+    })?;
     drop(collab_guard);
     let views = ViewMap::new(
       views,
@@ -303,6 +302,16 @@ impl Database {
     self.inner.lock().subscribe_snapshot_state()
   }
 
+  /// A read-only database rejects the local changes, see [ReadOnlyMode]. The rows are separate
+  /// collabs with their own mode.
+  pub fn is_read_only(&self) -> bool {
+    self.root.is_read_only()
+  }
+
+  pub fn set_read_only_mode(&self, mode: ReadOnlyMode) {
+    self.inner.set_read_only_mode(mode);
+  }
+
   /// Return the database id
   pub fn get_database_id(&self) -> String {
     let txn = self.root.transact();
@@ -321,20 +330,24 @@ impl Database {
   /// created successfully. Otherwise, return None.
   pub fn create_row(&self, params: CreateRowParams) -> Result<RowOrder, DatabaseError> {
     let params = CreateRowParamsValidator::validate(params)?;
+    if self.is_read_only() {
+      let object_id = self.inner.lock().object_id.clone();
+      return Err(CollabError::ReadOnly(object_id).into());
+    }
     let row_order = self.block.create_row(params);
-    self.root.with_transact_mut(|txn| {
+    self.root.try_with_transact_mut(|txn| {
       self
         .views
         .update_all_views_with_txn(txn, |_view_id, update| {
           update.insert_row_order(&row_order, &OrderObjectPosition::default());
         });
-    });
+    })?;
     Ok(row_order)
   }
 
   /// Create a new row from the given view.
   /// This row will be inserted into corresponding [Block]. The [RowOrder] of this row will
-  /// be inserted to each view.
+  /// be inserted to each view. Returns None if the database is read-only.
   pub fn create_row_in_view(
     &self,
     view_id: &str,
@@ -342,7 +355,9 @@ impl Database {
   ) -> Option<(usize, RowOrder)> {
     self
       .root
-      .with_transact_mut(|txn| self.create_row_with_txn(txn, view_id, params))
+      .try_with_transact_mut(|txn| self.create_row_with_txn(txn, view_id, params))
+      .ok()
+      .flatten()
   }
 
   /// Create a new row from the given view.
@@ -369,27 +384,35 @@ impl Database {
   }
 
   /// Remove the row
-  /// The [RowOrder] of each view representing this row will be removed.
+  /// The [RowOrder] of each view representing this row will be removed. Returns None if the
+  /// database is read-only.
   pub fn remove_row(&self, row_id: &RowId) -> Option<Row> {
-    self.root.with_transact_mut(|txn| {
-      self.views.update_all_views_with_txn(txn, |_, update| {
-        update.remove_row_order(row_id);
-      });
-    });
+    self
+      .root
+      .try_with_transact_mut(|txn| {
+        self.views.update_all_views_with_txn(txn, |_, update| {
+          update.remove_row_order(row_id);
+        });
+      })
+      .ok()?;
 
     let row = self.block.get_row(row_id);
     self.block.delete_row(row_id);
     Some(row)
   }
 
+  /// Returns an empty list if the database is read-only.
   pub fn remove_rows(&self, row_ids: &[RowId]) -> Vec<Row> {
-    self.root.with_transact_mut(|txn| {
+    let result = self.root.try_with_transact_mut(|txn| {
       self.views.update_all_views_with_txn(txn, |_, mut update| {
         for row_id in row_ids {
           update = update.remove_row_order(row_id);
         }
       });
     });
+    if result.is_err() {
+      return vec![];
+    }
 
     row_ids
       .iter()
@@ -584,9 +607,12 @@ impl Database {
     position: &OrderObjectPosition,
     field_settings_by_layout: HashMap<DatabaseLayout, FieldSettingsMap>,
   ) {
-    self.root.with_transact_mut(|txn| {
+    let result = self.root.try_with_transact_mut(|txn| {
       self.create_field_with_txn(txn, view_id, field, position, &field_settings_by_layout);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("Failed to create field: {}", err);
+    }
   }
 
   /// Create a new field that is used by `create_field`, `create_field_with_mut`, and
@@ -641,7 +667,7 @@ impl Database {
   ) -> (usize, Field) {
     let mut field = Field::new(gen_field_id(), name, field_type, false);
     f(&mut field);
-    let index = self.root.with_transact_mut(|txn| {
+    let result = self.root.try_with_transact_mut(|txn| {
       self.create_field_with_txn(
         txn,
         Some(view_id),
@@ -653,6 +679,10 @@ impl Database {
         .index_of_field_with_txn(txn, view_id, &field.id)
         .unwrap_or_default()
     });
+    let index = result.unwrap_or_else(|err| {
+      tracing::warn!("Failed to create field: {}", err);
+      0
+    });
 
     (index, field)
   }
@@ -672,7 +702,7 @@ impl Database {
   }
 
   pub fn delete_field(&self, field_id: &str) {
-    self.root.with_transact_mut(|txn| {
+    let result = self.root.try_with_transact_mut(|txn| {
       self
         .views
         .update_all_views_with_txn(txn, |_view_id, update| {
@@ -681,7 +711,10 @@ impl Database {
             .remove_field_setting(field_id);
         });
       self.fields.delete_field_with_txn(txn, field_id);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("Failed to delete field: {}", err);
+    }
   }
 
   pub fn get_all_group_setting<T: TryFrom<GroupSettingMap>>(&self, view_id: &str) -> Vec<T> {
@@ -1049,7 +1082,7 @@ impl Database {
   /// Create a linked view to existing database
   pub fn create_linked_view(&self, params: CreateViewParams) -> Result<(), DatabaseError> {
     let mut params = CreateViewParamsValidator::validate(params)?;
-    self.root.with_transact_mut(|txn| {
+    self.root.try_with_transact_mut(|txn| {
       let inline_view_id = self.get_inline_view_id_with_txn(txn);
       let row_orders = self.views.get_row_orders_with_txn(txn, &inline_view_id);
       let field_orders = self.views.get_field_orders_with_txn(txn, &inline_view_id);
@@ -1074,7 +1107,7 @@ impl Database {
           })
       }
       Ok::<(), DatabaseError>(())
-    })?;
+    })??;
     Ok(())
   }
 
@@ -1136,25 +1169,30 @@ impl Database {
     })
   }
 
+  /// Returns None if the field does not exist or the database is read-only.
   pub fn duplicate_field(
     &self,
     view_id: &str,
     field_id: &str,
     f: impl FnOnce(&Field) -> String,
   ) -> Option<(usize, Field)> {
-    self.root.with_transact_mut(|txn| {
-      if let Some(mut field) = self.fields.get_field_with_txn(txn, field_id) {
-        field.id = gen_field_id();
-        field.name = f(&field);
-        self.insert_field_with_txn(txn, field.clone(), field_id);
-        let index = self
-          .index_of_field_with_txn(txn, view_id, &field.id)
-          .unwrap_or_default();
-        Some((index, field))
-      } else {
-        None
-      }
-    })
+    self
+      .root
+      .try_with_transact_mut(|txn| {
+        if let Some(mut field) = self.fields.get_field_with_txn(txn, field_id) {
+          field.id = gen_field_id();
+          field.name = f(&field);
+          self.insert_field_with_txn(txn, field.clone(), field_id);
+          let index = self
+            .index_of_field_with_txn(txn, view_id, &field.id)
+            .unwrap_or_default();
+          Some((index, field))
+        } else {
+          None
+        }
+      })
+      .ok()
+      .flatten()
   }
 
   pub fn duplicate_database(&self) -> DatabaseData {
@@ -1236,20 +1274,25 @@ impl Database {
 
   /// Delete a view from the database and returns the deleted view ids.
   /// If the view is the inline view, it will clear all the views. Otherwise,
-  /// just delete the view with given view id.
+  /// just delete the view with given view id. Returns an empty list if the database is read-only.
   ///
   pub fn delete_view(&self, view_id: &str) -> Vec<String> {
     if self.is_inline_view(view_id) {
-      self.root.with_transact_mut(|txn| {
-        let views = self.views.get_all_views_meta_with_txn(txn);
-        self.views.clear_with_txn(txn);
-        views.into_iter().map(|view| view.id).collect()
-      })
+      self
+        .root
+        .try_with_transact_mut(|txn| {
+          let views = self.views.get_all_views_meta_with_txn(txn);
+          self.views.clear_with_txn(txn);
+          views.into_iter().map(|view| view.id).collect()
+        })
+        .unwrap_or_default()
     } else {
-      self.root.with_transact_mut(|txn| {
-        self.views.delete_view_with_txn(txn, view_id);
-      });
-      vec![view_id.to_string()]
+      self
+        .root
+        .try_with_transact_mut(|txn| {
+          self.views.delete_view_with_txn(txn, view_id);
+        })
+        .map_or(vec![], |_| vec![view_id.to_string()])
     }
   }
 
@@ -1369,7 +1412,7 @@ pub fn get_database_row_ids(collab: &Collab) -> Option<Vec<String>> {
   )
 }
 
-pub fn reset_inline_view_id<F>(collab: &Collab, f: F)
+pub fn reset_inline_view_id<F>(collab: &Collab, f: F) -> Result<(), DatabaseError>
 where
   F: Fn(String) -> String,
 {
@@ -1380,10 +1423,11 @@ where
       let new_inline_view_id = f(inline_view_id);
       map.set_inline_view_with_txn(txn, &new_inline_view_id);
     }
-  })
+  })?;
+  Ok(())
 }
 
-pub fn mut_database_views_with_collab<F>(collab: &Collab, f: F)
+pub fn mut_database_views_with_collab<F>(collab: &Collab, f: F) -> Result<(), DatabaseError>
 where
   F: Fn(&mut DatabaseView),
 {
@@ -1397,7 +1441,8 @@ where
         views.insert_view_with_txn(txn, view);
       }
     }
-  });
+  })?;
+  Ok(())
 }
 
 pub fn is_database_collab(collab: &Collab) -> bool {
@@ -18,6 +18,9 @@ pub enum DatabaseError {
   #[error(transparent)]
   SerdeJson(#[from] serde_json::Error),
 
+  #[error(transparent)]
+  CollabError(#[from] collab::error::CollabError),
+
   #[error(transparent)]
   UuidError(#[from] uuid::Error),
 
@@ -27,9 +27,12 @@ impl FieldMap {
 
   /// Get all fields in the map
   pub fn insert_field(&self, field: Field) {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       self.insert_field_with_txn(txn, field);
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to insert the field: {}", err);
+    }
   }
 
   /// Insert a field into the map with a transaction
@@ -150,12 +153,15 @@ impl FieldMap {
   where
     F: FnOnce(FieldUpdate),
   {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       let map_ref = self.container.get_or_create_map_with_txn(txn, field_id);
       let mut update = FieldUpdate::new(field_id, txn, &map_ref);
       update = update.set_last_modified(timestamp());
       f(update);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("Failed to update the field:{}. {}", field_id, err);
+    }
   }
 
   /// Delete a field with a transaction
@@ -13,7 +13,7 @@ use collab_plugins::local_storage::kv::doc::CollabKVAction;
 use collab_plugins::local_storage::kv::KVTransactionDB;
 use collab_plugins::CollabKVDB;
 use serde::{Deserialize, Serialize};
-use tracing::error;
+use tracing::{error, warn};
 use uuid::Uuid;
 
 use crate::database::{gen_row_id, timestamp};
@@ -55,9 +55,9 @@ impl DatabaseRow {
     collab_db: Weak<CollabKVDB>,
     collab: Arc<MutexCollab>,
     change_tx: Option<RowChangeSender>,
-  ) -> Self {
+  ) -> Result<Self, DatabaseError> {
     let row = row.into();
-    let mut database_row = Self::inner_new(uid, row_id, collab_db, collab);
+    let mut database_row = Self::inner_new(uid, row_id, collab_db, collab)?;
     let data = database_row.data.clone();
     let meta = database_row.meta.clone();
     database_row.collab.lock().with_origin_transact_mut(|txn| {
@@ -72,12 +72,12 @@ impl DatabaseRow {
             .set_cells(row.cells);
         })
         .done();
-    });
+    })?;
 
     database_row.subscription =
       change_tx.map(|sender| subscribe_row_data_change(&mut database_row.data, sender));
 
-    database_row
+    Ok(database_row)
   }
 
   pub fn new(
@@ -86,10 +86,10 @@ impl DatabaseRow {
     collab_db: Weak<CollabKVDB>,
     collab: Arc<MutexCollab>,
     change_tx: Option<RowChangeSender>,
-  ) -> Self {
-    let mut this = Self::inner_new(uid, row_id, collab_db, collab);
+  ) -> Result<Self, DatabaseError> {
+    let mut this = Self::inner_new(uid, row_id, collab_db, collab)?;
     this.subscription = change_tx.map(|sender| subscribe_row_data_change(&mut this.data, sender));
-    this
+    Ok(this)
   }
 
   fn inner_new(
@@ -97,7 +97,7 @@ impl DatabaseRow {
     row_id: RowId,
     collab_db: Weak<CollabKVDB>,
     collab: Arc<MutexCollab>,
-  ) -> Self {
+  ) -> Result<Self, DatabaseError> {
     let collab_guard = collab.lock();
     let (data, meta, comments) = {
       let txn = collab_guard.transact();
@@ -108,9 +108,9 @@ impl DatabaseRow {
       (data, meta, comments)
     };
 
-    // If any of the data is missing, we need to create it.
+    // If any of the data is missing, we need to create it, which fails if the row is read-only.
     let mut txn = if data.is_none() || meta.is_none() || comments.is_none() {
-      Some(collab_guard.origin_transact_mut())
+      Some(collab_guard.origin_transact_mut()?)
     } else {
       None
     };
@@ -126,7 +126,7 @@ impl DatabaseRow {
     drop(txn);
     drop(collab_guard);
 
-    Self {
+    Ok(Self {
       uid,
       row_id,
       collab,
@@ -135,7 +135,7 @@ impl DatabaseRow {
       comments,
       collab_db,
       subscription: None,
-    }
+    })
   }
 
   pub fn get_row(&self) -> Option<Row> {
@@ -163,6 +163,7 @@ impl DatabaseRow {
     cell_from_map_ref(&self.data, &txn, field_id)
   }
 
+  /// Logs a warning and does nothing if the row is read-only.
   pub fn update<F>(&self, f: F)
   where
     F: FnOnce(RowUpdate),
@@ -170,31 +171,39 @@ impl DatabaseRow {
     match self.collab.try_lock() {
       None => error!("failed to acquire lock for updating row"),
       Some(guard) => {
-        guard.with_origin_transact_mut(|txn| {
+        let result = guard.with_origin_transact_mut(|txn| {
           let mut update = RowUpdate::new(txn, &self.data, &self.meta);
 
           // Update the last modified timestamp before we call the update function.
           update = update.set_last_modified(timestamp());
           f(update)
         });
+        if let Err(err) = result {
+          warn!("Failed to update the row:{}. {}", self.row_id, err);
+        }
       },
     }
   }
 
+  /// Logs a warning and does nothing if the row is read-only.
   pub fn update_meta<F>(&self, f: F)
   where
     F: FnOnce(RowMetaUpdate),
   {
-    self
-      .collab
-      .lock()
-      .with_origin_transact_mut(|txn| match Uuid::parse_str(&self.row_id) {
-        Ok(row_id) => {
-          let update = RowMetaUpdate::new(txn, &self.meta, row_id);
-          f(update)
-        },
-        Err(e) => error!("🔴 can't update the row meta: {}", e),
-      })
+    let result =
+      self
+        .collab
+        .lock()
+        .with_origin_transact_mut(|txn| match Uuid::parse_str(&self.row_id) {
+          Ok(row_id) => {
+            let update = RowMetaUpdate::new(txn, &self.meta, row_id);
+            f(update)
+          },
+          Err(e) => error!("🔴 can't update the row meta: {}", e),
+        });
+    if let Err(err) = result {
+      warn!("Failed to update the row meta:{}. {}", self.row_id, err);
+    }
   }
 
   pub fn delete(&self) {
@@ -603,7 +612,10 @@ unsafe impl Sync for MutexDatabaseRow {}
 
 unsafe impl Send for MutexDatabaseRow {}
 
-pub fn mut_row_with_collab<F1: Fn(RowUpdate)>(collab: &Collab, mut_row: F1) {
+pub fn mut_row_with_collab<F1: Fn(RowUpdate)>(
+  collab: &Collab,
+  mut_row: F1,
+) -> Result<(), DatabaseError> {
   collab.with_origin_transact_mut(|txn| {
     if let (Some(data), Some(meta)) = (
       collab.get_map_with_txn(txn, vec![DATA]),
@@ -612,5 +624,6 @@ pub fn mut_row_with_collab<F1: Fn(RowUpdate)>(collab: &Collab, mut_row: F1) {
       let update = RowUpdate::new(txn, &data, &meta);
       mut_row(update);
     }
-  });
+  })?;
+  Ok(())
 }
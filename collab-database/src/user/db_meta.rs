@@ -7,6 +7,7 @@ use collab::preclude::{
 use std::collections::HashSet;
 
 use crate::database::timestamp;
+use crate::error::DatabaseError;
 
 const DATABASES: &str = "databases";
 
@@ -20,26 +21,28 @@ impl DatabaseMetaList {
     Self { array_ref }
   }
 
-  pub fn from_collab(collab: &Collab) -> Self {
+  /// Returns an error if the list doesn't exist yet and the collab is read-only.
+  pub fn from_collab(collab: &Collab) -> Result<Self, DatabaseError> {
     let array = {
       let txn = collab.transact();
       collab.get_array_with_txn(&txn, vec![DATABASES])
     };
 
-    let databases = array.unwrap_or_else(|| {
-      collab.with_origin_transact_mut(|txn| {
+    let databases = match array {
+      Some(array) => array,
+      None => collab.with_origin_transact_mut(|txn| {
         collab.create_array_with_txn::<MapPrelim<Any>>(txn, DATABASES, vec![])
-      })
-    });
+      })?,
+    };
 
-    Self::new(databases)
+    Ok(Self::new(databases))
   }
 
   /// Create a new [DatabaseMeta] for the given database id and view id
   /// use [Self::update_database] to attach more views to the existing database.
   ///
   pub fn add_database(&self, database_id: &str, view_ids: Vec<String>) {
-    self.array_ref.with_transact_mut(|txn| {
+    let result = self.array_ref.with_transact_mut(|txn| {
       // Use HashSet to remove duplicates
       let linked_views: HashSet<String> = view_ids.into_iter().collect();
       let record = DatabaseMeta {
@@ -50,11 +53,14 @@ impl DatabaseMetaList {
       let map_ref = self.array_ref.insert_map_with_txn(txn, None);
       record.fill_map_ref(txn, &map_ref);
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to add the database:{}. {}", database_id, err);
+    }
   }
 
   /// Update the database by the given id
   pub fn update_database(&self, database_id: &str, mut f: impl FnMut(&mut DatabaseMeta)) {
-    self.array_ref.with_transact_mut(|txn| {
+    let result = self.array_ref.with_transact_mut(|txn| {
       if let Some(index) = self.database_index_from_id(txn, database_id) {
         if let Some(Some(map_ref)) = self
           .array_ref
@@ -72,22 +78,27 @@ impl DatabaseMetaList {
         }
       }
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to update the database:{}. {}", database_id, err);
+    }
   }
 
   /// Delete the database by the given id
   pub fn delete_database(&self, database_id: &str) {
-    self.array_ref.with_transact_mut(|txn| {
+    let result = self.array_ref.with_transact_mut(|txn| {
       if let Some(index) = self.database_index_from_id(txn, database_id) {
         self.array_ref.remove(txn, index);
       }
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to delete the database:{}. {}", database_id, err);
+    }
   }
 
   /// Return all the database meta
   pub fn get_all_database_meta(&self) -> Vec<DatabaseMeta> {
-    self
-      .array_ref
-      .with_transact_mut(|txn| self.get_all_database_meta_with_txn(txn))
+    let txn = self.array_ref.transact();
+    self.get_all_database_meta_with_txn(&txn)
   }
 
   /// Test if the database with the given id exists
@@ -2,6 +2,7 @@ use std::sync::Arc;
 
 use collab::core::collab::MutexCollab;
 
+use crate::error::DatabaseError;
 use crate::user::relation::RowRelationMap;
 
 pub struct DatabaseRelation {
@@ -12,7 +13,8 @@ pub struct DatabaseRelation {
 
 const ROW_RELATION_MAP: &str = "row_relations";
 impl DatabaseRelation {
-  pub fn new(collab: Arc<MutexCollab>) -> DatabaseRelation {
+  /// Returns an error if the relation map doesn't exist yet and the collab is read-only.
+  pub fn new(collab: Arc<MutexCollab>) -> Result<DatabaseRelation, DatabaseError> {
     let collab_guard = collab.lock();
     let row_relation_map = {
       let txn = collab_guard.transact();
@@ -21,16 +23,16 @@ impl DatabaseRelation {
 
     let relation_map = match row_relation_map {
       None => collab_guard
-        .with_origin_transact_mut(|txn| collab_guard.insert_map_with_txn(txn, ROW_RELATION_MAP)),
+        .with_origin_transact_mut(|txn| collab_guard.insert_map_with_txn(txn, ROW_RELATION_MAP))?,
       Some(row_relation_map) => row_relation_map,
     };
 
     drop(collab_guard);
 
-    Self {
+    Ok(Self {
       inner: collab,
       row_relation_map: RowRelationMap::from_map_ref(relation_map),
-    }
+    })
   }
 
   pub fn row_relations(&self) -> &RowRelationMap {
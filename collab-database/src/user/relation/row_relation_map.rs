@@ -41,9 +41,12 @@ impl RowRelationMap {
   }
 
   pub fn insert_relation(&self, relation: RowRelation) {
-    self
+    let result = self
       .container
-      .with_transact_mut(|txn| self.insert_relation_with_txn(txn, relation))
+      .with_transact_mut(|txn| self.insert_relation_with_txn(txn, relation));
+    if let Err(err) = result {
+      tracing::warn!("Failed to insert the row relation: {}", err);
+    }
   }
 
   pub fn insert_relation_with_txn(&self, txn: &mut TransactionMut, relation: RowRelation) {
@@ -60,9 +63,12 @@ impl RowRelationMap {
   }
 
   pub fn remove_relation(&self, relation_id: &str) {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.with_transact_mut(|txn| {
       self.remove_relation_with_txn(txn, relation_id);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("Failed to remove the row relation:{}. {}", relation_id, err);
+    }
   }
 
   pub fn remove_relation_with_txn(&self, txn: &mut TransactionMut, relation_id: &str) {
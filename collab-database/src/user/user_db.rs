@@ -147,7 +147,8 @@ impl WorkspaceDatabase {
   /// Get the database with the given database id.
   /// Return None if the database does not exist.
   pub async fn get_database(&self, database_id: &str) -> Option<Arc<MutexDatabase>> {
-    if !self.database_meta_list().contains(database_id) {
+    // A read-only collab without the list has no databases.
+    if !self.database_meta_list().ok()?.contains(database_id) {
       return None;
     }
     let database = self.databases.lock().get(database_id).cloned();
@@ -194,6 +195,7 @@ impl WorkspaceDatabase {
   pub fn get_database_id_with_view_id(&self, view_id: &str) -> Option<String> {
     self
       .database_meta_list()
+      .ok()?
       .get_database_meta_with_view_id(view_id)
       .map(|record| record.database_id)
   }
@@ -222,7 +224,7 @@ impl WorkspaceDatabase {
 
     // Add a new database record.
     self
-      .database_meta_list()
+      .database_meta_list()?
       .add_database(&params.database_id, vec![params.view_id.clone()]);
     let database_id = params.database_id.clone();
     // TODO(RS): insert the first view of the database.
@@ -233,9 +235,10 @@ impl WorkspaceDatabase {
   }
 
   pub fn track_database(&self, database_id: &str, database_view_ids: Vec<String>) {
-    self
-      .database_meta_list()
-      .add_database(database_id, database_view_ids);
+    match self.database_meta_list() {
+      Ok(database_meta_list) => database_meta_list.add_database(database_id, database_view_ids),
+      Err(err) => tracing::warn!("Failed to track the database:{}. {}", database_id, err),
+    }
   }
 
   /// Create database with the data duplicated from the given database.
@@ -260,7 +263,7 @@ impl WorkspaceDatabase {
     let params = CreateViewParamsValidator::validate(params)?;
     if let Some(database) = self.get_database(&params.database_id).await {
       self
-        .database_meta_list()
+        .database_meta_list()?
         .update_database(&params.database_id, |record| {
           // Check if the view is already linked to the database.
           if record.linked_views.contains(&params.view_id) {
@@ -277,7 +280,10 @@ impl WorkspaceDatabase {
 
   /// Delete the database with the given database id.
   pub fn delete_database(&self, database_id: &str) {
-    self.database_meta_list().delete_database(database_id);
+    match self.database_meta_list() {
+      Ok(database_meta_list) => database_meta_list.delete_database(database_id),
+      Err(err) => tracing::warn!("Failed to delete the database:{}. {}", database_id, err),
+    }
     if let Some(collab_db) = self.collab_db.upgrade() {
       let _ = collab_db.with_write_txn(|w_db_txn| {
         match w_db_txn.delete_doc(self.uid, database_id) {
@@ -301,7 +307,10 @@ impl WorkspaceDatabase {
 
   /// Return all the database records.
   pub fn get_all_database_meta(&self) -> Vec<DatabaseMeta> {
-    self.database_meta_list().get_all_database_meta()
+    self
+      .database_meta_list()
+      .map(|database_meta_list| database_meta_list.get_all_database_meta())
+      .unwrap_or_default()
   }
 
   pub fn get_database_snapshots(&self, database_id: &str) -> Vec<CollabSnapshot> {
@@ -324,7 +333,7 @@ impl WorkspaceDatabase {
       Update::decode_v1(&snapshot.data).map_err(|err| DatabaseError::Internal(err.into()))?;
     collab.lock().with_origin_transact_mut(|txn| {
       txn.apply_update(update);
-    });
+    })?;
 
     let context = DatabaseContext {
       uid: self.uid,
@@ -384,11 +393,13 @@ impl WorkspaceDatabase {
     )
   }
 
-  fn database_meta_list(&self) -> DatabaseMetaList {
+  fn database_meta_list(&self) -> Result<DatabaseMetaList, DatabaseError> {
     DatabaseMetaList::from_collab(&self.collab.lock())
   }
 }
 
 pub fn get_all_database_meta(collab: &Collab) -> Vec<DatabaseMeta> {
-  DatabaseMetaList::from_collab(collab).get_all_database_meta()
+  DatabaseMetaList::from_collab(collab)
+    .map(|database_meta_list| database_meta_list.get_all_database_meta())
+    .unwrap_or_default()
 }
@@ -44,9 +44,12 @@ impl ViewMap {
   }
 
   pub fn insert_view(&self, view: DatabaseView) {
-    self
+    let result = self
       .container
-      .with_transact_mut(|txn| self.insert_view_with_txn(txn, view))
+      .try_with_transact_mut(|txn| self.insert_view_with_txn(txn, view));
+    if let Err(err) = result {
+      tracing::warn!("Failed to insert the database view: {}", err);
+    }
   }
 
   pub fn insert_view_with_txn(&self, txn: &mut TransactionMut, view: DatabaseView) {
@@ -263,9 +266,12 @@ impl ViewMap {
   where
     F: FnOnce(DatabaseViewUpdate),
   {
-    self
+    let result = self
       .container
-      .with_transact_mut(|txn| self.update_view_with_txn(txn, view_id, f))
+      .try_with_transact_mut(|txn| self.update_view_with_txn(txn, view_id, f));
+    if let Err(err) = result {
+      tracing::warn!("Failed to update the database view:{}. {}", view_id, err);
+    }
   }
 
   pub fn update_view_with_txn<F>(&self, txn: &mut TransactionMut, view_id: &str, f: F)
@@ -288,9 +294,12 @@ impl ViewMap {
   where
     F: Fn(String, DatabaseViewUpdate),
   {
-    self
+    let result = self
       .container
-      .with_transact_mut(|txn| self.update_all_views_with_txn(txn, f));
+      .try_with_transact_mut(|txn| self.update_all_views_with_txn(txn, f));
+    if let Err(err) = result {
+      tracing::warn!("Failed to update the database views: {}", err);
+    }
   }
 
   pub fn update_all_views_with_txn<F>(&self, txn: &mut TransactionMut, f: F)
@@ -312,9 +321,12 @@ impl ViewMap {
   }
 
   pub fn delete_view(&self, view_id: &str) {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       self.container.remove(txn, view_id);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("Failed to delete the database view:{}. {}", view_id, err);
+    }
   }
 
   pub fn clear_with_txn(&self, txn: &mut TransactionMut) {
@@ -9,6 +9,7 @@ mod layout_test;
 mod restore_test;
 mod row_observe_test;
 mod row_test;
+mod simulation_test;
 mod sort_test;
 mod type_option_test;
 mod view_test;
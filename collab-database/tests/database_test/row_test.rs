@@ -276,3 +276,25 @@ async fn row_document_id_test() {
     );
   }
 }
+
+#[tokio::test]
+async fn update_row_stamps_last_modified_test() {
+  let database_test = create_database(1, "1").await;
+  let row_id = gen_row_id();
+  database_test
+    .create_row(CreateRowParams {
+      id: row_id.clone(),
+      timestamp: 1,
+      ..Default::default()
+    })
+    .unwrap();
+  assert_eq!(database_test.get_row(&row_id).modified_at, 1);
+
+  database_test.update_row(&row_id, |update| {
+    update.set_height(100);
+  });
+  let row = database_test.get_row(&row_id);
+  assert_eq!(row.height, 100);
+  assert_eq!(row.created_at, 1);
+  assert!(row.modified_at > 1);
+}
@@ -0,0 +1,157 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use collab::core::collab::MutexCollab;
+use collab::simulation::{
+  simulation_seeds, Simulation, SimulationConfig, SimulationModel, SimulationRng,
+};
+use collab_database::database::{Database, DatabaseContext};
+use collab_database::fields::Field;
+use collab_database::rows::{CreateRowParams, RowId};
+use collab_database::views::{CreateDatabaseParams, OrderObjectPosition};
+use collab_plugins::CollabKVDB;
+
+use crate::database_test::helper::default_field_settings_by_layout;
+use crate::helper::make_rocks_db;
+use crate::user_test::helper::TestUserDatabaseCollabBuilderImpl;
+
+const DATABASE_ID: &str = "d1";
+const VIEW_ID: &str = "v1";
+
+struct DatabaseModel {
+  database: Database,
+  #[allow(dead_code)]
+  collab_db: Arc<CollabKVDB>,
+}
+
+impl DatabaseModel {
+  fn new(uid: i64, collab: MutexCollab, create: bool) -> Self {
+    let collab_db = make_rocks_db();
+    let context = DatabaseContext {
+      uid,
+      db: Arc::downgrade(&collab_db),
+      collab: Arc::new(collab),
+      collab_service: Arc::new(TestUserDatabaseCollabBuilderImpl()),
+      notifier: None,
+    };
+    let database = if create {
+      let params = CreateDatabaseParams {
+        database_id: DATABASE_ID.to_string(),
+        view_id: VIEW_ID.to_string(),
+        ..Default::default()
+      };
+      Database::create_with_inline_view(params, context).unwrap()
+    } else {
+      Database::get_or_create(DATABASE_ID, context).unwrap()
+    };
+    Self {
+      database,
+      collab_db,
+    }
+  }
+}
+
+impl SimulationModel for DatabaseModel {
+  fn apply_random_operation(&mut self, rng: &mut SimulationRng) -> String {
+    let view = self.database.get_view(VIEW_ID).unwrap();
+    let field_ids = view
+      .field_orders
+      .iter()
+      .map(|order| order.id.clone())
+      .collect::<Vec<_>>();
+    let row_ids = view
+      .row_orders
+      .iter()
+      .map(|order| order.id.clone())
+      .collect::<Vec<_>>();
+    match (rng.below(5), rng.choose(&field_ids), rng.choose(&row_ids)) {
+      (1, Some(field_id), _) => {
+        let name = rng.string(5);
+        self.database.fields.update_field(field_id, |update| {
+          update.set_name(&name);
+        });
+        format!("rename field {} to {}", field_id, name)
+      },
+      (2, Some(field_id), _) => {
+        self.database.delete_field(field_id);
+        format!("delete field {}", field_id)
+      },
+      (3, _, _) => {
+        let row_id = RowId::from(rng.string(10));
+        self
+          .database
+          .create_row(CreateRowParams::new(row_id.clone()))
+          .unwrap();
+        format!("create row {}", row_id)
+      },
+      (4, _, Some(row_id)) => {
+        self.database.remove_row(row_id);
+        format!("remove row {}", row_id)
+      },
+      _ => {
+        let field_id = rng.string(10);
+        let field = Field::new(field_id.clone(), rng.string(5), 0, false);
+        self.database.create_field(
+          None,
+          field,
+          &OrderObjectPosition::default(),
+          default_field_settings_by_layout(),
+        );
+        format!("create field {}", field_id)
+      },
+    }
+  }
+
+  fn check_invariants(&self) -> Result<(), String> {
+    let database_id = self.database.get_database_id();
+    if database_id != DATABASE_ID {
+      return Err(format!("the database id is {}", database_id));
+    }
+    let view = self
+      .database
+      .get_view(VIEW_ID)
+      .ok_or_else(|| "the inline view is missing".to_string())?;
+
+    let fields = self
+      .database
+      .get_fields(None)
+      .into_iter()
+      .map(|field| field.id)
+      .collect::<HashSet<_>>();
+    let mut seen = HashSet::new();
+    for order in &view.field_orders {
+      if !seen.insert(order.id.clone()) {
+        return Err(format!("the field order {} appears twice", order.id));
+      }
+      if !fields.contains(&order.id) {
+        return Err(format!("the field order {} has no field", order.id));
+      }
+    }
+
+    let mut seen = HashSet::new();
+    for order in &view.row_orders {
+      if !seen.insert(order.id.clone()) {
+        return Err(format!("the row order {} appears twice", order.id));
+      }
+    }
+    Ok(())
+  }
+}
+
+#[tokio::test]
+async fn database_convergence_test() {
+  let config = SimulationConfig {
+    object_id: DATABASE_ID.to_string(),
+    ..Default::default()
+  };
+  for seed in simulation_seeds(3) {
+    let mut simulation = Simulation::new(
+      seed,
+      config.clone(),
+      |uid, collab| DatabaseModel::new(uid, collab, true),
+      |uid, collab| DatabaseModel::new(uid, collab, false),
+    )
+    .unwrap();
+    simulation.assert_run(100);
+  }
+}
@@ -9,7 +9,7 @@ use std::fmt;
 use std::fmt::Display;
 use syn::Meta::{List, NameValue};
 use syn::NestedMeta::Meta;
-use syn::{self, punctuated::Punctuated, Fields, LitStr, Path, Token};
+use syn::{self, punctuated::Punctuated, Fields, Path, Token};
 
 pub struct ASTContainer<'a> {
   /// The struct or enum name (without generics).
@@ -73,7 +73,7 @@ pub struct ASTEnumVariant<'a> {
 pub struct ASTField<'a> {
   pub member: syn::Member,
   pub ty: &'a syn::Type,
-  pub yrs_attr: YrsAttribute,
+  pub attr: CollabAttribute,
   pub original: &'a syn::Field,
 }
 
@@ -85,49 +85,78 @@ impl<'a> ASTField<'a> {
         None => syn::Member::Unnamed(index.into()),
       },
       ty: &field.ty,
-      yrs_attr: YrsAttribute::from_ast(ast_result, field),
+      attr: CollabAttribute::from_ast(ast_result, field),
       original: field,
     })
   }
 }
 
-pub const YRS: Symbol = Symbol("yrs");
-pub const PRS_TY: Symbol = Symbol("ty");
-pub struct YrsAttribute {
-  #[allow(dead_code)]
-  ty: Option<LitStr>,
+pub const COLLAB: Symbol = Symbol("collab");
+pub const FIELD_KEY: Symbol = Symbol("key");
+pub const FIELD_MAP: Symbol = Symbol("map");
+pub const FIELD_DEFAULT: Symbol = Symbol("default");
+pub const FIELD_SKIP: Symbol = Symbol("skip");
+
+pub struct CollabAttribute {
+  /// The key of the field in the map. Defaults to the name of the field.
+  pub key: Option<String>,
+  /// The field is a nested struct, or a `Vec` of nested structs, that derives `Collab`.
+  pub map: bool,
+  /// A missing value is read as `Default::default()` instead of failing the read.
+  pub default: bool,
+  /// The field isn't stored. It's read as `Default::default()`.
+  pub skip: bool,
 }
 
-impl YrsAttribute {
-  /// Extract out the `#[yrs(...)]` attributes from a struct field.
+impl CollabAttribute {
+  /// Extract out the `#[collab(...)]` attributes from a struct field.
   pub fn from_ast(ast_result: &ASTResult, field: &syn::Field) -> Self {
-    let mut ty = ASTFieldAttr::none(ast_result, PRS_TY);
+    let mut key = ASTFieldAttr::none(ast_result, FIELD_KEY);
+    let mut map = false;
+    let mut default = false;
+    let mut skip = false;
     for meta_item in field
       .attrs
       .iter()
-      .flat_map(|attr| get_yrs_nested_meta(ast_result, attr))
+      .flat_map(|attr| get_collab_nested_meta(ast_result, attr))
       .flatten()
     {
       match &meta_item {
-        // Parse '#[yrs(ty = x)]'
-        Meta(NameValue(m)) if m.path == PRS_TY => {
+        // Parse '#[collab(key = "x")]'
+        Meta(NameValue(m)) if m.path == FIELD_KEY => {
           if let syn::Lit::Str(lit) = &m.lit {
-            ty.set(&m.path, lit.clone());
+            key.set(&m.path, lit.value());
+          } else {
+            ast_result
+              .error_spanned_by(&m.lit, "key must be a string, e.g. #[collab(key = \"x\")]");
           }
         },
-
+        // Parse '#[collab(map)]'
+        Meta(syn::Meta::Path(path)) if path == FIELD_MAP => map = true,
+        // Parse '#[collab(default)]'
+        Meta(syn::Meta::Path(path)) if path == FIELD_DEFAULT => default = true,
+        // Parse '#[collab(skip)]'
+        Meta(syn::Meta::Path(path)) if path == FIELD_SKIP => skip = true,
         _ => {
           ast_result.error_spanned_by(meta_item, "unexpected meta in field attribute");
         },
       }
     }
-    YrsAttribute { ty: ty.get() }
+    CollabAttribute {
+      key: key.get(),
+      map,
+      default,
+      skip,
+    }
   }
 }
 
-fn get_yrs_nested_meta(cx: &ASTResult, attr: &syn::Attribute) -> Result<Vec<syn::NestedMeta>, ()> {
+fn get_collab_nested_meta(
+  cx: &ASTResult,
+  attr: &syn::Attribute,
+) -> Result<Vec<syn::NestedMeta>, ()> {
   // Only handle the attribute that we have defined
-  if attr.path != YRS {
+  if attr.path != COLLAB {
     return Ok(vec![]);
   }
 
@@ -135,7 +164,10 @@ fn get_yrs_nested_meta(cx: &ASTResult, attr: &syn::Attribute) -> Result<Vec<syn:
     Ok(List(meta)) => Ok(meta.nested.into_iter().collect()),
     Ok(_) => Ok(vec![]),
     Err(err) => {
-      cx.error_spanned_by(attr, "attribute must be str, e.g. #[yrs(xx = \"xxx\")]");
+      cx.error_spanned_by(
+        attr,
+        "attribute must be a list, e.g. #[collab(key = \"xxx\")]",
+      );
       cx.syn_error(err);
       Err(())
     },
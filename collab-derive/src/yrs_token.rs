@@ -1,32 +1,245 @@
-use crate::internal::{ASTContainer, ASTResult};
+use crate::internal::{ASTContainer, ASTData, ASTField, ASTResult, ASTStyle};
 use proc_macro2::{Ident, TokenStream};
 
 use syn::{AngleBracketedGenericArguments, PathSegment, Type};
 
 pub fn make_yrs_token_steam(ast_result: &ASTResult, ast: &ASTContainer) -> Option<TokenStream> {
-  let map_token_stream = token_stream_for_yrs_map(ast_result, ast);
+  if !matches!(ast.data, ASTData::Struct(ASTStyle::Struct, _)) {
+    ast_result.error_spanned_by(
+      &ast.ident,
+      "Collab can only be derived for structs with named fields",
+    );
+    return None;
+  }
+  let fields = ast
+    .data
+    .all_fields()
+    .flat_map(|field| FieldBinding::from_field(ast_result, field))
+    .collect::<Vec<_>>();
+
+  let binding_token_stream = token_stream_for_map_binding(ast, &fields);
+  let map_token_stream = token_stream_for_yrs_map(ast, &fields);
+  let update_token_stream = token_stream_for_update(ast, &fields);
   let token_stream: TokenStream = quote! {
-      #map_token_stream
+      #binding_token_stream
 
+      #map_token_stream
 
+      #update_token_stream
   };
   Some(token_stream)
 }
 
-fn token_stream_for_yrs_map(ast_result: &ASTResult, ast: &ASTContainer) -> Option<TokenStream> {
-  let struct_name = ast.ident.clone();
-  let struct_map_modifier = format_ident!("{}MapRef", struct_name.to_string());
-  let setter_getter_stream_token = ast
-    .data
-    .all_fields()
-    .flat_map(|field| setter_getter_token_stream(ast_result, &field.member, field.ty));
+/// How the value of a field is stored in the map.
+enum ValueKind {
+  /// A `String`, `i64`, `f64` or `bool`.
+  Value,
+  /// A `Vec` of values.
+  Array(Type),
+  /// A `HashMap<String, _>` of values.
+  MapValues(Ident),
+  /// A struct that derives `Collab`.
+  Nested,
+  /// A `Vec` of structs that derive `Collab`.
+  NestedArray(Type),
+  /// Any other serializable type.
+  Json,
+}
 
-  let into_inner_token_stream = ast
-    .data
-    .all_fields()
-    .flat_map(|field| into_inner_token_stream(ast_result, &field.member, field.ty));
+struct FieldBinding<'a> {
+  ident: &'a Ident,
+  key: String,
+  /// The type of the value, without the `Option`.
+  value_ty: Type,
+  kind: ValueKind,
+  optional: bool,
+  default: bool,
+  skip: bool,
+}
+
+impl<'a> FieldBinding<'a> {
+  fn from_field(ast_result: &ASTResult, field: &'a ASTField<'a>) -> Option<Self> {
+    let ident = get_member_ident(ast_result, &field.member)?;
+    let key = field.attr.key.clone().unwrap_or_else(|| ident.to_string());
+    let (ident_type, value_ty, optional) = match IdentType::from_ty(ast_result, field.ty) {
+      IdentType::OptionType {
+        ident_type,
+        inner_ty,
+      } => (*ident_type, inner_ty, true),
+      ident_type => (ident_type, field.ty.clone(), false),
+    };
+    let kind = match ident_type {
+      IdentType::StringType | IdentType::I64Type | IdentType::F64Type | IdentType::BoolType => {
+        ValueKind::Value
+      },
+      IdentType::ArrayType {
+        ident_type,
+        inner_ty,
+      } => {
+        if field.attr.map {
+          ValueKind::NestedArray(inner_ty)
+        } else if ident_type.is_value() {
+          ValueKind::Array(inner_ty)
+        } else {
+          ValueKind::Json
+        }
+      },
+      IdentType::HashMapType { value_type } => {
+        if matches!(
+          value_type.to_string().as_str(),
+          "String" | "i64" | "f64" | "bool"
+        ) {
+          ValueKind::MapValues(value_type)
+        } else {
+          ValueKind::Json
+        }
+      },
+      IdentType::OptionType { .. } => {
+        ast_result.error_spanned_by(field.ty, "Nested Option is not supported");
+        return None;
+      },
+      IdentType::Others => {
+        if field.attr.map {
+          ValueKind::Nested
+        } else {
+          ValueKind::Json
+        }
+      },
+    };
+    if field.attr.map && !matches!(kind, ValueKind::Nested | ValueKind::NestedArray(_)) {
+      ast_result.error_spanned_by(
+        field.ty,
+        "#[collab(map)] only applies to structs and Vec of structs that derive Collab",
+      );
+    }
+    Some(Self {
+      ident,
+      key,
+      value_ty,
+      kind,
+      optional,
+      default: field.attr.default,
+      skip: field.attr.skip,
+    })
+  }
+
+  /// Returns an expression of type `Option<value_ty>` that reads the value from `map_ref`.
+  fn read_token_stream(&self) -> TokenStream {
+    let key = &self.key;
+    let value_ty = &self.value_ty;
+    match &self.kind {
+      ValueKind::Value => quote! {
+          collab::core::map_binding::read_value::<#value_ty, _>(txn, map_ref, #key)
+      },
+      ValueKind::Array(inner_ty) => quote! {
+          collab::core::map_binding::read_array::<#inner_ty, _>(txn, map_ref, #key)
+      },
+      ValueKind::MapValues(value_type) => quote! {
+          collab::core::map_binding::read_map_values::<#value_type, _>(txn, map_ref, #key)
+      },
+      ValueKind::Nested => quote! {
+          collab::core::map_binding::read_nested::<#value_ty, _>(txn, map_ref, #key)
+      },
+      ValueKind::NestedArray(inner_ty) => quote! {
+          collab::core::map_binding::read_nested_array::<#inner_ty, _>(txn, map_ref, #key)
+      },
+      ValueKind::Json => quote! {
+          collab::core::map_binding::read_json::<#value_ty, _>(txn, map_ref, #key)
+      },
+    }
+  }
 
-  Some(quote! {
+  /// Returns a statement that writes `value`, of type `value_ty`, into `map_ref`.
+  fn write_token_stream(&self) -> TokenStream {
+    let key = &self.key;
+    let function = match &self.kind {
+      ValueKind::Value => quote!(write_value),
+      ValueKind::Array(_) => quote!(write_array),
+      ValueKind::MapValues(_) => quote!(write_map_values),
+      ValueKind::Nested => quote!(write_nested),
+      ValueKind::NestedArray(_) => quote!(write_nested_array),
+      ValueKind::Json => quote!(write_json),
+    };
+    quote! {
+        collab::core::map_binding::#function(txn, map_ref, #key, value);
+    }
+  }
+
+  /// Returns a statement that writes `value`, of type `Option<value_ty>`, into `map_ref`. None
+  /// removes the key.
+  fn write_option_token_stream(&self) -> TokenStream {
+    let key = &self.key;
+    let write = self.write_token_stream();
+    quote! {
+        match value {
+            Some(value) => { #write },
+            None => collab::core::map_binding::remove_key(txn, map_ref, #key),
+        }
+    }
+  }
+}
+
+fn token_stream_for_map_binding(ast: &ASTContainer, fields: &[FieldBinding]) -> TokenStream {
+  let struct_name = &ast.ident;
+  let read_fields = fields.iter().map(|field| {
+    let ident = field.ident;
+    let read = field.read_token_stream();
+    if field.skip {
+      quote!(#ident: Default::default(),)
+    } else if field.optional {
+      quote!(#ident: #read,)
+    } else if field.default {
+      quote!(#ident: #read.unwrap_or_default(),)
+    } else {
+      quote!(#ident: #read?,)
+    }
+  });
+  let write_fields = fields.iter().filter(|field| !field.skip).map(|field| {
+    let ident = field.ident;
+    let write = if field.optional {
+      field.write_option_token_stream()
+    } else {
+      field.write_token_stream()
+    };
+    quote! {
+        {
+            let value = self.#ident;
+            #write
+        }
+    }
+  });
+
+  quote! {
+      impl collab::core::map_binding::MapBinding for #struct_name {
+          fn from_map_ref<T: collab::preclude::ReadTxn>(
+              txn: &T,
+              map_ref: &collab::preclude::MapRef,
+          ) -> Option<Self> {
+              Some(Self {
+                  #(#read_fields)*
+              })
+          }
+
+          fn fill_map_ref(
+              self,
+              txn: &mut collab::preclude::TransactionMut,
+              map_ref: &collab::preclude::MapRef,
+          ) {
+              #(#write_fields)*
+          }
+      }
+  }
+}
+
+fn token_stream_for_yrs_map(ast: &ASTContainer, fields: &[FieldBinding]) -> TokenStream {
+  let struct_name = &ast.ident;
+  let struct_map_modifier = format_ident!("{}MapRef", struct_name.to_string());
+  let setter_getter_stream_token = fields
+    .iter()
+    .filter(|field| !field.skip)
+    .map(setter_getter_token_stream);
+
+  quote! {
       pub struct #struct_map_modifier {
           map_ref: collab::preclude::MapRefWrapper,
       }
@@ -38,16 +251,18 @@ fn token_stream_for_yrs_map(ast_result: &ASTResult, ast: &ASTContainer) -> Optio
 
           #(#setter_getter_stream_token)*
 
-          pub fn into_object(&self, txn: &collab::preclude::Transaction) -> #struct_name {
-              #struct_name {
-                  #(#into_inner_token_stream)*
-              }
+          pub fn into_object<T: collab::preclude::ReadTxn>(&self, txn: &T) -> Option<#struct_name> {
+              <#struct_name as collab::core::map_binding::MapBinding>::from_map_ref(txn, &self.map_ref)
+          }
+
+          pub fn fill<V: Into<#struct_name>>(&self, txn: &mut collab::preclude::TransactionMut, value: V) {
+              collab::core::map_binding::MapBinding::fill_map_ref(value.into(), txn, &self.map_ref)
           }
       }
 
       impl collab::preclude::CustomMapRef for #struct_map_modifier {
           fn from_map_ref(map_ref: collab::preclude::MapRefWrapper) -> Self {
-              Self { map_ref}
+              Self { map_ref }
           }
       }
 
@@ -57,153 +272,97 @@ fn token_stream_for_yrs_map(ast_result: &ASTResult, ast: &ASTContainer) -> Optio
               &self.map_ref
           }
       }
-
-  })
+  }
 }
 
-fn into_inner_token_stream(
-  ast_result: &ASTResult,
-  member: &syn::Member,
-  ty: &Type,
-) -> Option<TokenStream> {
-  let ident_type = IdentType::from_ty(ast_result, ty);
-  into_inner_field_token_stream(ast_result, member, ty, &ident_type, false)
-}
+fn setter_getter_token_stream(field: &FieldBinding) -> TokenStream {
+  let setter = format_ident!("set_{}", field.ident.to_string());
+  let getter = format_ident!("get_{}", field.ident.to_string());
+  let value_ty = &field.value_ty;
+  let read = field.read_token_stream();
+  let (setter_ty, write) = if field.optional {
+    (quote!(Option<#value_ty>), field.write_option_token_stream())
+  } else {
+    (quote!(#value_ty), field.write_token_stream())
+  };
+  quote! {
+      pub fn #getter<T: collab::preclude::ReadTxn>(&self, txn: &T) -> Option<#value_ty> {
+          let map_ref: &collab::preclude::MapRef = &self.map_ref;
+          #read
+      }
 
-fn into_inner_field_token_stream(
-  ast_result: &ASTResult,
-  member: &syn::Member,
-  ty: &Type,
-  ident_type: &IdentType,
-  is_option: bool,
-) -> Option<TokenStream> {
-  let ident = get_member_ident(ast_result, member)?;
-  let getter = format_ident!("get_{}", ident.to_string());
-  match ident_type {
-    IdentType::StringType
-    | IdentType::I64Type
-    | IdentType::F64Type
-    | IdentType::BoolType
-    | IdentType::ArrayType { .. }
-    | IdentType::HashMapType { .. } => {
-      if is_option {
-        Some(quote! {
-            #ident: self.#getter(txn),
-        })
-      } else {
-        Some(quote! {
-            #ident: self.#getter(txn).unwrap_or_default(),
-        })
+      pub fn #setter(&self, txn: &mut collab::preclude::TransactionMut, value: #setter_ty) {
+          let map_ref: &collab::preclude::MapRef = &self.map_ref;
+          #write
       }
-    },
-    IdentType::Others => Some(quote! {
-       #ident: self.#getter::<#ty>(txn).unwrap_or_default(),
-    }),
-    IdentType::OptionType {
-      ident_type,
-      inner_ty,
-    } => into_inner_field_token_stream(ast_result, member, inner_ty, ident_type, true),
   }
 }
 
-fn setter_getter_token_steam_for_item_type(
-  key: String,
-  setter: Ident,
-  getter: Ident,
-  ty: &Type,
-  ident: &Ident,
-  ident_type: &IdentType,
-) -> Option<TokenStream> {
-  match ident_type {
-    IdentType::StringType => Some(quote! {
-        pub fn #setter(&mut self, txn: &mut collab::preclude::TransactionMut, value: #ty) {
-            self.map_ref.insert_with_txn(txn, #key, value)
-        }
-        pub fn #getter(&self, txn: &collab::preclude::Transaction) -> Option<#ty> {
-            self.map_ref.get_str_with_txn(txn, #key)
-        }
-    }),
-    IdentType::I64Type => Some(quote! {
-        pub fn #setter(&mut self, txn: &mut collab::preclude::TransactionMut, value: #ty) {
-            self.map_ref.insert_with_txn(txn, #key, value)
-        }
-        pub fn #getter(&self, txn: &collab::preclude::Transaction) -> Option<#ty> {
-            self.map_ref.get_i64_with_txn(txn, #key)
-        }
-    }),
-    IdentType::F64Type => Some(quote! {
-        pub fn #setter(&mut self, txn: &mut collab::preclude::TransactionMut, value: #ty) {
-            self.map_ref.insert_with_txn(txn, #key, value)
-        }
-        pub fn #getter(&self, txn: &collab::preclude::Transaction) -> Option<#ty> {
-            self.map_ref.get_f64_with_txn(txn, #key)
-        }
-    }),
-    IdentType::BoolType => Some(quote! {
-        pub fn #setter(&mut self, txn: &mut collab::preclude::TransactionMut, value: #ty) {
-            self.map_ref.insert_with_txn(txn, #key, value)
-        }
-        pub fn #getter(&self, txn: &collab::preclude::Transaction) -> Option<#ty> {
-            self.map_ref.get_bool_with_txn(txn, #key)
-        }
-    }),
-    IdentType::HashMapType { value_type } => {
-      let update = format_ident!("update_{}_key_value", ident.to_string());
-      Some(quote! {
-          pub fn #update(&mut self, txn: &mut collab::preclude::TransactionMut, key: &str, value: #value_type) {
-              if let Some(map_ref) = self.map_ref.get_map_with_txn(txn, #key) {
-                  map_ref.insert_with_txn(txn, key, value);
-              }
-          }
+/// Generates a builder that updates the fields one by one, like the hand-written `ViewUpdate`.
+fn token_stream_for_update(ast: &ASTContainer, fields: &[FieldBinding]) -> TokenStream {
+  let struct_name = &ast.ident;
+  let update_name = format_ident!("{}Update", struct_name.to_string());
+  let setters = fields
+    .iter()
+    .filter(|field| !field.skip)
+    .map(update_setter_token_stream);
 
-          pub fn #setter(&mut self, txn: &mut collab::preclude::TransactionMut, value: #ty) {
-              self.map_ref.insert_json_with_txn(txn, #key, value)
-          }
+  quote! {
+      pub struct #update_name<'a, 'b> {
+          map_ref: &'a collab::preclude::MapRef,
+          txn: &'a mut collab::preclude::TransactionMut<'b>,
+      }
 
-          pub fn #getter(&self, txn: &collab::preclude::Transaction) -> Option<#ty> {
-              self.map_ref.get_json_with_txn(txn, #key)
+      impl<'a, 'b> #update_name<'a, 'b> {
+          pub fn new(
+              txn: &'a mut collab::preclude::TransactionMut<'b>,
+              map_ref: &'a collab::preclude::MapRef,
+          ) -> Self {
+              Self { map_ref, txn }
           }
-      })
-    },
-    IdentType::Others => Some(quote! {
-        pub fn #setter<T: serde::Serialize>(&mut self, txn: &mut collab::preclude::TransactionMut, value: T) {
-            self.map_ref.insert_json_with_txn(txn, #key, value);
-        }
 
-        pub fn #getter<T: serde::de::DeserializeOwned>(&self, txn: &collab::preclude::Transaction) -> Option<#ty> {
-            self.map_ref.get_json_with_txn::<#ty>(txn, #key)
+          #(#setters)*
+      }
+  }
+}
+
+fn update_setter_token_stream(field: &FieldBinding) -> TokenStream {
+  let setter = format_ident!("set_{}", field.ident.to_string());
+  let value_ty = &field.value_ty;
+  if field.optional {
+    let write = field.write_option_token_stream();
+    quote! {
+        pub fn #setter(self, value: Option<#value_ty>) -> Self {
+            {
+                let txn = &mut *self.txn;
+                let map_ref = self.map_ref;
+                #write
+            }
+            self
+        }
+    }
+  } else {
+    let setter_if_not_none = format_ident!("set_{}_if_not_none", field.ident.to_string());
+    let write = field.write_token_stream();
+    quote! {
+        pub fn #setter(self, value: #value_ty) -> Self {
+            {
+                let txn = &mut *self.txn;
+                let map_ref = self.map_ref;
+                #write
+            }
+            self
         }
-    }),
-    IdentType::OptionType {
-      ident_type,
-      inner_ty,
-    } => setter_getter_token_steam_for_item_type(key, setter, getter, inner_ty, ident, ident_type),
-    IdentType::ArrayType {
-      ident_type: _,
-      inner_ty: _,
-    } => Some(quote! {
-          pub fn #setter(&mut self, txn: &mut collab::preclude::TransactionMut, value: #ty) {
-              self.map_ref.insert_json_with_txn(txn, #key, value)
-          }
 
-          pub fn #getter(&self, txn: &collab::preclude::Transaction) -> Option<#ty> {
-              self.map_ref.get_json_with_txn(txn, #key)
-          }
-    }),
+        pub fn #setter_if_not_none(self, value: Option<#value_ty>) -> Self {
+            match value {
+                Some(value) => self.#setter(value),
+                None => self,
+            }
+        }
+    }
   }
 }
-fn setter_getter_token_stream(
-  ast_result: &ASTResult,
-  member: &syn::Member,
-  ty: &Type,
-) -> Option<TokenStream> {
-  let ident = get_member_ident(ast_result, member)?;
-  let key = ident.to_string();
-  let setter = format_ident!("set_{}", ident.to_string());
-  let getter = format_ident!("get_{}", ident.to_string());
-  let ident_type = IdentType::from_ty(ast_result, ty);
-  setter_getter_token_steam_for_item_type(key, setter, getter, ty, ident, &ident_type)
-}
 
 pub(crate) fn get_member_ident<'a>(
   ast_result: &ASTResult,
@@ -241,6 +400,14 @@ enum IdentType {
 }
 
 impl IdentType {
+  /// Whether the type is stored as a single yrs value.
+  fn is_value(&self) -> bool {
+    matches!(
+      self,
+      IdentType::StringType | IdentType::I64Type | IdentType::F64Type | IdentType::BoolType
+    )
+  }
+
   pub fn from_ty(ast_result: &ASTResult, ty: &Type) -> Self {
     if let Type::Path(p) = &ty {
       let mut ident_type = match p.path.get_ident() {
@@ -258,8 +425,12 @@ impl IdentType {
         if let Some(seg) = p.path.segments.last() {
           if seg.ident == "HashMap" {
             let types = get_bracketed_value_type_from(ast_result, seg);
-            let ident = parse_ty(types[1]).unwrap();
-            ident_type = IdentType::HashMapType { value_type: ident };
+            match types.get(1).and_then(|ty| parse_ty(ty)) {
+              Some(ident) => ident_type = IdentType::HashMapType { value_type: ident },
+              None => {
+                ast_result.error_spanned_by(seg, "Can not infer the value type of the HashMap")
+              },
+            }
           }
 
           if seg.ident == "Vec" {
@@ -278,21 +449,6 @@ impl IdentType {
               ident_type: Box::new(item_type),
               inner_ty: types[0].clone(),
             };
-
-            // if let Type::Path(p) = types[0] {
-            //     let inner_ty = p.path.get_ident().cloned().unwrap();
-            //     let item_type = IdentType::from_ty(ast_result, types[0]);
-            //     ident_type = IdentType::OptionType {
-            //         ident_type: Box::new(item_type),
-            //         inner_ty: types[0].clone(),
-            //     };
-            // } else {
-            //     ast_result.error_spanned_by(
-            //         types[0],
-            //         "Can not infer the bracket inner type of the Option",
-            //     );
-            //     IdentType::Others
-            // };
           }
         }
       }
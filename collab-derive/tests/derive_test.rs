@@ -0,0 +1,236 @@
+use std::collections::HashMap;
+
+use collab::core::collab::MutexCollab;
+use collab::core::map_binding::MapBinding;
+use collab::core::origin::CollabOrigin;
+use collab::preclude::{Doc, Map, ReadTxn, Transact, Update, Value};
+use collab_derive::Collab;
+use serde::{Deserialize, Serialize};
+use yrs::updates::decoder::Decode;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Icon {
+  ty: u8,
+  value: String,
+}
+
+#[derive(Collab, Debug, Clone, PartialEq)]
+pub struct Address {
+  city: String,
+  zip: Option<String>,
+}
+
+#[derive(Collab, Debug, Clone, PartialEq)]
+pub struct Person {
+  #[collab(key = "person_id")]
+  id: String,
+  name: String,
+  age: i64,
+  score: f64,
+  is_admin: bool,
+  nickname: Option<String>,
+  #[collab(default)]
+  tags: Vec<String>,
+  attributes: HashMap<String, i64>,
+  icon: Option<Icon>,
+  #[collab(map)]
+  address: Address,
+  #[collab(map)]
+  previous_addresses: Vec<Address>,
+  #[collab(skip)]
+  cache: Option<String>,
+}
+
+fn make_person() -> Person {
+  Person {
+    id: "1".to_string(),
+    name: "Lucas".to_string(),
+    age: 30,
+    score: 9.5,
+    is_admin: true,
+    nickname: Some("lu".to_string()),
+    tags: vec!["a".to_string(), "b".to_string()],
+    attributes: HashMap::from([("height".to_string(), 180)]),
+    icon: Some(Icon {
+      ty: 0,
+      value: "🚀".to_string(),
+    }),
+    address: Address {
+      city: "Paris".to_string(),
+      zip: None,
+    },
+    previous_addresses: vec![Address {
+      city: "Berlin".to_string(),
+      zip: Some("10115".to_string()),
+    }],
+    cache: Some("not stored".to_string()),
+  }
+}
+
+#[test]
+fn read_and_write_map_ref_test() {
+  let doc = Doc::new();
+  let map = doc.get_or_insert_map("person");
+  make_person().fill_map_ref(&mut doc.transact_mut(), &map);
+
+  let txn = doc.transact();
+  assert!(map.get(&txn, "person_id").is_some());
+  let person = Person::from_map_ref(&txn, &map).unwrap();
+  assert_eq!(
+    person,
+    Person {
+      cache: None,
+      ..make_person()
+    }
+  );
+}
+
+#[test]
+fn missing_required_field_test() {
+  let doc = Doc::new();
+  let map = doc.get_or_insert_map("address");
+  assert!(Address::from_map_ref(&doc.transact(), &map).is_none());
+
+  map.insert(&mut doc.transact_mut(), "city", "Paris");
+  let address = Address::from_map_ref(&doc.transact(), &map).unwrap();
+  assert_eq!(address.city, "Paris");
+  assert_eq!(address.zip, None);
+}
+
+#[test]
+fn update_builder_test() {
+  let doc = Doc::new();
+  let map = doc.get_or_insert_map("person");
+  make_person().fill_map_ref(&mut doc.transact_mut(), &map);
+  {
+    let mut txn = doc.transact_mut();
+    PersonUpdate::new(&mut txn, &map)
+      .set_name("Nathan".to_string())
+      .set_age_if_not_none(None)
+      .set_nickname(None)
+      .set_tags(vec!["c".to_string()])
+      .set_address(Address {
+        city: "Lyon".to_string(),
+        zip: Some("69001".to_string()),
+      });
+  }
+
+  let person = Person::from_map_ref(&doc.transact(), &map).unwrap();
+  assert_eq!(person.name, "Nathan");
+  assert_eq!(person.age, 30);
+  assert_eq!(person.nickname, None);
+  assert_eq!(person.tags, vec!["c".to_string()]);
+  assert_eq!(person.address.city, "Lyon");
+}
+
+#[test]
+fn map_ref_wrapper_test() {
+  let collab = MutexCollab::new(CollabOrigin::Empty, "1", vec![]);
+  collab.lock().initialize();
+  let map = {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| collab.insert_map_with_txn(txn, "address"))
+      .unwrap()
+  };
+  let address_map = AddressMapRef::new(map);
+  address_map
+    .with_transact_mut(|txn| {
+      address_map.fill(
+        txn,
+        Address {
+          city: "Paris".to_string(),
+          zip: None,
+        },
+      );
+      address_map.set_zip(txn, Some("75001".to_string()));
+    })
+    .unwrap();
+
+  let txn = address_map.transact();
+  assert_eq!(address_map.get_city(&txn), Some("Paris".to_string()));
+  assert_eq!(
+    address_map.into_object(&txn).unwrap().zip,
+    Some("75001".to_string())
+  );
+}
+
+#[test]
+fn concurrent_nested_edits_merge_test() {
+  let doc_1 = Doc::with_client_id(1);
+  let map_1 = doc_1.get_or_insert_map("person");
+  make_person().fill_map_ref(&mut doc_1.transact_mut(), &map_1);
+  let doc_2 = Doc::with_client_id(2);
+  let map_2 = doc_2.get_or_insert_map("person");
+  sync_docs(&doc_1, &doc_2);
+  let nested_address = match map_1.get(&doc_1.transact(), "address") {
+    Some(Value::YMap(map)) => map,
+    _ => panic!("the address should be a nested map"),
+  };
+
+  PersonUpdate::new(&mut doc_1.transact_mut(), &map_1).set_address(Address {
+    city: "Lyon".to_string(),
+    zip: None,
+  });
+  PersonUpdate::new(&mut doc_2.transact_mut(), &map_2).set_address(Address {
+    city: "Paris".to_string(),
+    zip: Some("75001".to_string()),
+  });
+  sync_docs(&doc_1, &doc_2);
+
+  let expected = Address {
+    city: "Lyon".to_string(),
+    zip: Some("75001".to_string()),
+  };
+  let person_1 = Person::from_map_ref(&doc_1.transact(), &map_1).unwrap();
+  let person_2 = Person::from_map_ref(&doc_2.transact(), &map_2).unwrap();
+  assert_eq!(person_1.address, expected);
+  assert_eq!(person_2.address, expected);
+  assert_eq!(
+    Address::from_map_ref(&doc_1.transact(), &nested_address).unwrap(),
+    expected
+  );
+}
+
+#[test]
+fn rewrite_nested_array_in_place_test() {
+  let doc = Doc::new();
+  let map = doc.get_or_insert_map("person");
+  make_person().fill_map_ref(&mut doc.transact_mut(), &map);
+  let previous_addresses = || {
+    Person::from_map_ref(&doc.transact(), &map)
+      .unwrap()
+      .previous_addresses
+  };
+
+  let addresses = vec![
+    Address {
+      city: "Berlin".to_string(),
+      zip: None,
+    },
+    Address {
+      city: "Rome".to_string(),
+      zip: None,
+    },
+  ];
+  PersonUpdate::new(&mut doc.transact_mut(), &map).set_previous_addresses(addresses.clone());
+  assert_eq!(previous_addresses(), addresses);
+
+  PersonUpdate::new(&mut doc.transact_mut(), &map).set_previous_addresses(addresses[1..].to_vec());
+  assert_eq!(previous_addresses(), addresses[1..].to_vec());
+}
+
+fn sync_docs(doc_1: &Doc, doc_2: &Doc) {
+  let update_1 = doc_1
+    .transact()
+    .encode_state_as_update_v1(&doc_2.transact().state_vector());
+  let update_2 = doc_2
+    .transact()
+    .encode_state_as_update_v1(&doc_1.transact().state_vector());
+  doc_2
+    .transact_mut()
+    .apply_update(Update::decode_v1(&update_1).unwrap());
+  doc_1
+    .transact_mut()
+    .apply_update(Update::decode_v1(&update_2).unwrap());
+}
@@ -5,6 +5,7 @@ use std::vec;
 use collab::core::collab::{CollabDocState, MutexCollab};
 use collab::core::collab_state::SyncState;
 use collab::core::origin::CollabOrigin;
+use collab::core::read_only::ReadOnlyMode;
 use collab::preclude::*;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
@@ -95,11 +96,30 @@ impl Document {
     self.inner.lock().subscribe_sync_state()
   }
 
-  pub fn with_transact_mut<F, T>(&self, f: F) -> T
+  /// Returns an error if the document is read-only.
+  pub fn with_transact_mut<F, T>(&self, f: F) -> Result<T, DocumentError>
   where
     F: FnOnce(&mut TransactionMut) -> T,
   {
-    self.root.with_transact_mut(f)
+    Ok(self.root.with_transact_mut(f)?)
+  }
+
+  /// Like [Document::with_transact_mut], but also returns the error of the transaction hook that
+  /// aborted the transaction.
+  pub fn try_with_transact_mut<F, T>(&self, f: F) -> Result<T, DocumentError>
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    Ok(self.root.try_with_transact_mut(f)?)
+  }
+
+  /// A read-only document rejects the local changes, see [ReadOnlyMode].
+  pub fn is_read_only(&self) -> bool {
+    self.root.is_read_only()
+  }
+
+  pub fn set_read_only_mode(&self, mode: ReadOnlyMode) {
+    self.inner.set_read_only_mode(mode);
   }
 
   /// Get document data.
@@ -132,9 +152,12 @@ impl Document {
   /// - @param text_id: The text block's external_id.
   /// - @param delta: The text block's delta. "\[{"insert": "Hello", "attributes": { "bold": true, "italic": true } }, {"insert": " World!"}]".
   pub fn create_text(&self, text_id: &str, delta: String) {
-    self.inner.lock().with_origin_transact_mut(|txn| {
+    let result = self.inner.lock().try_with_origin_transact_mut(|txn| {
       self.create_text_with_txn(txn, text_id, delta);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("[Document] create_text error: {:?}", err);
+    }
   }
 
   pub fn create_text_with_txn(&self, txn: &mut TransactionMut, text_id: &str, delta: String) {
@@ -151,10 +174,13 @@ impl Document {
   /// - @param text_id: The text block's external_id.
   /// - @param delta: The text block's delta. "\[{"insert": "Hello", "attributes": { "bold": true, "italic": true } }, {"insert": " World!"}]".
   pub fn apply_text_delta(&self, text_id: &str, delta: String) {
-    self
+    let result = self
       .inner
       .lock()
-      .with_origin_transact_mut(|txn| self.apply_text_delta_with_txn(txn, text_id, delta))
+      .try_with_origin_transact_mut(|txn| self.apply_text_delta_with_txn(txn, text_id, delta));
+    if let Err(err) = result {
+      tracing::warn!("[Document] apply_text_delta error: {:?}", err);
+    }
   }
 
   pub fn apply_text_delta_with_txn(&self, txn: &mut TransactionMut, text_id: &str, delta: String) {
@@ -172,7 +198,7 @@ impl Document {
 
   /// Apply actions to the document.
   pub fn apply_action(&self, actions: Vec<BlockAction>) {
-    self.inner.lock().with_origin_transact_mut(|txn| {
+    let result = self.inner.lock().try_with_origin_transact_mut(|txn| {
       for action in actions {
         let result = match action.action {
           BlockActionType::Insert => self.handle_insert_action(txn, action.payload),
@@ -191,7 +217,10 @@ impl Document {
           return;
         }
       }
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("[Document] apply_action error: {:?}", err);
+    }
   }
 
   /// Get block with the given id.
@@ -440,7 +469,7 @@ impl Document {
   ) -> Result<Self, DocumentError> {
     let mut collab_guard = collab.lock();
     let (root, block_operation, children_operation, text_operation) = collab_guard
-      .with_origin_transact_mut(|txn| {
+      .try_with_origin_transact_mut(|txn| {
         // { document: {:} }
         let root = collab_guard.insert_map_with_txn(txn, ROOT);
         // { document: { blocks: {:} } }
@@ -479,7 +508,7 @@ impl Document {
         }
 
         Ok::<_, DocumentError>((root, block_operation, children_operation, text_operation))
-      })?;
+      })??;
 
     collab_guard.enable_undo_redo();
     let subscription = RootDeepSubscription::default();
@@ -499,28 +528,43 @@ impl Document {
 
   fn open_document_with_collab(collab: Arc<MutexCollab>) -> Result<Self, DocumentError> {
     let mut collab_guard = collab.lock();
-    let (root, block_operation, children_operation, text_operation) = collab_guard
-      .with_origin_transact_mut(|txn| {
-        let root = collab_guard.get_map_with_txn(txn, vec![ROOT]);
-        if root.is_none() {
-          return (None, None, None, None);
-        }
-        let root = root.unwrap();
+    let txn = collab_guard.transact();
+    let root = collab_guard.get_map_with_txn(&txn, vec![ROOT]);
+    let maps = root.as_ref().and_then(|root| {
+      let blocks = root.get_map_with_txn(&txn, BLOCKS)?;
+      let meta = root.get_map_with_txn(&txn, META)?;
+      let children_map = meta.get_map_with_txn(&txn, CHILDREN_MAP)?;
+      let text_map = meta.get_map_with_txn(&txn, TEXT_MAP)?;
+      Some((blocks, children_map, text_map))
+    });
+    drop(txn);
+
+    // Only the documents that miss some of their maps need a write transaction, so the
+    // complete ones can be opened read-only.
+    let maps = match (&root, maps) {
+      (Some(_), Some(maps)) => Some(maps),
+      (None, _) => None,
+      (Some(root), None) => Some(collab_guard.try_with_origin_transact_mut(|txn| {
         let blocks = root.create_map_with_txn_if_not_exist(txn, BLOCKS);
         let meta = root.create_map_with_txn_if_not_exist(txn, META);
-
         let children_map = meta.create_map_with_txn_if_not_exist(txn, CHILDREN_MAP);
         let text_map = meta.create_map_with_txn_if_not_exist(txn, TEXT_MAP);
+        (blocks, children_map, text_map)
+      })?),
+    };
+    let (block_operation, children_operation, text_operation) = match maps {
+      None => (None, None, None),
+      Some((blocks, children_map, text_map)) => {
         let children_operation = ChildrenOperation::new(children_map);
         let text_operation = TextOperation::new(text_map);
         let block_operation = BlockOperation::new(blocks, children_operation.clone());
         (
-          Some(root),
           Some(block_operation),
           Some(children_operation),
           Some(text_operation),
         )
-      });
+      },
+    };
 
     collab_guard.enable_undo_redo();
     drop(collab_guard);
@@ -194,41 +194,53 @@ impl BlockTestCore {
 
   pub fn insert_text_block(&self, text: String, parent_id: &str, prev_id: Option<String>) -> Block {
     let block = self.get_text_block(text, parent_id);
-    self.document.with_transact_mut(|txn| {
-      self
-        .document
-        .insert_block(txn, block, prev_id)
-        .unwrap_or_else(|e| panic!("insert block error: {:?}", e))
-    })
+    self
+      .document
+      .with_transact_mut(|txn| {
+        self
+          .document
+          .insert_block(txn, block, prev_id)
+          .unwrap_or_else(|e| panic!("insert block error: {:?}", e))
+      })
+      .unwrap()
   }
 
   pub fn update_block_data(&self, block_id: &str, data: HashMap<String, Value>) {
     let block = self.get_block(block_id);
 
-    self.document.with_transact_mut(|txn| {
-      self
-        .document
-        .update_block_data(txn, block.id.as_str(), data)
-        .unwrap_or_else(|e| panic!("update block error: {:?}", e))
-    })
+    self
+      .document
+      .with_transact_mut(|txn| {
+        self
+          .document
+          .update_block_data(txn, block.id.as_str(), data)
+          .unwrap_or_else(|e| panic!("update block error: {:?}", e))
+      })
+      .unwrap()
   }
 
   pub fn delete_block(&self, block_id: &str) {
-    self.document.with_transact_mut(|txn| {
-      self
-        .document
-        .delete_block(txn, block_id)
-        .unwrap_or_else(|e| panic!("delete block error: {:?}", e))
-    })
+    self
+      .document
+      .with_transact_mut(|txn| {
+        self
+          .document
+          .delete_block(txn, block_id)
+          .unwrap_or_else(|e| panic!("delete block error: {:?}", e))
+      })
+      .unwrap()
   }
 
   pub fn move_block(&self, block_id: &str, parent_id: &str, prev_id: Option<String>) {
-    self.document.with_transact_mut(|txn| {
-      self
-        .document
-        .move_block(txn, block_id, Some(parent_id.to_string()), prev_id)
-        .unwrap_or_else(|e| panic!("move block error: {:?}", e))
-    })
+    self
+      .document
+      .with_transact_mut(|txn| {
+        self
+          .document
+          .move_block(txn, block_id, Some(parent_id.to_string()), prev_id)
+          .unwrap_or_else(|e| panic!("move block error: {:?}", e))
+      })
+      .unwrap()
   }
 
   pub fn apply_action(&self, actions: Vec<BlockAction>) {
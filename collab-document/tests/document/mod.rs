@@ -2,3 +2,4 @@ mod document_data_test;
 mod document_test;
 mod redo_undo_test;
 mod restore_test;
+mod simulation_test;
@@ -37,9 +37,11 @@ async fn restore_default_document_test2() {
     data: Default::default(),
   };
 
-  test.with_transact_mut(|txn| {
-    test.insert_block(txn, block.clone(), None).unwrap();
-  });
+  test
+    .with_transact_mut(|txn| {
+      test.insert_block(txn, block.clone(), None).unwrap();
+    })
+    .unwrap();
 
   let restore_document = open_document_with_db(uid, doc_id, test.db).await;
   let restore_block = restore_document.get_block("b1").unwrap();
@@ -71,9 +73,11 @@ async fn multiple_thread_create_document_test() {
       ));
       let (page_id, _, _) = get_document_data(&doc.document);
       let block = create_block(page_id, i);
-      doc.with_transact_mut(|txn| {
-        doc.insert_block(txn, block, None).unwrap();
-      });
+      doc
+        .with_transact_mut(|txn| {
+          doc.insert_block(txn, block, None).unwrap();
+        })
+        .unwrap();
     });
     handles.push(handle);
   }
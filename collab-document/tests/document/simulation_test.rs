@@ -0,0 +1,157 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use collab::simulation::{
+  simulation_seeds, Simulation, SimulationConfig, SimulationModel, SimulationRng,
+};
+use collab_document::blocks::{Block, DocumentData, DocumentMeta};
+use collab_document::document::Document;
+use serde_json::json;
+
+const PAGE_ID: &str = "page";
+const PAGE_CHILDREN_ID: &str = "page_children";
+
+struct DocumentModel {
+  document: Document,
+}
+
+impl DocumentModel {
+  fn page_children(&self) -> Vec<String> {
+    let data = self.document.get_document_data().unwrap();
+    data
+      .meta
+      .children_map
+      .get(PAGE_CHILDREN_ID)
+      .cloned()
+      .unwrap_or_default()
+  }
+}
+
+impl SimulationModel for DocumentModel {
+  fn apply_random_operation(&mut self, rng: &mut SimulationRng) -> String {
+    let children = self.page_children();
+    let target = rng.choose(&children).cloned();
+    match (rng.below(4), target) {
+      (1, Some(block_id)) => {
+        let mut data = HashMap::new();
+        data.insert("checked".to_string(), json!(rng.chance(0.5)));
+        self
+          .document
+          .with_transact_mut(|txn| {
+            let _ = self.document.update_block_data(txn, &block_id, data);
+          })
+          .unwrap();
+        format!("update {}", block_id)
+      },
+      (2, Some(block_id)) => {
+        let text_id = self
+          .document
+          .get_block(&block_id)
+          .and_then(|block| block.external_id);
+        let text = rng.string(3);
+        if let Some(text_id) = text_id {
+          let delta = json!([{ "insert": text }]).to_string();
+          self.document.apply_text_delta(&text_id, delta);
+        }
+        format!("type {} in {}", text, block_id)
+      },
+      (3, Some(block_id)) => {
+        self
+          .document
+          .with_transact_mut(|txn| {
+            let _ = self.document.delete_block(txn, &block_id);
+          })
+          .unwrap();
+        format!("delete {}", block_id)
+      },
+      (_, prev_id) => {
+        let block_id = rng.string(10);
+        let text_id = rng.string(10);
+        let block = Block {
+          id: block_id.clone(),
+          ty: "text".to_string(),
+          parent: PAGE_ID.to_string(),
+          children: rng.string(10),
+          external_id: Some(text_id.clone()),
+          external_type: Some("text".to_string()),
+          data: HashMap::new(),
+        };
+        self
+          .document
+          .with_transact_mut(|txn| {
+            self
+              .document
+              .create_text_with_txn(txn, &text_id, "[]".to_string());
+            self
+              .document
+              .insert_block(txn, block, prev_id.clone())
+              .unwrap();
+          })
+          .unwrap();
+        format!("insert {} after {:?}", block_id, prev_id)
+      },
+    }
+  }
+
+  fn check_invariants(&self) -> Result<(), String> {
+    let data = self
+      .document
+      .get_document_data()
+      .map_err(|err| err.to_string())?;
+    if data.page_id != PAGE_ID || !data.blocks.contains_key(PAGE_ID) {
+      return Err("the page is missing".to_string());
+    }
+    let mut seen = HashSet::new();
+    for child in self.page_children() {
+      if !seen.insert(child.clone()) {
+        return Err(format!("{} appears twice in the page", child));
+      }
+      match data.blocks.get(&child) {
+        None => return Err(format!("the child {} has no block", child)),
+        Some(block) if block.parent != PAGE_ID => {
+          return Err(format!("{} has the parent {}", child, block.parent))
+        },
+        Some(_) => {},
+      }
+    }
+    Ok(())
+  }
+}
+
+fn make_document_data() -> DocumentData {
+  let page = Block {
+    id: PAGE_ID.to_string(),
+    ty: "page".to_string(),
+    parent: "".to_string(),
+    children: PAGE_CHILDREN_ID.to_string(),
+    external_id: None,
+    external_type: None,
+    data: HashMap::new(),
+  };
+  DocumentData {
+    page_id: PAGE_ID.to_string(),
+    blocks: HashMap::from([(PAGE_ID.to_string(), page)]),
+    meta: DocumentMeta {
+      children_map: HashMap::from([(PAGE_CHILDREN_ID.to_string(), vec![])]),
+      text_map: Some(HashMap::new()),
+    },
+  }
+}
+
+#[tokio::test]
+async fn document_convergence_test() {
+  for seed in simulation_seeds(5) {
+    let mut simulation = Simulation::new(
+      seed,
+      SimulationConfig::default(),
+      |_, collab| DocumentModel {
+        document: Document::create_with_data(Arc::new(collab), make_document_data()).unwrap(),
+      },
+      |_, collab| DocumentModel {
+        document: Document::open(Arc::new(collab)).unwrap(),
+      },
+    )
+    .unwrap();
+    simulation.assert_run(150);
+  }
+}
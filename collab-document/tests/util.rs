@@ -156,7 +156,7 @@ pub fn insert_block(
   block: Block,
   prev_id: String,
 ) -> Result<Block, DocumentError> {
-  document.with_transact_mut(|txn| document.insert_block(txn, block, Some(prev_id)))
+  document.with_transact_mut(|txn| document.insert_block(txn, block, Some(prev_id)))?
 }
 
 pub fn get_document_data(
@@ -173,7 +173,7 @@ pub fn get_document_data(
 }
 
 pub fn delete_block(document: &Document, block_id: &str) -> Result<(), DocumentError> {
-  document.with_transact_mut(|txn| document.delete_block(txn, block_id))
+  document.with_transact_mut(|txn| document.delete_block(txn, block_id))?
 }
 
 pub fn update_block(
@@ -181,7 +181,7 @@ pub fn update_block(
   block_id: &str,
   data: HashMap<String, Value>,
 ) -> Result<(), DocumentError> {
-  document.with_transact_mut(|txn| document.update_block_data(txn, block_id, data))
+  document.with_transact_mut(|txn| document.update_block_data(txn, block_id, data))?
 }
 
 pub fn apply_actions(document: &Document, actions: Vec<BlockAction>) {
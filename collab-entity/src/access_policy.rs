@@ -0,0 +1,49 @@
+use std::collections::HashMap;
+
+use collab::core::access_control::AccessPolicy;
+use collab::preclude::Collab;
+
+use crate::CollabType;
+
+/// The [AccessPolicy] of each [CollabType]. For example, to only allow the user 1 to change the
+/// fields of a database:
+///
+/// ```
+/// use collab::core::access_control::AccessPolicy;
+/// use collab_entity::{CollabAccessPolicies, CollabType};
+///
+/// let policies = CollabAccessPolicies::new().with_policy(
+///   CollabType::Database,
+///   AccessPolicy::new().with_protected_path(["data", "database", "fields"], [1]),
+/// );
+/// assert!(policies.get(&CollabType::Database).is_some());
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CollabAccessPolicies(HashMap<CollabType, AccessPolicy>);
+
+impl CollabAccessPolicies {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_policy(mut self, collab_type: CollabType, policy: AccessPolicy) -> Self {
+    self.0.insert(collab_type, policy);
+    self
+  }
+
+  pub fn get(&self, collab_type: &CollabType) -> Option<&AccessPolicy> {
+    self.0.get(collab_type)
+  }
+
+  /// Sets the policy of the given [CollabType] to the collab. Returns false if the type has no
+  /// policy.
+  pub fn apply(&self, collab_type: &CollabType, collab: &Collab) -> bool {
+    match self.get(collab_type) {
+      None => false,
+      Some(policy) => {
+        collab.set_access_policy(policy.clone());
+        true
+      },
+    }
+  }
+}
@@ -23,6 +23,22 @@ impl CollabType {
   }
 }
 
+impl TryFrom<i32> for CollabType {
+  type Error = anyhow::Error;
+
+  fn try_from(value: i32) -> Result<Self, Self::Error> {
+    match value {
+      0 => Ok(CollabType::Document),
+      1 => Ok(CollabType::Database),
+      2 => Ok(CollabType::WorkspaceDatabase),
+      3 => Ok(CollabType::Folder),
+      4 => Ok(CollabType::DatabaseRow),
+      5 => Ok(CollabType::UserAwareness),
+      _ => Err(anyhow::anyhow!("Unknown collab type: {}", value)),
+    }
+  }
+}
+
 impl Display for CollabType {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
     match self {
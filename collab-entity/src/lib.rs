@@ -1,4 +1,8 @@
+pub use access_policy::*;
 pub use collab_object::*;
+pub use migration::*;
 
+mod access_policy;
 mod collab_object;
+mod migration;
 pub mod reminder;
@@ -0,0 +1,76 @@
+use std::collections::HashMap;
+
+use collab::core::migration::{CollabMigrator, Migration, MigrationReport};
+use collab::error::CollabError;
+use collab::preclude::{Collab, CollabBuilder};
+
+use crate::CollabType;
+
+/// The [Migration]s of each [CollabType]. For example:
+///
+/// ```
+/// use collab::core::migration::Migration;
+/// use collab_entity::{CollabMigrations, CollabType};
+///
+/// let migrations = CollabMigrations::new().with_migration(
+///   CollabType::Folder,
+///   Migration::new(1, "remove favorites v1", |_doc, _txn| Ok(())),
+/// );
+/// assert_eq!(migrations.latest_version(&CollabType::Folder), 1);
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct CollabMigrations(HashMap<CollabType, CollabMigrator>);
+
+impl CollabMigrations {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_migration(mut self, collab_type: CollabType, migration: Migration) -> Self {
+    let migrator = self.0.remove(&collab_type).unwrap_or_default();
+    self
+      .0
+      .insert(collab_type, migrator.with_migration(migration));
+    self
+  }
+
+  pub fn get(&self, collab_type: &CollabType) -> Option<&CollabMigrator> {
+    self.0.get(collab_type)
+  }
+
+  pub fn latest_version(&self, collab_type: &CollabType) -> u32 {
+    self
+      .get(collab_type)
+      .map(|migrator| migrator.latest_version())
+      .unwrap_or(0)
+  }
+
+  /// Sets the migrator of the given [CollabType] to the builder, so the migrations run when the
+  /// collab is initialized.
+  pub fn apply_to_builder(
+    &self,
+    collab_type: &CollabType,
+    builder: CollabBuilder,
+  ) -> CollabBuilder {
+    match self.get(collab_type) {
+      None => builder,
+      Some(migrator) => builder.with_migrator(migrator.clone()),
+    }
+  }
+
+  /// Runs the pending migrations of the given [CollabType] on an opened collab.
+  pub fn run(
+    &self,
+    collab_type: &CollabType,
+    collab: &Collab,
+  ) -> Result<MigrationReport, CollabError> {
+    match self.get(collab_type) {
+      None => Ok(MigrationReport {
+        from_version: collab.get_schema_version(),
+        to_version: collab.get_schema_version(),
+        applied: vec![],
+      }),
+      Some(migrator) => migrator.run(collab),
+    }
+  }
+}
@@ -5,6 +5,7 @@ use collab::core::collab::{CollabDocState, IndexContentReceiver, MutexCollab};
 use collab::core::collab_plugin::EncodedCollab;
 use collab::core::collab_state::{SnapshotState, SyncState};
 pub use collab::core::origin::CollabOrigin;
+use collab::core::read_only::ReadOnlyMode;
 use collab::preclude::*;
 use serde::{Deserialize, Serialize};
 use tokio_stream::wrappers::WatchStream;
@@ -102,10 +103,13 @@ impl Folder {
     notifier: Option<FolderNotify>,
   ) -> Result<Self, FolderError> {
     let uid = uid.into();
-    let folder = open_folder(uid.clone(), collab.clone(), notifier.clone()).unwrap_or_else(|| {
-      tracing::info!("Create missing attributes of folder");
-      create_folder(uid, collab, notifier, None)
-    });
+    let folder = match open_folder(uid.clone(), collab.clone(), notifier.clone()) {
+      Some(folder) => folder,
+      None => {
+        tracing::info!("Create missing attributes of folder");
+        create_folder(uid, collab, notifier, None)?
+      },
+    };
 
     // When the folder is opened, the workspace id must be present.
     folder.try_get_workspace_id()?;
@@ -117,7 +121,7 @@ impl Folder {
     collab: Arc<MutexCollab>,
     notifier: Option<FolderNotify>,
     initial_folder_data: FolderData,
-  ) -> Self {
+  ) -> Result<Self, FolderError> {
     create_folder(uid, collab, notifier, Some(initial_folder_data))
   }
 
@@ -150,7 +154,7 @@ impl Folder {
   }
 
   pub fn update_workspace(&self, name: &str) {
-    self.root.with_transact_mut(|txn| {
+    let result = self.root.try_with_transact_mut(|txn| {
       let workspace_id = self.get_workspace_id_with_txn(txn);
       self
         .views
@@ -158,6 +162,9 @@ impl Folder {
           update.set_name(name).done()
         });
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to update workspace: {}", err);
+    }
   }
 
   /// Fetches the folder data based on the current workspace and view.
@@ -241,6 +248,15 @@ impl Folder {
     Some(Workspace::from(view.as_ref()))
   }
 
+  /// A read-only folder rejects the local changes, see [ReadOnlyMode].
+  pub fn is_read_only(&self) -> bool {
+    self.root.is_read_only()
+  }
+
+  pub fn set_read_only_mode(&self, mode: ReadOnlyMode) {
+    self.inner.set_read_only_mode(mode);
+  }
+
   pub fn get_workspace_id(&self) -> String {
     let txn = self.meta.transact();
     self.get_workspace_id_with_txn(&txn)
@@ -336,6 +352,8 @@ impl Folder {
   /// * `new_parent_id` - A string slice that holds the id of the new parent view.
   /// * `prev_view_id` - An `Option<String>` that holds the id of the view after which the `view_id` should be positioned.
   ///
+  /// Returns None if the folder is read-only.
+  ///
   pub fn move_nested_view(
     &self,
     view_id: &str,
@@ -356,23 +374,29 @@ impl Folder {
       return None;
     }
 
-    self.meta.with_transact_mut(|txn| {
-      // dissociate the child from its parent
-      self
-        .views
-        .dissociate_parent_child_with_txn(txn, parent_id, view_id);
-      // associate the child with its new parent and place it after the prev_view_id. If the prev_view_id is None,
-      // place it as the first child.
-      self
-        .views
-        .associate_parent_child_with_txn(txn, new_parent_id, view_id, prev_view_id.clone());
-      // Update the view's parent ID.
-      self
-        .views
-        .update_view_with_txn(&self.uid, txn, view_id, |update| {
-          update.set_bid(new_parent_id).done()
-        });
-    });
+    self
+      .meta
+      .try_with_transact_mut(|txn| {
+        // dissociate the child from its parent
+        self
+          .views
+          .dissociate_parent_child_with_txn(txn, parent_id, view_id);
+        // associate the child with its new parent and place it after the prev_view_id. If the prev_view_id is None,
+        // place it as the first child.
+        self.views.associate_parent_child_with_txn(
+          txn,
+          new_parent_id,
+          view_id,
+          prev_view_id.clone(),
+        );
+        // Update the view's parent ID.
+        self
+          .views
+          .update_view_with_txn(&self.uid, txn, view_id, |update| {
+            update.set_bid(new_parent_id).done()
+          });
+      })
+      .ok()?;
     Some(view)
   }
 
@@ -389,9 +413,12 @@ impl Folder {
       }
     }
 
-    self.meta.with_transact_mut(|txn| {
+    let result = self.meta.try_with_transact_mut(|txn| {
       self.meta.insert_with_txn(txn, CURRENT_VIEW, view_id);
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to set current view: {}", err);
+    }
   }
 
   pub fn get_current_view(&self) -> Option<String> {
@@ -565,10 +592,11 @@ impl Folder {
     }
   }
 
-  pub fn create_section<S: Into<Section>>(&self, section: S) -> MapRefWrapper {
-    self
+  pub fn create_section<S: Into<Section>>(&self, section: S) -> Result<MapRefWrapper, FolderError> {
+    let section = self
       .root
-      .with_transact_mut(|txn| self.section.create_section_with_txn(txn, section.into()))
+      .try_with_transact_mut(|txn| self.section.create_section_with_txn(txn, section.into()))?;
+    Ok(section)
   }
 
   pub fn section_op<S: Into<Section>>(&self, section: S) -> Option<SectionOperation> {
@@ -582,11 +610,11 @@ fn create_folder<T: Into<UserId>>(
   collab: Arc<MutexCollab>,
   notifier: Option<FolderNotify>,
   folder_data: Option<FolderData>,
-) -> Folder {
+) -> Result<Folder, FolderError> {
   let uid = uid.into();
   let collab_guard = collab.lock();
   let index_json_sender = collab_guard.index_json_sender.clone();
-  let (folder, views, section, meta, subscription) = collab_guard.with_origin_transact_mut(|txn| {
+  let result = collab_guard.try_with_origin_transact_mut(|txn| {
     // create the folder
     let mut folder = collab_guard.insert_map_with_txn_if_not_exist(txn, FOLDER);
     let subscription = subscribe_folder_change(&mut folder);
@@ -645,8 +673,9 @@ fn create_folder<T: Into<UserId>>(
     (folder, views, section, meta, subscription)
   });
   drop(collab_guard);
+  let (folder, views, section, meta, subscription) = result?;
 
-  Folder {
+  Ok(Folder {
     uid,
     inner: collab,
     root: folder,
@@ -655,7 +684,7 @@ fn create_folder<T: Into<UserId>>(
     meta,
     subscription,
     notifier,
-  }
+  })
 }
 
 pub fn check_folder_is_valid(collab: &Collab) -> Result<String, FolderError> {
@@ -29,9 +29,12 @@ impl Folder {
     drop(txn);
 
     if !favorites.is_empty() {
-      self.root.with_transact_mut(|txn| {
+      let result = self.root.try_with_transact_mut(|txn| {
         self.root.delete_with_txn(txn, FAVORITES_V1);
       });
+      if let Err(err) = result {
+        tracing::warn!("Failed to remove the v1 favorites: {}", err);
+      }
     }
     favorites
   }
@@ -48,12 +51,15 @@ impl Folder {
     };
     if !workspace.is_empty() {
       let workspace = workspace.pop().unwrap();
-      self.root.with_transact_mut(|txn| {
-        self.root.delete_with_txn(txn, WORKSPACES);
-        self
-          .views
-          .insert_view_with_txn(txn, View::from(workspace), None);
-      })
+      self
+        .root
+        .try_with_transact_mut(|txn| {
+          self.root.delete_with_txn(txn, WORKSPACES);
+          self
+            .views
+            .insert_view_with_txn(txn, View::from(workspace), None);
+        })
+        .ok()?;
     }
 
     Some(())
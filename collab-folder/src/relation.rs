@@ -24,9 +24,12 @@ impl ViewRelations {
 
   /// Move the child at `from` to `to` within the parent with `parent_id`.
   pub fn move_child(&self, parent_id: &str, from: u32, to: u32) {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       self.move_child_with_txn(txn, parent_id, from, to);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("Failed to move the child of {}: {}", parent_id, err);
+    }
   }
 
   /// Dissociates a parent-child relationship within a given transaction.
@@ -192,9 +195,12 @@ impl ChildrenArray {
   }
 
   pub fn move_child(&self, from: u32, to: u32) {
-    self.0.with_transact_mut(|txn| {
+    let result = self.0.try_with_transact_mut(|txn| {
       self.move_child_with_txn(txn, from, to);
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to move child: {}", err);
+    }
   }
   pub fn move_child_with_txn(&self, txn: &mut TransactionMut, from: u32, to: u32) {
     if let Some(YrsValue::Any(value)) = self.0.get(txn, from) {
@@ -219,15 +225,21 @@ impl ChildrenArray {
   }
 
   pub fn remove_child(&self, index: u32) {
-    self.0.with_transact_mut(|txn| {
+    let result = self.0.try_with_transact_mut(|txn| {
       self.0.remove_with_txn(txn, index);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("Failed to remove child: {}", err);
+    }
   }
 
   pub fn add_children(&self, belongings: Vec<ViewIdentifier>) {
-    self
+    let result = self
       .0
-      .with_transact_mut(|txn| self.add_children_with_txn(txn, belongings, None))
+      .try_with_transact_mut(|txn| self.add_children_with_txn(txn, belongings, None));
+    if let Err(err) = result {
+      tracing::warn!("Failed to add children: {}", err);
+    }
   }
 
   /// Add children to the views.
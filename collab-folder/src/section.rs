@@ -238,9 +238,12 @@ impl<'a> SectionOperation<'a> {
 
   #[allow(dead_code)]
   pub fn delete_section_items<T: AsRef<str>>(&self, ids: Vec<T>) {
-    self.container().with_transact_mut(|txn| {
+    let result = self.container().try_with_transact_mut(|txn| {
       self.delete_section_items_with_txn(txn, ids);
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to delete section items: {}", err);
+    }
   }
 
   pub fn delete_section_items_with_txn<T: AsRef<str>>(
@@ -276,9 +279,12 @@ impl<'a> SectionOperation<'a> {
 
   #[allow(dead_code)]
   pub fn add_section_items(&self, items: Vec<SectionItem>) {
-    self.container().with_transact_mut(|txn| {
+    let result = self.container().try_with_transact_mut(|txn| {
       self.add_sections_item_with_txn(txn, items);
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to add section items: {}", err);
+    }
   }
 
   pub fn add_sections_item_with_txn(&self, txn: &mut TransactionMut, items: Vec<SectionItem>) {
@@ -314,12 +320,15 @@ impl<'a> SectionOperation<'a> {
   }
 
   pub fn clear(&self) {
-    self.container().with_transact_mut(|txn| {
+    let result = self.container().try_with_transact_mut(|txn| {
       if let Some(array) = self.container().get_array_ref_with_txn(txn, self.uid()) {
         let len = array.iter(txn).count();
         array.remove_range(txn, 0, len as u32);
       }
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to clear section: {}", err);
+    }
   }
 }
 
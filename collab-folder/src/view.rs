@@ -87,9 +87,17 @@ impl ViewsMap {
   /// Because the views and workspaces are stored in two separate maps, we can't directly move a view from one map to another.
   /// So, we have to dissociate the relationship between parent_id and view_id, and then associate the relationship between parent_id and view_id.
   pub fn dissociate_parent_child(&self, parent_id: &str, view_id: &str) {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       self.dissociate_parent_child_with_txn(txn, parent_id, view_id);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!(
+        "Failed to dissociate {} from {}: {}",
+        view_id,
+        parent_id,
+        err
+      );
+    }
   }
 
   /// Establish a relationship between the parent_id and view_id, and insert the view below the prev_id.
@@ -97,9 +105,17 @@ impl ViewsMap {
   /// Because the view and workspace are stored in two separate maps, we can't directly move the view from one map to another.
   /// So we have to dissociate the relationship between parent_id and view_id, and then associate the relationship between parent_id and view_id.
   pub fn associate_parent_child(&self, parent_id: &str, view_id: &str, prev_id: Option<String>) {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       self.associate_parent_child_with_txn(txn, parent_id, view_id, prev_id);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!(
+        "Failed to associate {} with {}: {}",
+        view_id,
+        parent_id,
+        err
+      );
+    }
   }
 
   pub fn dissociate_parent_child_with_txn(
@@ -128,13 +144,16 @@ impl ViewsMap {
   }
 
   pub fn remove_child(&self, parent_id: &str, child_index: u32) {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       if let Some(parent) = self.view_relations.get_children_with_txn(txn, parent_id) {
         if let Some(identifier) = parent.remove_child_with_txn(txn, child_index) {
           self.delete_views_with_txn(txn, vec![identifier.id])
         }
       }
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to remove the child of {}: {}", parent_id, err);
+    }
   }
 
   pub fn get_views_belong_to(&self, parent_view_id: &str) -> Vec<Arc<View>> {
@@ -257,9 +276,12 @@ impl ViewsMap {
   }
 
   pub(crate) fn insert_view(&self, view: View, index: Option<u32>) {
-    self
+    let result = self
       .container
-      .with_transact_mut(|txn| self.insert_view_with_txn(txn, view, index));
+      .try_with_transact_mut(|txn| self.insert_view_with_txn(txn, view, index));
+    if let Err(err) = result {
+      tracing::warn!("Failed to insert view: {}", err);
+    }
   }
 
   pub(crate) fn insert_view_with_txn(
@@ -322,9 +344,12 @@ impl ViewsMap {
   }
 
   pub fn delete_views<T: AsRef<str>>(&self, view_ids: Vec<T>) {
-    self
+    let result = self
       .container
-      .with_transact_mut(|txn| self.delete_views_with_txn(txn, view_ids));
+      .try_with_transact_mut(|txn| self.delete_views_with_txn(txn, view_ids));
+    if let Err(err) = result {
+      tracing::warn!("Failed to delete views: {}", err);
+    }
   }
 
   pub fn delete_views_with_txn<T: AsRef<str>>(&self, txn: &mut TransactionMut, view_ids: Vec<T>) {
@@ -333,6 +358,7 @@ impl ViewsMap {
     });
   }
 
+  /// Returns None if the view does not exist or the folder is read-only.
   pub fn update_view<F>(&self, view_id: &str, f: F) -> Option<Arc<View>>
   where
     F: FnOnce(ViewUpdate) -> Option<View>,
@@ -340,7 +366,9 @@ impl ViewsMap {
     self.remove_cache_view(view_id);
     self
       .container
-      .with_transact_mut(|txn| self.update_view_with_txn(&self.uid, txn, view_id, f))
+      .try_with_transact_mut(|txn| self.update_view_with_txn(&self.uid, txn, view_id, f))
+      .ok()
+      .flatten()
   }
 
   /// Updates a view within a given transaction using a provided function.
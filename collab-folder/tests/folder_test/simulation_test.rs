@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::sync::Arc;
+
+use collab::simulation::{
+  simulation_seeds, Simulation, SimulationConfig, SimulationModel, SimulationRng,
+};
+use collab_folder::{Folder, FolderData, UserId, Workspace};
+
+use crate::util::make_test_view;
+
+const WORKSPACE_ID: &str = "w1";
+
+struct FolderModel {
+  folder: Folder,
+}
+
+impl FolderModel {
+  /// Returns the ids of the workspace and of all its views.
+  fn view_ids(&self) -> Vec<String> {
+    let mut ids = vec![WORKSPACE_ID.to_string()];
+    let mut index = 0;
+    while index < ids.len() {
+      let children = self.folder.views.get_views_belong_to(&ids[index]);
+      ids.extend(children.iter().map(|view| view.id.clone()));
+      index += 1;
+    }
+    ids
+  }
+}
+
+impl SimulationModel for FolderModel {
+  fn apply_random_operation(&mut self, rng: &mut SimulationRng) -> String {
+    let ids = self.view_ids();
+    let target = rng.choose(&ids).unwrap().clone();
+    match rng.below(4) {
+      0 | 1 => {
+        let view_id = rng.string(10);
+        self
+          .folder
+          .insert_view(make_test_view(&view_id, &target, vec![]), None);
+        format!("insert {} in {}", view_id, target)
+      },
+      2 if target != WORKSPACE_ID => {
+        let name = rng.string(5);
+        self
+          .folder
+          .views
+          .update_view(&target, |update| update.set_name(&name).done());
+        format!("rename {} to {}", target, name)
+      },
+      _ if target != WORKSPACE_ID => {
+        if rng.chance(0.5) {
+          self.folder.add_favorites(vec![target.clone()]);
+          format!("favorite {}", target)
+        } else {
+          self.folder.delete_favorites(vec![target.clone()]);
+          format!("unfavorite {}", target)
+        }
+      },
+      _ => "noop".to_string(),
+    }
+  }
+
+  fn check_invariants(&self) -> Result<(), String> {
+    let workspace_id = self
+      .folder
+      .try_get_workspace_id()
+      .map_err(|err| err.to_string())?;
+    if workspace_id != WORKSPACE_ID {
+      return Err(format!("the workspace id is {}", workspace_id));
+    }
+
+    for parent_id in self.view_ids() {
+      let parent = self
+        .folder
+        .views
+        .get_view(&parent_id)
+        .ok_or_else(|| format!("{} is missing", parent_id))?;
+      let mut seen = HashSet::new();
+      for child in parent.children.iter() {
+        if !seen.insert(child.id.clone()) {
+          return Err(format!("{} appears twice in {}", child.id, parent_id));
+        }
+        let view = self
+          .folder
+          .views
+          .get_view(&child.id)
+          .ok_or_else(|| format!("the child {} of {} is missing", child.id, parent_id))?;
+        if view.parent_view_id != parent_id {
+          return Err(format!(
+            "{} is a child of {} but its parent is {}",
+            view.id, parent_id, view.parent_view_id
+          ));
+        }
+      }
+    }
+
+    for favorite in self.folder.get_all_favorites() {
+      if self.folder.views.get_view(&favorite.id).is_none() {
+        return Err(format!("the favorite {} is missing", favorite.id));
+      }
+    }
+    Ok(())
+  }
+}
+
+#[tokio::test]
+async fn folder_convergence_test() {
+  let config = SimulationConfig {
+    object_id: WORKSPACE_ID.to_string(),
+    ..Default::default()
+  };
+  for seed in simulation_seeds(5) {
+    let mut simulation = Simulation::new(
+      seed,
+      config.clone(),
+      |uid, collab| {
+        let workspace = Workspace::new(WORKSPACE_ID.to_string(), "".to_string(), uid);
+        let folder_data = FolderData::new(workspace);
+        FolderModel {
+          folder: Folder::create(UserId::from(uid), Arc::new(collab), None, folder_data).unwrap(),
+        }
+      },
+      |uid, collab| FolderModel {
+        folder: Folder::open(UserId::from(uid), Arc::new(collab), None).unwrap(),
+      },
+    )
+    .unwrap();
+    simulation.assert_run(150);
+  }
+}
@@ -75,7 +75,7 @@ pub async fn create_folder_with_data(
     view_change_tx: view_tx,
     section_change_tx: section_tx,
   };
-  let folder = Folder::create(uid, Arc::new(collab), Some(context), folder_data);
+  let folder = Folder::create(uid, Arc::new(collab), Some(context), folder_data).unwrap();
   FolderTest {
     db,
     folder,
@@ -1,6 +1,7 @@
 use collab::core::collab::MutexCollab;
 use collab::core::origin::CollabOrigin;
-use collab_folder::{check_folder_is_valid, Folder, FolderData, UserId, Workspace};
+use collab::core::read_only::ReadOnlyMode;
+use collab_folder::{check_folder_is_valid, Folder, FolderData, Section, UserId, Workspace};
 use std::sync::Arc;
 
 use crate::util::create_folder;
@@ -25,8 +26,33 @@ async fn test_workspace_is_ready() {
   let workspace = Workspace::new("w1".to_string(), "".to_string(), uid.as_i64());
   let folder_data = FolderData::new(workspace);
   let collab = Arc::new(MutexCollab::new(CollabOrigin::Empty, object_id, vec![]));
-  let _ = Folder::create(uid, collab.clone(), None, folder_data);
+  let _ = Folder::create(uid, collab.clone(), None, folder_data).unwrap();
 
   let workspace_id = check_folder_is_valid(&collab.lock()).unwrap();
   assert_eq!(workspace_id, "w1".to_string());
 }
+
+#[tokio::test]
+async fn read_only_folder_rejects_local_changes_test() {
+  let uid = UserId::from(1);
+  let workspace = Workspace::new("w1".to_string(), "".to_string(), uid.as_i64());
+  let collab = Arc::new(MutexCollab::new(CollabOrigin::Empty, "1", vec![]));
+  let folder = Folder::create(
+    uid.clone(),
+    collab.clone(),
+    None,
+    FolderData::new(workspace),
+  )
+  .unwrap();
+  folder.set_read_only_mode(ReadOnlyMode::ReadOnly);
+
+  folder.update_workspace("My first workspace");
+  assert_eq!(folder.get_current_workspace().unwrap().name, "");
+  assert!(folder
+    .create_section(Section::Custom("private".to_string()))
+    .is_err());
+
+  // Reopening a read-only folder doesn't write to it.
+  let folder = Folder::open(uid, collab, None).unwrap();
+  assert!(folder.is_read_only());
+}
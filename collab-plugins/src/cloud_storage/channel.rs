@@ -2,12 +2,12 @@ use std::fmt::Debug;
 use std::pin::Pin;
 use std::task::{Context, Poll};
 
-use futures_util::{Sink, Stream};
+use futures_util::Sink;
 use tokio::sync::mpsc::UnboundedSender;
 
 use crate::cloud_storage::error::SyncError;
 
-pub trait CollabConnect<Item>: Sink<Item> + Stream {}
+pub use collab::sync_protocol::CollabConnect;
 
 pub struct TokioUnboundedSink<T>(pub UnboundedSender<T>);
 
@@ -9,6 +9,7 @@ use async_trait::async_trait;
 use collab::core::collab::{CollabDocState, MutexCollab, TransactionMutExt};
 use collab::core::collab_state::SyncState;
 use collab::core::origin::CollabOrigin;
+use collab::core::updates::merge_updates_v1;
 use collab_entity::{CollabObject, CollabType};
 use parking_lot::Mutex;
 use rand::Rng;
@@ -20,7 +21,7 @@ use tokio_stream::wrappers::WatchStream;
 use tokio_stream::StreamExt;
 use tracing::trace;
 use yrs::updates::decoder::Decode;
-use yrs::{merge_updates_v1, ReadTxn, Transact, Update};
+use yrs::{ReadTxn, Update};
 
 use crate::cloud_storage::channel::TokioUnboundedSink;
 use crate::cloud_storage::msg::{CollabSinkMessage, MsgId};
@@ -88,15 +89,9 @@ impl RemoteCollab {
           }
           if let Some(local_collab) = local_collab.upgrade() {
             if let Some(collab) = local_collab.try_lock_for(Duration::from_secs(1)) {
-              if let Ok(mut txn) = collab.try_transaction_mut() {
-                match Update::decode_v1(&update) {
-                  Ok(update) => {
-                    if let Err(e) = txn.try_apply_update(update) {
-                      tracing::error!("apply remote update failed: {:?}", e);
-                    }
-                  },
-                  Err(e) => tracing::error!("🔴Failed to decode remote update: {:?}", e),
-                }
+              // The storage relays the updates of all the members without their author.
+              if let Err(e) = collab.apply_remote_update(&CollabOrigin::Server, &update) {
+                tracing::error!("apply remote update failed: {:?}", e);
               }
             }
           }
@@ -230,6 +225,15 @@ impl RemoteCollab {
         remote_update = collab_doc_state;
       }
 
+      // A remote state at another compaction epoch can't be merged with the local one. The
+      // updates pushed by the remote storage are ignored until the init sync succeeds.
+      let remote_epoch = self.collab.lock().get_compaction_epoch();
+      local_collab
+        .upgrade()
+        .ok_or(anyhow!("local collab is drop"))?
+        .lock()
+        .check_remote_epoch(remote_epoch)?;
+
       let _ = self.sync_state.send(SyncState::InitSyncBegin);
       // Encode the remote collab state as update for local collab.
       let local_sv = local_collab
@@ -238,33 +242,35 @@ impl RemoteCollab {
         .lock()
         .transact()
         .state_vector();
+      // The remote collab may hold updates that were received before this fetch, so the whole
+      // remote state is sent to the local collab, not only the fetched doc state.
       let encode_update = self
         .collab
         .lock()
         .transact()
         .encode_state_as_update_v1(&local_sv);
-      if let Ok(update) = Update::decode_v1(&encode_update) {
-        {
-          // Don't use the with_transact_mut here, because it carries the origin information. So
-          // the update will consider as a local update. But here is apply the remote update.
-          // TODO: nathan define a sync protocol for cloud storage.
-          tracing::trace!(
-            "{}: apply remote update with diff len:{}",
-            self.object,
-            encode_update.len()
-          );
-          local_collab
-            .upgrade()
-            .ok_or(anyhow!("local collab is drop"))?
-            .lock()
-            .get_doc()
-            .transact_mut()
-            .apply_update(update);
-
+      // Don't use the with_transact_mut here, because it carries the origin information. So
+      // the update will consider as a local update. apply_remote_update also checks the update
+      // against the access policy and the read-only mode of the local collab. The state comes
+      // from the server, which is trusted by the access policy.
+      // TODO: nathan define a sync protocol for cloud storage.
+      tracing::trace!(
+        "{}: apply remote update with diff len:{}",
+        self.object,
+        encode_update.len()
+      );
+      let result = local_collab
+        .upgrade()
+        .ok_or(anyhow!("local collab is drop"))?
+        .lock()
+        .apply_remote_update(&CollabOrigin::Server, &encode_update);
+      match result {
+        Ok(()) => {
           if let Err(e) = self.sync_state.send(SyncState::InitSyncEnd) {
             tracing::error!("🔴Failed to send sync state: {:?}", e);
           }
-        }
+        },
+        Err(e) => tracing::error!("🔴apply remote update failed: {:?}", e),
       }
     }
 
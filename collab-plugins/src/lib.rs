@@ -19,10 +19,39 @@ macro_rules! if_wasm {
 #[cfg(all(feature = "postgres_plugin", not(target_arch = "wasm32")))]
 pub mod cloud_storage;
 pub mod connect_state;
+pub mod metrics;
 
-if_native! {
-    pub type CollabKVDB = local_storage::rocksdb::kv_impl::KVTransactionDBRocksdbImpl;
-}
+/// The store of the collabs on native targets. It is RocksDB with the default `rocksdb_storage`
+/// feature, redb with only the `redb_storage` feature, and the in-memory store with only the
+/// `memory_storage` feature.
+#[cfg(all(feature = "rocksdb_storage", not(target_arch = "wasm32")))]
+pub type CollabKVDB = local_storage::rocksdb::kv_impl::KVTransactionDBRocksdbImpl;
+
+#[cfg(all(
+  feature = "redb_storage",
+  not(feature = "rocksdb_storage"),
+  not(target_arch = "wasm32")
+))]
+pub type CollabKVDB = local_storage::redb::kv_impl::KVTransactionDBRedbImpl;
+
+#[cfg(all(
+  feature = "memory_storage",
+  not(feature = "redb_storage"),
+  not(feature = "rocksdb_storage"),
+  not(target_arch = "wasm32")
+))]
+pub type CollabKVDB = local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+
+#[cfg(all(
+  not(feature = "memory_storage"),
+  not(feature = "redb_storage"),
+  not(feature = "rocksdb_storage"),
+  not(target_arch = "wasm32")
+))]
+compile_error!(
+  "collab-plugins needs a storage backend: enable `rocksdb_storage`, `redb_storage` or \
+   `memory_storage`"
+);
 
 if_wasm! {
     pub type CollabKVDB = local_storage::indexeddb::CollabIndexeddb;
@@ -1,24 +1,27 @@
 use crate::local_storage::indexeddb::kv_impl::CollabIndexeddb;
 use crate::local_storage::kv::keys::{make_doc_state_key, make_state_vector_key};
 
-use async_stream::stream;
 use async_trait::async_trait;
 use collab::core::awareness::{AwarenessUpdate, Event};
 
+use collab::core::collab_plugin::EncodedCollab;
 use collab::core::origin::CollabOrigin;
+use collab::core::plugin_pipeline::AsyncCollabPlugin;
 use collab::preclude::{Collab, CollabPlugin};
 use collab_entity::CollabType;
-use futures::stream::StreamExt;
 
 use crate::local_storage::kv::PersistenceError;
-use collab::core::collab::make_yrs_doc;
 use collab::core::transaction::DocTransactionExtension;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering::SeqCst;
 use std::sync::{Arc, Weak};
-use tracing::{error, instrument};
+use tracing::error;
 use yrs::{Doc, TransactionMut};
 
+/// Persists a collab in the [CollabIndexeddb]. The document is loaded by the [CollabPlugin] and
+/// the updates are written on the plugin pipeline by its [CollabPlugin::pipeline_plugin], which
+/// is added together with the plugin.
+#[derive(Clone)]
 pub struct IndexeddbDiskPlugin {
   uid: i64,
   #[allow(dead_code)]
@@ -27,7 +30,6 @@ pub struct IndexeddbDiskPlugin {
   collab_type: CollabType,
   collab_db: Weak<CollabIndexeddb>,
   did_load: Arc<AtomicBool>,
-  edit_sender: DocEditStreamSender,
 }
 
 impl IndexeddbDiskPlugin {
@@ -38,32 +40,14 @@ impl IndexeddbDiskPlugin {
     collab_db: Weak<CollabIndexeddb>,
   ) -> Self {
     let did_load = Arc::new(AtomicBool::new(false));
-    let (edit_sender, rx) = tokio::sync::mpsc::unbounded_channel();
-    let edit_stream = DocEditStream::new(uid, &object_id, collab_db.clone(), rx);
-    tokio::task::spawn_local(edit_stream.run());
     Self {
       uid,
       object_id,
       collab_type,
       did_load,
       collab_db,
-      edit_sender,
     }
   }
-
-  #[instrument(skip_all)]
-  fn flush_doc(&self, db: Arc<CollabIndexeddb>, object_id: &str) {
-    let uid = self.uid;
-    let object_id = object_id.to_string();
-    tokio::task::spawn_local(async move {
-      let doc = make_yrs_doc();
-      db.load_doc(uid, &object_id, doc.clone()).await.unwrap();
-      let encoded_collab = doc.get_encoded_collab_v1();
-      db.flush_doc(uid, &object_id, &encoded_collab)
-        .await
-        .unwrap();
-    });
-  }
 }
 
 #[async_trait]
@@ -102,15 +86,7 @@ impl CollabPlugin for IndexeddbDiskPlugin {
     }
   }
 
-  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, update: &[u8]) {
-    // Only push update if the doc is loaded
-    if !self.did_load.load(SeqCst) {
-      return;
-    }
-    if let Err(err) = self.edit_sender.send(DocUpdate::Update(update.to_vec())) {
-      error!("failed to send update: {}", err);
-    }
-  }
+  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, _update: &[u8]) {}
 
   fn receive_local_state(
     &self,
@@ -125,61 +101,35 @@ impl CollabPlugin for IndexeddbDiskPlugin {
     self.did_load.store(true, SeqCst);
   }
 
-  fn flush(&self, object_id: &str, _doc: &Doc) {
-    if let Some(db) = self.collab_db.upgrade() {
-      self.flush_doc(db, object_id);
-    }
+  fn flush(&self, _object_id: &str, _doc: &Doc) {}
+
+  fn pipeline_plugin(&self) -> Option<Arc<dyn AsyncCollabPlugin>> {
+    Some(Arc::new(AsyncIndexeddbPlugin(self.clone())))
   }
 }
 
-type DocEditStreamSender = tokio::sync::mpsc::UnboundedSender<DocUpdate>;
-type DocEditStreamReceiver = tokio::sync::mpsc::UnboundedReceiver<DocUpdate>;
-struct DocEditStream {
-  uid: i64,
-  object_id: String,
-  collab_db: Weak<CollabIndexeddb>,
-  receiver: Option<DocEditStreamReceiver>,
-}
+/// The pipeline half of an [IndexeddbDiskPlugin].
+struct AsyncIndexeddbPlugin(IndexeddbDiskPlugin);
 
-#[derive(Clone)]
-enum DocUpdate {
-  Update(Vec<u8>),
-}
-
-impl DocEditStream {
-  fn new(
-    uid: i64,
-    object_id: &str,
-    collab_db: Weak<CollabIndexeddb>,
-    receiver: DocEditStreamReceiver,
-  ) -> Self {
-    Self {
-      uid,
-      object_id: object_id.to_string(),
-      collab_db,
-      receiver: Some(receiver),
+#[async_trait(?Send)]
+impl AsyncCollabPlugin for AsyncIndexeddbPlugin {
+  async fn receive_update(&self, object_id: &str, update: &[u8]) {
+    // Only push update if the doc is loaded
+    if !self.0.did_load.load(SeqCst) {
+      return;
+    }
+    if let Some(db) = self.0.collab_db.upgrade() {
+      if let Err(err) = db.push_update(self.0.uid, object_id, update).await {
+        error!("failed to push update: {}", err);
+      }
     }
   }
 
-  async fn run(mut self) {
-    let mut receiver = self.receiver.take().expect("Only take once");
-    let stream = stream! {
-      while let Some(data) = receiver.recv().await {
-        yield data;
+  async fn flush(&self, object_id: &str, encoded_collab: &EncodedCollab) {
+    if let Some(db) = self.0.collab_db.upgrade() {
+      if let Err(err) = db.flush_doc(self.0.uid, object_id, encoded_collab).await {
+        error!("failed to flush doc:{} {}", object_id, err);
       }
-    };
-    stream
-      .for_each(|data| async {
-        match data {
-          DocUpdate::Update(update) => {
-            if let Some(db) = self.collab_db.upgrade() {
-              if let Err(err) = db.push_update(self.uid, &self.object_id, &update).await {
-                error!("failed to push update: {}", err);
-              }
-            }
-          },
-        }
-      })
-      .await;
+    }
   }
 }
@@ -5,9 +5,11 @@ use js_sys::{ArrayBuffer, Uint8Array};
 
 use crate::local_storage::kv::keys::{
   clock_from_key, make_doc_end_key, make_doc_id_key, make_doc_start_key, make_doc_state_key,
-  make_doc_update_key, make_state_vector_key, Clock, DocID, DOC_ID_LEN,
+  make_doc_update_key, make_state_vector_key, make_user_doc_id_range, oid_from_key, Clock, DocID,
+  DOC_ID_LEN, DOC_UPDATE_KEY_LEN,
 };
 use crate::local_storage::kv::oid::{LOCAL_DOC_ID_GEN, OID};
+use crate::local_storage::kv::user::{UserObject, PURGE_USER_BATCH_SIZE};
 use anyhow::anyhow;
 use collab::core::collab::TransactionMutExt;
 use indexed_db_futures::web_sys::IdbKeyRange;
@@ -276,6 +278,74 @@ impl CollabIndexeddb {
     Ok(())
   }
 
+  /// Lists the objects of the given user. The snapshots are not stored in IndexedDB.
+  pub async fn list_user_objects(&self, uid: i64) -> Result<Vec<UserObject>, PersistenceError> {
+    let read_guard = self.db.read().await;
+    let transaction =
+      read_guard.transaction_on_one_with_mode(COLLAB_KV_STORE, IdbTransactionMode::Readonly)?;
+    let store = store_from_transaction(&transaction)?;
+    let (start, end) = make_user_doc_id_range(uid);
+    let mut objects = vec![];
+    for (key, value) in fetch_entries(&store, start, end, usize::MAX).await? {
+      let doc_id = match <[u8; DOC_ID_LEN]>::try_from(value.as_slice()) {
+        Ok(doc_id) => DocID::from_be_bytes(doc_id),
+        Err(_) => continue,
+      };
+      let mut object = UserObject {
+        object_id: String::from_utf8_lossy(oid_from_key(&key)).to_string(),
+        doc_id,
+        ..Default::default()
+      };
+      let doc_entries = fetch_entries(
+        &store,
+        make_doc_start_key(doc_id),
+        make_doc_end_key(doc_id),
+        usize::MAX,
+      )
+      .await?;
+      for (key, value) in doc_entries {
+        object.doc_size += value.len();
+        if key.len() == DOC_UPDATE_KEY_LEN {
+          object.update_count += 1;
+        }
+      }
+      objects.push(object);
+    }
+    Ok(objects)
+  }
+
+  /// Removes all the documents of the given user, [PURGE_USER_BATCH_SIZE] documents per
+  /// transaction. Returns the number of removed documents.
+  pub async fn purge_user(&self, uid: i64) -> Result<usize, PersistenceError> {
+    let (start, end) = make_user_doc_id_range(uid);
+    let mut count = 0;
+    loop {
+      let write_guard = self.db.write().await;
+      let transaction =
+        write_guard.transaction_on_one_with_mode(COLLAB_KV_STORE, IdbTransactionMode::Readwrite)?;
+      let store = store_from_transaction(&transaction)?;
+      let index = fetch_entries(&store, &start, &end, PURGE_USER_BATCH_SIZE).await?;
+      for (key, value) in &index {
+        if let Ok(doc_id) = <[u8; DOC_ID_LEN]>::try_from(value.as_slice()) {
+          let doc_id = DocID::from_be_bytes(doc_id);
+          let doc_start = to_js_value(make_doc_start_key(doc_id));
+          let doc_end = to_js_value(make_doc_end_key(doc_id));
+          let key_range = IdbKeyRange::bound(&doc_start, &doc_end).map_err(|err| {
+            PersistenceError::Internal(anyhow!("Create key range fail. error: {:?}", err))
+          })?;
+          store.delete(&key_range)?;
+        }
+        store.delete(&to_js_value(key))?;
+      }
+      transaction_result_to_result(transaction.await)?;
+
+      count += index.len();
+      if index.len() < PURGE_USER_BATCH_SIZE {
+        return Ok(count);
+      }
+    }
+  }
+
   async fn delete_all_updates(
     &self,
     store: &IdbObjectStore<'_>,
@@ -448,3 +518,39 @@ async fn fetch_updates(
       .collect(),
   )
 }
+
+/// Returns at most `limit` entries of the range, as (key, value).
+async fn fetch_entries<K: AsRef<[u8]>>(
+  store: &IdbObjectStore<'_>,
+  start: K,
+  end: K,
+  limit: usize,
+) -> Result<Vec<(Vec<u8>, Vec<u8>)>, PersistenceError> {
+  let start = to_js_value(start);
+  let end = to_js_value(end);
+  let key_range = IdbKeyRange::bound(&start, &end).map_err(|err| {
+    PersistenceError::Internal(anyhow!("Create key range fail. error: {:?}", err))
+  })?;
+  let cursor = match store.open_cursor_with_range(&key_range)?.await? {
+    None => return Ok(Vec::new()),
+    Some(cursor) => cursor,
+  };
+
+  let mut entries = Vec::new();
+  loop {
+    if let Some(key) = cursor.key() {
+      let key = key.dyn_into::<ArrayBuffer>().map_err(|key| {
+        PersistenceError::InvalidData(format!("The key is not an ArrayBuffer: {:?}", key))
+      })?;
+      let key = Uint8Array::new(&key).to_vec();
+      let value = cursor.value().dyn_into::<Uint8Array>().map_err(|value| {
+        PersistenceError::InvalidData(format!("The value is not a Uint8Array: {:?}", value))
+      })?;
+      let value = value.to_vec();
+      entries.push((key, value));
+    }
+    if entries.len() >= limit || !cursor.continue_cursor()?.await? {
+      return Ok(entries);
+    }
+  }
+}
@@ -0,0 +1,513 @@
+//! Portable backups of the collab objects of a user.
+//!
+//! [backup_collab_store] exports the objects of one uid into a writer, usually a single file, and
+//! [restore_collab_backup] restores it into any store. A backup can be incremental: given the
+//! [CollabBackup] of the previous backup, only the objects that changed since are exported, and
+//! only their new changes.
+//!
+//! The entries are streamed, so neither the export nor the restore holds the whole backup in
+//! memory. The file starts with [BACKUP_MAGIC] and the big endian [BACKUP_FORMAT_VERSION],
+//! followed by length-prefixed frames: the [BackupHeader], one frame per [BackupEntry], an empty
+//! frame that ends the entries, and the [BackupFooter]. Each frame is a big endian `u64` length
+//! followed by the bincode encoded value.
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io::{self, BufReader, Read, Write};
+use std::path::Path;
+
+use bytes::Bytes;
+use collab::core::collab_plugin::EncodedCollab;
+use collab::core::updates::{diff_update_v1, state_vector_from_update_v1};
+use collab_entity::CollabType;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::local_storage::kv::doc::CollabKVAction;
+use crate::local_storage::kv::metadata::CollabMetadataAction;
+use crate::local_storage::kv::snapshot::{CollabSnapshot, SnapshotAction};
+use crate::local_storage::kv::{insert_snapshot, KVStore, PersistenceError};
+
+pub const BACKUP_MAGIC: &[u8; 8] = b"COLLABBK";
+pub const BACKUP_FORMAT_VERSION: u16 = 2;
+
+/// The length of the frame that ends the entries.
+const END_OF_ENTRIES: u64 = 0;
+
+/// The first frame of a backup.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupHeader {
+  pub uid: i64,
+  /// The unix timestamp, in seconds, of the backup.
+  pub created_at: i64,
+  /// The creation time of the backup this one is based on, if it is incremental.
+  pub base_created_at: Option<i64>,
+  pub include_snapshots: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BackupEntry {
+  pub object_id: String,
+  pub collab_type: Option<CollabType>,
+  /// The whole document, or only the changes since the base backup if `is_incremental` is
+  /// true. The state vector is always the one of the whole document.
+  pub encoded_collab: EncodedCollab,
+  pub is_incremental: bool,
+  pub snapshots: Vec<CollabSnapshot>,
+}
+
+/// The state of an object as of a backup, including the objects that were left out because they
+/// didn't change since the base backup.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BackupObjectState {
+  pub state_vector: Bytes,
+  /// The SHA-256 of the whole document state. Deletions don't change the state vector, so it is
+  /// used to tell whether the object changed since the base backup.
+  pub checksum: [u8; 32],
+}
+
+/// The last frame of a backup, written once all the entries are exported.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BackupFooter {
+  /// The state of all the objects of the user. The state of the unchanged objects is carried
+  /// forward from the base backup, so a chain of incremental backups stays incremental.
+  pub objects: BTreeMap<String, BackupObjectState>,
+  /// The objects that couldn't be read from the store, so they are missing from the backup.
+  pub skipped: Vec<String>,
+}
+
+/// A backup without its entries, which is all that is needed to take an incremental backup on
+/// top of it. The entries are read with a [BackupReader].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CollabBackup {
+  pub header: BackupHeader,
+  pub footer: BackupFooter,
+}
+
+impl CollabBackup {
+  pub fn get(&self, object_id: &str) -> Option<&BackupObjectState> {
+    self.footer.objects.get(object_id)
+  }
+
+  pub fn is_incremental(&self) -> bool {
+    self.header.base_created_at.is_some()
+  }
+
+  /// Reads the header and the footer of a backup. The entries are skipped without being
+  /// decoded.
+  pub fn read_from<R: Read>(reader: R) -> Result<Self, PersistenceError> {
+    BackupReader::new(reader)?.finish()
+  }
+
+  pub fn open(path: impl AsRef<Path>) -> Result<Self, PersistenceError> {
+    let file = File::open(path).map_err(io_error)?;
+    Self::read_from(BufReader::new(file))
+  }
+}
+
+/// Reads the entries of a backup one by one.
+pub struct BackupReader<R> {
+  reader: R,
+  header: BackupHeader,
+  is_end_of_entries: bool,
+}
+
+impl BackupReader<BufReader<File>> {
+  pub fn open(path: impl AsRef<Path>) -> Result<Self, PersistenceError> {
+    let file = File::open(path).map_err(io_error)?;
+    Self::new(BufReader::new(file))
+  }
+}
+
+impl<R: Read> BackupReader<R> {
+  /// Checks the format of the backup and reads its header.
+  pub fn new(mut reader: R) -> Result<Self, PersistenceError> {
+    let mut magic = [0; BACKUP_MAGIC.len()];
+    reader.read_exact(&mut magic).map_err(io_error)?;
+    if &magic != BACKUP_MAGIC {
+      return Err(PersistenceError::InvalidData(
+        "not a collab backup".to_string(),
+      ));
+    }
+    let mut version = [0; 2];
+    reader.read_exact(&mut version).map_err(io_error)?;
+    let version = u16::from_be_bytes(version);
+    if version != BACKUP_FORMAT_VERSION {
+      return Err(PersistenceError::InvalidData(format!(
+        "unsupported backup format version: {}",
+        version
+      )));
+    }
+    let header = read_frame(&mut reader)?
+      .ok_or_else(|| PersistenceError::InvalidData("the backup has no header".to_string()))?;
+    Ok(Self {
+      reader,
+      header,
+      is_end_of_entries: false,
+    })
+  }
+
+  pub fn header(&self) -> &BackupHeader {
+    &self.header
+  }
+
+  /// Returns [None] once all the entries are read.
+  pub fn next_entry(&mut self) -> Result<Option<BackupEntry>, PersistenceError> {
+    if self.is_end_of_entries {
+      return Ok(None);
+    }
+    let entry = read_frame(&mut self.reader)?;
+    self.is_end_of_entries = entry.is_none();
+    Ok(entry)
+  }
+
+  /// Skips the remaining entries and reads the footer.
+  pub fn finish(mut self) -> Result<CollabBackup, PersistenceError> {
+    while !self.is_end_of_entries {
+      let len = read_frame_len(&mut self.reader)?;
+      if len == END_OF_ENTRIES {
+        self.is_end_of_entries = true;
+      } else {
+        let skipped =
+          io::copy(&mut (&mut self.reader).take(len), &mut io::sink()).map_err(io_error)?;
+        if skipped != len {
+          return Err(truncated_backup());
+        }
+      }
+    }
+    let footer = read_frame(&mut self.reader)?.ok_or_else(truncated_backup)?;
+    Ok(CollabBackup {
+      header: self.header,
+      footer,
+    })
+  }
+}
+
+impl<R: Read> Iterator for BackupReader<R> {
+  type Item = Result<BackupEntry, PersistenceError>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    self.next_entry().transpose()
+  }
+}
+
+/// Returns the [CollabType] of an object id.
+type CollabTypeFn<'b> = Box<dyn Fn(&str) -> Option<CollabType> + 'b>;
+
+#[derive(Default)]
+pub struct BackupOptions<'b> {
+  base: Option<&'b CollabBackup>,
+  include_snapshots: bool,
+  collab_type: Option<CollabTypeFn<'b>>,
+}
+
+impl<'b> BackupOptions<'b> {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Makes the backup incremental: the objects that didn't change since `base` are left out,
+  /// and the others only contain the changes since `base`. `base` can itself be incremental,
+  /// it tracks all the objects of its chain. The objects that are not in it are exported whole.
+  pub fn with_base(mut self, base: &'b CollabBackup) -> Self {
+    self.base = Some(base);
+    self
+  }
+
+  /// Also exports the snapshots of the objects. An incremental backup only exports the
+  /// snapshots that were created after its base.
+  pub fn with_snapshots(mut self, include_snapshots: bool) -> Self {
+    self.include_snapshots = include_snapshots;
+    self
+  }
+
+  /// The type of the objects is read from their metadata. The given function provides it for
+  /// the objects whose type is not recorded, and overrides the recorded ones.
+  pub fn with_collab_type<F>(mut self, collab_type: F) -> Self
+  where
+    F: Fn(&str) -> Option<CollabType> + 'b,
+  {
+    self.collab_type = Some(Box::new(collab_type));
+    self
+  }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BackupProgress<'b> {
+  /// The object that was just processed.
+  pub object_id: &'b str,
+  pub done: usize,
+  /// The number of objects to process. It is unknown when restoring, since the number of
+  /// entries is only known at the end of the backup.
+  pub total: Option<usize>,
+}
+
+/// Exports all the objects of the given user into `writer`. `progress` is called after each
+/// object. Returns the [CollabBackup] that can be the base of the next incremental backup.
+///
+/// The objects that can't be read are skipped and listed in [BackupFooter::skipped].
+pub fn backup_collab_store<'a, S, W, F>(
+  store: &S,
+  uid: i64,
+  options: BackupOptions,
+  mut writer: W,
+  mut progress: F,
+) -> Result<CollabBackup, PersistenceError>
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+  W: Write,
+  F: FnMut(BackupProgress),
+{
+  if let Some(base) = options.base {
+    if base.header.uid != uid {
+      return Err(PersistenceError::InvalidData(format!(
+        "the base backup belongs to user {}",
+        base.header.uid
+      )));
+    }
+  }
+
+  // The object id index contains the objects of all the users.
+  let object_ids = store
+    .get_all_docs()?
+    .filter(|object_id| store.is_exist(uid, object_id))
+    .collect::<BTreeSet<_>>();
+  let header = BackupHeader {
+    uid,
+    created_at: chrono::Utc::now().timestamp(),
+    base_created_at: options.base.map(|base| base.header.created_at),
+    include_snapshots: options.include_snapshots,
+  };
+  writer.write_all(BACKUP_MAGIC).map_err(io_error)?;
+  writer
+    .write_all(&BACKUP_FORMAT_VERSION.to_be_bytes())
+    .map_err(io_error)?;
+  write_frame(&mut writer, &header)?;
+
+  let mut footer = BackupFooter::default();
+  for (index, object_id) in object_ids.iter().enumerate() {
+    match backup_object(store, uid, object_id, &options, header.base_created_at) {
+      Ok((state, entry)) => {
+        if let Some(entry) = entry {
+          write_frame(&mut writer, &entry)?;
+        }
+        footer.objects.insert(object_id.clone(), state);
+      },
+      Err(e) => {
+        tracing::error!("🔴backup {} failed: {}", object_id, e);
+        // The object is still as of the base backup in the chain of backups.
+        if let Some(state) = options.base.and_then(|base| base.get(object_id)) {
+          footer.objects.insert(object_id.clone(), state.clone());
+        }
+        footer.skipped.push(object_id.clone());
+      },
+    }
+    progress(BackupProgress {
+      object_id,
+      done: index + 1,
+      total: Some(object_ids.len()),
+    });
+  }
+  writer
+    .write_all(&END_OF_ENTRIES.to_be_bytes())
+    .map_err(io_error)?;
+  write_frame(&mut writer, &footer)?;
+  writer.flush().map_err(io_error)?;
+  Ok(CollabBackup { header, footer })
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RestoreReport {
+  /// The objects that didn't exist in the store.
+  pub created: usize,
+  /// The objects that already existed in the store and were merged with the backup.
+  pub merged: usize,
+  pub snapshots: usize,
+  /// The incremental entries that were skipped because their object doesn't exist in the store,
+  /// which happens when the backup is restored without its base.
+  pub missing_base: Vec<String>,
+}
+
+/// Restores the objects of the backup read from `reader` for the given user, which doesn't need
+/// to be the one of the backup. The objects that already exist are merged with the backup, so
+/// the changes made after the backup are kept. The snapshots that already exist are not restored
+/// again.
+///
+/// An incremental backup must be restored after its base. Its entries whose object doesn't exist
+/// in the store only hold a part of the document, so they are skipped and listed in
+/// [RestoreReport::missing_base].
+pub fn restore_collab_backup<'a, S, R, F>(
+  store: &S,
+  uid: i64,
+  reader: R,
+  mut progress: F,
+) -> Result<RestoreReport, PersistenceError>
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+  R: Read,
+  F: FnMut(BackupProgress),
+{
+  let mut report = RestoreReport::default();
+  let mut reader = BackupReader::new(reader)?;
+  let mut done = 0;
+  while let Some(entry) = reader.next_entry()? {
+    let object_id = entry.object_id.as_str();
+    done += 1;
+    let is_exist = store.is_exist(uid, object_id);
+    if entry.is_incremental && !is_exist {
+      tracing::warn!(
+        "🟡skip the incremental backup of {}: missing base",
+        object_id
+      );
+      report.missing_base.push(entry.object_id.clone());
+      progress(BackupProgress {
+        object_id,
+        done,
+        total: None,
+      });
+      continue;
+    }
+
+    let encoded_collab = entry.encoded_collab.into_v1()?;
+    if is_exist {
+      let stored = store.get_compacted_doc_state(uid, object_id)?;
+      let update = diff_update_v1(&encoded_collab.doc_state, &stored.state_vector)?;
+      store.push_update(uid, object_id, &update)?;
+      report.merged += 1;
+    } else {
+      let state_vector = state_vector_from_update_v1(&encoded_collab.doc_state)?;
+      store.flush_doc(
+        uid,
+        object_id,
+        state_vector,
+        encoded_collab.doc_state.to_vec(),
+      )?;
+      report.created += 1;
+    }
+    if let Some(collab_type) = &entry.collab_type {
+      store.set_collab_type(uid, object_id, collab_type)?;
+    }
+
+    if !entry.snapshots.is_empty() {
+      let snapshot_id = store.create_snapshot_id(uid, object_id)?;
+      let existing = store.get_snapshots(uid, object_id);
+      for snapshot in &entry.snapshots {
+        if !existing.contains(snapshot) {
+          insert_snapshot(store, snapshot_id, object_id, snapshot)?;
+          report.snapshots += 1;
+        }
+      }
+    }
+    progress(BackupProgress {
+      object_id,
+      done,
+      total: None,
+    });
+  }
+  Ok(report)
+}
+
+/// Returns the state of the object and its entry, which is [None] if the object didn't change
+/// since the base backup.
+fn backup_object<'a, S>(
+  store: &S,
+  uid: i64,
+  object_id: &str,
+  options: &BackupOptions,
+  base_created_at: Option<i64>,
+) -> Result<(BackupObjectState, Option<BackupEntry>), PersistenceError>
+where
+  S: KVStore<'a> + 'a,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let encoded_collab = store.get_compacted_doc_state(uid, object_id)?;
+  let state = BackupObjectState {
+    state_vector: encoded_collab.state_vector.clone(),
+    checksum: Sha256::digest(&encoded_collab.doc_state).into(),
+  };
+  let snapshots = if options.include_snapshots {
+    let mut snapshots = store.get_snapshots(uid, object_id);
+    if let Some(base_created_at) = base_created_at {
+      // A snapshot of the same second as the base might already be in it. Restoring skips the
+      // snapshots that already exist.
+      snapshots.retain(|snapshot| snapshot.created_at >= base_created_at);
+    }
+    snapshots
+  } else {
+    vec![]
+  };
+
+  let base_state = options.base.and_then(|base| base.get(object_id));
+  let (encoded_collab, is_incremental) = match base_state {
+    None => (encoded_collab, false),
+    Some(base_state) => {
+      if *base_state == state && snapshots.is_empty() {
+        return Ok((state, None));
+      }
+      let doc_state = encoded_collab.diff(&base_state.state_vector)?;
+      let encoded_collab =
+        EncodedCollab::new_v1(encoded_collab.state_vector, Bytes::from(doc_state));
+      (encoded_collab, true)
+    },
+  };
+
+  let entry = BackupEntry {
+    object_id: object_id.to_string(),
+    collab_type: options
+      .collab_type
+      .as_ref()
+      .and_then(|collab_type| collab_type(object_id))
+      .or_else(|| {
+        store
+          .get_collab_metadata(uid, object_id)
+          .ok()
+          .flatten()
+          .and_then(|metadata| metadata.collab_type)
+      }),
+    encoded_collab,
+    is_incremental,
+    snapshots,
+  };
+  Ok((state, Some(entry)))
+}
+
+fn write_frame<W: Write, T: Serialize>(writer: &mut W, value: &T) -> Result<(), PersistenceError> {
+  let len = bincode::serialized_size(value)?;
+  writer.write_all(&len.to_be_bytes()).map_err(io_error)?;
+  bincode::serialize_into(writer, value)?;
+  Ok(())
+}
+
+fn read_frame_len<R: Read>(reader: &mut R) -> Result<u64, PersistenceError> {
+  let mut len = [0; 8];
+  reader.read_exact(&mut len).map_err(io_error)?;
+  Ok(u64::from_be_bytes(len))
+}
+
+/// Returns [None] for the frame that ends the entries.
+fn read_frame<R: Read, T: DeserializeOwned>(reader: &mut R) -> Result<Option<T>, PersistenceError> {
+  let len = read_frame_len(reader)?;
+  if len == END_OF_ENTRIES {
+    return Ok(None);
+  }
+  let mut frame = reader.by_ref().take(len);
+  let value = bincode::deserialize_from(&mut frame)?;
+  if frame.limit() != 0 {
+    return Err(PersistenceError::InvalidData(
+      "the length of a backup frame doesn't match its content".to_string(),
+    ));
+  }
+  Ok(Some(value))
+}
+
+fn truncated_backup() -> PersistenceError {
+  PersistenceError::InvalidData("the backup is truncated".to_string())
+}
+
+fn io_error(err: std::io::Error) -> PersistenceError {
+  PersistenceError::Internal(err.into())
+}
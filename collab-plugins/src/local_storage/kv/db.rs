@@ -13,7 +13,7 @@ use smallvec::SmallVec;
 use yrs::{TransactionMut, Update};
 
 pub trait KVTransactionDB: Send + Sync + 'static {
-  type TransactionAction<'a>;
+  type TransactionAction<'a>: KVStore<'a, Error = PersistenceError>;
 
   fn read_txn<'a, 'b>(&'b self) -> Self::TransactionAction<'a>
   where
@@ -53,6 +53,13 @@ pub trait KVStore<'a> {
 
   /// Return the entry prior to the given key
   fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error>;
+
+  /// Returns an error if a range of this store ended early because one of its entries couldn't
+  /// be read. A range yields entries rather than results, so the callers that need every entry
+  /// of a range, like loading or compacting a document, check it after reading the range.
+  fn check_ranges(&self) -> Result<(), Self::Error> {
+    Ok(())
+  }
 }
 
 impl<T> KVStore<'static> for Arc<T>
@@ -87,6 +94,10 @@ where
   fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
     (**self).next_back_entry(key)
   }
+
+  fn check_ranges(&self) -> Result<(), Self::Error> {
+    (**self).check_ranges()
+  }
 }
 
 pub fn insert_snapshot_update<'a, K, S>(
@@ -100,9 +111,23 @@ where
   S: KVStore<'a>,
   PersistenceError: From<<S as KVStore<'a>>::Error>,
 {
-  let snapshot = CollabSnapshot::new(data).to_vec();
+  insert_snapshot(store, snapshot_id, object_id, &CollabSnapshot::new(data))
+}
+
+/// Like [insert_snapshot_update], but keeps the creation time of the given snapshot.
+pub fn insert_snapshot<'a, K, S>(
+  store: &S,
+  snapshot_id: SnapshotID,
+  object_id: &K,
+  snapshot: &CollabSnapshot,
+) -> Result<(), PersistenceError>
+where
+  K: AsRef<[u8]> + ?Sized + Debug,
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
   let update_key = create_update_key(snapshot_id, store, object_id, make_snapshot_update_key)?;
-  store.insert(update_key, snapshot)?;
+  store.insert(update_key, snapshot.to_vec())?;
   Ok(())
 }
 
@@ -1,8 +1,11 @@
 use std::fmt::Debug;
 
 use crate::local_storage::kv::keys::*;
+use crate::local_storage::kv::metadata;
 use crate::local_storage::kv::snapshot::SnapshotAction;
 use crate::local_storage::kv::*;
+use collab::core::collab_plugin::{EncodedCollab, EncoderVersion};
+use collab::core::updates::compact_updates;
 use yrs::updates::decoder::Decode;
 use yrs::updates::encoder::Encode;
 use yrs::{Doc, ReadTxn, StateVector, Transact, TransactionMut, Update};
@@ -37,6 +40,7 @@ where
 
     self.insert(doc_state_key, doc_state)?;
     self.insert(sv_key, sv)?;
+    metadata::did_create_doc(self, uid, object_id.as_ref())?;
 
     Ok(())
   }
@@ -55,21 +59,77 @@ where
     state_vector: Vec<u8>,
     doc_state: Vec<u8>,
   ) -> Result<(), PersistenceError> {
-    let doc_id = get_or_create_did(uid, self, object_id)?;
+    replace_doc_state(self, uid, object_id, &state_vector, &doc_state, true)
+  }
 
-    // Remove the updates
-    let start = make_doc_start_key(doc_id);
-    let end = make_doc_end_key(doc_id);
+  /// Merges the stored document state and all the updates that were appended after it into a
+  /// single [EncodedCollab] without loading the document into a [Doc].
+  ///
+  /// Like [CollabKVAction::load_doc_with_txn], the first update that can't be decoded and all the
+  /// following updates are skipped.
+  fn get_compacted_doc_state<K: AsRef<[u8]> + ?Sized + Debug>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<EncodedCollab, PersistenceError> {
+    let (_, encoded_collab, _) = read_compacted_doc_state(self, uid, object_id)?;
+    Ok(encoded_collab)
+  }
 
-    tracing::debug!("[{}:{:?}]: flush doc", doc_id, object_id,);
-    self.remove_range(start.as_ref(), end.as_ref())?;
+  /// Replaces the stored document state and its updates with the result of
+  /// [CollabKVAction::get_compacted_doc_state].
+  fn compact_doc<K: AsRef<[u8]> + ?Sized + Debug>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<(), PersistenceError> {
+    let encoded_collab = self.get_compacted_doc_state(uid, object_id)?;
+    replace_doc_state(
+      self,
+      uid,
+      object_id,
+      &encoded_collab.state_vector,
+      &encoded_collab.doc_state,
+      false,
+    )
+  }
 
-    let doc_state_key = make_doc_state_key(doc_id);
-    let sv_key = make_state_vector_key(doc_id);
-    // Insert new doc state and state vector
-    self.insert(doc_state_key, doc_state)?;
-    self.insert(sv_key, state_vector)?;
-    Ok(())
+  /// Like [CollabKVAction::compact_doc], but only removes the updates that were merged into the
+  /// new document state. The updates pushed by another transaction in the meantime are kept, so
+  /// it can run concurrently with [CollabKVAction::push_update]. Returns the number of removed
+  /// updates.
+  fn compact_doc_updates<K: AsRef<[u8]> + ?Sized + Debug>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<usize, PersistenceError> {
+    let (doc_id, encoded_collab, update_keys) = read_compacted_doc_state(self, uid, object_id)?;
+    let last_update_key = match update_keys.last() {
+      None => return Ok(0),
+      Some(key) => key,
+    };
+    tracing::debug!(
+      "[{}:{:?}]: compact {} updates",
+      doc_id,
+      object_id,
+      update_keys.len()
+    );
+
+    // The smallest key after the last merged update.
+    let mut update_end = last_update_key.clone();
+    update_end.push(TERMINATOR);
+    let update_start = make_doc_update_key(doc_id, 0);
+    self.remove_range(update_start.as_ref(), &update_end)?;
+    self.insert(
+      make_doc_state_key(doc_id),
+      encoded_collab.doc_state.as_ref(),
+    )?;
+    self.insert(
+      make_state_vector_key(doc_id),
+      encoded_collab.state_vector.as_ref(),
+    )?;
+    metadata::did_rewrite_doc(self, uid, object_id.as_ref(), false)?;
+    Ok(update_keys.len())
   }
 
   fn is_exist<K: AsRef<[u8]> + ?Sized + Debug>(&self, uid: i64, object_id: &K) -> bool {
@@ -120,6 +180,7 @@ where
           }
           update_count += 1;
         }
+        self.check_ranges()?;
       } else {
         tracing::error!(
           "🔴collab => [{}-{:?}]: the doc state should not be empty",
@@ -201,7 +262,11 @@ where
           object_id
         )))
       },
-      Some(doc_id) => insert_doc_update(self, doc_id, object_id, update.to_vec()),
+      Some(doc_id) => {
+        let update_key = insert_doc_update(self, doc_id, object_id, update.to_vec())?;
+        metadata::did_push_update(self, uid, object_id.as_ref())?;
+        Ok(update_key)
+      },
     }
   }
 
@@ -215,6 +280,7 @@ where
     if let Some(doc_id) = get_doc_id(uid, self, object_id) {
       let start = make_doc_update_key(doc_id, 0);
       self.remove_range(start.as_ref(), end.as_ref())?;
+      metadata::did_rewrite_doc(self, uid, object_id.as_ref(), false)?;
     }
     Ok(())
   }
@@ -228,6 +294,7 @@ where
       let start = make_doc_update_key(doc_id, 0);
       let end = make_doc_update_key(doc_id, Clock::MAX);
       self.remove_range(start.as_ref(), end.as_ref())?;
+      metadata::did_rewrite_doc(self, uid, object_id.as_ref(), true)?;
     }
     Ok(())
   }
@@ -239,18 +306,7 @@ where
     doc_state: &[u8],
     sv: &[u8],
   ) -> Result<(), PersistenceError> {
-    let doc_id = get_or_create_did(uid, self, object_id)?;
-    let start = make_doc_start_key(doc_id);
-    let end = make_doc_end_key(doc_id);
-    self.remove_range(start.as_ref(), end.as_ref())?;
-
-    let doc_state_key = make_doc_state_key(doc_id);
-    let sv_key = make_state_vector_key(doc_id);
-
-    // Insert new doc state and state vector
-    self.insert(doc_state_key, doc_state)?;
-    self.insert(sv_key, sv)?;
-    Ok(())
+    replace_doc_state(self, uid, object_id, sv, doc_state, true)
   }
 
   fn get_all_updates<K: AsRef<[u8]> + ?Sized + Debug>(
@@ -297,6 +353,7 @@ where
 
       // Delete the snapshot
       self.delete_all_snapshots(uid, object_id)?;
+      metadata::delete_metadata(self, uid, object_id.as_ref())?;
     }
     Ok(())
   }
@@ -387,6 +444,88 @@ where
   }
 }
 
+/// Replaces the document state, the state vector and the updates of the document. `is_changed`
+/// is false when the new state has the same content, see [metadata::did_rewrite_doc].
+fn replace_doc_state<'a, K, S>(
+  store: &S,
+  uid: i64,
+  object_id: &K,
+  state_vector: &[u8],
+  doc_state: &[u8],
+  is_changed: bool,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+  K: AsRef<[u8]> + ?Sized + Debug,
+{
+  let doc_id = get_or_create_did(uid, store, object_id)?;
+
+  // Remove the updates
+  let start = make_doc_start_key(doc_id);
+  let end = make_doc_end_key(doc_id);
+
+  tracing::debug!("[{}:{:?}]: flush doc", doc_id, object_id,);
+  store.remove_range(start.as_ref(), end.as_ref())?;
+
+  let doc_state_key = make_doc_state_key(doc_id);
+  let sv_key = make_state_vector_key(doc_id);
+  // Insert new doc state and state vector
+  store.insert(doc_state_key, doc_state)?;
+  store.insert(sv_key, state_vector)?;
+  metadata::did_rewrite_doc(store, uid, object_id.as_ref(), is_changed)
+}
+
+/// Merges the stored document state and its updates. Returns the document id, the merged state
+/// and the keys of all the updates that were read, including the ones that couldn't be decoded.
+fn read_compacted_doc_state<'a, K, S>(
+  store: &S,
+  uid: i64,
+  object_id: &K,
+) -> Result<(DocID, EncodedCollab, Vec<Vec<u8>>), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+  K: AsRef<[u8]> + ?Sized + Debug,
+{
+  let doc_id = get_doc_id(uid, store, object_id).ok_or_else(|| {
+    PersistenceError::RecordNotFound(format!(
+      "doc with given object id: {:?} is not found",
+      object_id
+    ))
+  })?;
+
+  let doc_state_key = make_doc_state_key(doc_id);
+  let doc_state = store.get(doc_state_key.as_ref())?.ok_or_else(|| {
+    PersistenceError::RecordNotFound(format!(
+      "doc state with given object id: {:?} is not found",
+      object_id
+    ))
+  })?;
+
+  let update_start = make_doc_update_key(doc_id, 0);
+  let update_end = make_doc_update_key(doc_id, Clock::MAX);
+  let mut updates = vec![];
+  let mut update_keys = vec![];
+  let mut is_valid = true;
+  for encoded_update in store.range(update_start.as_ref()..update_end.as_ref())? {
+    update_keys.push(encoded_update.key().to_vec());
+    if !is_valid {
+      continue;
+    }
+    if let Err(e) = Update::decode_v1(encoded_update.value()) {
+      tracing::error!("🔴{:?} decode update error: {}", object_id, e);
+      is_valid = false;
+      continue;
+    }
+    updates.push(encoded_update.value().to_vec());
+  }
+  store.check_ranges()?;
+
+  let encoded_collab = compact_updates(doc_state.as_ref(), &updates, EncoderVersion::V1)?;
+  Ok((doc_id, encoded_collab, update_keys))
+}
+
 fn get_doc_id<'a, K, S>(uid: i64, store: &S, object_id: &K) -> Option<DocID>
 where
   S: KVStore<'a>,
@@ -0,0 +1,577 @@
+//! Encryption at rest for the local collab store.
+//!
+//! [EncryptedKVStore] wraps a [KVStore] and encrypts every value with AES-256-GCM before it is
+//! written. The stored key is the associated data of the encryption, so a value can't be moved to
+//! another key without failing to decrypt. The keys themselves are stored as they are, except
+//! the object id indexes, whose object ids can be replaced by a keyed hash, see
+//! [EncryptionConfig::with_hashed_object_ids]. The object id is then encrypted with the value:
+//! ```text
+//! [object id length: u32 BE] [object id] [value]
+//! ```
+//!
+//! An encrypted value is laid out as:
+//! ```text
+//! [ENCRYPTED_VALUE_VERSION] [key id: u32 BE] [nonce: 12 bytes] [ciphertext + tag]
+//! ```
+//! The key id tells which [EncryptionKey] encrypted the value, so the values encrypted before a
+//! key rotation can still be read until they are re-encrypted.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+use std::io::Write;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+use aes_gcm::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use aes_gcm::{Aes256Gcm, Nonce};
+use hmac::{Hmac, Mac};
+use parking_lot::{Mutex, RwLock};
+use sha2::Sha256;
+
+use crate::local_storage::kv::keys::{
+  COLLAB_SPACE, COLLAB_SPACE_LAST_UPDATED, COLLAB_SPACE_METADATA, COLLAB_SPACE_OBJECT, DOC_ID_LEN,
+  DOC_SPACE, DOC_SPACE_OBJECT, SEARCH_SPACE, SEARCH_SPACE_DOCUMENT, SNAPSHOT_ID_LEN,
+  SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT, SNAPSHOT_UPDATE, SNAPSHOT_UPDATE_KEY_LEN, TERMINATOR,
+};
+use crate::local_storage::kv::{KVEntry, KVStore, PersistenceError};
+
+pub const ENCRYPTION_KEY_LEN: usize = 32;
+
+/// The first byte of an encrypted value.
+pub const ENCRYPTED_VALUE_VERSION: u8 = 1;
+const KEY_ID_LEN: usize = 4;
+const NONCE_LEN: usize = 12;
+const ENCRYPTED_VALUE_HEADER_LEN: usize = 1 + KEY_ID_LEN + NONCE_LEN;
+/// The length of the object id that starts the plaintext of the values at hashed keys.
+const OBJECT_ID_LEN_LEN: usize = 4;
+
+/// The plaintext of the value used to check the key when opening an encrypted database.
+#[cfg(feature = "rocksdb_storage")]
+const KEY_CHECK_VALUE: &[u8] = b"collab-encryption";
+#[cfg(feature = "rocksdb_storage")]
+const OBJECT_ID_HASH_CHECK: &[u8] = b"object-id-index";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A 256-bit key supplied by the application, usually one per user. The id is stored with each
+/// value, so it must be unique among the keys of a database.
+#[derive(Clone)]
+pub struct EncryptionKey {
+  id: u32,
+  bytes: [u8; ENCRYPTION_KEY_LEN],
+}
+
+impl EncryptionKey {
+  pub fn new(id: u32, bytes: [u8; ENCRYPTION_KEY_LEN]) -> Self {
+    Self { id, bytes }
+  }
+
+  pub fn from_slice(id: u32, bytes: &[u8]) -> Result<Self, PersistenceError> {
+    let bytes = bytes.try_into().map_err(|_| {
+      PersistenceError::Encryption(format!(
+        "the encryption key must be {} bytes long",
+        ENCRYPTION_KEY_LEN
+      ))
+    })?;
+    Ok(Self { id, bytes })
+  }
+
+  pub fn id(&self) -> u32 {
+    self.id
+  }
+
+  fn cipher(&self) -> Aes256Gcm {
+    Aes256Gcm::new(&self.bytes.into())
+  }
+}
+
+impl Debug for EncryptionKey {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("EncryptionKey")
+      .field("id", &self.id)
+      .finish_non_exhaustive()
+  }
+}
+
+/// The keys used to encrypt a database.
+#[derive(Clone)]
+pub struct EncryptionConfig {
+  key: EncryptionKey,
+  previous_keys: Vec<EncryptionKey>,
+  object_id_key: Option<[u8; ENCRYPTION_KEY_LEN]>,
+}
+
+impl EncryptionConfig {
+  /// The new values are encrypted with `key`.
+  pub fn new(key: EncryptionKey) -> Self {
+    Self {
+      key,
+      previous_keys: vec![],
+      object_id_key: None,
+    }
+  }
+
+  /// Adds a key that is only used to decrypt. Needed to open a database whose key rotation
+  /// didn't finish.
+  pub fn with_previous_key(mut self, key: EncryptionKey) -> Self {
+    self.previous_keys.push(key);
+    self
+  }
+
+  /// Replaces the object ids in the keys of the object id indexes by their HMAC-SHA256 with
+  /// `key`, so the ids of the documents can't be read from the disk either.
+  ///
+  /// The object ids are encrypted with the values of the indexes, so reading an index returns the
+  /// object ids, but sorted by their hash. This key is not rotated by the key rotation, and must
+  /// be the same every time the database is opened.
+  pub fn with_hashed_object_ids(mut self, key: [u8; ENCRYPTION_KEY_LEN]) -> Self {
+    self.object_id_key = Some(key);
+    self
+  }
+}
+
+struct EncryptionKeys {
+  current: u32,
+  ciphers: HashMap<u32, Aes256Gcm>,
+}
+
+/// Encrypts and decrypts the values of a database. Shared by all the transactions of the
+/// database.
+pub struct KVEncryption {
+  keys: RwLock<EncryptionKeys>,
+  object_id_mac: Option<HmacSha256>,
+}
+
+impl KVEncryption {
+  pub fn new(config: EncryptionConfig) -> Self {
+    let mut ciphers = HashMap::new();
+    for key in config.previous_keys {
+      ciphers.insert(key.id, key.cipher());
+    }
+    ciphers.insert(config.key.id, config.key.cipher());
+    let object_id_mac = config
+      .object_id_key
+      .map(|key| <HmacSha256 as Mac>::new_from_slice(&key).expect("HMAC takes keys of any size"));
+    Self {
+      keys: RwLock::new(EncryptionKeys {
+        current: config.key.id,
+        ciphers,
+      }),
+      object_id_mac,
+    }
+  }
+
+  /// The id of the key that encrypts the new values.
+  pub fn current_key_id(&self) -> u32 {
+    self.keys.read().current
+  }
+
+  /// Encrypts `value` with the current key. `key` is the key the value is stored at.
+  pub fn encrypt(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+    let keys = self.keys.read();
+    let cipher = &keys.ciphers[&keys.current];
+    let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+    let ciphertext = cipher
+      .encrypt(
+        &nonce,
+        Payload {
+          msg: value,
+          aad: key,
+        },
+      )
+      .map_err(|_| PersistenceError::Encryption(format!("failed to encrypt {:?}", key)))?;
+
+    let mut encrypted = Vec::with_capacity(ENCRYPTED_VALUE_HEADER_LEN + ciphertext.len());
+    encrypted.push(ENCRYPTED_VALUE_VERSION);
+    encrypted.write_all(&keys.current.to_be_bytes()).unwrap();
+    encrypted.write_all(&nonce).unwrap();
+    encrypted.write_all(&ciphertext).unwrap();
+    Ok(encrypted)
+  }
+
+  /// Decrypts a value written by [KVEncryption::encrypt]. Fails if the value was changed or moved
+  /// to another key, or if it was encrypted with an unknown key.
+  pub fn decrypt(&self, key: &[u8], value: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+    let key_id = encrypted_key_id(value).ok_or_else(|| {
+      PersistenceError::Encryption(format!("the value of {:?} is not encrypted", key))
+    })?;
+    let keys = self.keys.read();
+    let cipher = keys.ciphers.get(&key_id).ok_or_else(|| {
+      PersistenceError::Encryption(format!("missing the encryption key {}", key_id))
+    })?;
+    let nonce = Nonce::from_slice(&value[1 + KEY_ID_LEN..ENCRYPTED_VALUE_HEADER_LEN]);
+    let payload = Payload {
+      msg: &value[ENCRYPTED_VALUE_HEADER_LEN..],
+      aad: key,
+    };
+    cipher
+      .decrypt(nonce, payload)
+      .map_err(|_| PersistenceError::Encryption(format!("failed to decrypt {:?}", key)))
+  }
+
+  /// Re-encrypts the value with the current key. Returns `None` if it already is.
+  pub fn reencrypt(&self, key: &[u8], value: &[u8]) -> Result<Option<Vec<u8>>, PersistenceError> {
+    if encrypted_key_id(value) == Some(self.current_key_id()) {
+      return Ok(None);
+    }
+    let plaintext = self.decrypt(key, value)?;
+    self.encrypt(key, &plaintext).map(Some)
+  }
+
+  /// Returns the key the value of `key` is stored at. Only differs from `key` for the object id
+  /// indexes, when the object ids are hashed.
+  pub fn stored_key<'k>(&self, key: &'k [u8]) -> Cow<'k, [u8]> {
+    let (mac, object_id_start) = match (&self.object_id_mac, object_id_start(key)) {
+      (Some(mac), Some(object_id_start)) => (mac, object_id_start),
+      _ => return Cow::Borrowed(key),
+    };
+    let mut mac = mac.clone();
+    mac.update(&key[object_id_start..key.len() - 1]);
+    let hash = mac.finalize().into_bytes();
+    let mut stored_key = Vec::with_capacity(object_id_start + hash.len() + 1);
+    stored_key.extend_from_slice(&key[..object_id_start]);
+    stored_key.extend_from_slice(&hash);
+    stored_key.push(TERMINATOR);
+    Cow::Owned(stored_key)
+  }
+
+  /// Returns the stored key and the encrypted value of an entry. When the key is hashed, the
+  /// object id is encrypted with the value, so [KVEncryption::decrypt_entry] can restore the key.
+  pub fn encrypt_entry<'k>(
+    &self,
+    key: &'k [u8],
+    value: &[u8],
+  ) -> Result<(Cow<'k, [u8]>, Vec<u8>), PersistenceError> {
+    let stored_key = self.stored_key(key);
+    let value = match &stored_key {
+      Cow::Borrowed(_) => self.encrypt(key, value)?,
+      Cow::Owned(stored_key) => {
+        let object_id = &key[object_id_start(key).unwrap()..key.len() - 1];
+        let mut plaintext = Vec::with_capacity(OBJECT_ID_LEN_LEN + object_id.len() + value.len());
+        plaintext.extend_from_slice(&(object_id.len() as u32).to_be_bytes());
+        plaintext.extend_from_slice(object_id);
+        plaintext.extend_from_slice(value);
+        self.encrypt(stored_key, &plaintext)?
+      },
+    };
+    Ok((stored_key, value))
+  }
+
+  /// Returns the key and the value of an entry written by [KVEncryption::encrypt_entry].
+  pub fn decrypt_entry<'k>(
+    &self,
+    stored_key: &'k [u8],
+    value: &[u8],
+  ) -> Result<(Cow<'k, [u8]>, Vec<u8>), PersistenceError> {
+    let mut value = self.decrypt(stored_key, value)?;
+    let object_id_start = match (&self.object_id_mac, object_id_start(stored_key)) {
+      (Some(_), Some(object_id_start)) => object_id_start,
+      _ => return Ok((Cow::Borrowed(stored_key), value)),
+    };
+    let object_id_len = value
+      .get(..OBJECT_ID_LEN_LEN)
+      .map(|len| u32::from_be_bytes(len.try_into().unwrap()) as usize)
+      .filter(|len| OBJECT_ID_LEN_LEN + len <= value.len())
+      .ok_or_else(|| {
+        PersistenceError::Encryption(format!("missing the object id of {:?}", stored_key))
+      })?;
+    let object_id_end = OBJECT_ID_LEN_LEN + object_id_len;
+    let mut key = Vec::with_capacity(object_id_start + object_id_len + 1);
+    key.extend_from_slice(&stored_key[..object_id_start]);
+    key.extend_from_slice(&value[OBJECT_ID_LEN_LEN..object_id_end]);
+    key.push(TERMINATOR);
+    value.drain(..object_id_end);
+    Ok((Cow::Owned(key), value))
+  }
+}
+
+/// Returns where the object id starts in a key of an object id index, or `None` for the other
+/// keys.
+fn object_id_start(key: &[u8]) -> Option<usize> {
+  let object_id_start = match key {
+    // [DOC_SPACE, DOC_SPACE_OBJECT, uid, object_id, TERMINATOR]
+    [DOC_SPACE, DOC_SPACE_OBJECT, ..] if key.len() > 2 + DOC_ID_LEN + 1 => 2 + DOC_ID_LEN,
+    // [COLLAB_SPACE, COLLAB_SPACE_OBJECT, object_id, TERMINATOR]
+    [COLLAB_SPACE, COLLAB_SPACE_OBJECT, ..] if key.len() > 3 => 2,
+    // [COLLAB_SPACE, COLLAB_SPACE_METADATA, uid, object_id, TERMINATOR], and the same with
+    // COLLAB_SPACE_LAST_UPDATED.
+    [COLLAB_SPACE, COLLAB_SPACE_METADATA | COLLAB_SPACE_LAST_UPDATED, ..]
+      if key.len() > 2 + DOC_ID_LEN + 1 =>
+    {
+      2 + DOC_ID_LEN
+    },
+    // [SEARCH_SPACE, SEARCH_SPACE_DOCUMENT, uid, object_id, TERMINATOR]
+    [SEARCH_SPACE, SEARCH_SPACE_DOCUMENT, ..] if key.len() > 2 + DOC_ID_LEN + 1 => 2 + DOC_ID_LEN,
+    // [SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT, uid, object_id, TERMINATOR], which shares its
+    // prefix with the snapshot updates.
+    [SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT, ..]
+      if key.len() > 2 + DOC_ID_LEN + 1 && !is_snapshot_update_key(key) =>
+    {
+      2 + DOC_ID_LEN
+    },
+    _ => return None,
+  };
+  (key.last() == Some(&TERMINATOR)).then_some(object_id_start)
+}
+
+fn is_snapshot_update_key(key: &[u8]) -> bool {
+  key.len() == SNAPSHOT_UPDATE_KEY_LEN && key[2 + SNAPSHOT_ID_LEN] == SNAPSHOT_UPDATE
+}
+
+/// The key checks and rotations of the stores that support encryption, only RocksDB for now.
+#[cfg(feature = "rocksdb_storage")]
+impl KVEncryption {
+  /// Returns the value stored at `check_key` to verify the keys when the database is opened.
+  pub(crate) fn make_key_check(&self, check_key: &[u8]) -> Result<Vec<u8>, PersistenceError> {
+    self.encrypt(check_key, &self.key_check_value())
+  }
+
+  /// Fails if the value made by [KVEncryption::make_key_check] can't be decrypted, or if the
+  /// object ids were hashed with another key.
+  pub(crate) fn verify_key_check(
+    &self,
+    check_key: &[u8],
+    value: &[u8],
+  ) -> Result<(), PersistenceError> {
+    let plaintext = self.decrypt(check_key, value).map_err(|err| {
+      PersistenceError::Encryption(format!("the encryption key doesn't match: {}", err))
+    })?;
+    if plaintext != self.key_check_value() {
+      return Err(PersistenceError::Encryption(
+        "the object id hashing doesn't match the database".to_string(),
+      ));
+    }
+    Ok(())
+  }
+
+  fn key_check_value(&self) -> Vec<u8> {
+    let mut value = KEY_CHECK_VALUE.to_vec();
+    if let Some(mac) = &self.object_id_mac {
+      let mut mac = mac.clone();
+      mac.update(OBJECT_ID_HASH_CHECK);
+      value.extend_from_slice(&mac.finalize().into_bytes());
+    }
+    value
+  }
+
+  /// Encrypts the new values with `key`. The previous keys are kept to decrypt the existing values.
+  pub(crate) fn set_current_key(&self, key: EncryptionKey) {
+    let mut keys = self.keys.write();
+    keys.ciphers.insert(key.id, key.cipher());
+    keys.current = key.id;
+  }
+
+  /// Drops all the keys but the current one, once no value is encrypted with them.
+  pub(crate) fn retire_previous_keys(&self) {
+    let mut keys = self.keys.write();
+    let current = keys.current;
+    keys.ciphers.retain(|id, _| *id == current);
+  }
+}
+
+/// Returns the id of the key that encrypted the value, or `None` if it is not an encrypted value.
+pub fn encrypted_key_id(value: &[u8]) -> Option<u32> {
+  if value.len() < ENCRYPTED_VALUE_HEADER_LEN || value[0] != ENCRYPTED_VALUE_VERSION {
+    return None;
+  }
+  let key_id = value[1..1 + KEY_ID_LEN].try_into().ok()?;
+  Some(u32::from_be_bytes(key_id))
+}
+
+/// A [KVStore] that encrypts the values of the wrapped store. Without an encryption, it passes
+/// everything through, so a database can use the same transaction type whether it is encrypted
+/// or not.
+///
+/// The bounds of [KVStore::range] and [KVStore::remove_range] are used as they are. When the
+/// object ids are hashed, the entries of an object id index are sorted by the hashes, so only the
+/// bounds that end before the object ids keep their meaning.
+///
+/// A value of a range that fails to decrypt ends the range, and [KVStore::check_ranges] returns
+/// the error from then on. [KVStore::remove_range] fails too, so the keys that couldn't be read
+/// are never removed, for example by the compaction of a document.
+pub struct EncryptedKVStore<S> {
+  inner: S,
+  encryption: Option<Arc<KVEncryption>>,
+  range_error: Arc<Mutex<Option<String>>>,
+}
+
+impl<S> EncryptedKVStore<S> {
+  pub fn new(inner: S, encryption: Option<Arc<KVEncryption>>) -> Self {
+    Self {
+      inner,
+      encryption,
+      range_error: Default::default(),
+    }
+  }
+
+  /// The wrapped store, which reads and writes the encrypted values.
+  pub fn inner(&self) -> &S {
+    &self.inner
+  }
+
+  pub fn into_inner(self) -> S {
+    self.inner
+  }
+
+  pub fn is_encrypted(&self) -> bool {
+    self.encryption.is_some()
+  }
+}
+
+impl<'a, S> KVStore<'a> for EncryptedKVStore<S>
+where
+  S: KVStore<'a>,
+  S::Value: Into<Vec<u8>>,
+  PersistenceError: From<S::Error>,
+{
+  type Range = EncryptedRange<S::Range>;
+  type Entry = EncryptedEntry<S::Entry>;
+  type Value = Vec<u8>;
+  type Error = PersistenceError;
+
+  fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Self::Value>, Self::Error> {
+    match &self.encryption {
+      None => Ok(self.inner.get(key)?.map(Into::into)),
+      Some(encryption) => {
+        let key = encryption.stored_key(key.as_ref());
+        match self.inner.get(key.as_ref())? {
+          None => Ok(None),
+          Some(value) => Ok(Some(encryption.decrypt_entry(&key, value.as_ref())?.1)),
+        }
+      },
+    }
+  }
+
+  fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), Self::Error> {
+    match &self.encryption {
+      None => self.inner.insert(key, value)?,
+      Some(encryption) => {
+        let (key, value) = encryption.encrypt_entry(key.as_ref(), value.as_ref())?;
+        self.inner.insert(key, value)?;
+      },
+    }
+    Ok(())
+  }
+
+  fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+    match &self.encryption {
+      None => self.inner.remove(key)?,
+      Some(encryption) => self.inner.remove(&encryption.stored_key(key))?,
+    }
+    Ok(())
+  }
+
+  fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+    self.check_ranges()?;
+    self.inner.remove_range(from, to)?;
+    Ok(())
+  }
+
+  fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> Result<Self::Range, Self::Error> {
+    let entries = self.inner.range(range)?;
+    match &self.encryption {
+      None => Ok(EncryptedRange::Plain(entries)),
+      Some(encryption) => Ok(EncryptedRange::Decrypted {
+        entries,
+        encryption: encryption.clone(),
+        error: self.range_error.clone(),
+      }),
+    }
+  }
+
+  fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+    let entry = self.inner.next_back_entry(key)?;
+    match (&self.encryption, entry) {
+      (_, None) => Ok(None),
+      (None, Some(entry)) => Ok(Some(EncryptedEntry::Plain(entry))),
+      (Some(encryption), Some(entry)) => decrypt_entry(encryption, entry).map(Some),
+    }
+  }
+
+  fn check_ranges(&self) -> Result<(), Self::Error> {
+    self.inner.check_ranges()?;
+    match self.range_error.lock().as_ref() {
+      None => Ok(()),
+      Some(err) => Err(PersistenceError::Encryption(err.clone())),
+    }
+  }
+}
+
+/// The range of an [EncryptedKVStore]. The entries are decrypted one at a time. A value that
+/// fails to decrypt ends the range, and its error is kept for [KVStore::check_ranges].
+pub enum EncryptedRange<R: Iterator> {
+  Plain(R),
+  Decrypted {
+    entries: R,
+    encryption: Arc<KVEncryption>,
+    error: Arc<Mutex<Option<String>>>,
+  },
+  Failed,
+}
+
+impl<R> Iterator for EncryptedRange<R>
+where
+  R: Iterator,
+  R::Item: KVEntry,
+{
+  type Item = EncryptedEntry<R::Item>;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    match self {
+      EncryptedRange::Plain(entries) => entries.next().map(EncryptedEntry::Plain),
+      EncryptedRange::Decrypted {
+        entries,
+        encryption,
+        error,
+      } => {
+        let entry = entries.next()?;
+        match decrypt_entry(encryption, entry) {
+          Ok(entry) => Some(entry),
+          Err(err) => {
+            tracing::error!("🔴stop reading the range: {}", err);
+            error.lock().get_or_insert_with(|| match err {
+              PersistenceError::Encryption(err) => err,
+              err => err.to_string(),
+            });
+            *self = EncryptedRange::Failed;
+            None
+          },
+        }
+      },
+      EncryptedRange::Failed => None,
+    }
+  }
+}
+
+fn decrypt_entry<E: KVEntry>(
+  encryption: &KVEncryption,
+  entry: E,
+) -> Result<EncryptedEntry<E>, PersistenceError> {
+  let (key, value) = encryption.decrypt_entry(entry.key(), entry.value())?;
+  Ok(EncryptedEntry::Decrypted {
+    key: key.into_owned(),
+    value,
+  })
+}
+
+pub enum EncryptedEntry<E> {
+  Plain(E),
+  Decrypted { key: Vec<u8>, value: Vec<u8> },
+}
+
+impl<E: KVEntry> KVEntry for EncryptedEntry<E> {
+  fn key(&self) -> &[u8] {
+    match self {
+      EncryptedEntry::Plain(entry) => entry.key(),
+      EncryptedEntry::Decrypted { key, .. } => key,
+    }
+  }
+
+  fn value(&self) -> &[u8] {
+    match self {
+      EncryptedEntry::Plain(entry) => entry.value(),
+      EncryptedEntry::Decrypted { value, .. } => value,
+    }
+  }
+}
@@ -18,6 +18,13 @@ pub enum PersistenceError {
   #[error("{0}")]
   RocksdbIOError(String),
 
+  #[cfg(all(feature = "redb_storage", not(target_arch = "wasm32")))]
+  #[error("Redb: {0}")]
+  RedbError(String),
+
+  #[error("Encryption: {0}")]
+  Encryption(String),
+
   #[error(transparent)]
   Bincode(#[from] bincode::Error),
 
@@ -62,7 +69,7 @@ impl From<indexed_db_futures::web_sys::DomException> for PersistenceError {
   }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
+#[cfg(all(feature = "rocksdb_storage", not(target_arch = "wasm32")))]
 impl From<rocksdb::Error> for PersistenceError {
   fn from(value: rocksdb::Error) -> Self {
     match value.kind() {
@@ -74,3 +81,23 @@ impl From<rocksdb::Error> for PersistenceError {
     }
   }
 }
+
+#[cfg(all(feature = "redb_storage", not(target_arch = "wasm32")))]
+macro_rules! impl_from_redb_error {
+  ($($error:ty),*) => {$(
+    impl From<$error> for PersistenceError {
+      fn from(value: $error) -> Self {
+        PersistenceError::RedbError(value.to_string())
+      }
+    }
+  )*};
+}
+
+#[cfg(all(feature = "redb_storage", not(target_arch = "wasm32")))]
+impl_from_redb_error!(
+  redb::DatabaseError,
+  redb::TransactionError,
+  redb::TableError,
+  redb::StorageError,
+  redb::CommitError
+);
@@ -0,0 +1,391 @@
+//! Integrity check of a collab store.
+//!
+//! [check_collab_store] walks the object id index of the [DOC_SPACE] and verifies every document
+//! it points to:
+//! * the [DocID] mapping is valid and has a document state,
+//! * the document state decodes and applies,
+//! * the stored state vector is the one of the document state,
+//! * every update decodes and applies on top of the document state.
+//!
+//! It also finds the keys of the document and snapshot spaces that don't belong to any object,
+//! for example the updates left behind by an interrupted deletion. The broken entries can be
+//! quarantined, see [make_quarantine_key], or deleted. An object whose document state is missing
+//! or corrupt can't be loaded, so all of its entries are repaired together.
+
+use std::collections::HashSet;
+
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{Doc, ReadTxn, StateVector, Transact, Update};
+
+use crate::local_storage::kv::keys::*;
+use crate::local_storage::kv::{
+  get_id_for_key, KVEntry, KVStore, PersistenceError, TransactionMutExt,
+};
+
+/// What to do with the broken entries found by [check_collab_store]. The repair writes to the
+/// store, so it must run in a write transaction.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FsckRepair {
+  /// Only report the broken entries.
+  #[default]
+  None,
+  /// Move the broken entries to the [QUARANTINE_SPACE], so they can still be inspected.
+  Quarantine,
+  /// Delete the broken entries.
+  Delete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FsckIssue {
+  /// The object id is mapped to a value that is not a [DocID].
+  InvalidDocId,
+  MissingDocState,
+  CorruptDocState(String),
+  MissingStateVector,
+  CorruptStateVector(String),
+  /// The stored state vector is not the one of the document state.
+  StateVectorMismatch,
+  CorruptUpdate {
+    key: Vec<u8>,
+    error: String,
+  },
+}
+
+/// The result of the check of one object.
+#[derive(Debug, Clone)]
+pub struct ObjectReport {
+  pub uid: i64,
+  pub object_id: String,
+  pub doc_id: Option<DocID>,
+  pub update_count: usize,
+  pub issues: Vec<FsckIssue>,
+  /// The keys of the entries that are broken because of the issues.
+  pub broken_keys: Vec<Vec<u8>>,
+}
+
+impl ObjectReport {
+  pub fn is_healthy(&self) -> bool {
+    self.issues.is_empty()
+  }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct FsckReport {
+  pub objects: Vec<ObjectReport>,
+  /// The keys of the document and snapshot spaces that don't belong to any object.
+  pub orphaned_keys: Vec<Vec<u8>>,
+  /// The number of broken entries that were quarantined or deleted.
+  pub repaired: usize,
+}
+
+impl FsckReport {
+  pub fn is_healthy(&self) -> bool {
+    self.orphaned_keys.is_empty() && self.objects.iter().all(ObjectReport::is_healthy)
+  }
+
+  pub fn broken_objects(&self) -> impl Iterator<Item = &ObjectReport> {
+    self.objects.iter().filter(|object| !object.is_healthy())
+  }
+}
+
+/// Checks every object of the store and looks for orphaned keys. With a [FsckRepair] other than
+/// [FsckRepair::None], the broken entries and the orphaned keys are then quarantined or deleted.
+///
+/// The state vectors that are missing or that don't match the document state are derived data,
+/// so they are written again from the document state when repairing.
+pub fn check_collab_store<'a, S>(
+  store: &S,
+  repair: FsckRepair,
+) -> Result<FsckReport, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let mut report = FsckReport::default();
+  let mut state_vectors = vec![];
+
+  let index_start = [DOC_SPACE, DOC_SPACE_OBJECT];
+  let index_end = [DOC_SPACE, DOC_SPACE_OBJECT_KEY];
+  let index = store
+    .range(index_start.as_ref()..index_end.as_ref())?
+    .map(|entry| (entry.key().to_vec(), entry.value().to_vec()))
+    .collect::<Vec<_>>();
+  for (key, value) in index {
+    let (object, state_vector) = check_object(store, &key, &value)?;
+    if let Some(state_vector) = state_vector {
+      state_vectors.push(state_vector);
+    }
+    report.objects.push(object);
+  }
+  find_orphaned_doc_keys(store, &mut report)?;
+  find_orphaned_snapshot_keys(store, &mut report)?;
+
+  for object in report.broken_objects() {
+    tracing::warn!(
+      "fsck: [{}:{}] {:?}",
+      object.uid,
+      object.object_id,
+      object.issues
+    );
+  }
+  if !report.orphaned_keys.is_empty() {
+    tracing::warn!("fsck: {} orphaned keys", report.orphaned_keys.len());
+  }
+
+  if repair != FsckRepair::None {
+    let broken_keys = report
+      .objects
+      .iter()
+      .flat_map(|object| object.broken_keys.iter())
+      .chain(report.orphaned_keys.iter())
+      .cloned()
+      .collect::<Vec<_>>();
+    for key in &broken_keys {
+      if repair == FsckRepair::Quarantine {
+        if let Some(value) = store.get(key)? {
+          store.insert(make_quarantine_key(key), value)?;
+        }
+      }
+      store.remove(key)?;
+    }
+    for (key, state_vector) in state_vectors {
+      store.insert(key, state_vector)?;
+    }
+    report.repaired = broken_keys.len();
+  }
+  Ok(report)
+}
+
+/// Checks the object of an entry of the object id index. Returns the state vector to write when
+/// repairing, if the stored one is missing or wrong.
+#[allow(clippy::type_complexity)]
+fn check_object<'a, S>(
+  store: &S,
+  index_key: &[u8],
+  index_value: &[u8],
+) -> Result<(ObjectReport, Option<(Vec<u8>, Vec<u8>)>), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  // [DOC_SPACE, DOC_SPACE_OBJECT, uid, object_id, TERMINATOR]
+  let uid = index_key
+    .get(2..2 + DOC_ID_LEN)
+    .map(|uid| i64::from_be_bytes(uid.try_into().unwrap()))
+    .unwrap_or_default();
+  let object_id = index_key
+    .get(2 + DOC_ID_LEN..index_key.len().saturating_sub(1))
+    .map(|object_id| String::from_utf8_lossy(object_id).to_string())
+    .unwrap_or_default();
+  let mut object = ObjectReport {
+    uid,
+    object_id,
+    doc_id: None,
+    update_count: 0,
+    issues: vec![],
+    broken_keys: vec![],
+  };
+
+  let doc_id = match <[u8; DOC_ID_LEN]>::try_from(index_value) {
+    Ok(doc_id) if index_key.len() > 2 + DOC_ID_LEN + 1 => DocID::from_be_bytes(doc_id),
+    _ => {
+      object.issues.push(FsckIssue::InvalidDocId);
+      object.broken_keys.push(index_key.to_vec());
+      return Ok((object, None));
+    },
+  };
+  object.doc_id = Some(doc_id);
+
+  let doc_state_key = make_doc_state_key(doc_id);
+  let doc_state = match store.get(doc_state_key.as_ref())? {
+    Some(doc_state) => doc_state,
+    None => {
+      object.issues.push(FsckIssue::MissingDocState);
+      push_object_keys(store, &mut object, index_key, doc_id)?;
+      return Ok((object, None));
+    },
+  };
+
+  let doc = Doc::new();
+  let mut txn = doc.transact_mut();
+  if let Err(err) = Update::decode_v1(doc_state.as_ref())
+    .map_err(PersistenceError::Yrs)
+    .and_then(|update| txn.try_apply_update(update))
+  {
+    object
+      .issues
+      .push(FsckIssue::CorruptDocState(err.to_string()));
+    push_object_keys(store, &mut object, index_key, doc_id)?;
+    return Ok((object, None));
+  }
+  let expected_state_vector = txn.state_vector();
+
+  let mut state_vector_to_write = None;
+  let sv_key = make_state_vector_key(doc_id);
+  let stored = store.get(sv_key.as_ref())?;
+  let issue = match stored
+    .as_ref()
+    .map(|sv| StateVector::decode_v1(sv.as_ref()))
+  {
+    None => Some(FsckIssue::MissingStateVector),
+    Some(Err(err)) => Some(FsckIssue::CorruptStateVector(err.to_string())),
+    Some(Ok(state_vector)) if state_vector != expected_state_vector => {
+      Some(FsckIssue::StateVectorMismatch)
+    },
+    Some(Ok(_)) => None,
+  };
+  if let Some(issue) = issue {
+    object.issues.push(issue);
+    state_vector_to_write = Some((sv_key.to_vec(), expected_state_vector.encode_v1()));
+  }
+
+  let update_start = make_doc_update_key(doc_id, 0);
+  let update_end = make_doc_update_key(doc_id, Clock::MAX);
+  for entry in store.range(update_start.as_ref()..update_end.as_ref())? {
+    object.update_count += 1;
+    if let Err(err) = Update::decode_v1(entry.value())
+      .map_err(PersistenceError::Yrs)
+      .and_then(|update| txn.try_apply_update(update))
+    {
+      object.issues.push(FsckIssue::CorruptUpdate {
+        key: entry.key().to_vec(),
+        error: err.to_string(),
+      });
+      object.broken_keys.push(entry.key().to_vec());
+    }
+  }
+  Ok((object, state_vector_to_write))
+}
+
+/// Adds all the keys of the object to its broken keys: the object id mapping, the document state,
+/// the state vector, the updates, the metadata and the snapshots. Used when the object can't be
+/// loaded anymore, so that repairing it moves or deletes the object as a whole.
+fn push_object_keys<'a, S>(
+  store: &S,
+  object: &mut ObjectReport,
+  index_key: &[u8],
+  doc_id: DocID,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  object.broken_keys.push(index_key.to_vec());
+  let start = make_doc_start_key(doc_id);
+  let end = make_doc_end_key(doc_id);
+  for entry in store.range(start.as_ref()..end.as_ref())? {
+    object.broken_keys.push(entry.key().to_vec());
+  }
+
+  // [DOC_SPACE, DOC_SPACE_OBJECT, uid, object_id, TERMINATOR]
+  let uid = &index_key[2..2 + DOC_ID_LEN];
+  let object_id = &index_key[2 + DOC_ID_LEN..index_key.len() - 1];
+  let metadata_key = make_collab_metadata_key(uid, object_id);
+  let last_updated_key = make_collab_last_updated_key(uid, object_id);
+  for key in [metadata_key, last_updated_key] {
+    if store.get(key.as_ref())?.is_some() {
+      object.broken_keys.push(key.to_vec());
+    }
+  }
+  let snapshot_id_key = make_snapshot_id_key(uid, object_id);
+  if let Some(snapshot_id) = get_id_for_key(store, snapshot_id_key.clone()) {
+    object.broken_keys.push(snapshot_id_key.to_vec());
+    let start = make_snapshot_update_key(snapshot_id, 0);
+    let end = make_snapshot_update_key(snapshot_id, Clock::MAX);
+    for entry in store.range(start.as_ref()..end.as_ref())? {
+      object.broken_keys.push(entry.key().to_vec());
+    }
+  }
+  Ok(())
+}
+
+/// Finds the keys of the document space whose [DocID] is not mapped to an object.
+fn find_orphaned_doc_keys<'a, S>(store: &S, report: &mut FsckReport) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let doc_ids = report
+    .objects
+    .iter()
+    .filter_map(|object| object.doc_id)
+    .collect::<HashSet<_>>();
+  let start = [DOC_SPACE, DOC_SPACE_OBJECT_KEY];
+  let end = [DOC_SPACE, DOC_SPACE_OBJECT_KEY + 1];
+  for entry in store.range(start.as_ref()..end.as_ref())? {
+    // [DOC_SPACE, DOC_SPACE_OBJECT_KEY, doc_id, ...]
+    let doc_id = entry
+      .key()
+      .get(2..2 + DOC_ID_LEN)
+      .map(|doc_id| DocID::from_be_bytes(doc_id.try_into().unwrap()));
+    if !doc_id
+      .map(|doc_id| doc_ids.contains(&doc_id))
+      .unwrap_or(false)
+    {
+      report.orphaned_keys.push(entry.key().to_vec());
+    }
+  }
+  Ok(())
+}
+
+/// Finds the snapshot ids whose object is not mapped to a [DocID], and the snapshots whose
+/// [SnapshotID] is not mapped to an object.
+///
+/// The snapshot ids and the snapshots share the same key space. A snapshot id is stored as a
+/// [SnapshotID], which is shorter than any encoded snapshot, so the value tells them apart when
+/// the key could be both.
+fn find_orphaned_snapshot_keys<'a, S>(
+  store: &S,
+  report: &mut FsckReport,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let start = [SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT];
+  let end = [SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT + 1];
+  let mut snapshot_ids = HashSet::new();
+  let mut snapshots = vec![];
+  for entry in store.range(start.as_ref()..end.as_ref())? {
+    let key = entry.key();
+    // [SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT, snapshot_id, SNAPSHOT_UPDATE, clock, TERMINATOR]
+    if key.len() == SNAPSHOT_UPDATE_KEY_LEN
+      && key[2 + SNAPSHOT_ID_LEN] == SNAPSHOT_UPDATE
+      && key.last() == Some(&TERMINATOR)
+      && entry.value().len() != SNAPSHOT_ID_LEN
+    {
+      let snapshot_id = SnapshotID::from_be_bytes(key[2..2 + SNAPSHOT_ID_LEN].try_into().unwrap());
+      snapshots.push((key.to_vec(), snapshot_id));
+      continue;
+    }
+
+    // [SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT, uid, object_id, TERMINATOR]
+    let snapshot_id = <[u8; SNAPSHOT_ID_LEN]>::try_from(entry.value())
+      .ok()
+      .filter(|_| key.len() > 2 + DOC_ID_LEN + 1);
+    let snapshot_id = match snapshot_id {
+      None => None,
+      Some(snapshot_id) => {
+        let doc_id_key =
+          make_doc_id_key(&key[2..2 + DOC_ID_LEN], &key[2 + DOC_ID_LEN..key.len() - 1]);
+        store
+          .get(doc_id_key.as_ref())?
+          .map(|_| SnapshotID::from_be_bytes(snapshot_id))
+      },
+    };
+    match snapshot_id {
+      None => report.orphaned_keys.push(key.to_vec()),
+      Some(snapshot_id) => {
+        snapshot_ids.insert(snapshot_id);
+      },
+    }
+  }
+
+  for (key, snapshot_id) in snapshots {
+    if !snapshot_ids.contains(&snapshot_id) {
+      report.orphaned_keys.push(key);
+    }
+  }
+  Ok(())
+}
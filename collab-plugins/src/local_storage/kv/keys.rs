@@ -21,6 +21,17 @@ use smallvec::{smallvec, SmallVec};
 // SNAPSHOT_SPACE
 //     SNAPSHOT_SPACE_OBJECT        object_id       TERMINATOR
 //     SNAPSHOT_SPACE_OBJECT_KEY    snapshot_id     SNAPSHOT_UPDATE(snapshot)
+//
+// SEARCH_SPACE
+//     SEARCH_SPACE_INDEX           uid             TERMINATOR (search index version)
+//     SEARCH_SPACE_DOCUMENT        uid             document_id TERMINATOR (document and postings)
+//     SEARCH_SPACE_TERM            uid             term TERMINATOR document_id (older postings)
+//
+// ENCRYPTION_SPACE
+//     ENCRYPTION_SPACE_KEY_CHECK                   (encryption key check)
+//
+// QUARANTINE_SPACE
+//     key                                          (entry quarantined by the integrity check)
 
 /// Prefix byte used for all of the yrs object entries.
 pub const DOC_SPACE: u8 = 1;
@@ -56,6 +67,33 @@ pub const SNAPSHOT_UPDATE: u8 = 1;
 
 pub const COLLAB_SPACE: u8 = 3;
 pub const COLLAB_SPACE_OBJECT: u8 = 0;
+/// Tag byte within [COLLAB_SPACE] used for the metadata of the objects of each user.
+pub const COLLAB_SPACE_METADATA: u8 = 1;
+/// Tag byte within [COLLAB_SPACE] used for the last update time of the objects of each user. It
+/// is not part of the metadata, so that pushing an update doesn't rewrite the metadata.
+pub const COLLAB_SPACE_LAST_UPDATED: u8 = 2;
+
+/// Prefix byte used for the search index of each user.
+pub const SEARCH_SPACE: u8 = 4;
+pub const SEARCH_SPACE_INDEX: u8 = 0;
+/// Tag byte within [SEARCH_SPACE] used for the indexed documents of each user.
+pub const SEARCH_SPACE_DOCUMENT: u8 = 1;
+/// Tag byte within [SEARCH_SPACE] used for the postings of the terms of each user, by the
+/// indexes saved before the postings moved into the documents.
+pub const SEARCH_SPACE_TERM: u8 = 2;
+
+/// Prefix byte used for the metadata of the encryption at rest.
+pub const ENCRYPTION_SPACE: u8 = 5;
+/// Tag byte within [ENCRYPTION_SPACE] used to identify the value that checks the encryption key.
+pub const ENCRYPTION_SPACE_KEY_CHECK: u8 = 0;
+
+/// Prefix byte used for the entries moved aside by the integrity check, see
+/// [fsck](crate::local_storage::kv::fsck).
+pub const QUARANTINE_SPACE: u8 = 6;
+
+/// Covers every key of the key spaces above.
+pub const ALL_KEYS_START: [u8; 1] = [u8::MIN];
+pub const ALL_KEYS_END: [u8; 1] = [u8::MAX];
 
 pub type DocID = u64;
 pub const DOC_ID_LEN: usize = 8;
@@ -79,6 +117,41 @@ pub fn make_doc_id_key(uid: &[u8], object_id: &[u8]) -> Key<20> {
   Key(v)
 }
 
+// [1,0, uid]..[1,0, uid + 1]
+pub fn make_user_doc_id_range(uid: i64) -> (Key<10>, Key<10>) {
+  make_user_range(DOC_SPACE, DOC_SPACE_OBJECT, uid)
+}
+
+// [2,0, uid]..[2,0, uid + 1]
+// The snapshot updates share the prefix, so the range might contain some of them.
+pub fn make_user_snapshot_id_range(uid: i64) -> (Key<10>, Key<10>) {
+  make_user_range(SNAPSHOT_SPACE, SNAPSHOT_SPACE_OBJECT, uid)
+}
+
+// [3,1, uid]..[3,1, uid + 1]
+pub fn make_user_collab_metadata_range(uid: i64) -> (Key<10>, Key<10>) {
+  make_user_range(COLLAB_SPACE, COLLAB_SPACE_METADATA, uid)
+}
+
+// [3,2, uid]..[3,2, uid + 1]
+pub fn make_user_collab_last_updated_range(uid: i64) -> (Key<10>, Key<10>) {
+  make_user_range(COLLAB_SPACE, COLLAB_SPACE_LAST_UPDATED, uid)
+}
+
+fn make_user_range(space: u8, space_object: u8, uid: i64) -> (Key<10>, Key<10>) {
+  let mut start: SmallVec<[u8; 10]> = smallvec![space, space_object];
+  start.write_all(&uid.to_be_bytes()).unwrap();
+  let end = match u64::from_be_bytes(uid.to_be_bytes()).checked_add(1) {
+    Some(next_uid) => {
+      let mut end: SmallVec<[u8; 10]> = smallvec![space, space_object];
+      end.write_all(&next_uid.to_be_bytes()).unwrap();
+      end
+    },
+    None => smallvec![space, space_object + 1],
+  };
+  (Key(start), Key(end))
+}
+
 pub fn oid_from_key(key: &[u8]) -> &[u8] {
   // [DOC_SPACE, DOC_SPACE_OBJECT] = 2
   // uid = 8
@@ -187,6 +260,73 @@ pub fn make_collab_id_key(object_id: &[u8]) -> Key<20> {
   Key(v)
 }
 
+// [3,1, uid, object_id, 0]
+pub fn make_collab_metadata_key(uid: &[u8], object_id: &[u8]) -> Key<20> {
+  let mut v: SmallVec<[u8; 20]> = smallvec![COLLAB_SPACE, COLLAB_SPACE_METADATA];
+  v.write_all(uid).unwrap();
+  v.write_all(object_id).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [3,2, uid, object_id, 0]
+pub fn make_collab_last_updated_key(uid: &[u8], object_id: &[u8]) -> Key<20> {
+  let mut v: SmallVec<[u8; 20]> = smallvec![COLLAB_SPACE, COLLAB_SPACE_LAST_UPDATED];
+  v.write_all(uid).unwrap();
+  v.write_all(object_id).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [4,0, uid, 0]
+pub fn make_search_index_key(uid: i64) -> Key<11> {
+  let mut v: SmallVec<[u8; 11]> = smallvec![SEARCH_SPACE, SEARCH_SPACE_INDEX];
+  v.write_all(&uid.to_be_bytes()).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [4,1, uid, document_id, 0]
+pub fn make_search_document_key(uid: i64, document_id: &[u8]) -> Key<48> {
+  let mut v: SmallVec<[u8; 48]> = smallvec![SEARCH_SPACE, SEARCH_SPACE_DOCUMENT];
+  v.write_all(&uid.to_be_bytes()).unwrap();
+  v.write_all(document_id).unwrap();
+  v.push(TERMINATOR);
+  Key(v)
+}
+
+// [4,2, uid, term, 0, document_id]
+pub fn make_search_term_key(uid: i64, term: &[u8], document_id: &[u8]) -> Key<64> {
+  let mut v: SmallVec<[u8; 64]> = smallvec![SEARCH_SPACE, SEARCH_SPACE_TERM];
+  v.write_all(&uid.to_be_bytes()).unwrap();
+  v.write_all(term).unwrap();
+  v.push(TERMINATOR);
+  v.write_all(document_id).unwrap();
+  Key(v)
+}
+
+// [4,1, uid]..[4,1, uid + 1]
+pub fn make_search_document_range(uid: i64) -> (Key<10>, Key<10>) {
+  make_user_range(SEARCH_SPACE, SEARCH_SPACE_DOCUMENT, uid)
+}
+
+// [4,2, uid]..[4,2, uid + 1]
+pub fn make_search_term_range(uid: i64) -> (Key<10>, Key<10>) {
+  make_user_range(SEARCH_SPACE, SEARCH_SPACE_TERM, uid)
+}
+
+// [5,0]
+pub fn make_encryption_key_check_key() -> Key<2> {
+  Key(smallvec![ENCRYPTION_SPACE, ENCRYPTION_SPACE_KEY_CHECK])
+}
+
+// [6, key]
+pub fn make_quarantine_key(key: &[u8]) -> Key<32> {
+  let mut v: SmallVec<[u8; 32]> = smallvec![QUARANTINE_SPACE];
+  v.write_all(key).unwrap();
+  Key(v)
+}
+
 #[repr(transparent)]
 #[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct Key<const N: usize>(pub SmallVec<[u8; N]>);
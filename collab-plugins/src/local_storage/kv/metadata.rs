@@ -0,0 +1,380 @@
+//! Metadata of the collab objects, stored in the [COLLAB_SPACE].
+//!
+//! The metadata of an object is kept up to date by
+//! [CollabKVAction](crate::local_storage::kv::doc::CollabKVAction) whenever its document is
+//! written. Only the type of the object is unknown to the store: it is set with
+//! [CollabMetadataAction::set_collab_type], which the disk plugin does when it opens the object.
+//!
+//! Pushing an update only writes the last update time, under its own key, so it never conflicts
+//! with a compaction of the same document. The size and the number of updates are read from the
+//! document when the metadata is read.
+
+use std::fmt::Debug;
+
+use collab_entity::CollabType;
+use serde::{Deserialize, Serialize};
+
+use crate::local_storage::kv::keys::*;
+use crate::local_storage::kv::*;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CollabMetadata {
+  pub uid: i64,
+  pub object_id: String,
+  /// [None] until the type of the object is set.
+  pub collab_type: Option<CollabType>,
+  /// Unix timestamp in milliseconds.
+  pub created_at: i64,
+  /// Unix timestamp in milliseconds of the last change of the document. Compacting the updates
+  /// of the document doesn't change it.
+  pub last_updated_at: i64,
+  /// The bytes of the document state, the state vector and the updates.
+  pub byte_size: u64,
+  pub update_count: u32,
+}
+
+/// The part of the [CollabMetadata] stored under the metadata key.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct StoredMetadata {
+  uid: i64,
+  object_id: String,
+  collab_type: Option<CollabType>,
+  created_at: i64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CollabMetadataFilter {
+  collab_type: Option<CollabType>,
+  updated_since: Option<i64>,
+  limit: Option<usize>,
+}
+
+impl CollabMetadataFilter {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_collab_type(mut self, collab_type: CollabType) -> Self {
+    self.collab_type = Some(collab_type);
+    self
+  }
+
+  /// Only keeps the objects updated at or after the given unix timestamp in milliseconds.
+  pub fn with_updated_since(mut self, timestamp: i64) -> Self {
+    self.updated_since = Some(timestamp);
+    self
+  }
+
+  /// Only keeps the given number of most recently updated objects.
+  pub fn with_limit(mut self, limit: usize) -> Self {
+    self.limit = Some(limit);
+    self
+  }
+
+  pub fn matches(&self, metadata: &CollabMetadata) -> bool {
+    self.matches_fields(metadata.collab_type.as_ref(), metadata.last_updated_at)
+  }
+
+  fn matches_fields(&self, collab_type: Option<&CollabType>, last_updated_at: i64) -> bool {
+    self
+      .collab_type
+      .as_ref()
+      .map_or(true, |expected| collab_type == Some(expected))
+      && self
+        .updated_since
+        .map_or(true, |timestamp| last_updated_at >= timestamp)
+  }
+}
+
+impl<'a, T> CollabMetadataAction<'a> for T
+where
+  T: KVStore<'a> + 'a,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+pub trait CollabMetadataAction<'a>: KVStore<'a> + Sized + 'a
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  fn get_collab_metadata<K: AsRef<[u8]> + ?Sized>(
+    &self,
+    uid: i64,
+    object_id: &K,
+  ) -> Result<Option<CollabMetadata>, PersistenceError> {
+    match read_metadata(self, uid, object_id.as_ref())? {
+      None => Ok(None),
+      Some(metadata) => Ok(Some(complete_metadata(self, metadata)?)),
+    }
+  }
+
+  /// Records the type of the object. The objects that were written before the metadata existed
+  /// get their metadata here.
+  fn set_collab_type<K: AsRef<[u8]> + ?Sized + Debug>(
+    &self,
+    uid: i64,
+    object_id: &K,
+    collab_type: &CollabType,
+  ) -> Result<(), PersistenceError> {
+    let mut metadata = match read_metadata(self, uid, object_id.as_ref())? {
+      Some(metadata) => metadata,
+      None => {
+        get_id_for_key(
+          self,
+          make_doc_id_key(&uid.to_be_bytes(), object_id.as_ref()),
+        )
+        .ok_or_else(|| {
+          PersistenceError::RecordNotFound(format!(
+            "doc with given object id: {:?} is not found",
+            object_id
+          ))
+        })?;
+        init_metadata(self, uid, object_id.as_ref())?
+      },
+    };
+    if metadata.collab_type.as_ref() == Some(collab_type) {
+      return Ok(());
+    }
+    metadata.collab_type = Some(collab_type.clone());
+    write_metadata(self, &metadata)
+  }
+
+  /// Returns the metadata of the objects of the user that match the filter, the most recently
+  /// updated first.
+  fn list_collab_metadata(
+    &self,
+    uid: i64,
+    filter: &CollabMetadataFilter,
+  ) -> Result<Vec<CollabMetadata>, PersistenceError> {
+    let (start, end) = make_user_collab_metadata_range(uid);
+    let mut objects = vec![];
+    for entry in self.range(start.as_ref()..end.as_ref())? {
+      match bincode::deserialize::<StoredMetadata>(entry.value()) {
+        Ok(metadata) => {
+          let last_updated_at = read_last_updated_at(self, &metadata)?;
+          objects.push((metadata, last_updated_at));
+        },
+        Err(e) => tracing::error!("🔴invalid collab metadata at {:?}: {}", entry.key(), e),
+      }
+    }
+    // The sizes are only read for the objects that are returned.
+    objects.retain(|(metadata, last_updated_at)| {
+      filter.matches_fields(metadata.collab_type.as_ref(), *last_updated_at)
+    });
+    objects.sort_by(|a, b| b.1.cmp(&a.1));
+    if let Some(limit) = filter.limit {
+      objects.truncate(limit);
+    }
+    objects
+      .into_iter()
+      .map(|(metadata, _)| complete_metadata(self, metadata))
+      .collect()
+  }
+}
+
+/// Called after a new document was written.
+pub(crate) fn did_create_doc<'a, S>(
+  store: &S,
+  uid: i64,
+  object_id: &[u8],
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let now = chrono::Utc::now().timestamp_millis();
+  let metadata = StoredMetadata {
+    uid,
+    object_id: String::from_utf8_lossy(object_id).to_string(),
+    collab_type: None,
+    created_at: now,
+  };
+  write_metadata(store, &metadata)?;
+  write_last_updated_at(store, uid, object_id, now)
+}
+
+/// Called after an update was appended to the document. It doesn't read anything, and only
+/// writes the last update time.
+pub(crate) fn did_push_update<'a, S>(
+  store: &S,
+  uid: i64,
+  object_id: &[u8],
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let now = chrono::Utc::now().timestamp_millis();
+  write_last_updated_at(store, uid, object_id, now)
+}
+
+/// Called after the document state or the updates were replaced. `is_changed` is false when the
+/// content of the document is the same, like after a compaction, and then nothing is written.
+pub(crate) fn did_rewrite_doc<'a, S>(
+  store: &S,
+  uid: i64,
+  object_id: &[u8],
+  is_changed: bool,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  if !is_changed {
+    return Ok(());
+  }
+  if read_metadata(store, uid, object_id)?.is_none() {
+    let metadata = init_metadata(store, uid, object_id)?;
+    write_metadata(store, &metadata)?;
+  }
+  let now = chrono::Utc::now().timestamp_millis();
+  write_last_updated_at(store, uid, object_id, now)
+}
+
+pub(crate) fn delete_metadata<'a, S>(
+  store: &S,
+  uid: i64,
+  object_id: &[u8],
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let key = make_collab_metadata_key(&uid.to_be_bytes(), object_id);
+  store.remove(key.as_ref())?;
+  let key = make_collab_last_updated_key(&uid.to_be_bytes(), object_id);
+  store.remove(key.as_ref())?;
+  Ok(())
+}
+
+fn read_metadata<'a, S>(
+  store: &S,
+  uid: i64,
+  object_id: &[u8],
+) -> Result<Option<StoredMetadata>, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let key = make_collab_metadata_key(&uid.to_be_bytes(), object_id);
+  match store.get(key.as_ref())? {
+    None => Ok(None),
+    Some(value) => Ok(Some(bincode::deserialize(value.as_ref())?)),
+  }
+}
+
+fn write_metadata<'a, S>(store: &S, metadata: &StoredMetadata) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let key = make_collab_metadata_key(&metadata.uid.to_be_bytes(), metadata.object_id.as_bytes());
+  store.insert(key, bincode::serialize(metadata)?)?;
+  Ok(())
+}
+
+fn read_last_updated_at<'a, S>(
+  store: &S,
+  metadata: &StoredMetadata,
+) -> Result<i64, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let key =
+    make_collab_last_updated_key(&metadata.uid.to_be_bytes(), metadata.object_id.as_bytes());
+  match store.get(key.as_ref())? {
+    Some(value) => match <[u8; 8]>::try_from(value.as_ref()) {
+      Ok(timestamp) => Ok(i64::from_be_bytes(timestamp)),
+      Err(_) => Err(PersistenceError::InvalidData(format!(
+        "invalid last update time of {}",
+        metadata.object_id
+      ))),
+    },
+    None => Ok(metadata.created_at),
+  }
+}
+
+fn write_last_updated_at<'a, S>(
+  store: &S,
+  uid: i64,
+  object_id: &[u8],
+  timestamp: i64,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let key = make_collab_last_updated_key(&uid.to_be_bytes(), object_id);
+  store.insert(key, timestamp.to_be_bytes())?;
+  Ok(())
+}
+
+/// Adds the last update time and the size of the document to the stored metadata.
+fn complete_metadata<'a, S>(
+  store: &S,
+  metadata: StoredMetadata,
+) -> Result<CollabMetadata, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let last_updated_at = read_last_updated_at(store, &metadata)?;
+  let doc_id = get_id_for_key(
+    store,
+    make_doc_id_key(&metadata.uid.to_be_bytes(), metadata.object_id.as_bytes()),
+  );
+  let (byte_size, update_count) = match doc_id {
+    Some(doc_id) => doc_size(store, doc_id)?,
+    None => (0, 0),
+  };
+  Ok(CollabMetadata {
+    uid: metadata.uid,
+    object_id: metadata.object_id,
+    collab_type: metadata.collab_type,
+    created_at: metadata.created_at,
+    last_updated_at,
+    byte_size,
+    update_count,
+  })
+}
+
+/// The metadata of an object written before the metadata existed. Its creation time is unknown,
+/// so it is its last update time if there is one, or the current time.
+fn init_metadata<'a, S>(
+  store: &S,
+  uid: i64,
+  object_id: &[u8],
+) -> Result<StoredMetadata, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let mut metadata = StoredMetadata {
+    uid,
+    object_id: String::from_utf8_lossy(object_id).to_string(),
+    collab_type: None,
+    created_at: chrono::Utc::now().timestamp_millis(),
+  };
+  metadata.created_at = read_last_updated_at(store, &metadata)?;
+  Ok(metadata)
+}
+
+/// Returns the bytes and the number of updates stored for the document.
+fn doc_size<'a, S>(store: &S, doc_id: DocID) -> Result<(u64, u32), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let start = make_doc_start_key(doc_id);
+  let end = make_doc_end_key(doc_id);
+  let mut byte_size = 0;
+  let mut update_count = 0;
+  for entry in store.range(start.as_ref()..end.as_ref())? {
+    byte_size += entry.value().len() as u64;
+    if entry.key().len() == DOC_UPDATE_KEY_LEN {
+      update_count += 1;
+    }
+  }
+  Ok((byte_size, update_count))
+}
@@ -2,10 +2,16 @@ pub use db::*;
 pub use error::*;
 pub use range::*;
 
+pub mod backup;
 mod db;
 pub mod doc;
+pub mod encryption;
 pub mod error;
+pub mod fsck;
 pub mod keys;
+pub mod metadata;
 pub mod oid;
 mod range;
+pub mod search;
 pub mod snapshot;
+pub mod user;
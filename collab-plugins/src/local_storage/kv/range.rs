@@ -1,4 +1,4 @@
-use std::ops::{Range, RangeInclusive, RangeToInclusive};
+use std::ops::{Bound, Range, RangeBounds, RangeInclusive, RangeToInclusive};
 
 #[derive(Clone)]
 pub struct CLRange {
@@ -46,3 +46,29 @@ impl Iterator for CLRange {
     Some(val)
   }
 }
+
+pub(crate) type KVBounds<'a> = (Bound<&'a [u8]>, Bound<&'a [u8]>);
+
+/// Returns the bounds of the range the way the [KVStore](crate::local_storage::kv::KVStore)
+/// implementations read them: like RocksDB, the lower bound is always included and the upper
+/// bound never is. Returns None if the range is empty.
+pub(crate) fn kv_bounds<'a, K, R>(range: &'a R) -> Option<KVBounds<'a>>
+where
+  K: AsRef<[u8]> + 'a,
+  R: RangeBounds<K>,
+{
+  let from = match range.start_bound() {
+    Bound::Included(key) | Bound::Excluded(key) => Bound::Included(key.as_ref()),
+    Bound::Unbounded => Bound::Unbounded,
+  };
+  let to = match range.end_bound() {
+    Bound::Included(key) | Bound::Excluded(key) => Bound::Excluded(key.as_ref()),
+    Bound::Unbounded => Bound::Unbounded,
+  };
+  if let (Bound::Included(from), Bound::Excluded(to)) = (from, to) {
+    if from >= to {
+      return None;
+    }
+  }
+  Some((from, to))
+}
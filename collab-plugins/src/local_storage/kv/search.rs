@@ -0,0 +1,182 @@
+use collab::core::collab_search::{CollabSearch, IndexedDocument, SearchIndex, TermFrequency};
+use serde::{Deserialize, Serialize};
+
+use crate::local_storage::kv::keys::*;
+use crate::local_storage::kv::*;
+
+/// The value of the [make_search_index_key] entry of the indexes that are stored document by
+/// document, each with the postings of its terms. Older indexes were stored as a single
+/// [SearchIndex::encode] value under that key.
+const SEARCH_INDEX_VERSION: u8 = 2;
+/// The version of the indexes whose postings are stored under [make_search_term_key] keys. The
+/// terms are in plain text in these keys, so they are not encrypted at rest.
+const SEARCH_INDEX_VERSION_TERM_KEYS: u8 = 1;
+/// The length of the space, the tag and the uid that start the [make_search_term_key] keys.
+const SEARCH_TERM_PREFIX_LEN: usize = 10;
+
+/// The value of a [make_search_document_key] entry.
+#[derive(Serialize, Deserialize)]
+struct StoredSearchDocument {
+  document: IndexedDocument,
+  postings: Vec<(String, TermFrequency)>,
+}
+
+impl<'a, T> SearchIndexAction<'a> for T
+where
+  T: KVStore<'a>,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// Persists the [SearchIndex] of a user. Each document is a separate entry that holds the
+/// postings of its terms, so saving the changes of a [CollabSearch] only writes the documents
+/// that changed. The terms are only in the values, so they are encrypted at rest like the
+/// documents.
+pub trait SearchIndexAction<'a>: KVStore<'a> + Sized
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  /// Replaces the persisted index of the user with the index.
+  fn save_search_index(&self, uid: i64, index: &SearchIndex) -> Result<(), PersistenceError> {
+    self.delete_search_index(uid)?;
+    self.insert(make_search_index_key(uid), [SEARCH_INDEX_VERSION])?;
+    for document in index.documents() {
+      insert_search_document(self, uid, index, document)?;
+    }
+    Ok(())
+  }
+
+  /// Saves the documents that changed since the last save. Returns false if nothing changed.
+  fn save_search_index_if_dirty(
+    &self,
+    uid: i64,
+    search: &CollabSearch,
+  ) -> Result<bool, PersistenceError> {
+    let ids = search.take_changes();
+    if ids.is_empty() {
+      return Ok(false);
+    }
+    let result = save_search_documents(self, uid, &search.read_index(), &ids);
+    if result.is_err() {
+      search.mark_changed(ids);
+    }
+    result.map(|_| true)
+  }
+
+  fn load_search_index(&self, uid: i64) -> Result<Option<SearchIndex>, PersistenceError> {
+    match self.get(make_search_index_key(uid))? {
+      None => Ok(None),
+      Some(value) if value.as_ref() == [SEARCH_INDEX_VERSION] => {
+        let (start, end) = make_search_document_range(uid);
+        let mut documents = vec![];
+        let mut postings = vec![];
+        for entry in self.range(start.as_ref()..end.as_ref())? {
+          let stored = bincode::deserialize::<StoredSearchDocument>(entry.value())?;
+          for (term, frequency) in stored.postings {
+            postings.push((term, stored.document.id.clone(), frequency));
+          }
+          documents.push(stored.document);
+        }
+        self.check_ranges()?;
+        Ok(Some(SearchIndex::from_postings(documents, postings)))
+      },
+      Some(value) if value.as_ref() == [SEARCH_INDEX_VERSION_TERM_KEYS] => {
+        load_search_index_with_term_keys(self, uid).map(Some)
+      },
+      Some(value) => Ok(Some(SearchIndex::decode(value.as_ref())?)),
+    }
+  }
+
+  fn delete_search_index(&self, uid: i64) -> Result<(), PersistenceError> {
+    self.remove(make_search_index_key(uid).as_ref())?;
+    let (start, end) = make_search_document_range(uid);
+    self.remove_range(start.as_ref(), end.as_ref())?;
+    let (start, end) = make_search_term_range(uid);
+    self.remove_range(start.as_ref(), end.as_ref())?;
+    Ok(())
+  }
+}
+
+/// Loads an index saved with [SEARCH_INDEX_VERSION_TERM_KEYS]. It is saved again with
+/// [SEARCH_INDEX_VERSION] by the next save, which removes the term keys.
+fn load_search_index_with_term_keys<'a, S>(
+  store: &S,
+  uid: i64,
+) -> Result<SearchIndex, PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let (start, end) = make_search_document_range(uid);
+  let documents = store
+    .range(start.as_ref()..end.as_ref())?
+    .map(|entry| bincode::deserialize::<IndexedDocument>(entry.value()))
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let (start, end) = make_search_term_range(uid);
+  let mut postings = vec![];
+  for entry in store.range(start.as_ref()..end.as_ref())? {
+    if let Some((term, document_id)) = parse_search_term_key(entry.key()) {
+      let frequency = bincode::deserialize::<TermFrequency>(entry.value())?;
+      postings.push((term, document_id, frequency));
+    }
+  }
+  store.check_ranges()?;
+  Ok(SearchIndex::from_postings(documents, postings))
+}
+
+/// Replaces the persisted documents with the given ids by their version in the index. The
+/// documents that are not in the index anymore are removed.
+fn save_search_documents<'a, S>(
+  store: &S,
+  uid: i64,
+  index: &SearchIndex,
+  ids: &[String],
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  match store.get(make_search_index_key(uid))? {
+    Some(value) if value.as_ref() == [SEARCH_INDEX_VERSION] => {},
+    // Not persisted yet, or persisted in an older format: persist the whole index.
+    _ => return store.save_search_index(uid, index),
+  }
+  for id in ids {
+    match index.get(id) {
+      None => store.remove(make_search_document_key(uid, id.as_bytes()).as_ref())?,
+      Some(document) => insert_search_document(store, uid, index, document)?,
+    }
+  }
+  Ok(())
+}
+
+fn insert_search_document<'a, S>(
+  store: &S,
+  uid: i64,
+  index: &SearchIndex,
+  document: &IndexedDocument,
+) -> Result<(), PersistenceError>
+where
+  S: KVStore<'a>,
+  PersistenceError: From<<S as KVStore<'a>>::Error>,
+{
+  let stored = StoredSearchDocument {
+    document: document.clone(),
+    postings: index.postings(&document.id),
+  };
+  store.insert(
+    make_search_document_key(uid, document.id.as_bytes()),
+    bincode::serialize(&stored)?,
+  )?;
+  Ok(())
+}
+
+/// Returns the term and the document id of a [make_search_term_key] key.
+fn parse_search_term_key(key: &[u8]) -> Option<(String, String)> {
+  let rest = key.get(SEARCH_TERM_PREFIX_LEN..)?;
+  let terminator = rest.iter().position(|b| *b == TERMINATOR)?;
+  let term = std::str::from_utf8(&rest[..terminator]).ok()?;
+  let document_id = std::str::from_utf8(&rest[terminator + 1..]).ok()?;
+  Some((term.to_string(), document_id.to_string()))
+}
@@ -193,7 +193,7 @@ pub trait SnapshotPersistence: Send + Sync {
   ) -> Result<(), PersistenceError>;
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct CollabSnapshot {
   pub data: Vec<u8>,
   pub created_at: i64,
@@ -0,0 +1,141 @@
+use crate::local_storage::kv::keys::*;
+use crate::local_storage::kv::search::SearchIndexAction;
+use crate::local_storage::kv::snapshot::{get_snapshot_id, SnapshotAction};
+use crate::local_storage::kv::*;
+
+/// The number of objects removed by each transaction of a purge.
+pub const PURGE_USER_BATCH_SIZE: usize = 100;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UserObject {
+  pub object_id: String,
+  pub doc_id: DocID,
+  /// The bytes of the document state, the state vector and the updates.
+  pub doc_size: usize,
+  pub update_count: usize,
+  pub snapshot_count: usize,
+  pub snapshot_size: usize,
+}
+
+impl UserObject {
+  pub fn size(&self) -> usize {
+    self.doc_size + self.snapshot_size
+  }
+}
+
+impl<'a, T> UserDataAction<'a> for T
+where
+  T: KVStore<'a> + 'a,
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+}
+
+/// Lists and removes all the data of one user. The keys of a user are found through the object
+/// id index.
+pub trait UserDataAction<'a>: KVStore<'a> + Sized + 'a
+where
+  PersistenceError: From<<Self as KVStore<'a>>::Error>,
+{
+  fn list_user_objects(&self, uid: i64) -> Result<Vec<UserObject>, PersistenceError> {
+    let (start, end) = make_user_doc_id_range(uid);
+    let index = self
+      .range(start.as_ref()..end.as_ref())?
+      .map(|entry| (oid_from_key(entry.key()).to_vec(), entry.value().to_vec()))
+      .collect::<Vec<_>>();
+
+    let mut objects = vec![];
+    for (object_id, value) in index {
+      let doc_id = match <[u8; DOC_ID_LEN]>::try_from(value.as_slice()) {
+        Ok(doc_id) => DocID::from_be_bytes(doc_id),
+        Err(_) => continue,
+      };
+      let mut object = UserObject {
+        object_id: String::from_utf8_lossy(&object_id).to_string(),
+        doc_id,
+        ..Default::default()
+      };
+      let start = make_doc_start_key(doc_id);
+      let end = make_doc_end_key(doc_id);
+      for entry in self.range(start.as_ref()..end.as_ref())? {
+        object.doc_size += entry.value().len();
+        if entry.key().len() == DOC_UPDATE_KEY_LEN {
+          object.update_count += 1;
+        }
+      }
+      if let Some(snapshot_id) = get_snapshot_id(uid, self, &object_id) {
+        let start = make_snapshot_update_key(snapshot_id, 0);
+        let end = make_snapshot_update_key(snapshot_id, Clock::MAX);
+        for entry in self.range(start.as_ref()..end.as_ref())? {
+          object.snapshot_size += entry.value().len();
+          object.snapshot_count += 1;
+        }
+      }
+      objects.push(object);
+    }
+    Ok(objects)
+  }
+
+  /// Removes at most `limit` objects of the given user, with their updates, metadata and
+  /// snapshots. The keys are removed by range from the object id index, so the objects are
+  /// removed even if their object id or [DocID] can't be decoded. Once the documents are gone,
+  /// the snapshots left without a document and the search index of the user are removed too.
+  /// Returns the number of removed objects, which is less than `limit` once nothing of the user
+  /// is left.
+  fn purge_user_objects(&self, uid: i64, limit: usize) -> Result<usize, PersistenceError> {
+    let uid_bytes = uid.to_be_bytes();
+    let (start, end) = make_user_doc_id_range(uid);
+    let index = self
+      .range(start.as_ref()..end.as_ref())?
+      .take(limit)
+      .map(|entry| (entry.key().to_vec(), entry.value().to_vec()))
+      .collect::<Vec<_>>();
+
+    // The index entries are removed, so the next batch starts after them.
+    let mut removed = index.len();
+    for (key, value) in index {
+      if let Ok(doc_id) = <[u8; DOC_ID_LEN]>::try_from(value.as_slice()) {
+        let doc_id = DocID::from_be_bytes(doc_id);
+        let start = make_doc_start_key(doc_id);
+        let end = make_doc_end_key(doc_id);
+        self.remove_range(start.as_ref(), end.as_ref())?;
+      }
+      let object_id = oid_from_key(&key);
+      self.delete_all_snapshots(uid, object_id)?;
+      self.remove(make_snapshot_id_key(&uid_bytes, object_id).as_ref())?;
+      self.remove(make_collab_metadata_key(&uid_bytes, object_id).as_ref())?;
+      self.remove(make_collab_last_updated_key(&uid_bytes, object_id).as_ref())?;
+      self.remove(&key)?;
+    }
+    if removed == limit {
+      return Ok(removed);
+    }
+
+    // The snapshot updates that share the prefix of the snapshot ids are not 8 bytes long.
+    let (start, end) = make_user_snapshot_id_range(uid);
+    let snapshot_ids = self
+      .range(start.as_ref()..end.as_ref())?
+      .filter_map(|entry| {
+        let snapshot_id = <[u8; SNAPSHOT_ID_LEN]>::try_from(entry.value()).ok()?;
+        Some((entry.key().to_vec(), SnapshotID::from_be_bytes(snapshot_id)))
+      })
+      .take(limit - removed)
+      .collect::<Vec<_>>();
+    for (key, snapshot_id) in snapshot_ids {
+      let start = make_snapshot_update_key(snapshot_id, 0);
+      let end = make_snapshot_update_key(snapshot_id, Clock::MAX);
+      self.remove_range(start.as_ref(), end.as_ref())?;
+      self.remove(&key)?;
+      removed += 1;
+    }
+    if removed < limit {
+      for (start, end) in [
+        make_user_collab_metadata_range(uid),
+        make_user_collab_last_updated_range(uid),
+      ] {
+        self.remove_range(start.as_ref(), end.as_ref())?;
+      }
+      self.delete_search_index(uid)?;
+    }
+    Ok(removed)
+  }
+}
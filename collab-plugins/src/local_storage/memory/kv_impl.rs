@@ -0,0 +1,233 @@
+use std::collections::BTreeMap;
+use std::ops::Bound;
+use std::ops::RangeBounds;
+use std::sync::Arc;
+
+use parking_lot::{Mutex, MutexGuard, RwLock};
+
+use crate::local_storage::kv::{kv_bounds, KVEntry, KVStore, KVTransactionDB, PersistenceError};
+
+type MemoryMap = BTreeMap<Vec<u8>, Vec<u8>>;
+
+/// An in-memory [KVTransactionDB]. Nothing is written to disk, so it suits the tests and the
+/// sessions that must not leave any data behind, like guest or preview sessions.
+///
+/// Each transaction reads from a snapshot of the database taken when it starts, and sees its own
+/// writes on top of it. The writes of [KVTransactionDB::with_write_txn] are applied atomically
+/// when the closure returns `Ok`, and discarded when it returns an error. Write transactions run
+/// one at a time, so a write transaction must not start another one on the same database.
+#[derive(Clone, Default)]
+pub struct KVTransactionDBMemoryImpl {
+  data: Arc<RwLock<Arc<MemoryMap>>>,
+  write_lock: Arc<Mutex<()>>,
+}
+
+impl KVTransactionDBMemoryImpl {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Returns the number of keys in the database.
+  pub fn len(&self) -> usize {
+    self.data.read().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.data.read().is_empty()
+  }
+
+  fn snapshot(&self) -> Arc<MemoryMap> {
+    self.data.read().clone()
+  }
+
+  fn commit(&self, pending: BTreeMap<Vec<u8>, Option<Vec<u8>>>) {
+    if pending.is_empty() {
+      return;
+    }
+    let mut data = self.data.write();
+    // Only copies the map if a transaction still reads from the current snapshot.
+    let map = Arc::make_mut(&mut *data);
+    for (key, value) in pending {
+      match value {
+        None => map.remove(&key),
+        Some(value) => map.insert(key, value),
+      };
+    }
+  }
+}
+
+impl KVTransactionDB for KVTransactionDBMemoryImpl {
+  type TransactionAction<'a> = MemoryKVStoreImpl<'a>;
+
+  fn read_txn<'a, 'b>(&'b self) -> Self::TransactionAction<'a>
+  where
+    'b: 'a,
+  {
+    MemoryKVStoreImpl::new(self, self.snapshot(), None)
+  }
+
+  fn with_write_txn<'a, 'b, Output>(
+    &'b self,
+    f: impl FnOnce(&Self::TransactionAction<'a>) -> Result<Output, PersistenceError>,
+  ) -> Result<Output, PersistenceError>
+  where
+    'b: 'a,
+  {
+    let write_guard = self.write_lock.lock();
+    let store = MemoryKVStoreImpl::new(self, self.snapshot(), Some(write_guard));
+    let result = f(&store)?;
+    store.commit_transaction()?;
+    Ok(result)
+  }
+
+  fn flush(&self) -> Result<(), PersistenceError> {
+    Ok(())
+  }
+}
+
+/// Implementation of [KVStore] for [KVTransactionDBMemoryImpl]. The writes are kept aside until
+/// the transaction is committed.
+pub struct MemoryKVStoreImpl<'a> {
+  db: &'a KVTransactionDBMemoryImpl,
+  snapshot: Arc<MemoryMap>,
+  /// The writes of the transaction. `None` marks a removed key.
+  pending: Mutex<BTreeMap<Vec<u8>, Option<Vec<u8>>>>,
+  /// Held by the write transactions until they are committed or dropped.
+  _write_guard: Option<MutexGuard<'a, ()>>,
+}
+
+impl<'a> MemoryKVStoreImpl<'a> {
+  fn new(
+    db: &'a KVTransactionDBMemoryImpl,
+    snapshot: Arc<MemoryMap>,
+    write_guard: Option<MutexGuard<'a, ()>>,
+  ) -> Self {
+    Self {
+      db,
+      snapshot,
+      pending: Default::default(),
+      _write_guard: write_guard,
+    }
+  }
+
+  /// Applies the writes of the transaction to the database. Dropping the transaction without
+  /// committing it discards them.
+  pub fn commit_transaction(self) -> Result<(), PersistenceError> {
+    self.db.commit(self.pending.into_inner());
+    Ok(())
+  }
+
+  /// Returns the entries in the range, with the writes of the transaction applied.
+  fn merged_range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: &R) -> Vec<MemoryEntry> {
+    let (from, to) = match kv_bounds(range) {
+      None => return vec![],
+      Some(bounds) => bounds,
+    };
+    let mut entries = self
+      .snapshot
+      .range::<[u8], _>((from, to))
+      .map(|(key, value)| (key.clone(), value.clone()))
+      .collect::<MemoryMap>();
+    for (key, value) in self.pending.lock().range::<[u8], _>((from, to)) {
+      match value {
+        None => entries.remove(key),
+        Some(value) => entries.insert(key.clone(), value.clone()),
+      };
+    }
+    entries
+      .into_iter()
+      .map(|(key, value)| MemoryEntry::new(key, value))
+      .collect()
+  }
+}
+
+impl<'a> KVStore<'a> for MemoryKVStoreImpl<'a> {
+  type Range = std::vec::IntoIter<MemoryEntry>;
+  type Entry = MemoryEntry;
+  type Value = Vec<u8>;
+  type Error = PersistenceError;
+
+  fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Self::Value>, Self::Error> {
+    if let Some(value) = self.pending.lock().get(key.as_ref()) {
+      return Ok(value.clone());
+    }
+    Ok(self.snapshot.get(key.as_ref()).cloned())
+  }
+
+  fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), Self::Error> {
+    self
+      .pending
+      .lock()
+      .insert(key.as_ref().to_vec(), Some(value.as_ref().to_vec()));
+    Ok(())
+  }
+
+  fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+    self.pending.lock().insert(key.to_vec(), None);
+    Ok(())
+  }
+
+  fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+    let entries = self.merged_range(&(from..to));
+    let mut pending = self.pending.lock();
+    for entry in entries {
+      pending.insert(entry.key, None);
+    }
+    Ok(())
+  }
+
+  fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> Result<Self::Range, Self::Error> {
+    Ok(self.merged_range(&range).into_iter())
+  }
+
+  fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+    let pending = self.pending.lock();
+    let range = (Bound::Unbounded, Bound::Included(key));
+    let mut committed = self.snapshot.range::<[u8], _>(range).rev().peekable();
+    let mut written = pending.range::<[u8], _>(range).rev().peekable();
+    loop {
+      let committed_entry = committed.peek().map(|(key, value)| (*key, *value));
+      let written_key = written.peek().map(|(key, _)| *key);
+      match (committed_entry, written_key) {
+        (None, None) => return Ok(None),
+        (Some((key, value)), None) => {
+          return Ok(Some(MemoryEntry::new(key.clone(), value.clone())));
+        },
+        (Some((key, value)), Some(written_key)) if key > written_key => {
+          return Ok(Some(MemoryEntry::new(key.clone(), value.clone())));
+        },
+        (committed_entry, Some(written_key)) => {
+          // The write of the transaction shadows the committed value of the same key.
+          if committed_entry.map(|(key, _)| key == written_key) == Some(true) {
+            committed.next();
+          }
+          let (key, value) = written.next().unwrap();
+          if let Some(value) = value {
+            return Ok(Some(MemoryEntry::new(key.clone(), value.clone())));
+          }
+        },
+      }
+    }
+  }
+}
+
+pub struct MemoryEntry {
+  key: Vec<u8>,
+  value: Vec<u8>,
+}
+
+impl MemoryEntry {
+  pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+    Self { key, value }
+  }
+}
+
+impl KVEntry for MemoryEntry {
+  fn key(&self) -> &[u8] {
+    self.key.as_ref()
+  }
+
+  fn value(&self) -> &[u8] {
+    self.value.as_ref()
+  }
+}
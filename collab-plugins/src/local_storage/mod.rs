@@ -1,8 +1,12 @@
 pub mod kv;
+pub mod memory;
 
 #[cfg(not(target_arch = "wasm32"))]
 pub mod rocksdb;
 
+#[cfg(all(feature = "redb_storage", not(target_arch = "wasm32")))]
+pub mod redb;
+
 #[cfg(target_arch = "wasm32")]
 pub mod indexeddb;
 
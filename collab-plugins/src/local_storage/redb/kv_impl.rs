@@ -0,0 +1,372 @@
+use std::ops::{Bound, RangeBounds};
+use std::path::Path;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use redb::{
+  Database, ReadOnlyTable, ReadTransaction, ReadableTable, StorageError, Table, TableDefinition,
+  WriteTransaction,
+};
+
+use crate::local_storage::kv::doc::CollabKVAction;
+use crate::local_storage::kv::{kv_bounds, KVEntry, KVStore, KVTransactionDB, PersistenceError};
+
+/// All the keys live in a single table, ordered like in RocksDB.
+const COLLAB_TABLE: TableDefinition<&[u8], &[u8]> = TableDefinition::new("collab_kv");
+
+/// The number of entries that a [RedbRange] reads at a time.
+const RANGE_PAGE_SIZE: usize = 256;
+
+/// A [KVTransactionDB] backed by [redb](https://github.com/cberner/redb), a pure-Rust embedded
+/// database. It doesn't need a C++ toolchain, so it's an alternative to the RocksDB store when
+/// building or cross-compiling RocksDB is a problem. Build with only the `redb_storage` feature to
+/// leave RocksDB out, the [CollabKVDB](crate::CollabKVDB) is then this store.
+///
+/// The read transactions read from a snapshot and can't write. Only one write transaction runs
+/// at a time, so a write transaction must not start another one on the same database.
+#[derive(Clone)]
+pub struct KVTransactionDBRedbImpl {
+  db: Arc<Database>,
+}
+
+impl KVTransactionDBRedbImpl {
+  /// Open the database file at the given path, or create it if it doesn't exist.
+  pub fn open(path: impl AsRef<Path>) -> Result<Self, PersistenceError> {
+    let db = Database::create(path)?;
+    // Create the table, so the read transactions can open it.
+    let txn = db.begin_write()?;
+    txn.open_table(COLLAB_TABLE)?;
+    txn.commit()?;
+    Ok(Self { db: Arc::new(db) })
+  }
+
+  pub async fn is_exist(&self, uid: i64, object_id: &str) -> Result<bool, PersistenceError> {
+    let read_txn = self.read_txn();
+    Ok(read_txn.is_exist(uid, object_id))
+  }
+
+  pub async fn delete_doc(&self, uid: i64, doc_id: &str) -> Result<(), PersistenceError> {
+    self.with_write_txn(|txn| txn.delete_doc(uid, doc_id))?;
+    Ok(())
+  }
+}
+
+impl KVTransactionDB for KVTransactionDBRedbImpl {
+  type TransactionAction<'a> = RedbKVStoreImpl<'a>;
+
+  fn read_txn<'a, 'b>(&'b self) -> Self::TransactionAction<'a>
+  where
+    'b: 'a,
+  {
+    match self.db.begin_read() {
+      Ok(txn) => RedbKVStoreImpl(Rc::new(RedbTransaction::Read(txn))),
+      Err(err) => {
+        tracing::error!("🔴failed to begin redb read transaction: {:?}", err);
+        RedbKVStoreImpl(Rc::new(RedbTransaction::Unavailable(err.to_string())))
+      },
+    }
+  }
+
+  fn with_write_txn<'a, 'b, Output>(
+    &'b self,
+    f: impl FnOnce(&Self::TransactionAction<'a>) -> Result<Output, PersistenceError>,
+  ) -> Result<Output, PersistenceError>
+  where
+    'b: 'a,
+  {
+    let store = RedbKVStoreImpl(Rc::new(RedbTransaction::Write(self.db.begin_write()?)));
+    match f(&store) {
+      Ok(result) => {
+        store.commit_transaction()?;
+        Ok(result)
+      },
+      Err(err) => {
+        store.abort_transaction()?;
+        Err(err)
+      },
+    }
+  }
+
+  fn flush(&self) -> Result<(), PersistenceError> {
+    Ok(())
+  }
+}
+
+enum RedbTransaction<'a> {
+  Read(ReadTransaction<'a>),
+  Write(WriteTransaction<'a>),
+  /// The read transaction couldn't be created. Every operation returns the error.
+  Unavailable(String),
+}
+
+impl<'a> RedbTransaction<'a> {
+  /// Reads the table with `read` in a read transaction, and with `write` in a write transaction.
+  fn read<T, F, G>(&self, read: F, write: G) -> Result<T, PersistenceError>
+  where
+    F: FnOnce(&ReadOnlyTable<'_, &'static [u8], &'static [u8]>) -> Result<T, StorageError>,
+    G: FnOnce(&Table<'_, '_, &'static [u8], &'static [u8]>) -> Result<T, StorageError>,
+  {
+    match self {
+      RedbTransaction::Read(txn) => Ok(read(&txn.open_table(COLLAB_TABLE)?)?),
+      RedbTransaction::Write(txn) => Ok(write(&txn.open_table(COLLAB_TABLE)?)?),
+      RedbTransaction::Unavailable(err) => Err(PersistenceError::RedbError(err.clone())),
+    }
+  }
+}
+
+/// Implementation of [KVStore] for [KVTransactionDBRedbImpl]. This is a wrapper around a redb
+/// transaction, which is shared with the ranges read from it.
+pub struct RedbKVStoreImpl<'a>(Rc<RedbTransaction<'a>>);
+
+impl<'a> RedbKVStoreImpl<'a> {
+  /// Commits the writes of a write transaction. Does nothing for a read transaction.
+  pub fn commit_transaction(self) -> Result<(), PersistenceError> {
+    if let RedbTransaction::Write(txn) = Self::into_transaction(self)? {
+      txn.commit()?;
+    }
+    Ok(())
+  }
+
+  fn abort_transaction(self) -> Result<(), PersistenceError> {
+    if let RedbTransaction::Write(txn) = Self::into_transaction(self)? {
+      txn.abort()?;
+    }
+    Ok(())
+  }
+
+  /// Returns the number of keys in the range. Unlike [KVStore::range], the values are not copied.
+  pub fn count_keys<K: AsRef<[u8]>, R: RangeBounds<K>>(
+    &self,
+    range: R,
+  ) -> Result<usize, PersistenceError> {
+    match kv_bounds(&range) {
+      None => Ok(0),
+      Some((from, to)) => self.0.read(
+        |table| count_keys(table, from, to),
+        |table| count_keys(table, from, to),
+      ),
+    }
+  }
+
+  fn into_transaction(self) -> Result<RedbTransaction<'a>, PersistenceError> {
+    Rc::try_unwrap(self.0).map_err(|_| {
+      PersistenceError::RedbError("a range of the transaction is still in use".to_string())
+    })
+  }
+
+  fn write_txn(&self) -> Result<&WriteTransaction<'a>, PersistenceError> {
+    match self.0.as_ref() {
+      RedbTransaction::Write(txn) => Ok(txn),
+      RedbTransaction::Read(_) => Err(PersistenceError::RedbError(
+        "can't write in a read transaction".to_string(),
+      )),
+      RedbTransaction::Unavailable(err) => Err(PersistenceError::RedbError(err.clone())),
+    }
+  }
+}
+
+fn get_value<T>(table: &T, key: &[u8]) -> Result<Option<Vec<u8>>, StorageError>
+where
+  T: ReadableTable<&'static [u8], &'static [u8]>,
+{
+  Ok(table.get(key)?.map(|value| value.value().to_vec()))
+}
+
+/// Reads at most [RANGE_PAGE_SIZE] entries of the range.
+fn range_page<T>(
+  table: &T,
+  from: Bound<&[u8]>,
+  to: Bound<&[u8]>,
+) -> Result<Vec<RedbEntry>, StorageError>
+where
+  T: ReadableTable<&'static [u8], &'static [u8]>,
+{
+  let mut entries = Vec::with_capacity(RANGE_PAGE_SIZE);
+  for item in table.range::<&[u8]>((from, to))?.take(RANGE_PAGE_SIZE) {
+    let (key, value) = item?;
+    entries.push(RedbEntry::new(key.value().to_vec(), value.value().to_vec()));
+  }
+  Ok(entries)
+}
+
+fn count_keys<T>(table: &T, from: Bound<&[u8]>, to: Bound<&[u8]>) -> Result<usize, StorageError>
+where
+  T: ReadableTable<&'static [u8], &'static [u8]>,
+{
+  let mut count = 0;
+  for item in table.range::<&[u8]>((from, to))? {
+    item?;
+    count += 1;
+  }
+  Ok(count)
+}
+
+fn last_entry<T>(table: &T, key: &[u8]) -> Result<Option<RedbEntry>, StorageError>
+where
+  T: ReadableTable<&'static [u8], &'static [u8]>,
+{
+  match table.range(..=key)?.next_back() {
+    None => Ok(None),
+    Some(item) => {
+      let (key, value) = item?;
+      Ok(Some(RedbEntry::new(
+        key.value().to_vec(),
+        value.value().to_vec(),
+      )))
+    },
+  }
+}
+
+impl<'a> KVStore<'a> for RedbKVStoreImpl<'a> {
+  type Range = RedbRange<'a>;
+  type Entry = RedbEntry;
+  type Value = Vec<u8>;
+  type Error = PersistenceError;
+
+  fn get<K: AsRef<[u8]>>(&self, key: K) -> Result<Option<Self::Value>, Self::Error> {
+    let key = key.as_ref();
+    self
+      .0
+      .read(|table| get_value(table, key), |table| get_value(table, key))
+  }
+
+  fn insert<K: AsRef<[u8]>, V: AsRef<[u8]>>(&self, key: K, value: V) -> Result<(), Self::Error> {
+    let mut table = self.write_txn()?.open_table(COLLAB_TABLE)?;
+    table.insert(key.as_ref(), value.as_ref())?;
+    Ok(())
+  }
+
+  fn remove(&self, key: &[u8]) -> Result<(), Self::Error> {
+    let mut table = self.write_txn()?.open_table(COLLAB_TABLE)?;
+    table.remove(key)?;
+    Ok(())
+  }
+
+  fn remove_range(&self, from: &[u8], to: &[u8]) -> Result<(), Self::Error> {
+    let mut table = self.write_txn()?.open_table(COLLAB_TABLE)?;
+    if let Some((from, to)) = kv_bounds(&(from..to)) {
+      // The removed entries are dropped as they are read.
+      table.drain::<&[u8]>((from, to))?;
+    }
+    Ok(())
+  }
+
+  fn range<K: AsRef<[u8]>, R: RangeBounds<K>>(&self, range: R) -> Result<Self::Range, Self::Error> {
+    let (from, to) = match kv_bounds(&range) {
+      None => return Ok(RedbRange::empty(self.0.clone())),
+      Some(bounds) => bounds,
+    };
+    let mut range = RedbRange {
+      txn: self.0.clone(),
+      from: to_owned_bound(from),
+      to: to_owned_bound(to),
+      page: vec![].into_iter(),
+      is_last_page: false,
+    };
+    // Read the first page now, so the errors of the table are returned here.
+    range.read_page()?;
+    Ok(range)
+  }
+
+  fn next_back_entry(&self, key: &[u8]) -> Result<Option<Self::Entry>, Self::Error> {
+    self.0.read(
+      |table| last_entry(table, key),
+      |table| last_entry(table, key),
+    )
+  }
+}
+
+/// The range of a [RedbKVStoreImpl]. A redb range borrows the table it reads, so the entries are
+/// read in pages of [RANGE_PAGE_SIZE] entries, each from the table opened again in the
+/// transaction of the store.
+pub struct RedbRange<'a> {
+  txn: Rc<RedbTransaction<'a>>,
+  /// The start of the next page, which is after the key of the last read entry.
+  from: Bound<Vec<u8>>,
+  to: Bound<Vec<u8>>,
+  page: std::vec::IntoIter<RedbEntry>,
+  is_last_page: bool,
+}
+
+impl<'a> RedbRange<'a> {
+  fn empty(txn: Rc<RedbTransaction<'a>>) -> Self {
+    Self {
+      txn,
+      from: Bound::Unbounded,
+      to: Bound::Unbounded,
+      page: vec![].into_iter(),
+      is_last_page: true,
+    }
+  }
+
+  fn read_page(&mut self) -> Result<(), PersistenceError> {
+    let from = as_slice_bound(&self.from);
+    let to = as_slice_bound(&self.to);
+    let page = self.txn.read(
+      |table| range_page(table, from, to),
+      |table| range_page(table, from, to),
+    )?;
+    self.is_last_page = page.len() < RANGE_PAGE_SIZE;
+    if let Some(last) = page.last() {
+      self.from = Bound::Excluded(last.key.clone());
+    }
+    self.page = page.into_iter();
+    Ok(())
+  }
+}
+
+fn to_owned_bound(bound: Bound<&[u8]>) -> Bound<Vec<u8>> {
+  match bound {
+    Bound::Included(key) => Bound::Included(key.to_vec()),
+    Bound::Excluded(key) => Bound::Excluded(key.to_vec()),
+    Bound::Unbounded => Bound::Unbounded,
+  }
+}
+
+fn as_slice_bound(bound: &Bound<Vec<u8>>) -> Bound<&[u8]> {
+  match bound {
+    Bound::Included(key) => Bound::Included(key.as_slice()),
+    Bound::Excluded(key) => Bound::Excluded(key.as_slice()),
+    Bound::Unbounded => Bound::Unbounded,
+  }
+}
+
+impl<'a> Iterator for RedbRange<'a> {
+  type Item = RedbEntry;
+
+  fn next(&mut self) -> Option<Self::Item> {
+    if let Some(entry) = self.page.next() {
+      return Some(entry);
+    }
+    if self.is_last_page {
+      return None;
+    }
+    if let Err(err) = self.read_page() {
+      tracing::error!("🔴stop reading the redb range: {}", err);
+      self.is_last_page = true;
+      return None;
+    }
+    self.page.next()
+  }
+}
+
+pub struct RedbEntry {
+  key: Vec<u8>,
+  value: Vec<u8>,
+}
+
+impl RedbEntry {
+  pub fn new(key: Vec<u8>, value: Vec<u8>) -> Self {
+    Self { key, value }
+  }
+}
+
+impl KVEntry for RedbEntry {
+  fn key(&self) -> &[u8] {
+    self.key.as_ref()
+  }
+
+  fn value(&self) -> &[u8] {
+    self.value.as_ref()
+  }
+}
@@ -0,0 +1,76 @@
+use std::path::Path;
+
+use crate::local_storage::kv::keys::{ALL_KEYS_END, ALL_KEYS_START};
+use crate::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+use crate::local_storage::redb::kv_impl::KVTransactionDBRedbImpl;
+use crate::local_storage::rocksdb::kv_impl::KVTransactionDBRocksdbImpl;
+
+/// The number of keys copied by each write transaction of the migration.
+const MIGRATION_BATCH_SIZE: usize = 1000;
+
+/// Opens the RocksDB database at `rocksdb_path` and copies it into a new redb database at
+/// `redb_path`. See [migrate_from_rocksdb].
+pub fn migrate_rocksdb_dir(
+  rocksdb_path: impl AsRef<Path>,
+  redb_path: impl AsRef<Path>,
+) -> Result<KVTransactionDBRedbImpl, PersistenceError> {
+  let rocksdb = KVTransactionDBRocksdbImpl::open(rocksdb_path)?;
+  let redb = KVTransactionDBRedbImpl::open(redb_path)?;
+  migrate_from_rocksdb(&rocksdb, &redb)?;
+  Ok(redb)
+}
+
+/// Copies every key of `rocksdb` into `redb`, which must be empty, and returns the number of
+/// copied keys. Both backends use the same key layout, so the keys and the values are copied as
+/// they are. The redb backend doesn't support encryption, so an encrypted `rocksdb` can't be
+/// migrated.
+///
+/// The keys are read from a snapshot of `rocksdb`, so it shouldn't be written to during the
+/// migration: the later writes would not be copied.
+pub fn migrate_from_rocksdb(
+  rocksdb: &KVTransactionDBRocksdbImpl,
+  redb: &KVTransactionDBRedbImpl,
+) -> Result<usize, PersistenceError> {
+  if rocksdb.is_encrypted() {
+    return Err(PersistenceError::InvalidData(
+      "can't migrate an encrypted database".to_string(),
+    ));
+  }
+  if redb
+    .read_txn()
+    .range(ALL_KEYS_START..ALL_KEYS_END)?
+    .next()
+    .is_some()
+  {
+    return Err(PersistenceError::InvalidData(
+      "the target of the migration is not empty".to_string(),
+    ));
+  }
+
+  let read_txn = rocksdb.read_txn();
+  let mut entries = read_txn.range(ALL_KEYS_START..ALL_KEYS_END)?.peekable();
+  let mut copied = 0;
+  while entries.peek().is_some() {
+    let batch = entries
+      .by_ref()
+      .take(MIGRATION_BATCH_SIZE)
+      .collect::<Vec<_>>();
+    redb.with_write_txn(|store| {
+      for entry in &batch {
+        store.insert(entry.key(), entry.value())?;
+      }
+      Ok(())
+    })?;
+    copied += batch.len();
+  }
+
+  let migrated = redb.read_txn().count_keys(ALL_KEYS_START..ALL_KEYS_END)?;
+  if migrated != copied {
+    return Err(PersistenceError::InvalidData(format!(
+      "copied {} keys, but the target has {}",
+      copied, migrated
+    )));
+  }
+  tracing::info!("migrated {} keys from rocksdb to redb", copied);
+  Ok(copied)
+}
@@ -0,0 +1,3 @@
+pub mod kv_impl;
+#[cfg(feature = "rocksdb_storage")]
+pub mod migration;
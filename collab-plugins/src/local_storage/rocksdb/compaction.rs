@@ -0,0 +1,179 @@
+use std::sync::atomic::Ordering::SeqCst;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicU64, AtomicUsize};
+use std::sync::{Arc, Weak};
+
+use parking_lot::{Mutex, MutexGuard};
+use tokio::runtime::Handle;
+
+use crate::local_storage::kv::doc::CollabKVAction;
+use crate::local_storage::kv::KVTransactionDB;
+use crate::local_storage::CompactionPolicy;
+
+/// Merges the update log of a document into its state in the background, following a
+/// [CompactionPolicy]. The updates keep being pushed while the log is compacted.
+pub(crate) struct AutoCompaction<DB> {
+  uid: i64,
+  object_id: String,
+  collab_db: Weak<DB>,
+  policy: CompactionPolicy,
+  /// The updates pushed since the last compaction.
+  pending_updates: AtomicU32,
+  pending_bytes: AtomicUsize,
+  /// Increased by each update, so the idle timer can tell whether an update was pushed while it
+  /// was sleeping.
+  update_seq: AtomicU64,
+  idle_timer_running: AtomicBool,
+  compacting: AtomicBool,
+  /// Held while the update log is compacted or flushed. Two compactions at the same time could
+  /// overwrite the document state with an older one.
+  lock: Mutex<()>,
+}
+
+impl<DB: KVTransactionDB> AutoCompaction<DB> {
+  pub(crate) fn new(
+    uid: i64,
+    object_id: &str,
+    collab_db: Weak<DB>,
+    policy: CompactionPolicy,
+  ) -> Self {
+    Self {
+      uid,
+      object_id: object_id.to_string(),
+      collab_db,
+      policy,
+      pending_updates: AtomicU32::new(0),
+      pending_bytes: AtomicUsize::new(0),
+      update_seq: AtomicU64::new(0),
+      idle_timer_running: AtomicBool::new(false),
+      compacting: AtomicBool::new(false),
+      lock: Mutex::new(()),
+    }
+  }
+
+  /// Called with the number of updates in the log when the document is loaded. The size of
+  /// these updates is not known, so only the update count applies to them.
+  pub(crate) fn did_load(self: &Arc<Self>, update_count: u32) {
+    self.pending_updates.store(update_count, SeqCst);
+    if self.policy.should_compact(update_count, 0) {
+      self.compact_in_background();
+    }
+  }
+
+  /// Called after an update was appended to the log.
+  pub(crate) fn did_push_update(self: &Arc<Self>, update_len: usize) {
+    if !self.policy.is_enabled() {
+      return;
+    }
+    let updates = self.pending_updates.fetch_add(1, SeqCst) + 1;
+    let bytes = self.pending_bytes.fetch_add(update_len, SeqCst) + update_len;
+    self.update_seq.fetch_add(1, SeqCst);
+    if self.policy.should_compact(updates, bytes) {
+      self.compact_in_background();
+    } else {
+      self.start_idle_timer();
+    }
+  }
+
+  /// Locks out the background compaction while the update log is flushed by someone else.
+  pub(crate) fn lock(&self) -> MutexGuard<'_, ()> {
+    self.lock.lock()
+  }
+
+  /// Called after the whole update log was merged into the document state.
+  pub(crate) fn did_flush(&self) {
+    self.pending_updates.store(0, SeqCst);
+    self.pending_bytes.store(0, SeqCst);
+  }
+
+  fn compact_in_background(self: &Arc<Self>) {
+    if self.compacting.swap(true, SeqCst) {
+      return;
+    }
+    let this = self.clone();
+    let task = move || {
+      this.compact();
+      this.compacting.store(false, SeqCst);
+    };
+    match Handle::try_current() {
+      Ok(runtime) => {
+        runtime.spawn_blocking(task);
+      },
+      Err(_) => {
+        std::thread::spawn(task);
+      },
+    }
+  }
+
+  fn compact(&self) {
+    let db = match self.collab_db.upgrade() {
+      None => return,
+      Some(db) => db,
+    };
+    let _guard = self.lock();
+    // The updates pushed while compacting might not be merged, so they count for the next one.
+    self.did_flush();
+    match db.with_write_txn(|txn| txn.compact_doc_updates(self.uid, &self.object_id)) {
+      Ok(count) => tracing::debug!("{} compacted {} updates", self.object_id, count),
+      Err(e) => tracing::error!("🔴compact doc:{} failed: {:?}", self.object_id, e),
+    }
+  }
+
+  /// Compacts the update log once no update was pushed for the idle timeout. Only one timer
+  /// runs at a time, it sleeps again for as long as updates keep coming. The timer runs on the
+  /// current tokio runtime, or on its own thread outside of one.
+  fn start_idle_timer(self: &Arc<Self>) {
+    let idle_timeout = match self.policy.idle_timeout {
+      None => return,
+      Some(idle_timeout) => idle_timeout,
+    };
+    if self.idle_timer_running.swap(true, SeqCst) {
+      return;
+    }
+
+    let weak_this = Arc::downgrade(self);
+    match Handle::try_current() {
+      Ok(runtime) => {
+        runtime.spawn(async move {
+          while let Some(seq) = Self::idle_seq(&weak_this) {
+            tokio::time::sleep(idle_timeout).await;
+            if Self::did_idle(&weak_this, seq) {
+              return;
+            }
+          }
+        });
+      },
+      Err(_) => {
+        std::thread::spawn(move || {
+          while let Some(seq) = Self::idle_seq(&weak_this) {
+            std::thread::sleep(idle_timeout);
+            if Self::did_idle(&weak_this, seq) {
+              return;
+            }
+          }
+        });
+      },
+    }
+  }
+
+  /// Returns the update sequence the idle timer waits on, or None if the document was closed.
+  fn idle_seq(weak_this: &Weak<Self>) -> Option<u64> {
+    weak_this.upgrade().map(|this| this.update_seq.load(SeqCst))
+  }
+
+  /// Returns true if the idle timer is done: either the document was closed, or no update was
+  /// pushed since `seq`, in which case the log is compacted.
+  fn did_idle(weak_this: &Weak<Self>, seq: u64) -> bool {
+    let this = match weak_this.upgrade() {
+      None => return true,
+      Some(this) => this,
+    };
+    if this.update_seq.load(SeqCst) != seq {
+      return false;
+    }
+    this.idle_timer_running.store(false, SeqCst);
+    if this.pending_updates.load(SeqCst) > 0 {
+      this.compact_in_background();
+    }
+    true
+  }
+}
@@ -2,8 +2,14 @@ use std::ops;
 use std::ops::RangeBounds;
 use std::path::Path;
 use std::sync::Arc;
+use std::thread::JoinHandle;
 
 use crate::local_storage::kv::doc::CollabKVAction;
+use crate::local_storage::kv::encryption::{
+  EncryptedKVStore, EncryptionConfig, EncryptionKey, KVEncryption,
+};
+use crate::local_storage::kv::keys::{make_encryption_key_check_key, ALL_KEYS_END, ALL_KEYS_START};
+use crate::local_storage::kv::user::{UserDataAction, UserObject, PURGE_USER_BATCH_SIZE};
 
 use crate::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
 use rocksdb::Direction::Forward;
@@ -13,15 +19,46 @@ use rocksdb::{
   WriteOptions,
 };
 
+/// The number of values re-encrypted by each write transaction of a key rotation.
+const KEY_ROTATION_BATCH_SIZE: usize = 500;
+
 #[derive(Clone)]
 pub struct KVTransactionDBRocksdbImpl {
   db: Arc<TransactionDB>,
+  encryption: Option<Arc<KVEncryption>>,
 }
 
 impl KVTransactionDBRocksdbImpl {
   /// Open a new RocksDB database at the given path.
   /// If the database is corrupted, try to repair it. If it cannot be repaired, return an error.
+  /// Fails if the database is encrypted, see [KVTransactionDBRocksdbImpl::open_with_encryption].
   pub fn open(path: impl AsRef<Path>) -> Result<Self, PersistenceError> {
+    Self::open_with(path, None)
+  }
+
+  /// Open a RocksDB database whose values are encrypted with the keys of `config`. A new
+  /// database is encrypted from the start. An existing database must have been encrypted when
+  /// it was created: the unencrypted values are not encrypted afterwards.
+  pub fn open_with_encryption(
+    path: impl AsRef<Path>,
+    config: EncryptionConfig,
+  ) -> Result<Self, PersistenceError> {
+    Self::open_with(path, Some(Arc::new(KVEncryption::new(config))))
+  }
+
+  fn open_with(
+    path: impl AsRef<Path>,
+    encryption: Option<Arc<KVEncryption>>,
+  ) -> Result<Self, PersistenceError> {
+    let db = Self {
+      db: Arc::new(Self::open_db(path)?),
+      encryption,
+    };
+    db.check_encryption()?;
+    Ok(db)
+  }
+
+  fn open_db(path: impl AsRef<Path>) -> Result<TransactionDB, PersistenceError> {
     let auto_repair = false;
     let txn_db_opts = TransactionDBOptions::default();
     let mut db_opts = Options::default();
@@ -109,7 +146,119 @@ impl KVTransactionDBRocksdbImpl {
       },
     }?;
 
-    Ok(Self { db: Arc::new(db) })
+    Ok(db)
+  }
+
+  /// Verifies that the database is opened with the keys it was encrypted with. The first time
+  /// an empty database is opened with encryption, stores the value used for the verification.
+  fn check_encryption(&self) -> Result<(), PersistenceError> {
+    let check_key = make_encryption_key_check_key();
+    let txn = self.read_txn();
+    let check_value = txn.inner().get(check_key.as_ref())?;
+    match (&self.encryption, check_value) {
+      (None, None) => Ok(()),
+      (None, Some(_)) => Err(PersistenceError::Encryption(
+        "the database is encrypted, it must be opened with its encryption key".to_string(),
+      )),
+      (Some(encryption), Some(check_value)) => {
+        encryption.verify_key_check(check_key.as_ref(), &check_value)
+      },
+      (Some(encryption), None) => {
+        if txn
+          .inner()
+          .range(ALL_KEYS_START..ALL_KEYS_END)?
+          .next()
+          .is_some()
+        {
+          return Err(PersistenceError::Encryption(
+            "can't encrypt a database that already contains unencrypted data".to_string(),
+          ));
+        }
+        drop(txn);
+        let check_value = encryption.make_key_check(check_key.as_ref())?;
+        self.with_write_txn(|store| store.inner().insert(check_key.as_ref(), &check_value))
+      },
+    }
+  }
+
+  pub fn is_encrypted(&self) -> bool {
+    self.encryption.is_some()
+  }
+
+  /// Encrypts the new values with `key`, and re-encrypts the existing values on a background
+  /// thread. The previous keys keep decrypting the values that are not re-encrypted yet, and are
+  /// dropped once the thread is done. Until then, reopening the database needs the previous keys
+  /// too, see [EncryptionConfig::with_previous_key].
+  ///
+  /// Rotating to the current key again resumes a rotation that failed or was interrupted. The
+  /// returned handle yields the number of re-encrypted values.
+  pub fn rotate_encryption_key(
+    &self,
+    key: EncryptionKey,
+  ) -> Result<JoinHandle<Result<usize, PersistenceError>>, PersistenceError> {
+    let encryption = self.encryption.clone().ok_or_else(|| {
+      PersistenceError::Encryption("can't rotate the key of an unencrypted database".to_string())
+    })?;
+    encryption.set_current_key(key);
+
+    let db = self.clone();
+    std::thread::Builder::new()
+      .name("collab-key-rotation".to_string())
+      .spawn(move || {
+        let result = db.reencrypt_all(&encryption);
+        match &result {
+          Ok(count) => {
+            encryption.retire_previous_keys();
+            tracing::info!(
+              "re-encrypted {} values with the encryption key {}",
+              count,
+              encryption.current_key_id()
+            );
+          },
+          Err(err) => tracing::error!("🔴failed to rotate the encryption key: {}", err),
+        }
+        result
+      })
+      .map_err(|err| PersistenceError::Internal(err.into()))
+  }
+
+  /// Re-encrypts the values that are not encrypted with the current key, batch by batch.
+  fn reencrypt_all(&self, encryption: &KVEncryption) -> Result<usize, PersistenceError> {
+    let mut start = ALL_KEYS_START.to_vec();
+    let mut count = 0;
+    loop {
+      let keys = self
+        .read_txn()
+        .inner()
+        .range(start.as_slice()..ALL_KEYS_END.as_slice())?
+        .take(KEY_ROTATION_BATCH_SIZE)
+        .map(|entry| entry.key().to_vec())
+        .collect::<Vec<_>>();
+      let last_key = match keys.last() {
+        None => return Ok(count),
+        Some(key) => key.clone(),
+      };
+
+      count += self.with_write_txn(|store| {
+        let mut count = 0;
+        for key in &keys {
+          // Lock the key, so a concurrent write of the value can't be overwritten by the old one.
+          let value = match store.inner().get_for_update(key)? {
+            None => continue,
+            Some(value) => value,
+          };
+          if let Some(value) = encryption.reencrypt(key, &value)? {
+            store.inner().insert(key, value)?;
+            count += 1;
+          }
+        }
+        Ok(count)
+      })?;
+
+      // The smallest key after the last key of the batch.
+      start = last_key;
+      start.push(0);
+    }
   }
 
   pub async fn is_exist(&self, uid: i64, object_id: &str) -> Result<bool, PersistenceError> {
@@ -121,10 +270,30 @@ impl KVTransactionDBRocksdbImpl {
     self.with_write_txn(|txn| txn.delete_doc(uid, doc_id))?;
     Ok(())
   }
+
+  pub async fn list_user_objects(&self, uid: i64) -> Result<Vec<UserObject>, PersistenceError> {
+    self.read_txn().list_user_objects(uid)
+  }
+
+  /// Removes all the data of the given user, [PURGE_USER_BATCH_SIZE] objects per write
+  /// transaction, so the database is not locked for the whole purge. Returns the number of
+  /// removed objects.
+  pub async fn purge_user(&self, uid: i64) -> Result<usize, PersistenceError> {
+    let mut count = 0;
+    loop {
+      let removed =
+        self.with_write_txn(|txn| txn.purge_user_objects(uid, PURGE_USER_BATCH_SIZE))?;
+      count += removed;
+      if removed < PURGE_USER_BATCH_SIZE {
+        tracing::info!("purged {} objects of user {}", count, uid);
+        return Ok(count);
+      }
+    }
+  }
 }
 
 impl KVTransactionDB for KVTransactionDBRocksdbImpl {
-  type TransactionAction<'a> = RocksdbKVStoreImpl<'a, TransactionDB>;
+  type TransactionAction<'a> = EncryptedKVStore<RocksdbKVStoreImpl<'a, TransactionDB>>;
 
   fn read_txn<'a, 'b>(&'b self) -> Self::TransactionAction<'a>
   where
@@ -139,7 +308,7 @@ impl KVTransactionDB for KVTransactionDBRocksdbImpl {
     let txn = self
       .db
       .transaction_opt(&WriteOptions::default(), &txn_options);
-    RocksdbKVStoreImpl::new(txn)
+    EncryptedKVStore::new(RocksdbKVStoreImpl::new(txn), self.encryption.clone())
   }
 
   fn with_write_txn<'a, 'b, Output>(
@@ -153,9 +322,9 @@ impl KVTransactionDB for KVTransactionDBRocksdbImpl {
     let txn = self
       .db
       .transaction_opt(&WriteOptions::default(), &txn_options);
-    let store = RocksdbKVStoreImpl::new(txn);
+    let store = EncryptedKVStore::new(RocksdbKVStoreImpl::new(txn), self.encryption.clone());
     let result = f(&store)?;
-    store.0.commit()?;
+    store.into_inner().commit_transaction()?;
     Ok(result)
   }
 
@@ -179,6 +348,14 @@ impl<'a, DB: Send + Sync> RocksdbKVStoreImpl<'a, DB> {
     self.0.commit()?;
     Ok(())
   }
+
+  /// Reads the value and locks the key until the transaction ends.
+  pub fn get_for_update<K: AsRef<[u8]>>(
+    &self,
+    key: K,
+  ) -> Result<Option<Vec<u8>>, PersistenceError> {
+    Ok(self.0.get_for_update(key, true)?)
+  }
 }
 
 impl<'a, DB: Send + Sync> KVStore<'a> for RocksdbKVStoreImpl<'a, DB> {
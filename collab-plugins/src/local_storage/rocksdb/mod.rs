@@ -1,3 +1,5 @@
+mod compaction;
+#[cfg(feature = "rocksdb_storage")]
 pub mod kv_impl;
 pub mod rocksdb_plugin;
 pub mod snapshot_plugin;
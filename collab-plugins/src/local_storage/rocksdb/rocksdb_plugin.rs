@@ -5,19 +5,21 @@ use std::sync::{Arc, Weak};
 
 use crate::CollabKVDB;
 
+use async_trait::async_trait;
 use collab::core::awareness::{AwarenessUpdate, Event};
-use collab::core::collab::make_yrs_doc;
 use collab::core::collab_plugin::EncodedCollab;
 use collab::core::origin::CollabOrigin;
+use collab::core::plugin_pipeline::AsyncCollabPlugin;
 use collab::preclude::{Collab, CollabPlugin};
 use collab_entity::CollabType;
 use tracing::{debug, error};
-use yrs::updates::encoder::Encode;
-use yrs::{Doc, ReadTxn, StateVector, Transact, TransactionMut};
+use yrs::{Doc, Transact, TransactionMut};
 
 use crate::local_storage::kv::doc::CollabKVAction;
+use crate::local_storage::kv::metadata::CollabMetadataAction;
 use crate::local_storage::kv::snapshot::SnapshotPersistence;
 use crate::local_storage::kv::KVTransactionDB;
+use crate::local_storage::rocksdb::compaction::AutoCompaction;
 use crate::local_storage::rocksdb::snapshot_plugin::CollabSnapshot;
 use crate::local_storage::CollabPersistenceConfig;
 
@@ -26,32 +28,58 @@ pub trait RocksdbBackup: Send + Sync {
   fn get_doc(&self, uid: i64, object_id: &str) -> Result<EncodedCollab, anyhow::Error>;
 }
 
-#[derive(Clone)]
-pub struct RocksdbDiskPlugin {
+/// Persists a collab in a [KVTransactionDB], the [CollabKVDB] by default. Any other store works
+/// too, like the in-memory [KVTransactionDBMemoryImpl] for sessions that must not leave any data
+/// behind.
+///
+/// By default the updates are written inside the Yrs observer, which blocks the writer. With
+/// [CollabPersistenceConfig::async_write], they are written on the plugin pipeline instead. The
+/// pipeline half is the [CollabPlugin::pipeline_plugin] of the plugin, so it is added together
+/// with the plugin, and adding the plugin fails without an async runtime.
+///
+/// [KVTransactionDBMemoryImpl]: crate::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl
+pub struct RocksdbDiskPlugin<DB = CollabKVDB> {
   uid: i64,
   object_id: String,
   collab_type: CollabType,
-  collab_db: Weak<CollabKVDB>,
+  collab_db: Weak<DB>,
   did_load: Arc<AtomicBool>,
   update_count: Arc<AtomicU32>,
   config: CollabPersistenceConfig,
   snapshot: Option<CollabSnapshot>,
+  compaction: Arc<AutoCompaction<DB>>,
 }
 
-impl Deref for RocksdbDiskPlugin {
-  type Target = Weak<CollabKVDB>;
+impl<DB> Clone for RocksdbDiskPlugin<DB> {
+  fn clone(&self) -> Self {
+    Self {
+      uid: self.uid,
+      object_id: self.object_id.clone(),
+      collab_type: self.collab_type.clone(),
+      collab_db: self.collab_db.clone(),
+      did_load: self.did_load.clone(),
+      update_count: self.update_count.clone(),
+      config: self.config.clone(),
+      snapshot: self.snapshot.clone(),
+      compaction: self.compaction.clone(),
+    }
+  }
+}
+
+impl<DB> Deref for RocksdbDiskPlugin<DB> {
+  type Target = Weak<DB>;
 
   fn deref(&self) -> &Self::Target {
     &self.collab_db
   }
 }
 
-impl RocksdbDiskPlugin {
+impl<DB: KVTransactionDB> RocksdbDiskPlugin<DB> {
   pub fn new_with_config(
     uid: i64,
     object_id: String,
     collab_type: CollabType,
-    collab_db: Weak<CollabKVDB>,
+    collab_db: Weak<DB>,
     config: CollabPersistenceConfig,
     snapshot_persistence: Option<Arc<dyn SnapshotPersistence>>,
   ) -> Self {
@@ -62,6 +90,12 @@ impl RocksdbDiskPlugin {
     if config.enable_snapshot {
       snapshot = snapshot_persistence.map(CollabSnapshot::new);
     }
+    let compaction = Arc::new(AutoCompaction::new(
+      uid,
+      &object_id,
+      collab_db.clone(),
+      config.compaction.clone(),
+    ));
 
     Self {
       object_id,
@@ -72,6 +106,7 @@ impl RocksdbDiskPlugin {
       update_count,
       config,
       snapshot,
+      compaction,
     }
   }
 
@@ -79,7 +114,7 @@ impl RocksdbDiskPlugin {
     uid: i64,
     object_id: String,
     collab_type: CollabType,
-    collab_db: Weak<CollabKVDB>,
+    collab_db: Weak<DB>,
     snapshot_persistence: Option<Arc<dyn SnapshotPersistence>>,
   ) -> Self {
     Self::new_with_config(
@@ -117,29 +152,59 @@ impl RocksdbDiskPlugin {
     }
   }
 
-  fn flush_doc(&self, db: &Arc<CollabKVDB>, object_id: &str) {
-    let _ = db.with_write_txn(|w_db_txn| {
-      let doc = make_yrs_doc();
-      w_db_txn.load_doc_with_txn(self.uid, object_id, &mut doc.transact_mut())?;
-      if let Ok(read_txn) = doc.try_transact() {
-        let doc_state = read_txn.encode_state_as_update_v1(&StateVector::default());
-        let state_vector = read_txn.state_vector().encode_v1();
-        let encoded = EncodedCollab::new_v1(state_vector, doc_state);
-
-        w_db_txn.flush_doc(
-          self.uid,
-          object_id,
-          encoded.state_vector.to_vec(),
-          encoded.doc_state.to_vec(),
-        )?;
+  fn flush_doc(&self, db: &Arc<DB>, object_id: &str) {
+    let _guard = self.compaction.lock();
+    let result = db.with_write_txn(|w_db_txn| w_db_txn.compact_doc(self.uid, object_id));
+    match result {
+      Ok(_) => self.compaction.did_flush(),
+      Err(e) => error!("🔴flush doc:{} failed: {:?}", object_id, e),
+    }
+  }
+
+  fn push_update(&self, object_id: &str, update: &[u8]) {
+    if let Some(db) = self.collab_db.upgrade() {
+      self.increase_count();
+      //Acquire a write transaction to ensure consistency
+      let result = db.with_write_txn(|w_db_txn| {
+        let _ = w_db_txn.push_update(self.uid, object_id, update)?;
+        Ok(())
+      });
+
+      match result {
+        Ok(_) => self.compaction.did_push_update(update.len()),
+        Err(e) => error!("🔴Save update failed: {:?}", e),
       }
+    } else {
+      tracing::warn!("collab_db is dropped");
+    };
+  }
 
-      Ok(())
-    });
+  fn delete_updates(&self, object_id: &str) {
+    if let Some(db) = self.collab_db.upgrade() {
+      if let Err(e) = db.with_write_txn(|w_db_txn| {
+        w_db_txn.delete_all_updates(self.uid, object_id)?;
+        Ok(())
+      }) {
+        error!("🔴Reset failed: {:?}", e);
+      }
+    }
+  }
+
+  /// Runs the write on the blocking thread pool, so the plugin pipeline doesn't block the
+  /// runtime.
+  async fn write_blocking<F>(&self, object_id: &str, write: F)
+  where
+    F: FnOnce(&Self, &str) + Send + 'static,
+  {
+    let plugin = self.clone();
+    let object_id = object_id.to_string();
+    if let Err(e) = tokio::task::spawn_blocking(move || write(&plugin, &object_id)).await {
+      error!("🔴write doc:{} failed: {:?}", self.object_id, e);
+    }
   }
 }
 
-impl CollabPlugin for RocksdbDiskPlugin {
+impl<DB: KVTransactionDB> CollabPlugin for RocksdbDiskPlugin<DB> {
   fn init(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
     if let Some(db) = self.collab_db.upgrade() {
       let rocksdb_read = db.read_txn();
@@ -157,18 +222,35 @@ impl CollabPlugin for RocksdbDiskPlugin {
             0
           },
         };
+        // The documents written before the metadata existed don't have a type yet.
+        let has_collab_type = matches!(
+          rocksdb_read.get_collab_metadata(self.uid, object_id),
+          Ok(Some(metadata)) if metadata.collab_type.as_ref() == Some(&self.collab_type)
+        );
         drop(rocksdb_read);
         txn.commit();
         drop(txn);
 
+        if !has_collab_type {
+          let result = db.with_write_txn(|w_db_txn| {
+            w_db_txn.set_collab_type(self.uid, object_id, &self.collab_type)
+          });
+          if let Err(e) = result {
+            error!("🔴 save the type of doc:{} failed: {}", object_id, e)
+          }
+        }
+
         if update_count != 0 && update_count % self.config.snapshot_per_update == 0 {
           self.flush_doc(&db, object_id);
           self.create_snapshot_if_need(update_count);
+        } else {
+          self.compaction.did_load(update_count);
         }
       } else {
         let txn = doc.transact();
         let result = db.with_write_txn(|w_db_txn| {
           w_db_txn.create_new_doc(self.uid, object_id, &txn)?;
+          w_db_txn.set_collab_type(self.uid, object_id, &self.collab_type)?;
           Ok(())
         });
 
@@ -185,25 +267,66 @@ impl CollabPlugin for RocksdbDiskPlugin {
     self.did_load.store(true, SeqCst);
   }
 
-  fn receive_update(&self, object_id: &str, _txn: &TransactionMut, update: &[u8]) {
-    // Only push update if the doc is loaded
-    if !self.did_load.load(SeqCst) {
-      return;
-    }
-    if let Some(db) = self.collab_db.upgrade() {
-      self.increase_count();
-      //Acquire a write transaction to ensure consistency
-      let result = db.with_write_txn(|w_db_txn| {
-        let _ = w_db_txn.push_update(self.uid, object_id, update)?;
-        Ok(())
-      });
+  /// Loads the child document, or creates it if it doesn't exist. The child doesn't count
+  /// towards the updates, the compaction or the snapshots of this document, so it is compacted
+  /// when it's loaded instead.
+  fn init_subdoc(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
+    let db = match self.collab_db.upgrade() {
+      None => return tracing::warn!("collab_db is dropped"),
+      Some(db) => db,
+    };
+    let rocksdb_read = db.read_txn();
+    if rocksdb_read.is_exist(self.uid, object_id) {
+      let mut txn = doc.transact_mut_with(origin.clone());
+      let update_count = match rocksdb_read.load_doc_with_txn(self.uid, object_id, &mut txn) {
+        Ok(update_count) => update_count,
+        Err(e) => {
+          error!("🔴 load subdoc:{} failed: {}", object_id, e);
+          0
+        },
+      };
+      drop(rocksdb_read);
+      txn.commit();
+      drop(txn);
 
-      if let Err(e) = result {
-        error!("🔴Save update failed: {:?}", e);
+      if update_count >= self.config.snapshot_per_update {
+        if let Err(e) = db.with_write_txn(|w_db_txn| w_db_txn.compact_doc(self.uid, object_id)) {
+          error!("🔴flush subdoc:{} failed: {:?}", object_id, e);
+        }
       }
     } else {
-      tracing::warn!("collab_db is dropped");
+      drop(rocksdb_read);
+      let txn = doc.transact();
+      if let Err(e) =
+        db.with_write_txn(|w_db_txn| w_db_txn.create_new_doc(self.uid, object_id, &txn))
+      {
+        error!("🔴 create subdoc for {:?} failed: {}", object_id, e)
+      }
+    }
+  }
+
+  /// The updates of the children are written in the Yrs observer, even with
+  /// [CollabPersistenceConfig::async_write].
+  fn receive_subdoc_update(&self, object_id: &str, update: &[u8]) {
+    let db = match self.collab_db.upgrade() {
+      None => return tracing::warn!("collab_db is dropped"),
+      Some(db) => db,
     };
+    let result = db.with_write_txn(|w_db_txn| {
+      let _ = w_db_txn.push_update(self.uid, object_id, update)?;
+      Ok(())
+    });
+    if let Err(e) = result {
+      error!("🔴Save subdoc update failed: {:?}", e);
+    }
+  }
+
+  fn receive_update(&self, object_id: &str, _txn: &TransactionMut, update: &[u8]) {
+    // Only push update if the doc is loaded
+    if self.config.async_write || !self.did_load.load(SeqCst) {
+      return;
+    }
+    self.push_update(object_id, update);
   }
 
   fn receive_local_state(
@@ -218,19 +341,62 @@ impl CollabPlugin for RocksdbDiskPlugin {
   fn after_transaction(&self, _object_id: &str, _txn: &mut TransactionMut) {}
 
   fn reset(&self, object_id: &str) {
-    if let Some(db) = self.collab_db.upgrade() {
-      if let Err(e) = db.with_write_txn(|w_db_txn| {
-        w_db_txn.delete_all_updates(self.uid, object_id)?;
-        Ok(())
-      }) {
-        error!("🔴Reset failed: {:?}", e);
-      }
+    if !self.config.async_write {
+      self.delete_updates(object_id);
     }
   }
 
   fn flush(&self, object_id: &str, _doc: &Doc) {
+    if self.config.async_write {
+      return;
+    }
     if let Some(db) = self.collab_db.upgrade() {
       self.flush_doc(&db, object_id);
     }
   }
+
+  fn pipeline_plugin(&self) -> Option<Arc<dyn AsyncCollabPlugin>> {
+    if self.config.async_write {
+      Some(Arc::new(AsyncDiskPlugin(self.clone())))
+    } else {
+      None
+    }
+  }
+}
+
+/// The pipeline half of a [RocksdbDiskPlugin] with [CollabPersistenceConfig::async_write]. The
+/// pipeline only receives the updates made after the document was loaded.
+struct AsyncDiskPlugin<DB>(RocksdbDiskPlugin<DB>);
+
+#[async_trait]
+impl<DB: KVTransactionDB> AsyncCollabPlugin for AsyncDiskPlugin<DB> {
+  async fn receive_update(&self, object_id: &str, update: &[u8]) {
+    let update = update.to_vec();
+    self
+      .0
+      .write_blocking(object_id, move |plugin, object_id| {
+        plugin.push_update(object_id, &update)
+      })
+      .await;
+  }
+
+  async fn reset(&self, object_id: &str) {
+    self
+      .0
+      .write_blocking(object_id, |plugin, object_id| {
+        plugin.delete_updates(object_id)
+      })
+      .await;
+  }
+
+  async fn flush(&self, object_id: &str, _encoded_collab: &EncodedCollab) {
+    self
+      .0
+      .write_blocking(object_id, |plugin, object_id| {
+        if let Some(db) = plugin.collab_db.upgrade() {
+          plugin.flush_doc(&db, object_id);
+        }
+      })
+      .await;
+  }
 }
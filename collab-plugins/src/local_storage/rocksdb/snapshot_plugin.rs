@@ -3,13 +3,9 @@ use std::sync::{Arc, Weak};
 use crate::local_storage::kv::doc::CollabKVAction;
 use crate::local_storage::kv::snapshot::SnapshotPersistence;
 use crate::local_storage::kv::{KVTransactionDB, PersistenceError};
-use crate::CollabKVDB;
-use collab::preclude::Collab;
 use collab_entity::CollabType;
 use parking_lot::RwLock;
 
-use yrs::{ReadTxn, StateVector};
-
 #[derive(Clone, Debug)]
 pub(crate) enum SnapshotState {
   Idle,
@@ -48,9 +44,9 @@ impl CollabSnapshot {
     false
   }
 
-  pub(crate) fn create_snapshot(
+  pub(crate) fn create_snapshot<DB: KVTransactionDB>(
     &self,
-    weak_collab_db: Weak<CollabKVDB>,
+    weak_collab_db: Weak<DB>,
     uid: i64,
     object_id: &str,
     collab_type: &CollabType,
@@ -68,21 +64,18 @@ impl CollabSnapshot {
           weak_collab_db.upgrade(),
           weak_snapshot_persistence.upgrade(),
         ) {
-          let snapshot_collab = Collab::new(uid, object_id.clone(), "1", vec![]);
-          let mut txn = snapshot_collab.origin_transact_mut();
-          if let Err(e) = collab_db
+          // Generate the snapshot
+          let encoded_v1 = match collab_db
             .read_txn()
-            .load_doc_with_txn(uid, &object_id, &mut txn)
+            .get_compacted_doc_state(uid, &object_id)
           {
-            tracing::error!("{} snapshot generation failed: {}", object_id, e);
-            *state.write() = SnapshotState::Fail;
-            return Ok::<(), PersistenceError>(());
-          }
-          drop(txn);
-
-          // Generate the snapshot
-          let txn = snapshot_collab.transact();
-          let encoded_v1 = txn.encode_state_as_update_v1(&StateVector::default());
+            Ok(encoded_collab) => encoded_collab.doc_state.to_vec(),
+            Err(e) => {
+              tracing::error!("{} snapshot generation failed: {}", object_id, e);
+              *state.write() = SnapshotState::Fail;
+              return Ok::<(), PersistenceError>(());
+            },
+          };
           match snapshot_persistence.create_snapshot(uid, &object_id, &collab_type, encoded_v1) {
             Ok(_) => *state.write() = SnapshotState::Idle,
             Err(e) => {
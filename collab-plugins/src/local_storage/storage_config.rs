@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 #[derive(Clone)]
 pub struct CollabPersistenceConfig {
   /// Enable snapshot. Default is [false].
@@ -5,6 +7,12 @@ pub struct CollabPersistenceConfig {
   /// Generate a snapshot every N updates
   /// Default is 100. The value must be greater than 0.
   pub snapshot_per_update: u32,
+  /// When to merge the update log of a document into its state while it is open.
+  /// Default is [CompactionPolicy::default], which never does.
+  pub compaction: CompactionPolicy,
+  /// Write the updates on the plugin pipeline of the collab instead of inside the Yrs observer.
+  /// Adding the disk plugin then requires an async runtime. Default is [false].
+  pub async_write: bool,
 }
 
 impl CollabPersistenceConfig {
@@ -22,6 +30,16 @@ impl CollabPersistenceConfig {
     self.snapshot_per_update = snapshot_per_update;
     self
   }
+
+  pub fn compaction(mut self, compaction: CompactionPolicy) -> Self {
+    self.compaction = compaction;
+    self
+  }
+
+  pub fn async_write(mut self, async_write: bool) -> Self {
+    self.async_write = async_write;
+    self
+  }
 }
 
 impl Default for CollabPersistenceConfig {
@@ -29,6 +47,65 @@ impl Default for CollabPersistenceConfig {
     Self {
       enable_snapshot: true,
       snapshot_per_update: 100,
+      compaction: CompactionPolicy::default(),
+      async_write: false,
     }
   }
 }
+
+/// Decides when the update log of a document is merged into the document state, in the
+/// background. The log is compacted as soon as one of the enabled limits is reached. All the
+/// limits are disabled by default.
+#[derive(Clone, Debug, Default)]
+pub struct CompactionPolicy {
+  /// Compact once this number of updates was appended to the log.
+  pub max_updates: Option<u32>,
+  /// Compact once the updates appended to the log take this number of bytes.
+  pub max_update_bytes: Option<usize>,
+  /// Compact when no update was appended for this duration. The timer runs on the current tokio
+  /// runtime, or on a dedicated thread when there is none.
+  pub idle_timeout: Option<Duration>,
+}
+
+impl CompactionPolicy {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Panics if `max_updates` is 0.
+  pub fn max_updates(mut self, max_updates: u32) -> Self {
+    assert!(max_updates > 0, "max_updates must be greater than 0");
+    self.max_updates = Some(max_updates);
+    self
+  }
+
+  /// Panics if `max_update_bytes` is 0.
+  pub fn max_update_bytes(mut self, max_update_bytes: usize) -> Self {
+    assert!(
+      max_update_bytes > 0,
+      "max_update_bytes must be greater than 0"
+    );
+    self.max_update_bytes = Some(max_update_bytes);
+    self
+  }
+
+  pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+    self.idle_timeout = Some(idle_timeout);
+    self
+  }
+
+  pub fn is_enabled(&self) -> bool {
+    self.max_updates.is_some() || self.max_update_bytes.is_some() || self.idle_timeout.is_some()
+  }
+
+  /// Returns true if the given number of updates and bytes reach one of the limits. A limit set
+  /// to 0 through the public fields is ignored.
+  pub fn should_compact(&self, updates: u32, update_bytes: usize) -> bool {
+    self
+      .max_updates
+      .map_or(false, |max| max > 0 && updates >= max)
+      || self
+        .max_update_bytes
+        .map_or(false, |max| max > 0 && update_bytes >= max)
+  }
+}
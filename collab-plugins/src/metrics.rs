@@ -0,0 +1,451 @@
+//! Per object metrics of [Collab]s.
+//!
+//! Add a [CollabMetricsPlugin] to each collab that should be measured. All the plugins created by
+//! the same [CollabMetricsRegistry] record into it, so the registry can be queried for the
+//! metrics of one object or for the most expensive ones. Each recorded value is also emitted as a
+//! `trace` event, so the metrics can be collected with any tracing subscriber.
+//!
+//! The transaction and lock timings are only measured for the collabs that have the plugin, and
+//! recording a value only updates the atomics of its object.
+
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicI64, AtomicU64, Ordering};
+use std::sync::{Arc, OnceLock};
+use std::time::Duration;
+
+use collab::core::awareness::{AwarenessUpdate, Event};
+use collab::core::collab_plugin::CollabPlugin;
+use collab::core::origin::CollabOrigin;
+use collab::preclude::Collab;
+use parking_lot::RwLock;
+use tokio::sync::broadcast;
+use tracing::trace;
+use yrs::{Doc, ReadTxn, StateVector, Transact, TransactionMut};
+
+/// The number of objects listed in [MetricsSummary::slowest_objects].
+const SUMMARY_TOP_N: usize = 5;
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct CollabMetrics {
+  pub object_id: String,
+  /// All the updates, including the local ones.
+  pub update_count: u64,
+  pub update_bytes: u64,
+  pub max_update_bytes: u64,
+  pub local_update_count: u64,
+  pub local_update_bytes: u64,
+  pub transaction_count: u64,
+  pub transaction_time: Duration,
+  pub max_transaction_time: Duration,
+  pub lock_count: u64,
+  pub lock_wait_time: Duration,
+  pub max_lock_wait_time: Duration,
+  pub awareness_added: u64,
+  pub awareness_updated: u64,
+  pub awareness_removed: u64,
+  /// The size of the encoded document when the collab was initialized or last flushed.
+  pub encoded_state_bytes: Option<u64>,
+  /// The timestamp of the last recorded value, in seconds.
+  pub last_updated_at: i64,
+}
+
+impl CollabMetrics {
+  pub fn remote_update_count(&self) -> u64 {
+    self.update_count.saturating_sub(self.local_update_count)
+  }
+
+  pub fn remote_update_bytes(&self) -> u64 {
+    self.update_bytes.saturating_sub(self.local_update_bytes)
+  }
+
+  pub fn avg_transaction_time(&self) -> Duration {
+    average(self.transaction_time, self.transaction_count)
+  }
+
+  pub fn avg_lock_wait_time(&self) -> Duration {
+    average(self.lock_wait_time, self.lock_count)
+  }
+
+  pub fn awareness_churn(&self) -> u64 {
+    self.awareness_added + self.awareness_updated + self.awareness_removed
+  }
+}
+
+fn average(total: Duration, count: u64) -> Duration {
+  if count == 0 {
+    Duration::ZERO
+  } else {
+    total / count as u32
+  }
+}
+
+/// The aggregated metrics of all the objects of a [CollabMetricsRegistry].
+#[derive(Debug, Clone, Default)]
+pub struct MetricsSummary {
+  pub object_count: usize,
+  pub update_count: u64,
+  pub update_bytes: u64,
+  pub transaction_count: u64,
+  pub transaction_time: Duration,
+  pub lock_wait_time: Duration,
+  pub awareness_churn: u64,
+  /// The objects that spent the most time in transactions, slowest first.
+  pub slowest_objects: Vec<CollabMetrics>,
+}
+
+/// The metrics of one object. Each value is a separate atomic, so recording never takes a lock.
+struct ObjectMetrics {
+  update_count: AtomicU64,
+  update_bytes: AtomicU64,
+  max_update_bytes: AtomicU64,
+  local_update_count: AtomicU64,
+  local_update_bytes: AtomicU64,
+  transaction_count: AtomicU64,
+  transaction_nanos: AtomicU64,
+  max_transaction_nanos: AtomicU64,
+  lock_count: AtomicU64,
+  lock_wait_nanos: AtomicU64,
+  max_lock_wait_nanos: AtomicU64,
+  awareness_added: AtomicU64,
+  awareness_updated: AtomicU64,
+  awareness_removed: AtomicU64,
+  /// [NO_ENCODED_STATE] until the size is recorded.
+  encoded_state_bytes: AtomicU64,
+  last_updated_at: AtomicI64,
+}
+
+const NO_ENCODED_STATE: u64 = u64::MAX;
+
+impl ObjectMetrics {
+  fn new() -> Self {
+    Self {
+      update_count: Default::default(),
+      update_bytes: Default::default(),
+      max_update_bytes: Default::default(),
+      local_update_count: Default::default(),
+      local_update_bytes: Default::default(),
+      transaction_count: Default::default(),
+      transaction_nanos: Default::default(),
+      max_transaction_nanos: Default::default(),
+      lock_count: Default::default(),
+      lock_wait_nanos: Default::default(),
+      max_lock_wait_nanos: Default::default(),
+      awareness_added: Default::default(),
+      awareness_updated: Default::default(),
+      awareness_removed: Default::default(),
+      encoded_state_bytes: AtomicU64::new(NO_ENCODED_STATE),
+      last_updated_at: Default::default(),
+    }
+  }
+
+  fn touch(&self) {
+    self
+      .last_updated_at
+      .store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+  }
+
+  fn snapshot(&self, object_id: &str) -> CollabMetrics {
+    let load = |value: &AtomicU64| value.load(Ordering::Relaxed);
+    let load_duration = |value: &AtomicU64| Duration::from_nanos(value.load(Ordering::Relaxed));
+    let encoded_state_bytes = load(&self.encoded_state_bytes);
+    CollabMetrics {
+      object_id: object_id.to_string(),
+      update_count: load(&self.update_count),
+      update_bytes: load(&self.update_bytes),
+      max_update_bytes: load(&self.max_update_bytes),
+      local_update_count: load(&self.local_update_count),
+      local_update_bytes: load(&self.local_update_bytes),
+      transaction_count: load(&self.transaction_count),
+      transaction_time: load_duration(&self.transaction_nanos),
+      max_transaction_time: load_duration(&self.max_transaction_nanos),
+      lock_count: load(&self.lock_count),
+      lock_wait_time: load_duration(&self.lock_wait_nanos),
+      max_lock_wait_time: load_duration(&self.max_lock_wait_nanos),
+      awareness_added: load(&self.awareness_added),
+      awareness_updated: load(&self.awareness_updated),
+      awareness_removed: load(&self.awareness_removed),
+      encoded_state_bytes: (encoded_state_bytes != NO_ENCODED_STATE).then_some(encoded_state_bytes),
+      last_updated_at: self.last_updated_at.load(Ordering::Relaxed),
+    }
+  }
+}
+
+/// Adds `value` to `total` and raises `max` to `value` if it's greater.
+fn record_max(total: &AtomicU64, max: &AtomicU64, value: u64) {
+  total.fetch_add(value, Ordering::Relaxed);
+  max.fetch_max(value, Ordering::Relaxed);
+}
+
+#[derive(Clone)]
+pub struct CollabMetricsRegistry {
+  /// Only written when an object records its first value.
+  metrics: Arc<RwLock<HashMap<String, Arc<ObjectMetrics>>>>,
+  summary_sender: broadcast::Sender<MetricsSummary>,
+}
+
+impl Default for CollabMetricsRegistry {
+  fn default() -> Self {
+    let (summary_sender, _) = broadcast::channel(10);
+    Self {
+      metrics: Default::default(),
+      summary_sender,
+    }
+  }
+}
+
+impl CollabMetricsRegistry {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Returns a plugin that records the metrics of its collab into this registry.
+  pub fn plugin(&self) -> CollabMetricsPlugin {
+    CollabMetricsPlugin::new(self.clone())
+  }
+
+  pub fn get(&self, object_id: &str) -> Option<CollabMetrics> {
+    self
+      .metrics
+      .read()
+      .get(object_id)
+      .map(|metrics| metrics.snapshot(object_id))
+  }
+
+  pub fn all(&self) -> Vec<CollabMetrics> {
+    self
+      .metrics
+      .read()
+      .iter()
+      .map(|(object_id, metrics)| metrics.snapshot(object_id))
+      .collect()
+  }
+
+  /// Returns the `n` objects with the greatest key, greatest first.
+  pub fn top_by<K, F>(&self, n: usize, key: F) -> Vec<CollabMetrics>
+  where
+    K: Ord,
+    F: Fn(&CollabMetrics) -> K,
+  {
+    let mut metrics = self.all();
+    metrics.sort_by_key(|metrics| Reverse(key(metrics)));
+    metrics.truncate(n);
+    metrics
+  }
+
+  /// Removes the metrics of the object. The plugin of the object keeps recording into the removed
+  /// metrics, so call it after the collab is closed.
+  pub fn remove(&self, object_id: &str) -> Option<CollabMetrics> {
+    self
+      .metrics
+      .write()
+      .remove(object_id)
+      .map(|metrics| metrics.snapshot(object_id))
+  }
+
+  pub fn clear(&self) {
+    self.metrics.write().clear();
+  }
+
+  pub fn summary(&self) -> MetricsSummary {
+    let metrics = self.all();
+    let mut summary = MetricsSummary {
+      object_count: metrics.len(),
+      ..Default::default()
+    };
+    for m in &metrics {
+      summary.update_count += m.update_count;
+      summary.update_bytes += m.update_bytes;
+      summary.transaction_count += m.transaction_count;
+      summary.transaction_time += m.transaction_time;
+      summary.lock_wait_time += m.lock_wait_time;
+      summary.awareness_churn += m.awareness_churn();
+    }
+    let mut slowest_objects = metrics;
+    slowest_objects.sort_by_key(|m| Reverse(m.transaction_time));
+    slowest_objects.truncate(SUMMARY_TOP_N);
+    summary.slowest_objects = slowest_objects;
+    summary
+  }
+
+  pub fn subscribe_summary(&self) -> broadcast::Receiver<MetricsSummary> {
+    self.summary_sender.subscribe()
+  }
+
+  /// Sends the current summary to the subscribers and logs it.
+  pub fn emit_summary(&self) -> MetricsSummary {
+    let summary = self.summary();
+    tracing::info!(
+      objects = summary.object_count,
+      updates = summary.update_count,
+      update_bytes = summary.update_bytes,
+      transactions = summary.transaction_count,
+      transaction_ms = summary.transaction_time.as_millis() as u64,
+      lock_wait_ms = summary.lock_wait_time.as_millis() as u64,
+      awareness_churn = summary.awareness_churn,
+      "collab metrics summary"
+    );
+    let _ = self.summary_sender.send(summary.clone());
+    summary
+  }
+
+  /// Calls [CollabMetricsRegistry::emit_summary] periodically until all the clones of the
+  /// registry are dropped. Must be called within a tokio runtime.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn start_periodic_summary(&self, interval: Duration) {
+    let weak_metrics = Arc::downgrade(&self.metrics);
+    let summary_sender = self.summary_sender.clone();
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval(interval);
+      // The first tick completes immediately.
+      interval.tick().await;
+      loop {
+        interval.tick().await;
+        match weak_metrics.upgrade() {
+          None => break,
+          Some(metrics) => {
+            let registry = CollabMetricsRegistry {
+              metrics,
+              summary_sender: summary_sender.clone(),
+            };
+            registry.emit_summary();
+          },
+        }
+      }
+    });
+  }
+
+  fn object_metrics(&self, object_id: &str) -> Arc<ObjectMetrics> {
+    if let Some(metrics) = self.metrics.read().get(object_id) {
+      return metrics.clone();
+    }
+    self
+      .metrics
+      .write()
+      .entry(object_id.to_string())
+      .or_insert_with(|| Arc::new(ObjectMetrics::new()))
+      .clone()
+  }
+}
+
+/// Records the metrics of a collab into a [CollabMetricsRegistry].
+#[derive(Clone)]
+pub struct CollabMetricsPlugin {
+  registry: CollabMetricsRegistry,
+  /// The metrics of the collab, looked up in the registry on the first recorded value.
+  object: Arc<OnceLock<(String, Arc<ObjectMetrics>)>>,
+}
+
+impl CollabMetricsPlugin {
+  pub fn new(registry: CollabMetricsRegistry) -> Self {
+    Self {
+      registry,
+      object: Default::default(),
+    }
+  }
+
+  fn record<F>(&self, object_id: &str, f: F)
+  where
+    F: FnOnce(&ObjectMetrics),
+  {
+    let (cached_id, metrics) = self.object.get_or_init(|| {
+      (
+        object_id.to_string(),
+        self.registry.object_metrics(object_id),
+      )
+    });
+    if cached_id == object_id {
+      f(metrics);
+      metrics.touch();
+    } else {
+      // The plugin was added to more than one collab.
+      let metrics = self.registry.object_metrics(object_id);
+      f(&metrics);
+      metrics.touch();
+    }
+  }
+
+  fn record_encoded_state_size<T: ReadTxn>(&self, object_id: &str, txn: &T) {
+    let bytes = txn.encode_state_as_update_v1(&StateVector::default()).len() as u64;
+    trace!(object_id, bytes, "collab encoded state");
+    self.record(object_id, |m| {
+      m.encoded_state_bytes.store(bytes, Ordering::Relaxed);
+    });
+  }
+}
+
+impl CollabPlugin for CollabMetricsPlugin {
+  fn did_init(&self, collab: &Collab, object_id: &str, _last_sync_at: i64) {
+    self.record_encoded_state_size(object_id, &collab.transact());
+  }
+
+  fn receive_update(&self, object_id: &str, _txn: &TransactionMut, update: &[u8]) {
+    let bytes = update.len() as u64;
+    trace!(object_id, bytes, "collab update");
+    self.record(object_id, |m| {
+      m.update_count.fetch_add(1, Ordering::Relaxed);
+      record_max(&m.update_bytes, &m.max_update_bytes, bytes);
+    });
+  }
+
+  fn receive_local_update(&self, _origin: &CollabOrigin, object_id: &str, update: &[u8]) {
+    let bytes = update.len() as u64;
+    trace!(object_id, bytes, "collab local update");
+    self.record(object_id, |m| {
+      m.local_update_count.fetch_add(1, Ordering::Relaxed);
+      m.local_update_bytes.fetch_add(bytes, Ordering::Relaxed);
+    });
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    object_id: &str,
+    event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+    let added = event.added().len() as u64;
+    let updated = event.updated().len() as u64;
+    let removed = event.removed().len() as u64;
+    trace!(object_id, added, updated, removed, "collab awareness");
+    self.record(object_id, |m| {
+      m.awareness_added.fetch_add(added, Ordering::Relaxed);
+      m.awareness_updated.fetch_add(updated, Ordering::Relaxed);
+      m.awareness_removed.fetch_add(removed, Ordering::Relaxed);
+    });
+  }
+
+  fn measures_timing(&self) -> bool {
+    true
+  }
+
+  fn did_transact(&self, object_id: &str, elapsed: Duration) {
+    let elapsed_us = elapsed.as_micros() as u64;
+    trace!(object_id, elapsed_us, "collab transaction");
+    self.record(object_id, |m| {
+      m.transaction_count.fetch_add(1, Ordering::Relaxed);
+      record_max(
+        &m.transaction_nanos,
+        &m.max_transaction_nanos,
+        elapsed.as_nanos() as u64,
+      );
+    });
+  }
+
+  fn did_acquire_lock(&self, object_id: &str, waited: Duration) {
+    let waited_us = waited.as_micros() as u64;
+    trace!(object_id, waited_us, "collab lock");
+    self.record(object_id, |m| {
+      m.lock_count.fetch_add(1, Ordering::Relaxed);
+      record_max(
+        &m.lock_wait_nanos,
+        &m.max_lock_wait_nanos,
+        waited.as_nanos() as u64,
+      );
+    });
+  }
+
+  fn flush(&self, object_id: &str, doc: &Doc) {
+    self.record_encoded_state_size(object_id, &doc.transact());
+  }
+}
@@ -0,0 +1,235 @@
+use collab_entity::CollabType;
+use std::fs::File;
+use std::io::BufWriter;
+
+use collab_plugins::local_storage::kv::backup::{
+  backup_collab_store, restore_collab_backup, BackupEntry, BackupOptions, BackupReader,
+  CollabBackup,
+};
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::snapshot::SnapshotAction;
+use collab_plugins::local_storage::kv::KVTransactionDB;
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+use tempfile::TempDir;
+use yrs::Doc;
+
+use crate::disk::util::{create_doc, load_text, memory_db, push_text};
+
+const UID: i64 = 1;
+
+fn backup(db: &KVTransactionDBMemoryImpl, options: BackupOptions) -> (CollabBackup, Vec<u8>) {
+  let mut data = vec![];
+  let backup = backup_collab_store(&db.read_txn(), UID, options, &mut data, |_| {}).unwrap();
+  (backup, data)
+}
+
+fn entries(data: &[u8]) -> Vec<BackupEntry> {
+  BackupReader::new(data)
+    .unwrap()
+    .collect::<Result<Vec<_>, _>>()
+    .unwrap()
+}
+
+fn restore(db: &KVTransactionDBMemoryImpl, data: &[u8]) {
+  db.with_write_txn(|store| restore_collab_backup(store, UID, data, |_| {}))
+    .unwrap();
+}
+
+#[tokio::test]
+async fn backup_and_restore_into_empty_store_test() {
+  let db = memory_db();
+  let doc = create_doc(&db, UID, "1", "hello", &[]);
+  push_text(&db, UID, &doc, "1", " world");
+  create_doc(&db, UID, "2", "folder", &[]);
+  create_doc(&db, 2, "3", "another user", &[]);
+  db.with_write_txn(|store| store.create_snapshot_with_data(UID, "1", vec![1, 2, 3]))
+    .unwrap();
+
+  let mut progress = vec![];
+  let options = BackupOptions::new()
+    .with_snapshots(true)
+    .with_collab_type(|object_id| (object_id == "2").then_some(CollabType::Folder));
+  let backup_path = TempDir::new().unwrap().into_path().join("backup.bin");
+  let file = BufWriter::new(File::create(&backup_path).unwrap());
+  let backup = backup_collab_store(&db.read_txn(), UID, options, file, |p| {
+    progress.push((p.object_id.to_string(), p.done, p.total))
+  })
+  .unwrap();
+  assert_eq!(
+    progress,
+    vec![("1".to_string(), 1, Some(2)), ("2".to_string(), 2, Some(2))]
+  );
+  assert!(!backup.is_incremental());
+  assert_eq!(backup.footer.objects.len(), 2);
+
+  let mut reader = BackupReader::open(&backup_path).unwrap();
+  assert_eq!(reader.header().uid, UID);
+  let entry = reader.next_entry().unwrap().unwrap();
+  assert_eq!(entry.object_id, "1");
+  assert_eq!(entry.snapshots.len(), 1);
+  let entry = reader.next_entry().unwrap().unwrap();
+  assert_eq!(entry.collab_type, Some(CollabType::Folder));
+  assert!(reader.next_entry().unwrap().is_none());
+  let opened = CollabBackup::open(&backup_path).unwrap();
+  assert_eq!(opened.footer.objects, backup.footer.objects);
+
+  let restored_db = memory_db();
+  let report = restored_db
+    .with_write_txn(|store| {
+      let file = File::open(&backup_path).unwrap();
+      restore_collab_backup(store, UID, file, |_| {})
+    })
+    .unwrap();
+  assert_eq!(report.created, 2);
+  assert_eq!(report.merged, 0);
+  assert_eq!(report.snapshots, 1);
+  assert_eq!(load_text(&restored_db, UID, "1"), "hello world");
+  assert_eq!(load_text(&restored_db, UID, "2"), "folder");
+  assert!(!restored_db.read_txn().is_exist(UID, "3"));
+  assert_eq!(
+    restored_db.read_txn().get_snapshots(UID, "1"),
+    db.read_txn().get_snapshots(UID, "1")
+  );
+
+  // Restoring again doesn't duplicate the snapshots.
+  let report = restored_db
+    .with_write_txn(|store| {
+      let file = File::open(&backup_path).unwrap();
+      restore_collab_backup(store, UID, file, |_| {})
+    })
+    .unwrap();
+  assert_eq!(report.merged, 2);
+  assert_eq!(report.snapshots, 0);
+  assert_eq!(load_text(&restored_db, UID, "1"), "hello world");
+}
+
+#[tokio::test]
+async fn incremental_backup_test() {
+  let db = memory_db();
+  let doc_1 = create_doc(&db, UID, "1", "hello", &[]);
+  create_doc(&db, UID, "2", "unchanged", &[]);
+  let (full, full_data) = backup(&db, BackupOptions::new());
+
+  push_text(&db, UID, &doc_1, "1", " world");
+  create_doc(&db, UID, "3", "new", &[]);
+  let (incremental, incremental_data) = backup(&db, BackupOptions::new().with_base(&full));
+  assert!(incremental.is_incremental());
+  let entries = entries(&incremental_data);
+  assert_eq!(entries.len(), 2);
+  assert_eq!(entries[0].object_id, "1");
+  assert!(entries[0].is_incremental);
+  assert_eq!(entries[1].object_id, "3");
+  assert!(!entries[1].is_incremental);
+  // The unchanged object is still tracked.
+  assert_eq!(incremental.get("2"), full.get("2"));
+
+  let restored_db = memory_db();
+  restore(&restored_db, &full_data);
+  restore(&restored_db, &incremental_data);
+  assert_eq!(load_text(&restored_db, UID, "1"), "hello world");
+  assert_eq!(load_text(&restored_db, UID, "2"), "unchanged");
+  assert_eq!(load_text(&restored_db, UID, "3"), "new");
+
+  let options = BackupOptions::new().with_base(&full);
+  let other_user = backup_collab_store(&db.read_txn(), 2, options, vec![], |_| {});
+  assert!(other_user.is_err());
+}
+
+#[tokio::test]
+async fn chained_incremental_backup_test() {
+  let db = memory_db();
+  let doc_1 = create_doc(&db, UID, "1", "hello", &[]);
+  let doc_2 = create_doc(&db, UID, "2", "folder", &[]);
+  let (full, full_data) = backup(&db, BackupOptions::new());
+
+  push_text(&db, UID, &doc_1, "1", " world");
+  let (first, first_data) = backup(&db, BackupOptions::new().with_base(&full));
+  assert_eq!(entries(&first_data).len(), 1);
+
+  // The object that is not in the first incremental backup is still left out when unchanged,
+  // and only its changes are exported when it changes.
+  let (_, unchanged_data) = backup(&db, BackupOptions::new().with_base(&first));
+  assert!(entries(&unchanged_data).is_empty());
+  push_text(&db, UID, &doc_2, "2", " view");
+  let (_, second_data) = backup(&db, BackupOptions::new().with_base(&first));
+  let second_entries = entries(&second_data);
+  assert_eq!(second_entries.len(), 1);
+  assert_eq!(second_entries[0].object_id, "2");
+  assert!(second_entries[0].is_incremental);
+
+  let restored_db = memory_db();
+  for data in [&full_data, &first_data, &second_data] {
+    restore(&restored_db, data);
+  }
+  assert_eq!(load_text(&restored_db, UID, "1"), "hello world");
+  assert_eq!(load_text(&restored_db, UID, "2"), "folder view");
+}
+
+#[tokio::test]
+async fn restore_incremental_backup_without_base_test() {
+  let db = memory_db();
+  let doc_1 = create_doc(&db, UID, "1", "hello", &[]);
+  let (full, _) = backup(&db, BackupOptions::new());
+  push_text(&db, UID, &doc_1, "1", " world");
+  create_doc(&db, UID, "2", "new", &[]);
+  let (_, incremental_data) = backup(&db, BackupOptions::new().with_base(&full));
+
+  let restored_db = memory_db();
+  let mut progress = vec![];
+  let report = restored_db
+    .with_write_txn(|store| {
+      restore_collab_backup(store, UID, incremental_data.as_slice(), |p| {
+        progress.push(p.object_id.to_string())
+      })
+    })
+    .unwrap();
+  assert_eq!(report.missing_base, vec!["1".to_string()]);
+  assert_eq!(report.created, 1);
+  assert_eq!(progress, vec!["1".to_string(), "2".to_string()]);
+  assert!(!restored_db.read_txn().is_exist(UID, "1"));
+  assert_eq!(load_text(&restored_db, UID, "2"), "new");
+}
+
+#[tokio::test]
+async fn restore_merges_with_existing_store_test() {
+  let db = memory_db();
+  let doc = create_doc(&db, UID, "1", "hello", &[]);
+  let (_, data) = backup(&db, BackupOptions::new());
+
+  // Both the store and the backup change after the backup was taken.
+  let other_db = memory_db();
+  restore(&other_db, &data);
+  let other_doc = Doc::new();
+  other_db
+    .read_txn()
+    .load_doc(UID, "1", other_doc.clone())
+    .unwrap();
+  push_text(&other_db, UID, &other_doc, "1", " from backup");
+  push_text(&db, UID, &doc, "1", " from store");
+  let (_, data) = backup(&other_db, BackupOptions::new());
+
+  let report = db
+    .with_write_txn(|store| restore_collab_backup(store, UID, data.as_slice(), |_| {}))
+    .unwrap();
+  assert_eq!(report.merged, 1);
+  let text = load_text(&db, UID, "1");
+  assert!(text.starts_with("hello"));
+  assert!(text.contains(" from backup"));
+  assert!(text.contains(" from store"));
+}
+
+#[test]
+fn reject_invalid_backup_file_test() {
+  assert!(CollabBackup::read_from(&b"not a backup"[..]).is_err());
+
+  let mut data = b"COLLABBK".to_vec();
+  data.extend_from_slice(&u16::MAX.to_be_bytes());
+  assert!(CollabBackup::read_from(data.as_slice()).is_err());
+
+  // A backup cut in the middle of its entries.
+  let db = memory_db();
+  create_doc(&db, UID, "1", "hello", &[]);
+  let (_, data) = backup(&db, BackupOptions::new());
+  let truncated = &data[..data.len() - 20];
+  assert!(CollabBackup::read_from(truncated).is_err());
+}
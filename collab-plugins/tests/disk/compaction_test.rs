@@ -0,0 +1,223 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use collab::core::collab::MutexCollab;
+use collab::preclude::CollabBuilder;
+use collab_entity::CollabType;
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+#[cfg(feature = "rocksdb_storage")]
+use collab_plugins::local_storage::kv::metadata::CollabMetadataAction;
+use collab_plugins::local_storage::kv::KVTransactionDB;
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+use collab_plugins::local_storage::rocksdb::rocksdb_plugin::RocksdbDiskPlugin;
+use collab_plugins::local_storage::{CollabPersistenceConfig, CompactionPolicy};
+use serde_json::{json, Map, Value};
+use yrs::{Doc, GetString, Text, Transact};
+
+use crate::disk::util::memory_db;
+#[cfg(feature = "rocksdb_storage")]
+use crate::disk::util::{create_doc, load_text, push_text, rocks_db};
+
+fn open_collab(
+  db: &Arc<KVTransactionDBMemoryImpl>,
+  object_id: &str,
+  compaction: CompactionPolicy,
+) -> MutexCollab {
+  let config = CollabPersistenceConfig::new()
+    .snapshot_per_update(1000)
+    .compaction(compaction);
+  let plugin = RocksdbDiskPlugin::new_with_config(
+    1,
+    object_id.to_string(),
+    CollabType::Document,
+    Arc::downgrade(db),
+    config,
+    None,
+  );
+  let collab = CollabBuilder::new(1, object_id)
+    .with_device_id("1")
+    .with_plugin(plugin)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  collab
+}
+
+/// Waits for the background compaction to leave at most `max` updates in the log.
+async fn wait_for_updates(db: &KVTransactionDBMemoryImpl, object_id: &str, max: usize) -> usize {
+  let mut updates = 0;
+  for _ in 0..50 {
+    updates = db.read_txn().number_of_updates(1, object_id);
+    if updates <= max {
+      break;
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+  }
+  updates
+}
+
+fn expected_json(count: usize) -> Value {
+  let map = (0..count)
+    .map(|i| (i.to_string(), json!(i.to_string())))
+    .collect::<Map<_, _>>();
+  Value::Object(map)
+}
+
+#[tokio::test]
+async fn compact_after_max_updates_test() {
+  let db = Arc::new(memory_db());
+  let collab = open_collab(&db, "1", CompactionPolicy::new().max_updates(10));
+  for i in 0..25 {
+    collab.lock().insert(&i.to_string(), i.to_string());
+  }
+  assert!(wait_for_updates(&db, "1", 9).await <= 9);
+  drop(collab);
+
+  let collab = open_collab(&db, "1", CompactionPolicy::default());
+  assert_eq!(collab.to_json_value(), expected_json(25));
+}
+
+#[tokio::test]
+async fn compact_after_max_update_bytes_test() {
+  let db = Arc::new(memory_db());
+  let collab = open_collab(&db, "1", CompactionPolicy::new().max_update_bytes(1024));
+  for i in 0..10 {
+    collab.lock().insert(&i.to_string(), "a".repeat(200));
+  }
+  assert!(wait_for_updates(&db, "1", 5).await <= 5);
+}
+
+#[tokio::test]
+async fn compact_when_idle_test() {
+  let db = Arc::new(memory_db());
+  let policy = CompactionPolicy::new().idle_timeout(Duration::from_millis(100));
+  let collab = open_collab(&db, "1", policy);
+  for i in 0..5 {
+    collab.lock().insert(&i.to_string(), i.to_string());
+  }
+  assert_eq!(db.read_txn().number_of_updates(1, "1"), 5);
+  assert_eq!(wait_for_updates(&db, "1", 0).await, 0);
+
+  drop(collab);
+  let collab = open_collab(&db, "1", CompactionPolicy::default());
+  assert_eq!(collab.to_json_value(), expected_json(5));
+}
+
+#[test]
+fn compact_when_idle_without_runtime_test() {
+  let db = Arc::new(memory_db());
+  let policy = CompactionPolicy::new().idle_timeout(Duration::from_millis(100));
+  let collab = open_collab(&db, "1", policy);
+  for i in 0..5 {
+    collab.lock().insert(&i.to_string(), i.to_string());
+  }
+  let mut updates = db.read_txn().number_of_updates(1, "1");
+  for _ in 0..50 {
+    if updates == 0 {
+      break;
+    }
+    std::thread::sleep(Duration::from_millis(50));
+    updates = db.read_txn().number_of_updates(1, "1");
+  }
+  assert_eq!(updates, 0);
+}
+
+#[test]
+#[should_panic(expected = "max_updates must be greater than 0")]
+fn reject_zero_max_updates_test() {
+  let _ = CompactionPolicy::new().max_updates(0);
+}
+
+#[test]
+#[should_panic(expected = "max_update_bytes must be greater than 0")]
+fn reject_zero_max_update_bytes_test() {
+  let _ = CompactionPolicy::new().max_update_bytes(0);
+}
+
+#[test]
+fn zero_limit_fields_are_ignored_test() {
+  let policy = CompactionPolicy {
+    max_updates: Some(0),
+    max_update_bytes: Some(0),
+    idle_timeout: None,
+  };
+  assert!(!policy.should_compact(0, 0));
+  assert!(!policy.should_compact(100, 100));
+}
+
+#[tokio::test]
+async fn disabled_compaction_keeps_updates_test() {
+  let db = Arc::new(memory_db());
+  let collab = open_collab(&db, "1", CompactionPolicy::default());
+  for i in 0..20 {
+    collab.lock().insert(&i.to_string(), i.to_string());
+  }
+  tokio::time::sleep(Duration::from_millis(100)).await;
+  assert_eq!(db.read_txn().number_of_updates(1, "1"), 20);
+}
+
+#[tokio::test]
+async fn compact_doc_updates_test() {
+  let db = memory_db();
+  let doc = Doc::new();
+  let text = doc.get_or_insert_text("text");
+  db.with_write_txn(|store| store.create_new_doc(1, "1", &doc.transact()))
+    .unwrap();
+  let push = |content: &str| {
+    let update = {
+      let mut txn = doc.transact_mut();
+      text.push(&mut txn, content);
+      txn.encode_update_v1()
+    };
+    db.with_write_txn(|store| store.push_update(1, "1", &update))
+      .unwrap();
+  };
+  push("a");
+  push("b");
+
+  let compacted = db
+    .with_write_txn(|store| {
+      let count = store.compact_doc_updates(1, "1")?;
+      assert_eq!(store.number_of_updates(1, "1"), 0);
+      Ok(count)
+    })
+    .unwrap();
+  assert_eq!(compacted, 2);
+  push("c");
+  assert_eq!(db.read_txn().number_of_updates(1, "1"), 1);
+  assert_eq!(
+    db.with_write_txn(|store| store.compact_doc_updates(1, "1"))
+      .unwrap(),
+    1
+  );
+
+  let restored = Doc::new();
+  let restored_text = restored.get_or_insert_text("text");
+  db.read_txn().load_doc(1, "1", restored.clone()).unwrap();
+  let txn = restored.transact();
+  assert_eq!(restored_text.get_string(&txn), "abc");
+}
+
+/// The update is pushed while the compaction of the same document holds its locks, like when the
+/// disk plugin receives an update during a background compaction.
+#[cfg(feature = "rocksdb_storage")]
+#[tokio::test]
+async fn push_update_during_compaction_test() {
+  let (_, db) = rocks_db();
+  let doc = create_doc(&db, 1, "1", "a", &["b", "c"]);
+  let compacted = db
+    .with_write_txn(|store| {
+      let count = store.compact_doc_updates(1, "1")?;
+      push_text(&db, 1, &doc, "1", "d");
+      Ok(count)
+    })
+    .unwrap();
+  assert_eq!(compacted, 2);
+
+  let txn = db.read_txn();
+  assert_eq!(txn.number_of_updates(1, "1"), 1);
+  let metadata = txn.get_collab_metadata(1, "1").unwrap().unwrap();
+  assert_eq!(metadata.update_count, 1);
+  drop(txn);
+  assert_eq!(load_text(&db, 1, "1"), "abcd");
+}
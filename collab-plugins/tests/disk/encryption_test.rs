@@ -0,0 +1,278 @@
+use std::path::Path;
+use std::sync::Arc;
+
+use collab::core::collab::IndexContent;
+use collab::core::collab_search::{CollabSearch, SearchQuery};
+use collab::preclude::CollabBuilder;
+use collab_entity::CollabType;
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::encryption::{
+  encrypted_key_id, EncryptionConfig, EncryptionKey,
+};
+use collab_plugins::local_storage::kv::keys::{
+  make_doc_id_key, DOC_ID_LEN, DOC_SPACE, DOC_SPACE_OBJECT_KEY, DOC_UPDATE, DOC_UPDATE_KEY_LEN,
+};
+use collab_plugins::local_storage::kv::search::SearchIndexAction;
+use collab_plugins::local_storage::kv::snapshot::SnapshotAction;
+use collab_plugins::local_storage::kv::user::UserDataAction;
+use collab_plugins::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+use collab_plugins::CollabKVDB;
+use serde_json::json;
+use tempfile::TempDir;
+use yrs::Doc;
+
+use crate::disk::script::disk_plugin_with_db;
+use crate::disk::util::{create_doc, load_text};
+
+fn key(id: u32) -> EncryptionKey {
+  EncryptionKey::new(id, [id as u8; 32])
+}
+
+fn encrypted_db(path: &Path, config: EncryptionConfig) -> CollabKVDB {
+  CollabKVDB::open_with_encryption(path, config).unwrap()
+}
+
+#[tokio::test]
+async fn encrypted_values_round_trip_test() {
+  let uid = 1;
+  let path = TempDir::new().unwrap().into_path();
+  let db = encrypted_db(&path, EncryptionConfig::new(key(1)));
+  create_doc(&db, uid, "1", "hello world", &[]);
+  assert!(db.read_txn().is_exist(uid, "1"));
+  assert_eq!(load_text(&db, uid, "1"), "hello world");
+
+  // The stored values are encrypted.
+  let txn = db.read_txn();
+  let entries = txn.inner().range([1]..[2]).unwrap().collect::<Vec<_>>();
+  assert!(!entries.is_empty());
+  for entry in entries {
+    assert_eq!(encrypted_key_id(entry.value()), Some(1));
+    assert!(!entry
+      .value()
+      .windows(b"hello".len())
+      .any(|window| window == b"hello"));
+  }
+  drop(txn);
+
+  db.with_write_txn(|store| store.create_snapshot_with_data(uid, "1", vec![1, 2, 3]))
+    .unwrap();
+  assert_eq!(
+    db.read_txn().get_last_snapshot(uid, "1").unwrap().data,
+    vec![1, 2, 3]
+  );
+}
+
+#[tokio::test]
+async fn open_with_wrong_key_test() {
+  let path = TempDir::new().unwrap().into_path();
+  let db = encrypted_db(&path, EncryptionConfig::new(key(1)));
+  create_doc(&db, 1, "1", "hello", &[]);
+  drop(db);
+
+  assert!(CollabKVDB::open(&path).is_err());
+  assert!(CollabKVDB::open_with_encryption(&path, EncryptionConfig::new(key(2))).is_err());
+  let wrong_bytes = EncryptionKey::new(1, [9; 32]);
+  assert!(CollabKVDB::open_with_encryption(&path, EncryptionConfig::new(wrong_bytes)).is_err());
+
+  let db = encrypted_db(&path, EncryptionConfig::new(key(1)));
+  assert_eq!(load_text(&db, 1, "1"), "hello");
+}
+
+#[tokio::test]
+async fn undecryptable_update_fails_load_and_compaction_test() {
+  let uid = 1;
+  let path = TempDir::new().unwrap().into_path();
+  let db = encrypted_db(&path, EncryptionConfig::new(key(1)));
+  create_doc(&db, uid, "1", "hello", &[" world", "!"]);
+  let update_keys = db
+    .read_txn()
+    .inner()
+    .range([DOC_SPACE, DOC_SPACE_OBJECT_KEY]..[DOC_SPACE, DOC_SPACE_OBJECT_KEY + 1])
+    .unwrap()
+    .map(|entry| entry.key().to_vec())
+    .filter(|key| key.len() == DOC_UPDATE_KEY_LEN && key[2 + DOC_ID_LEN] == DOC_UPDATE)
+    .collect::<Vec<_>>();
+  assert_eq!(update_keys.len(), 2);
+
+  // Tamper with the ciphertext of the first update.
+  db.with_write_txn(|store| {
+    let mut value = store.inner().get(&update_keys[0])?.unwrap();
+    *value.last_mut().unwrap() ^= 1;
+    store.inner().insert(&update_keys[0], value)?;
+    Ok(())
+  })
+  .unwrap();
+
+  let result = db.read_txn().load_doc(uid, "1", Doc::new());
+  assert!(matches!(result, Err(PersistenceError::Encryption(_))));
+  let result = db.with_write_txn(|store| store.compact_doc_updates(uid, "1"));
+  assert!(matches!(result, Err(PersistenceError::Encryption(_))));
+  // The updates that couldn't be read are still there.
+  let txn = db.read_txn();
+  for key in &update_keys {
+    assert!(txn.inner().get(key).unwrap().is_some());
+  }
+}
+
+#[tokio::test]
+async fn unencrypted_db_is_not_encrypted_afterwards_test() {
+  let path = TempDir::new().unwrap().into_path();
+  let db = CollabKVDB::open(&path).unwrap();
+  create_doc(&db, 1, "1", "hello", &[]);
+  drop(db);
+  assert!(CollabKVDB::open_with_encryption(&path, EncryptionConfig::new(key(1))).is_err());
+}
+
+#[tokio::test]
+async fn encrypted_db_with_disk_plugin_test() {
+  let uid = 1;
+  let path = TempDir::new().unwrap().into_path();
+  let db = Arc::new(encrypted_db(&path, EncryptionConfig::new(key(1))));
+  let collab = CollabBuilder::new(uid, "1")
+    .with_device_id("1")
+    .with_plugin(disk_plugin_with_db(
+      uid,
+      db.clone(),
+      "1",
+      CollabType::Document,
+    ))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  collab.lock().insert("name", "appflowy");
+  drop(collab);
+  drop(db);
+
+  let db = Arc::new(encrypted_db(&path, EncryptionConfig::new(key(1))));
+  let collab = CollabBuilder::new(uid, "1")
+    .with_device_id("1")
+    .with_plugin(disk_plugin_with_db(
+      uid,
+      db.clone(),
+      "1",
+      CollabType::Document,
+    ))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  assert_eq!(collab.to_json_value(), json!({ "name": "appflowy" }));
+}
+
+#[tokio::test]
+async fn rotate_encryption_key_test() {
+  let uid = 1;
+  let path = TempDir::new().unwrap().into_path();
+  let db = encrypted_db(&path, EncryptionConfig::new(key(1)));
+  for i in 0..10 {
+    create_doc(&db, uid, &i.to_string(), &format!("doc {}", i), &[]);
+  }
+
+  let rotated = db
+    .rotate_encryption_key(key(2))
+    .unwrap()
+    .join()
+    .unwrap()
+    .unwrap();
+  // The documents, their indexes and the key check.
+  assert!(rotated > 10);
+  let txn = db.read_txn();
+  for entry in txn.inner().range([0]..[255]).unwrap() {
+    assert_eq!(encrypted_key_id(entry.value()), Some(2));
+  }
+  drop(txn);
+  // Resuming a finished rotation has nothing to do.
+  assert_eq!(
+    db.rotate_encryption_key(key(2))
+      .unwrap()
+      .join()
+      .unwrap()
+      .unwrap(),
+    0
+  );
+  drop(db);
+
+  // The previous key is not needed anymore.
+  let db = encrypted_db(&path, EncryptionConfig::new(key(2)));
+  for i in 0..10 {
+    assert_eq!(load_text(&db, uid, &i.to_string()), format!("doc {}", i));
+  }
+}
+
+#[tokio::test]
+async fn hashed_object_ids_test() {
+  let uid = 1;
+  let path = TempDir::new().unwrap().into_path();
+  let config = EncryptionConfig::new(key(1)).with_hashed_object_ids([7; 32]);
+  let db = encrypted_db(&path, config);
+  create_doc(&db, uid, "my_secret_doc", "hello", &[]);
+  db.with_write_txn(|store| store.create_snapshot_with_data(uid, "my_secret_doc", vec![1, 2, 3]))
+    .unwrap();
+
+  let txn = db.read_txn();
+  assert!(txn.is_exist(uid, "my_secret_doc"));
+  // The object ids are restored from the values of the index.
+  assert_eq!(
+    txn.get_all_docs().unwrap().collect::<Vec<_>>(),
+    vec!["my_secret_doc".to_string()]
+  );
+  assert_eq!(
+    txn.list_user_objects(uid).unwrap()[0].object_id,
+    "my_secret_doc"
+  );
+  assert_eq!(txn.get_snapshots(uid, "my_secret_doc").len(), 1);
+  assert!(txn
+    .inner()
+    .get(make_doc_id_key(&uid.to_be_bytes(), b"my_secret_doc"))
+    .unwrap()
+    .is_none());
+  for entry in txn.inner().range([0]..[255]).unwrap() {
+    assert!(!entry
+      .key()
+      .windows(b"my_secret_doc".len())
+      .any(|window| window == b"my_secret_doc"));
+  }
+  drop(txn);
+  assert_eq!(load_text(&db, uid, "my_secret_doc"), "hello");
+
+  db.with_write_txn(|store| store.delete_doc(uid, "my_secret_doc"))
+    .unwrap();
+  assert!(!db.read_txn().is_exist(uid, "my_secret_doc"));
+  drop(db);
+
+  // The object ids must be hashed with the same key.
+  let config = EncryptionConfig::new(key(1)).with_hashed_object_ids([8; 32]);
+  assert!(CollabKVDB::open_with_encryption(&path, config).is_err());
+  assert!(CollabKVDB::open_with_encryption(&path, EncryptionConfig::new(key(1))).is_err());
+}
+
+#[tokio::test]
+async fn encrypted_search_index_test() {
+  let uid = 1;
+  let path = TempDir::new().unwrap().into_path();
+  let config = EncryptionConfig::new(key(1)).with_hashed_object_ids([7; 32]);
+  let db = encrypted_db(&path, config);
+  let search = CollabSearch::new();
+  search.apply(&IndexContent::Create(json!({
+    "id": "my_secret_doc",
+    "name": "Zanzibar itinerary",
+  })));
+  db.with_write_txn(|txn| txn.save_search_index_if_dirty(uid, &search))
+    .unwrap();
+
+  // Neither the words nor the id of the document can be read from the disk.
+  let txn = db.read_txn();
+  for entry in txn.inner().range([0]..[255]).unwrap() {
+    for word in [
+      &b"zanzibar"[..],
+      b"Zanzibar",
+      b"itinerary",
+      b"my_secret_doc",
+    ] {
+      assert!(!entry.key().windows(word.len()).any(|w| w == word));
+      assert!(!entry.value().windows(word.len()).any(|w| w == word));
+    }
+  }
+  let index = txn.load_search_index(uid).unwrap().unwrap();
+  let results = index.search(&SearchQuery::new("zanzibar"));
+  assert_eq!(results[0].id, "my_secret_doc");
+}
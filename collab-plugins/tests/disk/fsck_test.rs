@@ -0,0 +1,215 @@
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::fsck::{check_collab_store, FsckIssue, FsckRepair};
+use collab_plugins::local_storage::kv::keys::*;
+use collab_plugins::local_storage::kv::snapshot::SnapshotAction;
+use collab_plugins::local_storage::kv::{KVStore, KVTransactionDB};
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+
+use crate::disk::util::{create_doc, load_text, memory_db};
+
+const UID: i64 = 1;
+
+fn doc_id(db: &KVTransactionDBMemoryImpl, object_id: &str) -> DocID {
+  let key = make_doc_id_key(&UID.to_be_bytes(), object_id.as_bytes());
+  let value = db.read_txn().get(key.as_ref()).unwrap().unwrap();
+  DocID::from_be_bytes(value.try_into().unwrap())
+}
+
+#[tokio::test]
+async fn healthy_store_test() {
+  let db = memory_db();
+  create_doc(&db, UID, "1", "", &["hello", " world"]);
+  create_doc(&db, UID, "2", "", &[]);
+  db.with_write_txn(|store| store.create_snapshot_with_data(UID, "1", vec![1, 2, 3]))
+    .unwrap();
+
+  let report = check_collab_store(&db.read_txn(), FsckRepair::None).unwrap();
+  assert!(report.is_healthy(), "{:?}", report);
+  assert_eq!(report.objects.len(), 2);
+  let object = report
+    .objects
+    .iter()
+    .find(|object| object.object_id == "1")
+    .unwrap();
+  assert_eq!(object.uid, UID);
+  assert_eq!(object.update_count, 2);
+}
+
+#[tokio::test]
+async fn quarantine_corrupt_update_test() {
+  let db = memory_db();
+  create_doc(&db, UID, "1", "", &["hello", " world"]);
+  let update_key = make_doc_update_key(doc_id(&db, "1"), 100);
+  db.with_write_txn(|store| store.insert(update_key.as_ref(), [255, 255, 255]))
+    .unwrap();
+
+  let report = check_collab_store(&db.read_txn(), FsckRepair::None).unwrap();
+  let broken = report.broken_objects().collect::<Vec<_>>();
+  assert_eq!(broken.len(), 1);
+  assert!(matches!(
+    &broken[0].issues[..],
+    [FsckIssue::CorruptUpdate { key, .. }] if key == update_key.as_ref()
+  ));
+
+  let report = db
+    .with_write_txn(|store| check_collab_store(store, FsckRepair::Quarantine))
+    .unwrap();
+  assert_eq!(report.repaired, 1);
+  let txn = db.read_txn();
+  assert!(txn.get(update_key.as_ref()).unwrap().is_none());
+  assert_eq!(
+    txn
+      .get(make_quarantine_key(update_key.as_ref()).as_ref())
+      .unwrap()
+      .unwrap(),
+    vec![255, 255, 255]
+  );
+  drop(txn);
+
+  assert!(check_collab_store(&db.read_txn(), FsckRepair::None)
+    .unwrap()
+    .is_healthy());
+  assert_eq!(load_text(&db, UID, "1"), "hello world");
+}
+
+#[tokio::test]
+async fn rewrite_state_vector_test() {
+  let db = memory_db();
+  create_doc(&db, UID, "1", "", &["hello"]);
+  create_doc(&db, UID, "2", "", &["world"]);
+  db.with_write_txn(|store| {
+    store.remove(make_state_vector_key(doc_id(&db, "1")).as_ref())?;
+    store.insert(make_state_vector_key(doc_id(&db, "2")), [1, 1, 1])?;
+    Ok(())
+  })
+  .unwrap();
+
+  let report = db
+    .with_write_txn(|store| check_collab_store(store, FsckRepair::Delete))
+    .unwrap();
+  let mut issues = report
+    .broken_objects()
+    .map(|object| (object.object_id.clone(), object.issues.clone()))
+    .collect::<Vec<_>>();
+  issues.sort_by(|a, b| a.0.cmp(&b.0));
+  assert_eq!(
+    issues[0],
+    ("1".to_string(), vec![FsckIssue::MissingStateVector])
+  );
+  assert_eq!(issues[1].0, "2");
+  assert!(matches!(
+    issues[1].1[..],
+    [FsckIssue::StateVectorMismatch] | [FsckIssue::CorruptStateVector(_)]
+  ));
+  assert_eq!(report.repaired, 0);
+
+  assert!(check_collab_store(&db.read_txn(), FsckRepair::None)
+    .unwrap()
+    .is_healthy());
+}
+
+#[tokio::test]
+async fn delete_orphaned_keys_test() {
+  let db = memory_db();
+  create_doc(&db, UID, "1", "", &["hello"]);
+  db.with_write_txn(|store| store.create_snapshot_with_data(UID, "1", vec![1, 2, 3]))
+    .unwrap();
+
+  let orphaned_update = make_doc_update_key(u64::MAX - 1, 0);
+  let orphaned_snapshot = make_snapshot_update_key(u64::MAX - 1, 0);
+  db.with_write_txn(|store| {
+    store.insert(orphaned_update.as_ref(), [1, 2, 3])?;
+    store.create_snapshot_with_data(UID, "deleted", vec![4, 5, 6])?;
+    Ok(())
+  })
+  .unwrap();
+  db.with_write_txn(|store| store.insert(orphaned_snapshot.as_ref(), [0; 24]))
+    .unwrap();
+
+  let report = check_collab_store(&db.read_txn(), FsckRepair::None).unwrap();
+  assert!(report.broken_objects().next().is_none());
+  assert!(report.orphaned_keys.contains(&orphaned_update.to_vec()));
+  assert!(report.orphaned_keys.contains(&orphaned_snapshot.to_vec()));
+  // The snapshot id of the object without document and its snapshot.
+  assert_eq!(report.orphaned_keys.len(), 4);
+
+  db.with_write_txn(|store| check_collab_store(store, FsckRepair::Delete))
+    .unwrap();
+  assert!(check_collab_store(&db.read_txn(), FsckRepair::None)
+    .unwrap()
+    .is_healthy());
+  assert_eq!(db.read_txn().get_snapshots(UID, "1").len(), 1);
+  assert_eq!(load_text(&db, UID, "1"), "hello");
+}
+
+#[tokio::test]
+async fn delete_object_without_doc_state_test() {
+  let db = memory_db();
+  create_doc(&db, UID, "1", "", &["hello"]);
+  create_doc(&db, UID, "2", "", &["world"]);
+  db.with_write_txn(|store| store.remove(make_doc_state_key(doc_id(&db, "1")).as_ref()))
+    .unwrap();
+
+  let report = db
+    .with_write_txn(|store| check_collab_store(store, FsckRepair::Delete))
+    .unwrap();
+  let broken = report.broken_objects().collect::<Vec<_>>();
+  assert_eq!(broken.len(), 1);
+  assert_eq!(broken[0].issues, vec![FsckIssue::MissingDocState]);
+  // The object id mapping, the state vector, the update, the metadata and the last update time.
+  assert_eq!(report.repaired, 5);
+
+  let txn = db.read_txn();
+  assert!(!txn.is_exist(UID, "1"));
+  assert!(txn.is_exist(UID, "2"));
+  drop(txn);
+  assert!(check_collab_store(&db.read_txn(), FsckRepair::None)
+    .unwrap()
+    .is_healthy());
+}
+
+#[tokio::test]
+async fn quarantine_object_with_corrupt_doc_state_test() {
+  let db = memory_db();
+  create_doc(&db, UID, "1", "", &["hello"]);
+  create_doc(&db, UID, "2", "", &["world"]);
+  db.with_write_txn(|store| store.create_snapshot_with_data(UID, "1", vec![1, 2, 3]))
+    .unwrap();
+  let doc_id = doc_id(&db, "1");
+  let doc_state_key = make_doc_state_key(doc_id);
+  db.with_write_txn(|store| store.insert(doc_state_key.as_ref(), [255, 255, 255]))
+    .unwrap();
+
+  let report = db
+    .with_write_txn(|store| check_collab_store(store, FsckRepair::Quarantine))
+    .unwrap();
+  let broken = report.broken_objects().collect::<Vec<_>>();
+  assert_eq!(broken.len(), 1);
+  assert!(matches!(
+    broken[0].issues[..],
+    [FsckIssue::CorruptDocState(_)]
+  ));
+  // The object id mapping, the document state, the state vector, the update, the metadata, the
+  // last update time, the snapshot id and the snapshot.
+  assert_eq!(report.repaired, 8);
+
+  let txn = db.read_txn();
+  assert!(!txn.is_exist(UID, "1"));
+  assert!(txn.get_snapshots(UID, "1").is_empty());
+  assert_eq!(
+    txn
+      .get(make_quarantine_key(doc_state_key.as_ref()).as_ref())
+      .unwrap()
+      .unwrap(),
+    vec![255, 255, 255]
+  );
+  assert!(txn
+    .get(make_quarantine_key(make_state_vector_key(doc_id).as_ref()).as_ref())
+    .unwrap()
+    .is_some());
+  drop(txn);
+  assert!(check_collab_store(&db.read_txn(), FsckRepair::None)
+    .unwrap()
+    .is_healthy());
+  assert_eq!(load_text(&db, UID, "2"), "world");
+}
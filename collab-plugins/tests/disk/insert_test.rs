@@ -5,7 +5,9 @@ use collab::preclude::CollabBuilder;
 use collab_entity::CollabType;
 use collab_plugins::local_storage::kv::doc::CollabKVAction;
 use collab_plugins::local_storage::kv::KVTransactionDB;
+use collab_plugins::local_storage::rocksdb::rocksdb_plugin::RocksdbDiskPlugin;
 use collab_plugins::local_storage::CollabPersistenceConfig;
+use serde_json::json;
 use std::sync::Arc;
 
 #[tokio::test]
@@ -57,16 +59,65 @@ async fn flush_test() {
   }
   let before_flush_value = collab.to_json_value();
 
-  let read = test.db.read_txn();
-  let before_flush_updates = read.get_all_updates(test.uid, &doc_id).unwrap();
+  let before_flush_updates = test
+    .db
+    .read_txn()
+    .get_all_updates(test.uid, &doc_id)
+    .unwrap();
   collab.lock().flush();
-  let after_flush_updates = read.get_all_updates(test.uid, &doc_id).unwrap();
+  let after_flush_updates = test
+    .db
+    .read_txn()
+    .get_all_updates(test.uid, &doc_id)
+    .unwrap();
   let after_flush_value = collab.to_json_value();
   assert_eq!(before_flush_updates.len(), 100);
   assert_eq!(after_flush_updates.len(), 0);
   assert_json_eq!(before_flush_value, after_flush_value);
 }
 
+#[tokio::test]
+async fn async_write_test() {
+  let doc_id = "1".to_string();
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  let disk_plugin = RocksdbDiskPlugin::new_with_config(
+    test.uid,
+    doc_id.clone(),
+    CollabType::Document,
+    Arc::downgrade(&test.db),
+    CollabPersistenceConfig::new().async_write(true),
+    None,
+  );
+  let collab = CollabBuilder::new(1, &doc_id)
+    .with_device_id("1")
+    .with_plugin(disk_plugin)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  for i in 0..100 {
+    collab.lock().insert(&i.to_string(), i.to_string());
+  }
+  // The writes wait for the plugin pipeline.
+  assert_eq!(test.db.read_txn().number_of_updates(test.uid, &doc_id), 0);
+
+  let flush = collab.lock().flush();
+  flush.await;
+  assert_eq!(test.db.read_txn().number_of_updates(test.uid, &doc_id), 0);
+
+  let restored = CollabBuilder::new(1, &doc_id)
+    .with_device_id("1")
+    .with_plugin(*disk_plugin_with_db(
+      test.uid,
+      test.db.clone(),
+      &doc_id,
+      CollabType::Document,
+    ))
+    .build()
+    .unwrap();
+  restored.lock().initialize();
+  assert_json_eq!(collab.to_json_value(), restored.to_json_value());
+}
+
 #[tokio::test]
 async fn insert_multiple_changes_and_restore_from_disk() {
   let mut test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
@@ -166,3 +217,36 @@ async fn insert_multiple_docs() {
     ])
     .await;
 }
+
+#[tokio::test]
+async fn subdoc_is_persisted_by_the_parent_plugin_test() {
+  let test = CollabPersistenceTest::new(CollabPersistenceConfig::new());
+  let open_page = || {
+    let collab = CollabBuilder::new(1, "page")
+      .with_device_id("1")
+      .with_plugin(*disk_plugin_with_db(
+        test.uid,
+        test.db.clone(),
+        "page",
+        CollabType::Document,
+      ))
+      .build()
+      .unwrap();
+    collab.lock().initialize();
+    collab
+  };
+
+  {
+    let page = open_page();
+    let child = page.lock().create_subdoc("row_document").unwrap();
+    child.lock().insert("text", "hello");
+  }
+  let read_txn = test.db.read_txn();
+  assert!(read_txn.is_exist(test.uid, "row_document"));
+  assert_eq!(read_txn.number_of_updates(test.uid, "row_document"), 1);
+  drop(read_txn);
+
+  let page = open_page();
+  let child = page.lock().load_subdoc("row_document").unwrap();
+  assert_json_eq!(child.to_json_value(), json!({ "text": "hello" }));
+}
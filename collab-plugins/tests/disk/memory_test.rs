@@ -0,0 +1,184 @@
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::snapshot::SnapshotAction;
+use collab_plugins::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+use yrs::{Doc, GetString, Text, Transact};
+
+#[tokio::test]
+async fn memory_range_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  db.with_write_txn(|store| {
+    store.insert([0, 0, 0, 0, 0, 0, 0, 0], [0, 1, 1]).unwrap();
+    store.insert([0, 0, 0, 0, 0, 0, 0, 1], [0, 1, 2]).unwrap();
+    store.insert([0, 0, 0, 0, 0, 0, 0, 2], [0, 1, 3]).unwrap();
+    store.insert([0, 0, 0, 0, 0, 0, 0, 3], [0, 1, 4]).unwrap();
+    store.insert([0, 1, 0, 0, 0, 0, 0, 4], [0, 1, 5]).unwrap();
+    Ok(())
+  })
+  .unwrap();
+
+  let txn = db.read_txn();
+  let entry = txn
+    .next_back_entry(&[0, 0, 0, 0, 0, 0, 0, 1])
+    .unwrap()
+    .unwrap();
+  assert_eq!(entry.value(), &[0, 1, 2]);
+  let entry = txn.next_back_entry(&[0, 1]).unwrap().unwrap();
+  assert_eq!(entry.value(), &[0, 1, 4]);
+  assert!(txn.next_back_entry(&[0]).unwrap().is_none());
+
+  // The end key is exclusive, even for an inclusive range
+  let values = txn
+    .range([0, 0, 0, 0, 0, 0, 0, 0]..=[0, 0, 0, 0, 0, 0, 0, 2])
+    .unwrap()
+    .map(|entry| entry.value().to_vec())
+    .collect::<Vec<_>>();
+  assert_eq!(values, vec![vec![0, 1, 1], vec![0, 1, 2]]);
+
+  db.with_write_txn(|store| {
+    store.remove_range(&[0, 0, 0, 0, 0, 0, 0, 0], &[0, 0, 0, 0, 0, 0, 0, 2])?;
+    Ok(())
+  })
+  .unwrap();
+  let txn = db.read_txn();
+  assert!(txn.get([0, 0, 0, 0, 0, 0, 0, 1]).unwrap().is_none());
+  assert_eq!(
+    txn.get([0, 0, 0, 0, 0, 0, 0, 2]).unwrap().unwrap(),
+    vec![0, 1, 3]
+  );
+  assert_eq!(db.len(), 3);
+}
+
+#[tokio::test]
+async fn memory_txn_sees_own_writes_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  db.with_write_txn(|store| {
+    store.insert([1], [1])?;
+    store.insert([3], [3])?;
+    Ok(())
+  })
+  .unwrap();
+
+  db.with_write_txn(|store| {
+    store.insert([2], [2])?;
+    store.remove(&[3])?;
+    assert_eq!(store.get([2])?, Some(vec![2]));
+    assert!(store.get([3])?.is_none());
+    assert_eq!(store.next_back_entry(&[9])?.unwrap().value(), &[2]);
+    let keys = store
+      .range([0]..[9])?
+      .map(|entry| entry.key().to_vec())
+      .collect::<Vec<_>>();
+    assert_eq!(keys, vec![vec![1], vec![2]]);
+    Ok(())
+  })
+  .unwrap();
+  assert_eq!(db.len(), 2);
+}
+
+#[tokio::test]
+async fn memory_rollback_on_error_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  let result: Result<(), PersistenceError> = db.with_write_txn(|store| {
+    store.insert([1], [1])?;
+    Err(PersistenceError::InvalidData("abort".to_string()))
+  });
+  assert!(result.is_err());
+  assert!(db.is_empty());
+  assert!(db.read_txn().get([1]).unwrap().is_none());
+
+  // The writes of a read transaction are only applied when it is committed.
+  let txn = db.read_txn();
+  txn.insert([2], [2]).unwrap();
+  drop(txn);
+  assert!(db.is_empty());
+  let txn = db.read_txn();
+  txn.insert([2], [2]).unwrap();
+  txn.commit_transaction().unwrap();
+  assert_eq!(db.len(), 1);
+}
+
+#[tokio::test]
+async fn memory_snapshot_read_test() {
+  let db = KVTransactionDBMemoryImpl::new();
+  db.with_write_txn(|store| store.insert([1], [1])).unwrap();
+
+  let old_txn = db.read_txn();
+  db.with_write_txn(|store| {
+    store.insert([1], [2])?;
+    store.insert([2], [2])?;
+    Ok(())
+  })
+  .unwrap();
+
+  assert_eq!(old_txn.get([1]).unwrap(), Some(vec![1]));
+  assert!(old_txn.get([2]).unwrap().is_none());
+  assert_eq!(old_txn.range([0]..[9]).unwrap().count(), 1);
+
+  let new_txn = db.read_txn();
+  assert_eq!(new_txn.get([1]).unwrap(), Some(vec![2]));
+  assert_eq!(new_txn.range([0]..[9]).unwrap().count(), 2);
+}
+
+#[tokio::test]
+async fn memory_collab_kv_action_test() {
+  let uid = 1;
+  let db = KVTransactionDBMemoryImpl::new();
+  let doc = Doc::new();
+  let text = doc.get_or_insert_text("text");
+  text.insert(&mut doc.transact_mut(), 0, "hello");
+  db.with_write_txn(|store| store.create_new_doc(uid, "1", &doc.transact()))
+    .unwrap();
+
+  for word in [" world", "!"] {
+    let update = {
+      let mut txn = doc.transact_mut();
+      let len = text.len(&txn);
+      text.insert(&mut txn, len, word);
+      txn.encode_update_v1()
+    };
+    db.with_write_txn(|store| store.push_update(uid, "1", &update))
+      .unwrap();
+  }
+
+  let txn = db.read_txn();
+  assert!(txn.is_exist(uid, "1"));
+  assert_eq!(txn.number_of_updates(uid, "1"), 2);
+  assert_eq!(txn.get_all_docs().unwrap().count(), 1);
+
+  let restored = Doc::new();
+  let restored_text = restored.get_or_insert_text("text");
+  assert_eq!(txn.load_doc(uid, "1", restored.clone()).unwrap(), 2);
+  assert_eq!(
+    restored_text.get_string(&restored.transact()),
+    "hello world!"
+  );
+  drop(txn);
+
+  db.with_write_txn(|store| store.delete_doc(uid, "1"))
+    .unwrap();
+  let txn = db.read_txn();
+  assert!(!txn.is_exist(uid, "1"));
+  assert_eq!(txn.number_of_updates(uid, "1"), 0);
+}
+
+#[tokio::test]
+async fn memory_snapshot_action_test() {
+  let uid = 1;
+  let db = KVTransactionDBMemoryImpl::new();
+  db.with_write_txn(|store| {
+    store.create_snapshot_with_data(uid, "1", vec![1, 2, 3])?;
+    store.create_snapshot_with_data(uid, "1", vec![4, 5, 6])?;
+    Ok(())
+  })
+  .unwrap();
+
+  let txn = db.read_txn();
+  assert_eq!(txn.get_snapshots(uid, "1").len(), 2);
+  assert_eq!(txn.get_last_snapshot(uid, "1").unwrap().data, vec![4, 5, 6]);
+  drop(txn);
+
+  db.with_write_txn(|store| store.delete_all_snapshots(uid, "1"))
+    .unwrap();
+  assert!(db.read_txn().get_snapshots(uid, "1").is_empty());
+}
@@ -0,0 +1,180 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use collab::core::collab::MutexCollab;
+use collab::preclude::CollabBuilder;
+use collab_entity::CollabType;
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::keys::make_collab_metadata_key;
+use collab_plugins::local_storage::kv::metadata::{CollabMetadataAction, CollabMetadataFilter};
+use collab_plugins::local_storage::kv::{KVStore, KVTransactionDB};
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+use collab_plugins::local_storage::rocksdb::rocksdb_plugin::RocksdbDiskPlugin;
+use yrs::{Doc, Text, Transact};
+
+use crate::disk::util::memory_db;
+
+const UID: i64 = 1;
+
+fn open_collab(
+  db: &Arc<KVTransactionDBMemoryImpl>,
+  object_id: &str,
+  collab_type: CollabType,
+) -> MutexCollab {
+  let plugin = RocksdbDiskPlugin::new(
+    UID,
+    object_id.to_string(),
+    collab_type,
+    Arc::downgrade(db),
+    None,
+  );
+  let collab = CollabBuilder::new(UID, object_id)
+    .with_device_id("1")
+    .with_plugin(plugin)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  collab
+}
+
+fn object_ids(db: &KVTransactionDBMemoryImpl, filter: CollabMetadataFilter) -> Vec<String> {
+  db.read_txn()
+    .list_collab_metadata(UID, &filter)
+    .unwrap()
+    .into_iter()
+    .map(|metadata| metadata.object_id)
+    .collect()
+}
+
+#[tokio::test]
+async fn update_metadata_with_document_test() {
+  let db = memory_db();
+  let doc = Doc::new();
+  let text = doc.get_or_insert_text("text");
+  db.with_write_txn(|store| store.create_new_doc(UID, "1", &doc.transact()))
+    .unwrap();
+  let created = db
+    .read_txn()
+    .get_collab_metadata(UID, "1")
+    .unwrap()
+    .unwrap();
+  assert_eq!(created.object_id, "1");
+  assert_eq!(created.collab_type, None);
+  assert_eq!(created.update_count, 0);
+  assert_eq!(created.created_at, created.last_updated_at);
+
+  tokio::time::sleep(Duration::from_millis(5)).await;
+  let mut update_len = 0;
+  for content in ["hello", " world"] {
+    let update = {
+      let mut txn = doc.transact_mut();
+      text.push(&mut txn, content);
+      txn.encode_update_v1()
+    };
+    update_len += update.len() as u64;
+    db.with_write_txn(|store| store.push_update(UID, "1", &update))
+      .unwrap();
+  }
+  let updated = db
+    .read_txn()
+    .get_collab_metadata(UID, "1")
+    .unwrap()
+    .unwrap();
+  assert_eq!(updated.update_count, 2);
+  assert_eq!(updated.byte_size, created.byte_size + update_len);
+  assert_eq!(updated.created_at, created.created_at);
+  assert!(updated.last_updated_at > created.last_updated_at);
+
+  db.with_write_txn(|store| store.compact_doc_updates(UID, "1"))
+    .unwrap();
+  let compacted = db
+    .read_txn()
+    .get_collab_metadata(UID, "1")
+    .unwrap()
+    .unwrap();
+  assert_eq!(compacted.update_count, 0);
+  assert_eq!(compacted.last_updated_at, updated.last_updated_at);
+  let encoded_collab = db.read_txn().get_compacted_doc_state(UID, "1").unwrap();
+  assert_eq!(
+    compacted.byte_size as usize,
+    encoded_collab.doc_state.len() + encoded_collab.state_vector.len()
+  );
+
+  db.with_write_txn(|store| store.delete_doc(UID, "1"))
+    .unwrap();
+  assert!(db
+    .read_txn()
+    .get_collab_metadata(UID, "1")
+    .unwrap()
+    .is_none());
+}
+
+#[tokio::test]
+async fn list_objects_by_type_and_recency_test() {
+  let db = Arc::new(memory_db());
+  let folder = open_collab(&db, "folder", CollabType::Folder);
+  let _row_1 = open_collab(&db, "row_1", CollabType::DatabaseRow);
+  let _row_2 = open_collab(&db, "row_2", CollabType::DatabaseRow);
+  tokio::time::sleep(Duration::from_millis(5)).await;
+  let since = chrono::Utc::now().timestamp_millis();
+  folder.lock().insert("name", "my folder");
+
+  let metadata = db
+    .read_txn()
+    .get_collab_metadata(UID, "folder")
+    .unwrap()
+    .unwrap();
+  assert_eq!(metadata.collab_type, Some(CollabType::Folder));
+  assert_eq!(metadata.update_count, 1);
+
+  let mut rows = object_ids(
+    &db,
+    CollabMetadataFilter::new().with_collab_type(CollabType::DatabaseRow),
+  );
+  rows.sort();
+  assert_eq!(rows, vec!["row_1", "row_2"]);
+  assert_eq!(
+    object_ids(&db, CollabMetadataFilter::new().with_updated_since(since)),
+    vec!["folder"]
+  );
+  assert_eq!(
+    object_ids(&db, CollabMetadataFilter::new().with_limit(1)),
+    vec!["folder"]
+  );
+  assert_eq!(object_ids(&db, CollabMetadataFilter::new()).len(), 3);
+  assert!(db
+    .read_txn()
+    .list_collab_metadata(2, &CollabMetadataFilter::new())
+    .unwrap()
+    .is_empty());
+}
+
+#[tokio::test]
+async fn record_type_of_existing_object_test() {
+  let db = Arc::new(memory_db());
+  let doc = Doc::new();
+  db.with_write_txn(|store| {
+    store.create_new_doc(UID, "1", &doc.transact())?;
+    // As if the object was written before the metadata existed.
+    store.remove(make_collab_metadata_key(&UID.to_be_bytes(), b"1").as_ref())?;
+    Ok(())
+  })
+  .unwrap();
+  assert!(db
+    .read_txn()
+    .get_collab_metadata(UID, "1")
+    .unwrap()
+    .is_none());
+
+  let _collab = open_collab(&db, "1", CollabType::Document);
+  let metadata = db
+    .read_txn()
+    .get_collab_metadata(UID, "1")
+    .unwrap()
+    .unwrap();
+  assert_eq!(metadata.collab_type, Some(CollabType::Document));
+  assert!(metadata.byte_size > 0);
+
+  let result = db.with_write_txn(|store| store.set_collab_type(UID, "2", &CollabType::Folder));
+  assert!(result.unwrap_err().is_record_not_found());
+}
@@ -1,7 +1,20 @@
+mod backup_test;
+mod compaction_test;
 mod delete_test;
+#[cfg(feature = "rocksdb_storage")]
+mod encryption_test;
+mod fsck_test;
 mod insert_test;
+mod memory_test;
+mod metadata_test;
+#[cfg(feature = "rocksdb_storage")]
+mod purge_user_test;
 mod range_test;
+#[cfg(feature = "redb_storage")]
+mod redb_test;
+#[cfg(feature = "rocksdb_storage")]
 mod restore_test;
 mod script;
+mod search_test;
 mod undo_test;
 mod util;
@@ -0,0 +1,111 @@
+use collab::core::collab_search::SearchIndex;
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::fsck::{check_collab_store, FsckRepair};
+use collab_plugins::local_storage::kv::keys::{make_doc_id_key, make_user_doc_id_range};
+use collab_plugins::local_storage::kv::metadata::CollabMetadataAction;
+use collab_plugins::local_storage::kv::search::SearchIndexAction;
+use collab_plugins::local_storage::kv::snapshot::SnapshotAction;
+use collab_plugins::local_storage::kv::user::{UserDataAction, PURGE_USER_BATCH_SIZE};
+use collab_plugins::local_storage::kv::{KVStore, KVTransactionDB};
+
+use crate::disk::util::{create_doc, memory_db, rocks_db};
+
+#[tokio::test]
+async fn list_user_objects_test() {
+  let (_, db) = rocks_db();
+  create_doc(&db, 1, "a", "", &["abc"; 2]);
+  create_doc(&db, 1, "b", "", &["abc"; 0]);
+  create_doc(&db, 2, "c", "", &["abc"; 1]);
+  db.with_write_txn(|store| store.create_snapshot_with_data(1, "a", vec![0; 100]))
+    .unwrap();
+
+  let mut objects = db.list_user_objects(1).await.unwrap();
+  objects.sort_by(|a, b| a.object_id.cmp(&b.object_id));
+  assert_eq!(objects.len(), 2);
+  assert_eq!(objects[0].object_id, "a");
+  assert_eq!(objects[0].update_count, 2);
+  assert_eq!(objects[0].snapshot_count, 1);
+  assert!(objects[0].snapshot_size > 100);
+  assert!(objects[0].doc_size > objects[1].doc_size);
+  assert_eq!(objects[1].object_id, "b");
+  assert_eq!(objects[1].update_count, 0);
+  assert_eq!(objects[1].snapshot_size, 0);
+  assert_eq!(objects[1].size(), objects[1].doc_size);
+
+  let objects = db.list_user_objects(2).await.unwrap();
+  assert_eq!(objects.len(), 1);
+  assert_eq!(objects[0].object_id, "c");
+  assert!(db.list_user_objects(3).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn purge_user_test() {
+  let (_, db) = rocks_db();
+  let count = PURGE_USER_BATCH_SIZE + 10;
+  for i in 0..count {
+    create_doc(&db, 1, &i.to_string(), "", &["abc"; 1]);
+  }
+  create_doc(&db, 2, "0", "", &["abc"; 1]);
+  db.with_write_txn(|store| {
+    store.create_snapshot_with_data(1, "0", vec![1, 2, 3])?;
+    store.create_snapshot_with_data(2, "0", vec![1, 2, 3])?;
+    // A snapshot of an object that was deleted.
+    store.create_snapshot_with_data(1, "deleted", vec![4, 5, 6])?;
+    store.save_search_index(1, &SearchIndex::new())?;
+    store.save_search_index(2, &SearchIndex::new())
+  })
+  .unwrap();
+
+  assert_eq!(db.purge_user(1).await.unwrap(), count + 1);
+  assert!(db.list_user_objects(1).await.unwrap().is_empty());
+  let txn = db.read_txn();
+  assert!(!txn.is_exist(1, "0"));
+  assert!(txn.get_snapshots(1, "0").is_empty());
+  assert!(txn.get_snapshots(1, "deleted").is_empty());
+  assert!(txn.load_search_index(1).unwrap().is_none());
+
+  // The data of the other user is kept.
+  assert!(txn.is_exist(2, "0"));
+  assert_eq!(txn.number_of_updates(2, "0"), 1);
+  assert_eq!(txn.get_snapshots(2, "0").len(), 1);
+  assert!(txn.load_search_index(2).unwrap().is_some());
+  let report = check_collab_store(&txn, FsckRepair::None).unwrap();
+  assert!(report.is_healthy(), "{:?}", report);
+  drop(txn);
+
+  assert_eq!(db.purge_user(1).await.unwrap(), 0);
+}
+
+#[tokio::test]
+async fn purge_undecodable_objects_test() {
+  let db = memory_db();
+  create_doc(&db, 1, "doc", "", &["abc"; 1]);
+  // More index entries without a valid DocID than a purge batch.
+  db.with_write_txn(|store| {
+    for i in 0..PURGE_USER_BATCH_SIZE + 5 {
+      let key = make_doc_id_key(&1_i64.to_be_bytes(), format!("broken-{}", i).as_bytes());
+      store.insert(key.as_ref(), [1, 2, 3])?;
+    }
+    Ok(())
+  })
+  .unwrap();
+
+  let mut removed = 0;
+  loop {
+    let count = db
+      .with_write_txn(|store| store.purge_user_objects(1, PURGE_USER_BATCH_SIZE))
+      .unwrap();
+    removed += count;
+    if count < PURGE_USER_BATCH_SIZE {
+      break;
+    }
+  }
+  assert_eq!(removed, PURGE_USER_BATCH_SIZE + 6);
+  let txn = db.read_txn();
+  assert!(!txn.is_exist(1, "doc"));
+  assert!(txn.get_collab_metadata(1, "doc").unwrap().is_none());
+  let (start, end) = make_user_doc_id_range(1);
+  assert_eq!(txn.range(start.as_ref()..end.as_ref()).unwrap().count(), 0);
+  let report = check_collab_store(&txn, FsckRepair::None).unwrap();
+  assert!(report.is_healthy(), "{:?}", report);
+}
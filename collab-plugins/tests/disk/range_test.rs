@@ -2,28 +2,27 @@ use std::ops::{Deref, Range, RangeTo};
 use std::sync::Arc;
 use std::thread;
 
-use crate::disk::util::rocks_db;
+use crate::disk::util::memory_db;
 use collab_plugins::local_storage::kv::keys::{clock_from_key, make_doc_update_key, Clock};
 use collab_plugins::local_storage::kv::{KVEntry, KVStore, KVTransactionDB};
 use smallvec::SmallVec;
 
 #[tokio::test]
-async fn rocks_id_test() {
-  let rocks_db = rocks_db().1;
-  rocks_db
-    .with_write_txn(|store| {
-      store.insert([0, 0, 0, 0, 0, 0, 0, 0], [0, 1, 1]).unwrap();
-      store.insert([0, 0, 0, 0, 0, 0, 0, 1], [0, 1, 2]).unwrap();
-      store.insert([0, 0, 0, 0, 0, 0, 0, 2], [0, 1, 3]).unwrap();
-      store.insert([0, 0, 0, 0, 0, 0, 0, 3], [0, 1, 4]).unwrap();
-      store.insert([0, 1, 0, 0, 0, 0, 0, 4], [0, 1, 5]).unwrap();
-      store.insert([0, 1, 0, 0, 0, 0, 0, 5], [0, 1, 6]).unwrap();
-      Ok(())
-    })
-    .unwrap();
+async fn next_back_entry_test() {
+  let db = memory_db();
+  db.with_write_txn(|store| {
+    store.insert([0, 0, 0, 0, 0, 0, 0, 0], [0, 1, 1]).unwrap();
+    store.insert([0, 0, 0, 0, 0, 0, 0, 1], [0, 1, 2]).unwrap();
+    store.insert([0, 0, 0, 0, 0, 0, 0, 2], [0, 1, 3]).unwrap();
+    store.insert([0, 0, 0, 0, 0, 0, 0, 3], [0, 1, 4]).unwrap();
+    store.insert([0, 1, 0, 0, 0, 0, 0, 4], [0, 1, 5]).unwrap();
+    store.insert([0, 1, 0, 0, 0, 0, 0, 5], [0, 1, 6]).unwrap();
+    Ok(())
+  })
+  .unwrap();
 
   let given_key: &[u8; 8] = &[0, 0, 0, 0, 0, 0, 0, 1];
-  let last_entry_prior = rocks_db
+  let last_entry_prior = db
     .read_txn()
     .next_back_entry(given_key)
     .expect("No entry found prior to the given key")
@@ -31,7 +30,7 @@ async fn rocks_id_test() {
   assert_eq!(last_entry_prior.value(), &[0, 1, 2]);
 
   let given_key: &[u8; 2] = &[0, 1];
-  let last_entry_prior = rocks_db
+  let last_entry_prior = db
     .read_txn()
     .next_back_entry(given_key)
     .expect("No entry found prior to the given key")
@@ -39,7 +38,7 @@ async fn rocks_id_test() {
   println!("{:?}", last_entry_prior.value());
 
   // The start key is exclusive
-  let txn = rocks_db.read_txn();
+  let txn = db.read_txn();
   let mut range = txn
     .range([0, 0, 0, 0, 0, 0, 0, 0]..[0, 0, 0, 0, 0, 0, 0, 2])
     .unwrap();
@@ -48,23 +47,22 @@ async fn rocks_id_test() {
   assert!(range.next().is_none());
 
   // The end key is exclusive
-  rocks_db
-    .with_write_txn(|txn| {
-      txn
-        .remove_range(&[0, 0, 0, 0, 0, 0, 0, 0], &[0, 0, 0, 0, 0, 0, 0, 2])
-        .unwrap();
-      Ok(())
-    })
-    .unwrap();
+  db.with_write_txn(|txn| {
+    txn
+      .remove_range(&[0, 0, 0, 0, 0, 0, 0, 0], &[0, 0, 0, 0, 0, 0, 0, 2])
+      .unwrap();
+    Ok(())
+  })
+  .unwrap();
 
-  let txn = rocks_db.read_txn();
+  let txn = db.read_txn();
   let value = txn.get([0, 0, 0, 0, 0, 0, 0, 2]).unwrap().unwrap();
   assert_eq!(&value, &[0, 1, 3]);
 }
 
 #[tokio::test]
 async fn key_range_test() {
-  let db = rocks_db().1;
+  let db = memory_db();
   let next = || {
     let given_key: &[u8; 2] = &[0, 2];
     let val = db
@@ -96,7 +94,7 @@ async fn key_range_test() {
 
 #[tokio::test]
 async fn scan_prefix_multi_thread() {
-  let db = Arc::new(rocks_db().1);
+  let db = Arc::new(memory_db());
   let mut handles = vec![];
   let doc_id: u64 = 1;
 
@@ -136,7 +134,7 @@ async fn scan_prefix_multi_thread() {
 
 #[tokio::test]
 async fn range_key_test() {
-  let db = rocks_db().1;
+  let db = memory_db();
   db.with_write_txn(|store| {
     store.insert([0, 0, 0, 0, 0, 0, 0, 0], [0, 1, 1]).unwrap();
     store.insert([0, 0, 0, 0, 0, 0, 0, 1], [0, 1, 2]).unwrap();
@@ -196,7 +194,7 @@ async fn range_key_test() {
 
 #[tokio::test]
 async fn delete_range_test() {
-  let db = rocks_db().1;
+  let db = memory_db();
   db.with_write_txn(|store| {
     store.insert([0, 0, 0, 0, 0, 0, 0, 0], [0, 1, 1]).unwrap();
     store.insert([0, 0, 0, 0, 0, 0, 0, 1], [0, 1, 2]).unwrap();
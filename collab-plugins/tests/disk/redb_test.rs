@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use collab::preclude::CollabBuilder;
+use collab_entity::CollabType;
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::{KVEntry, KVStore, KVTransactionDB, PersistenceError};
+use collab_plugins::local_storage::redb::kv_impl::KVTransactionDBRedbImpl;
+#[cfg(feature = "rocksdb_storage")]
+use collab_plugins::local_storage::redb::migration::migrate_from_rocksdb;
+use serde_json::json;
+use tempfile::TempDir;
+#[cfg(feature = "rocksdb_storage")]
+use yrs::{Doc, GetString, Text, Transact};
+
+use crate::disk::script::disk_plugin_with_db;
+#[cfg(feature = "rocksdb_storage")]
+use crate::disk::util::rocks_db;
+
+fn redb() -> KVTransactionDBRedbImpl {
+  let path = TempDir::new().unwrap().into_path().join("collab.redb");
+  KVTransactionDBRedbImpl::open(path).unwrap()
+}
+
+#[tokio::test]
+async fn redb_range_test() {
+  let db = redb();
+  db.with_write_txn(|store| {
+    store.insert([0, 0, 0, 0, 0, 0, 0, 0], [0, 1, 1])?;
+    store.insert([0, 0, 0, 0, 0, 0, 0, 1], [0, 1, 2])?;
+    store.insert([0, 0, 0, 0, 0, 0, 0, 2], [0, 1, 3])?;
+    store.insert([0, 1, 0, 0, 0, 0, 0, 4], [0, 1, 5])?;
+    Ok(())
+  })
+  .unwrap();
+
+  let txn = db.read_txn();
+  let entry = txn.next_back_entry(&[0, 1]).unwrap().unwrap();
+  assert_eq!(entry.value(), &[0, 1, 3]);
+
+  // The end key is exclusive, even for an inclusive range
+  let values = txn
+    .range([0, 0, 0, 0, 0, 0, 0, 0]..=[0, 0, 0, 0, 0, 0, 0, 2])
+    .unwrap()
+    .map(|entry| entry.value().to_vec())
+    .collect::<Vec<_>>();
+  assert_eq!(values, vec![vec![0, 1, 1], vec![0, 1, 2]]);
+
+  // A read transaction can't write
+  assert!(txn.insert([1], [1]).is_err());
+  drop(txn);
+
+  db.with_write_txn(|store| {
+    store.remove_range(&[0, 0, 0, 0, 0, 0, 0, 0], &[0, 0, 0, 0, 0, 0, 0, 2])?;
+    assert!(store.get([0, 0, 0, 0, 0, 0, 0, 1])?.is_none());
+    Ok(())
+  })
+  .unwrap();
+  assert_eq!(db.read_txn().range([0]..[255]).unwrap().count(), 2);
+}
+
+#[tokio::test]
+async fn redb_range_across_pages_test() {
+  let db = redb();
+  db.with_write_txn(|store| {
+    for i in 0..1000u32 {
+      store.insert(i.to_be_bytes(), i.to_le_bytes())?;
+    }
+    Ok(())
+  })
+  .unwrap();
+
+  let txn = db.read_txn();
+  let keys = txn
+    .range(10u32.to_be_bytes()..990u32.to_be_bytes())
+    .unwrap()
+    .map(|entry| u32::from_be_bytes(entry.key().try_into().unwrap()))
+    .collect::<Vec<_>>();
+  assert_eq!(keys, (10..990).collect::<Vec<_>>());
+  assert_eq!(txn.count_keys([0]..[255]).unwrap(), 1000);
+  drop(txn);
+
+  // The transaction can write while one of its ranges is read.
+  db.with_write_txn(|store| {
+    let mut count = 0;
+    for entry in store.range([0]..[255])? {
+      store.remove(entry.key())?;
+      count += 1;
+    }
+    assert_eq!(count, 1000);
+    Ok(())
+  })
+  .unwrap();
+  assert_eq!(db.read_txn().count_keys([0]..[255]).unwrap(), 0);
+}
+
+#[tokio::test]
+async fn redb_rollback_on_error_test() {
+  let db = redb();
+  let result: Result<(), PersistenceError> = db.with_write_txn(|store| {
+    store.insert([1], [1])?;
+    Err(PersistenceError::InvalidData("abort".to_string()))
+  });
+  assert!(result.is_err());
+  assert!(db.read_txn().get([1]).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn redb_disk_plugin_test() {
+  let uid = 1;
+  let path = TempDir::new().unwrap().into_path().join("collab.redb");
+  let db = Arc::new(KVTransactionDBRedbImpl::open(&path).unwrap());
+  let collab = CollabBuilder::new(uid, "1")
+    .with_device_id("1")
+    .with_plugin(disk_plugin_with_db(
+      uid,
+      db.clone(),
+      "1",
+      CollabType::Document,
+    ))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  collab.lock().insert("name", "appflowy");
+  drop(collab);
+  drop(db);
+
+  let db = Arc::new(KVTransactionDBRedbImpl::open(&path).unwrap());
+  assert_eq!(db.read_txn().number_of_updates(uid, "1"), 1);
+  let collab = CollabBuilder::new(uid, "1")
+    .with_device_id("1")
+    .with_plugin(disk_plugin_with_db(
+      uid,
+      db.clone(),
+      "1",
+      CollabType::Document,
+    ))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  assert_eq!(collab.to_json_value(), json!({ "name": "appflowy" }));
+}
+
+#[cfg(feature = "rocksdb_storage")]
+#[tokio::test]
+async fn migrate_rocksdb_to_redb_test() {
+  let uid = 1;
+  let rocksdb = rocks_db().1;
+  let doc = Doc::new();
+  let text = doc.get_or_insert_text("text");
+  text.insert(&mut doc.transact_mut(), 0, "hello");
+  rocksdb
+    .with_write_txn(|store| store.create_new_doc(uid, "1", &doc.transact()))
+    .unwrap();
+  let update = {
+    let mut txn = doc.transact_mut();
+    text.insert(&mut txn, 5, " world");
+    txn.encode_update_v1()
+  };
+  rocksdb
+    .with_write_txn(|store| store.push_update(uid, "1", &update))
+    .unwrap();
+
+  let redb = redb();
+  let copied = migrate_from_rocksdb(&rocksdb, &redb).unwrap();
+  assert_eq!(
+    copied,
+    rocksdb.read_txn().range([0]..[255]).unwrap().count()
+  );
+
+  let txn = redb.read_txn();
+  assert_eq!(txn.number_of_updates(uid, "1"), 1);
+  let restored = Doc::new();
+  let restored_text = restored.get_or_insert_text("text");
+  txn.load_doc(uid, "1", restored.clone()).unwrap();
+  assert_eq!(
+    restored_text.get_string(&restored.transact()),
+    "hello world"
+  );
+  drop(txn);
+
+  // The target must be empty
+  assert!(migrate_from_rocksdb(&rocksdb, &redb).is_err());
+}
@@ -1,5 +1,4 @@
 use std::collections::HashMap;
-use std::path::PathBuf;
 use std::sync::Arc;
 
 use collab::core::collab::MutexCollab;
@@ -12,15 +11,15 @@ use collab_plugins::local_storage::rocksdb::rocksdb_plugin::RocksdbDiskPlugin;
 
 use collab_entity::CollabType;
 use collab_plugins::local_storage::kv::KVTransactionDB;
-use collab_plugins::CollabKVDB;
-use tempfile::TempDir;
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
 
+use crate::disk::util::memory_db;
 use crate::setup_log;
 
 pub enum Script {
   CreateDocumentWithCollabDB {
     id: String,
-    db: Arc<CollabKVDB>,
+    db: Arc<KVTransactionDBMemoryImpl>,
   },
   OpenDocumentWithDiskPlugin {
     id: String,
@@ -58,9 +57,7 @@ pub struct CollabPersistenceTest {
   pub uid: i64,
   collab_by_id: HashMap<String, Arc<MutexCollab>>,
   #[allow(dead_code)]
-  cleaner: Cleaner,
-  #[allow(dead_code)]
-  pub db: Arc<CollabKVDB>,
+  pub db: Arc<KVTransactionDBMemoryImpl>,
   #[allow(dead_code)]
   config: CollabPersistenceConfig,
 }
@@ -68,15 +65,11 @@ pub struct CollabPersistenceTest {
 impl CollabPersistenceTest {
   pub fn new(config: CollabPersistenceConfig) -> Self {
     setup_log();
-    let tempdir = TempDir::new().unwrap();
-    let db_path = tempdir.into_path();
     let uid = 1;
-    let db = Arc::new(CollabKVDB::open(db_path.clone()).unwrap());
-    let cleaner = Cleaner::new(db_path);
+    let db = Arc::new(memory_db());
     Self {
       uid,
       collab_by_id: HashMap::default(),
-      cleaner,
       db,
       config,
     }
@@ -97,7 +90,7 @@ impl CollabPersistenceTest {
     );
     let disk_plugin = disk_plugin_with_db(self.uid, self.db.clone(), &doc_id, CollabType::Document)
       as Box<dyn CollabPlugin>;
-    collab.lock().add_plugin(disk_plugin);
+    collab.lock().add_plugin(disk_plugin).unwrap();
     collab.lock().initialize();
 
     self.collab_by_id.insert(doc_id, collab);
@@ -132,7 +125,7 @@ impl CollabPersistenceTest {
     );
     let disk_plugin = disk_plugin_with_db(self.uid, self.db.clone(), id, CollabType::Document)
       as Box<dyn CollabPlugin>;
-    collab.lock().add_plugin(disk_plugin);
+    collab.lock().add_plugin(disk_plugin).unwrap();
     collab.lock().initialize();
 
     let json = collab.to_json_value();
@@ -226,12 +219,12 @@ impl CollabPersistenceTest {
   }
 }
 
-pub fn disk_plugin_with_db(
+pub fn disk_plugin_with_db<DB: KVTransactionDB>(
   uid: i64,
-  db: Arc<CollabKVDB>,
+  db: Arc<DB>,
   object_id: &str,
   collab_type: CollabType,
-) -> Box<RocksdbDiskPlugin> {
+) -> Box<RocksdbDiskPlugin<DB>> {
   let object_id = object_id.to_string();
   let collab_type = collab_type.clone();
   Box::new(RocksdbDiskPlugin::new_with_config(
@@ -243,21 +236,3 @@ pub fn disk_plugin_with_db(
     None,
   ))
 }
-
-struct Cleaner(PathBuf);
-
-impl Cleaner {
-  fn new(dir: PathBuf) -> Self {
-    Cleaner(dir)
-  }
-
-  fn cleanup(dir: &PathBuf) {
-    let _ = std::fs::remove_dir_all(dir);
-  }
-}
-
-impl Drop for Cleaner {
-  fn drop(&mut self) {
-    Self::cleanup(&self.0)
-  }
-}
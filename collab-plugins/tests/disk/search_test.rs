@@ -0,0 +1,134 @@
+use collab::core::collab::IndexContent;
+use collab::core::collab_search::{
+  CollabSearch, IndexedDocument, SearchIndex, SearchQuery, TermFrequency,
+};
+use collab_plugins::local_storage::kv::keys::{
+  make_search_document_key, make_search_index_key, make_search_term_key, make_search_term_range,
+};
+use collab_plugins::local_storage::kv::search::SearchIndexAction;
+use collab_plugins::local_storage::kv::{KVStore, KVTransactionDB};
+use serde_json::json;
+
+use crate::disk::util::memory_db;
+
+#[tokio::test]
+async fn persist_search_index_test() {
+  let db = memory_db();
+  let uid = 1;
+  let search = CollabSearch::new();
+  search.apply(&IndexContent::Create(json!({
+    "id": "1",
+    "name": "Meeting notes",
+  })));
+
+  let saved = db
+    .with_write_txn(|txn| txn.save_search_index_if_dirty(uid, &search))
+    .unwrap();
+  assert!(saved);
+  let saved = db
+    .with_write_txn(|txn| txn.save_search_index_if_dirty(uid, &search))
+    .unwrap();
+  assert!(!saved);
+
+  let index = db.read_txn().load_search_index(uid).unwrap().unwrap();
+  let results = index.search(&SearchQuery::new("meet"));
+  assert_eq!(results[0].id, "1");
+  assert!(db.read_txn().load_search_index(2).unwrap().is_none());
+
+  db.with_write_txn(|txn| txn.delete_search_index(uid))
+    .unwrap();
+  assert!(db.read_txn().load_search_index(uid).unwrap().is_none());
+}
+
+#[tokio::test]
+async fn persist_search_index_changes_test() {
+  let db = memory_db();
+  let uid = 1;
+  let search = CollabSearch::new();
+  search.apply(&IndexContent::Create(
+    json!({ "id": "1", "name": "Meeting notes" }),
+  ));
+  search.apply(&IndexContent::Create(
+    json!({ "id": "2", "name": "Roadmap" }),
+  ));
+  db.with_write_txn(|txn| txn.save_search_index_if_dirty(uid, &search))
+    .unwrap();
+
+  search.apply(&IndexContent::Update(
+    json!({ "id": "2", "name": "Quarterly planning" }),
+  ));
+  search.apply(&IndexContent::Delete(vec!["1".to_string()]));
+  db.with_write_txn(|txn| txn.save_search_index_if_dirty(uid, &search))
+    .unwrap();
+
+  let index = db.read_txn().load_search_index(uid).unwrap().unwrap();
+  assert_eq!(index.len(), 1);
+  assert!(index.search(&SearchQuery::new("meeting")).is_empty());
+  assert!(index.search(&SearchQuery::new("roadmap")).is_empty());
+  let results = index.search(&SearchQuery::new("planing").with_fuzzy(1));
+  assert_eq!(results[0].id, "2");
+  assert_eq!(index.postings("2"), search.read_index().postings("2"),);
+}
+
+#[tokio::test]
+async fn load_search_index_saved_as_single_value_test() {
+  let db = memory_db();
+  let uid = 1;
+  let mut index = SearchIndex::new();
+  index.upsert(IndexedDocument::new("1", "Meeting notes", ""));
+  db.with_write_txn(|txn| txn.insert(make_search_index_key(uid), index.encode().unwrap()))
+    .unwrap();
+
+  let search = CollabSearch::from_index(db.read_txn().load_search_index(uid).unwrap().unwrap());
+  search.upsert(IndexedDocument::new("2", "Roadmap", ""));
+  db.with_write_txn(|txn| txn.save_search_index_if_dirty(uid, &search))
+    .unwrap();
+  let index = db.read_txn().load_search_index(uid).unwrap().unwrap();
+  assert_eq!(index.len(), 2);
+  assert_eq!(index.search(&SearchQuery::new("meeting"))[0].id, "1");
+}
+
+#[tokio::test]
+async fn load_search_index_saved_with_term_keys_test() {
+  let db = memory_db();
+  let uid = 1;
+  let document = IndexedDocument::new("1", "Meeting notes", "");
+  db.with_write_txn(|txn| {
+    txn.insert(make_search_index_key(uid), [1])?;
+    txn.insert(
+      make_search_document_key(uid, b"1"),
+      bincode::serialize(&document).unwrap(),
+    )?;
+    for term in ["meeting", "notes"] {
+      let frequency = TermFrequency { title: 1, body: 0 };
+      txn.insert(
+        make_search_term_key(uid, term.as_bytes(), b"1"),
+        bincode::serialize(&frequency).unwrap(),
+      )?;
+    }
+    Ok(())
+  })
+  .unwrap();
+
+  let search = CollabSearch::from_index(db.read_txn().load_search_index(uid).unwrap().unwrap());
+  assert_eq!(
+    search.read_index().search(&SearchQuery::new("notes"))[0].id,
+    "1"
+  );
+
+  // The next save moves the postings into the documents.
+  search.upsert(IndexedDocument::new("2", "Roadmap", ""));
+  db.with_write_txn(|txn| txn.save_search_index_if_dirty(uid, &search))
+    .unwrap();
+  let (start, end) = make_search_term_range(uid);
+  assert_eq!(
+    db.read_txn()
+      .range(start.as_ref()..end.as_ref())
+      .unwrap()
+      .count(),
+    0
+  );
+  let index = db.read_txn().load_search_index(uid).unwrap().unwrap();
+  assert_eq!(index.len(), 2);
+  assert_eq!(index.search(&SearchQuery::new("meeting"))[0].id, "1");
+}
@@ -1,11 +1,69 @@
+#[cfg(feature = "rocksdb_storage")]
 use std::path::PathBuf;
 
-use collab_plugins::CollabKVDB;
+use collab_plugins::local_storage::kv::doc::CollabKVAction;
+use collab_plugins::local_storage::kv::KVTransactionDB;
+use collab_plugins::local_storage::memory::kv_impl::KVTransactionDBMemoryImpl;
+#[cfg(feature = "rocksdb_storage")]
+use collab_plugins::local_storage::rocksdb::kv_impl::KVTransactionDBRocksdbImpl;
+#[cfg(feature = "rocksdb_storage")]
 use tempfile::TempDir;
+use yrs::{Doc, GetString, Text, Transact};
 
-pub fn rocks_db() -> (PathBuf, CollabKVDB) {
+#[cfg(feature = "rocksdb_storage")]
+pub fn rocks_db() -> (PathBuf, KVTransactionDBRocksdbImpl) {
   let tempdir = TempDir::new().unwrap();
   let path = tempdir.into_path();
   let cloned_path = path.clone();
-  (path, CollabKVDB::open(cloned_path).unwrap())
+  (path, KVTransactionDBRocksdbImpl::open(cloned_path).unwrap())
+}
+
+pub fn memory_db() -> KVTransactionDBMemoryImpl {
+  KVTransactionDBMemoryImpl::new()
+}
+
+/// Creates a document whose text is `content`, then pushes one update per item of `updates`
+/// that appends it to the text.
+pub fn create_doc<DB: KVTransactionDB>(
+  db: &DB,
+  uid: i64,
+  object_id: &str,
+  content: &str,
+  updates: &[&str],
+) -> Doc {
+  let doc = Doc::new();
+  let text = doc.get_or_insert_text("text");
+  text.push(&mut doc.transact_mut(), content);
+  db.with_write_txn(|store| store.create_new_doc(uid, object_id, &doc.transact()))
+    .unwrap();
+  for update in updates {
+    push_text(db, uid, &doc, object_id, update);
+  }
+  doc
+}
+
+/// Appends `content` to the text of the document and pushes the update.
+pub fn push_text<DB: KVTransactionDB>(
+  db: &DB,
+  uid: i64,
+  doc: &Doc,
+  object_id: &str,
+  content: &str,
+) {
+  let text = doc.get_or_insert_text("text");
+  let update = {
+    let mut txn = doc.transact_mut();
+    text.push(&mut txn, content);
+    txn.encode_update_v1()
+  };
+  db.with_write_txn(|store| store.push_update(uid, object_id, &update))
+    .unwrap();
+}
+
+pub fn load_text<DB: KVTransactionDB>(db: &DB, uid: i64, object_id: &str) -> String {
+  let doc = Doc::new();
+  let text = doc.get_or_insert_text("text");
+  db.read_txn().load_doc(uid, object_id, doc.clone()).unwrap();
+  let txn = doc.transact();
+  text.get_string(&txn)
 }
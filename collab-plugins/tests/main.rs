@@ -1,6 +1,9 @@
 #[cfg(not(target_arch = "wasm32"))]
 mod disk;
 
+#[cfg(not(target_arch = "wasm32"))]
+mod metrics;
+
 #[cfg(target_arch = "wasm32")]
 mod web;
 
@@ -0,0 +1,134 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use collab::core::awareness::{AwarenessUpdate, Event};
+use collab::core::collab::MutexCollab;
+use collab::core::collab_plugin::CollabPlugin;
+use collab::core::origin::CollabOrigin;
+use collab_plugins::metrics::CollabMetricsRegistry;
+use serde_json::json;
+
+fn create_collab(registry: &CollabMetricsRegistry, object_id: &str) -> MutexCollab {
+  let collab = MutexCollab::new(
+    CollabOrigin::Empty,
+    object_id,
+    vec![Box::new(registry.plugin())],
+  );
+  collab.lock().initialize();
+  collab
+}
+
+#[tokio::test]
+async fn record_updates_and_transactions_test() {
+  let registry = CollabMetricsRegistry::new();
+  let collab = create_collab(&registry, "1");
+  collab.lock().insert("name", "Nathan");
+  collab.lock().insert("age", 30);
+
+  let metrics = registry.get("1").unwrap();
+  assert!(metrics.update_count >= 2);
+  assert_eq!(metrics.local_update_count, metrics.update_count);
+  assert_eq!(metrics.remote_update_count(), 0);
+  assert!(metrics.update_bytes > 0);
+  assert!(metrics.transaction_count >= 2);
+  assert!(metrics.lock_count >= 3);
+  assert!(metrics.encoded_state_bytes.is_some());
+}
+
+#[tokio::test]
+async fn record_awareness_churn_test() {
+  let registry = CollabMetricsRegistry::new();
+  let collab = create_collab(&registry, "1");
+  collab
+    .lock()
+    .get_mut_awareness()
+    .set_local_state(json!({ "uid": 1 }));
+  collab
+    .lock()
+    .get_mut_awareness()
+    .set_local_state(json!({ "uid": 1, "viewing": "doc" }));
+
+  let metrics = registry.get("1").unwrap();
+  assert_eq!(metrics.awareness_churn(), 2);
+}
+
+#[tokio::test]
+async fn query_most_expensive_objects_test() {
+  let registry = CollabMetricsRegistry::new();
+  let collab_1 = create_collab(&registry, "1");
+  let collab_2 = create_collab(&registry, "2");
+  collab_1.lock().insert("text", "a".repeat(10));
+  collab_2.lock().insert("text", "a".repeat(1000));
+
+  let top = registry.top_by(1, |m| m.update_bytes);
+  assert_eq!(top.len(), 1);
+  assert_eq!(top[0].object_id, "2");
+
+  let summary = registry.summary();
+  assert_eq!(summary.object_count, 2);
+  assert!(summary.update_bytes >= 1000);
+
+  registry.remove("2");
+  assert!(registry.get("2").is_none());
+  assert_eq!(registry.all().len(), 1);
+}
+
+#[tokio::test]
+async fn periodic_summary_test() {
+  let registry = CollabMetricsRegistry::new();
+  let collab = create_collab(&registry, "1");
+  collab.lock().insert("name", "Nathan");
+
+  let mut rx = registry.subscribe_summary();
+  registry.start_periodic_summary(Duration::from_millis(50));
+  let summary = tokio::time::timeout(Duration::from_secs(1), rx.recv())
+    .await
+    .unwrap()
+    .unwrap();
+  assert_eq!(summary.object_count, 1);
+  assert_eq!(summary.slowest_objects[0].object_id, "1");
+}
+
+#[derive(Default)]
+struct TimingCounter {
+  count: Arc<AtomicUsize>,
+}
+
+impl CollabPlugin for TimingCounter {
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+
+  fn did_transact(&self, _object_id: &str, _elapsed: Duration) {
+    self.count.fetch_add(1, Ordering::SeqCst);
+  }
+
+  fn did_acquire_lock(&self, _object_id: &str, _waited: Duration) {
+    self.count.fetch_add(1, Ordering::SeqCst);
+  }
+}
+
+#[tokio::test]
+async fn timing_is_only_measured_when_a_plugin_asks_for_it_test() {
+  let counter = TimingCounter::default();
+  let count = counter.count.clone();
+  let collab = MutexCollab::new(CollabOrigin::Empty, "1", vec![Box::new(counter)]);
+  collab.lock().initialize();
+  collab.lock().insert("name", "Nathan");
+  assert_eq!(count.load(Ordering::SeqCst), 0);
+
+  let registry = CollabMetricsRegistry::new();
+  collab
+    .lock()
+    .add_plugin(Box::new(registry.plugin()))
+    .unwrap();
+  collab.lock().insert("age", 30);
+  assert!(count.load(Ordering::SeqCst) > 0);
+  assert_eq!(registry.get("1").unwrap().transaction_count, 1);
+}
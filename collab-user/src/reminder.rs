@@ -32,24 +32,30 @@ impl Reminders {
   }
 
   pub fn remove(&self, id: &str) {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       self.container.remove_with_id(txn, id, REMINDER_ID);
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to remove reminder: {}", err);
+    }
   }
 
   pub fn add(&self, reminder: Reminder) {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       let _ = self
         .container
         .insert_map_with_txn(txn, Some(reminder.into()));
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to add reminder: {}", err);
+    }
   }
 
   pub fn update_reminder<F>(&self, reminder_id: &str, f: F)
   where
     F: FnOnce(&mut Reminder),
   {
-    self.container.with_transact_mut(|txn| {
+    let result = self.container.try_with_transact_mut(|txn| {
       self
         .container
         .mut_map_element_with_txn(txn, reminder_id, REMINDER_ID, |txn, map| {
@@ -58,6 +64,9 @@ impl Reminders {
           Some(MapPrelim::from(reminder))
         });
     });
+    if let Err(err) = result {
+      tracing::warn!("Failed to update reminder: {}", err);
+    }
   }
 
   pub fn get_all_reminders(&self) -> Vec<Reminder> {
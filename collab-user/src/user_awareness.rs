@@ -69,10 +69,10 @@ impl UserAwareness {
   /// - A new instance containing references to parts of the collaboration
   ///   object like `container`, `appearance_settings`, and `reminders`.
   ///
-  /// # Panics
-  /// - This function might panic if it fails to lock the `collab` mutex.
+  /// # Errors
+  /// - Returns an error if the collab is read-only.
   ///
-  pub fn create(collab: Arc<MutexCollab>, notifier: Option<UserAwarenessNotifier>) -> Self {
+  pub fn create(collab: Arc<MutexCollab>, notifier: Option<UserAwarenessNotifier>) -> Result<Self> {
     let collab_guard = collab.lock();
     let (container, appearance_settings, reminders) =
       collab_guard.with_origin_transact_mut(|txn| {
@@ -94,9 +94,15 @@ impl UserAwareness {
         );
 
         (awareness, appearance_settings, reminders)
-      });
+      })?;
     drop(collab_guard);
-    Self::new(collab, container, appearance_settings, reminders, notifier)
+    Ok(Self::new(
+      collab,
+      container,
+      appearance_settings,
+      reminders,
+      notifier,
+    ))
   }
 
   /// Provides mechanisms to manage user awareness in a collaborative context.
@@ -108,11 +114,18 @@ impl UserAwareness {
   /// If the user awareness attributes are not present, it logs an informational message and
   /// proceeds to create them. The method encapsulates the logic to seamlessly handle existing
   /// or missing attributes, offering a single point of access.
-  pub fn open(collab: Arc<MutexCollab>, notifier: Option<UserAwarenessNotifier>) -> Self {
-    Self::try_open(collab.clone(), notifier.clone()).unwrap_or_else(|| {
-      tracing::info!("Create missing attributes of user awareness");
-      Self::create(collab, notifier)
-    })
+  ///
+  /// # Errors
+  ///
+  /// Returns an error if the attributes are missing and the collab is read-only.
+  pub fn open(collab: Arc<MutexCollab>, notifier: Option<UserAwarenessNotifier>) -> Result<Self> {
+    match Self::try_open(collab.clone(), notifier.clone()) {
+      Some(user_awareness) => Ok(user_awareness),
+      None => {
+        tracing::info!("Create missing attributes of user awareness");
+        Self::create(collab, notifier)
+      },
+    }
   }
 
   /// Constructs a new instance with the provided parameters.
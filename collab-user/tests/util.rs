@@ -59,7 +59,7 @@ impl UserAwarenessTest {
     let notifier = UserAwarenessNotifier {
       reminder_change_tx: reminder_change_tx.clone(),
     };
-    let user_awareness = UserAwareness::create(Arc::new(collab), Some(notifier));
+    let user_awareness = UserAwareness::create(Arc::new(collab), Some(notifier)).unwrap();
     Self {
       user_awareness: MutexUserAwareness::new(user_awareness),
       cleaner: Arc::new(cleaner),
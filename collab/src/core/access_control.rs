@@ -0,0 +1,394 @@
+//! Write protection for a [Collab](crate::core::collab::Collab).
+//!
+//! An [AccessPolicy] decides which [CollabOrigin] may change which part of the document. The
+//! parts are identified by their path from the root of the document, starting with the section
+//! name, for example `["data", "database", "fields"]`.
+//!
+//! The policy is enforced in two places:
+//! * Remote updates that are applied with `Collab::apply_remote_update` are checked before they
+//!   are applied. An update that breaks the policy is rejected as a whole.
+//! * Local transactions can't be checked up front. The changes they make are inspected when they
+//!   commit and the transaction is reverted if it breaks the policy.
+//!
+//! In both cases an [AccessViolationEvent] is sent to the subscribers of
+//! `Collab::subscribe_access_violation`.
+//!
+//! The rules are checked against the [CollabOrigin] of the change. The read-only users and the
+//! writers of a protected path are identified by the uid of a [CollabOrigin::Client], so the
+//! policy can only tell them apart for the local transactions and for the remote updates that are
+//! applied with the origin of their author. Updates relayed by the server, like the ones of the
+//! cloud storage plugin, are applied with [CollabOrigin::Server] and are trusted: the server must
+//! enforce the policy for them.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::broadcast;
+use yrs::types::{DeepEventsSubscription, DeepObservable, Event, Events, PathSegment};
+use yrs::{Doc, MapRef, TransactionMut, UndoManager, UpdateSubscription};
+
+use crate::core::collab::{DATA_SECTION, META_SECTION};
+use crate::core::origin::CollabOrigin;
+use crate::core::update_blocks::ItemIndex;
+use crate::error::CollabError;
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ProtectedPath {
+  path: Vec<String>,
+  writers: HashSet<i64>,
+}
+
+/// Describes who may change what in a collab. An empty policy allows everything.
+///
+/// Changes made by [CollabOrigin::Server] are always allowed. Changes with an unknown origin,
+/// [CollabOrigin::Empty], could be made by anyone, so they are rejected by every rule that
+/// applies to their path.
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct AccessPolicy {
+  read_only_users: HashSet<i64>,
+  protected_paths: Vec<ProtectedPath>,
+}
+
+impl AccessPolicy {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The user can't change anything in the data section of the collab. The meta section is still
+  /// writable, because it holds the bookkeeping of the local client.
+  pub fn with_read_only_user(mut self, uid: i64) -> Self {
+    self.read_only_users.insert(uid);
+    self
+  }
+
+  /// Only the given users can change the values under `path`. Replacing or removing one of the
+  /// ancestors of `path` counts as a change of `path`. The path is made of map keys, the elements
+  /// of an array are covered by the path of the array.
+  pub fn with_protected_path<P, W>(mut self, path: P, writers: W) -> Self
+  where
+    P: IntoIterator,
+    P::Item: ToString,
+    W: IntoIterator<Item = i64>,
+  {
+    self.protected_paths.push(ProtectedPath {
+      path: path.into_iter().map(|s| s.to_string()).collect(),
+      writers: writers.into_iter().collect(),
+    });
+    self
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.read_only_users.is_empty() && self.protected_paths.is_empty()
+  }
+
+  /// Returns false if no change made by the origin can break the policy.
+  pub fn applies_to(&self, origin: &CollabOrigin) -> bool {
+    match origin {
+      CollabOrigin::Server => false,
+      CollabOrigin::Empty => !self.is_empty(),
+      CollabOrigin::Client(client) => {
+        !self.protected_paths.is_empty() || self.read_only_users.contains(&client.uid)
+      },
+    }
+  }
+
+  /// Checks if the origin is allowed to change the value at the given path. An empty path stands
+  /// for a change whose path is unknown and is only allowed if no rule applies to the origin.
+  pub fn check(&self, origin: &CollabOrigin, path: &[String]) -> Result<(), AccessViolation> {
+    if !self.applies_to(origin) {
+      return Ok(());
+    }
+
+    // Without a uid, the change could be made by one of the read-only users.
+    let uid = origin.client_user_id();
+    let is_read_only = match uid {
+      Some(uid) => self.read_only_users.contains(&uid),
+      None => !self.read_only_users.is_empty(),
+    };
+    if is_read_only && path.first().map_or(true, |section| section == DATA_SECTION) {
+      return Err(AccessViolation {
+        origin: origin.clone(),
+        path: path.to_vec(),
+        reason: ViolationReason::ReadOnly,
+      });
+    }
+
+    for protected in &self.protected_paths {
+      if !is_prefix(path, &protected.path) && !is_prefix(&protected.path, path) {
+        continue;
+      }
+      let allowed = uid
+        .map(|uid| protected.writers.contains(&uid))
+        .unwrap_or(false);
+      if !allowed {
+        return Err(AccessViolation {
+          origin: origin.clone(),
+          path: path.to_vec(),
+          reason: ViolationReason::ProtectedPath(protected.path.clone()),
+        });
+      }
+    }
+    Ok(())
+  }
+}
+
+fn is_prefix(prefix: &[String], path: &[String]) -> bool {
+  prefix.len() <= path.len() && prefix.iter().zip(path).all(|(a, b)| a == b)
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum ViolationReason {
+  /// The origin is a read-only user.
+  ReadOnly,
+  /// The origin isn't one of the writers of the protected path.
+  ProtectedPath(Vec<String>),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AccessViolation {
+  pub origin: CollabOrigin,
+  /// The path that was changed.
+  pub path: Vec<String>,
+  pub reason: ViolationReason,
+}
+
+impl Display for AccessViolation {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match &self.reason {
+      ViolationReason::ReadOnly => f.write_fmt(format_args!(
+        "{} is read-only, can't change {}",
+        self.origin,
+        self.path.join("/")
+      )),
+      ViolationReason::ProtectedPath(protected) => f.write_fmt(format_args!(
+        "{} can't change {}, {} is protected",
+        self.origin,
+        self.path.join("/"),
+        protected.join("/")
+      )),
+    }
+  }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum ViolationAction {
+  /// The remote update was not applied.
+  Rejected,
+  /// The local transaction was reverted.
+  Reverted,
+}
+
+#[derive(Debug, Clone)]
+pub struct AccessViolationEvent {
+  pub object_id: String,
+  pub violation: AccessViolation,
+  pub action: ViolationAction,
+}
+
+pub type AccessViolationSender = broadcast::Sender<AccessViolationEvent>;
+pub type AccessViolationReceiver = broadcast::Receiver<AccessViolationEvent>;
+
+/// Enforces the [AccessPolicy] of a collab. It is shared with the wrappers created by the
+/// collab, so their transactions are checked too.
+pub(crate) struct AccessGuard {
+  object_id: String,
+  local_origin: CollabOrigin,
+  policy: Arc<RwLock<AccessPolicy>>,
+  /// The first violation made by a local transaction since the last call of
+  /// [AccessGuard::enforce].
+  violation: Arc<Mutex<Option<AccessViolation>>>,
+  /// The locations of the items of the document. Only filled while the policy isn't empty.
+  index: Arc<Mutex<ItemIndex>>,
+  observers: Mutex<DocObservers>,
+  sender: AccessViolationSender,
+}
+
+/// The yrs handles owned by the [AccessGuard].
+#[derive(Default)]
+struct DocObservers {
+  /// Records the local transactions, so the ones that break the policy can be reverted.
+  undo_manager: Option<UndoManager>,
+  /// Finds the violations of the local transactions.
+  deep_subscriptions: Vec<DeepEventsSubscription>,
+  /// Keeps the [ItemIndex] up to date.
+  update_subscription: Option<UpdateSubscription>,
+}
+
+// SAFETY: yrs doesn't mark these handles as Send: the undo manager keeps its options in `Rc`s and
+// its flags in `Cell`s, and the subscriptions hold callbacks that don't have to be Send. The
+// undo manager never clones its `Rc`s, and the callbacks registered by the AccessGuard only
+// capture Send + Sync values. The handles are only used behind the mutex of the AccessGuard, by
+// `set_policy`, by `enforce` and by the transactions of the collab, which all run on the thread
+// that holds the collab. So they are never used from two threads at once, the same guarantee that
+// makes `MutexCollab` Send and Sync.
+unsafe impl Send for DocObservers {}
+
+impl AccessGuard {
+  pub(crate) fn new(object_id: &str, local_origin: CollabOrigin) -> Self {
+    Self {
+      object_id: object_id.to_string(),
+      local_origin,
+      policy: Default::default(),
+      violation: Default::default(),
+      index: Default::default(),
+      observers: Default::default(),
+      sender: broadcast::channel(100).0,
+    }
+  }
+
+  pub(crate) fn policy(&self) -> AccessPolicy {
+    self.policy.read().clone()
+  }
+
+  pub(crate) fn subscribe(&self) -> AccessViolationReceiver {
+    self.sender.subscribe()
+  }
+
+  /// Replaces the policy. The local transactions are only observed while the policy applies to
+  /// the local origin, and the [ItemIndex] is only kept while the policy isn't empty.
+  pub(crate) fn set_policy(&self, policy: AccessPolicy, doc: &Doc, data: &MapRef, meta: &MapRef) {
+    let applies_to_local = policy.applies_to(&self.local_origin);
+    let is_empty = policy.is_empty();
+    *self.policy.write() = policy;
+    *self.violation.lock() = None;
+
+    let mut observers = self.observers.lock();
+    if is_empty {
+      observers.update_subscription = None;
+      *self.index.lock() = ItemIndex::default();
+    } else if observers.update_subscription.is_none() {
+      observers.update_subscription = ItemIndex::track(&self.index, doc, &self.object_id);
+    }
+
+    if !applies_to_local {
+      observers.deep_subscriptions.clear();
+      observers.undo_manager = None;
+      return;
+    }
+
+    if observers.undo_manager.is_none() {
+      let options = yrs::undo::Options {
+        capture_timeout_millis: 0,
+        ..Default::default()
+      };
+      let mut undo_manager = UndoManager::with_options(doc, data, options);
+      undo_manager.expand_scope(meta);
+      undo_manager.include_origin(self.local_origin.clone());
+      observers.undo_manager = Some(undo_manager);
+
+      for (section, root) in [(DATA_SECTION, data), (META_SECTION, meta)] {
+        let policy = self.policy.clone();
+        let violation = self.violation.clone();
+        let local_origin = self.local_origin.clone();
+        let mut root = root.clone();
+        observers
+          .deep_subscriptions
+          .push(root.observe_deep(move |txn, events| {
+            if CollabOrigin::from(txn) != local_origin || violation.lock().is_some() {
+              return;
+            }
+            let policy = policy.read();
+            let first_violation = touched_paths(section, txn, events)
+              .into_iter()
+              .find_map(|path| policy.check(&local_origin, &path).err());
+            if first_violation.is_some() {
+              *violation.lock() = first_violation;
+            }
+          }));
+      }
+    }
+  }
+
+  /// Reverts the local transactions since the last call if one of them broke the policy. Called
+  /// after each transaction of the collab.
+  pub(crate) fn enforce(&self) {
+    let mut observers = self.observers.lock();
+    let undo_manager = match observers.undo_manager.as_mut() {
+      None => return,
+      Some(undo_manager) => undo_manager,
+    };
+
+    if let Some(violation) = self.violation.lock().take() {
+      loop {
+        match undo_manager.undo() {
+          Ok(true) => continue,
+          Ok(false) => break,
+          Err(err) => {
+            tracing::error!(
+              "Failed to revert the change of {}: {}",
+              violation.origin,
+              err
+            );
+            break;
+          },
+        }
+      }
+      tracing::warn!("[{}]: revert local change: {}", self.object_id, violation);
+      let _ = self.sender.send(AccessViolationEvent {
+        object_id: self.object_id.clone(),
+        violation,
+        action: ViolationAction::Reverted,
+      });
+    }
+    if let Err(err) = undo_manager.clear() {
+      tracing::error!("Failed to clear the access guard history: {}", err);
+    }
+  }
+
+  /// Checks the update against the policy without touching the document. The changed paths are
+  /// read from the blocks of the update and looked up in the [ItemIndex].
+  pub(crate) fn check_remote_update(
+    &self,
+    origin: &CollabOrigin,
+    update: &[u8],
+  ) -> Result<(), CollabError> {
+    let policy = self.policy();
+    if !policy.applies_to(origin) {
+      return Ok(());
+    }
+
+    let touched = self.index.lock().touched_paths(update)?;
+    for path in touched {
+      if let Err(violation) = policy.check(origin, &path) {
+        tracing::warn!("[{}]: reject remote update: {}", self.object_id, violation);
+        let _ = self.sender.send(AccessViolationEvent {
+          object_id: self.object_id.clone(),
+          violation: violation.clone(),
+          action: ViolationAction::Rejected,
+        });
+        return Err(CollabError::AccessDenied(violation));
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Returns the paths changed by the events, prefixed with the name of the section. For map
+/// events the changed keys are part of the path.
+pub(crate) fn touched_paths(
+  section: &str,
+  txn: &TransactionMut,
+  events: &Events,
+) -> Vec<Vec<String>> {
+  let mut paths = vec![];
+  for event in events.iter() {
+    let mut path = vec![section.to_string()];
+    path.extend(event.path().iter().map(|segment| match segment {
+      PathSegment::Key(key) => key.to_string(),
+      PathSegment::Index(index) => index.to_string(),
+    }));
+    match event {
+      Event::Map(map_event) => {
+        for key in map_event.keys(txn).keys() {
+          let mut key_path = path.clone();
+          key_path.push(key.to_string());
+          paths.push(key_path);
+        }
+      },
+      _ => paths.push(path),
+    }
+  }
+  paths
+}
@@ -8,6 +8,7 @@ use yrs::{
   Any, Array, ArrayRef, MapPrelim, MapRef, ReadTxn, Transact, Transaction, TransactionMut,
 };
 
+use crate::error::CollabError;
 use crate::preclude::{CollabContext, MapRefExtension, MapRefWrapper, YrsValue};
 use crate::util::insert_json_value_to_array_ref;
 
@@ -29,17 +30,28 @@ impl ArrayRefWrapper {
     self.collab_ctx.transact()
   }
 
-  pub fn with_transact_mut<F, T>(&self, f: F) -> T
+  pub fn with_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
   where
     F: FnOnce(&mut TransactionMut) -> T,
   {
     self.collab_ctx.with_transact_mut(f)
   }
 
+  pub fn try_with_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    self.collab_ctx.try_with_transact_mut(f)
+  }
+
+  /// Logs an error if the collab is read-only.
   pub fn push<V: Prelim>(&self, value: V) {
-    self.with_transact_mut(|txn| {
+    let result = self.try_with_transact_mut(|txn| {
       self.array_ref.push_back(txn, value);
     });
+    if let Err(err) = result {
+      tracing::warn!("failed to push: {}", err);
+    }
   }
 
   pub fn push_json_with_txn<T: Serialize>(&self, txn: &mut TransactionMut, value: T) -> Result<()> {
@@ -3,6 +3,7 @@ use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::sync::Arc;
+use std::time::Duration;
 
 use crate::error::CollabError;
 use thiserror::Error;
@@ -11,6 +12,13 @@ use yrs::updates::decoder::{Decode, Decoder};
 use yrs::updates::encoder::{Encode, Encoder};
 use yrs::{Doc, Observer, Subscription};
 
+/// A remote client is removed if its state wasn't renewed within this time. The local state is
+/// renewed after half of it. Same as the `outdatedTimeout` of y-protocols.
+pub const OUTDATED_TIMEOUT_MILLIS: i64 = 30_000;
+
+/// How often [Awareness::check_outdated] is called by the heartbeat of a collab.
+pub const AWARENESS_HEARTBEAT_INTERVAL: Duration = Duration::from_secs(3);
+
 /// The Awareness class implements a simple shared state protocol that can be used for non-persistent
 /// data like awareness information (cursor, username, status, ..). Each client can update its own
 /// local state and listen to state changes of remote clients.
@@ -155,8 +163,56 @@ impl Awareness {
     self.remove_state(client_id);
   }
 
+  /// Implements the heartbeat of y-protocols. It should be called every few seconds, see
+  /// [Awareness::check_outdated_at].
+  pub fn check_outdated(&mut self) -> Vec<ClientID> {
+    self.check_outdated_at(chrono::Utc::now().timestamp_millis())
+  }
+
+  /// Renews the local state if it wasn't updated within half of the [OUTDATED_TIMEOUT_MILLIS],
+  /// so the other clients know this client is still alive. Removes the remote clients whose state
+  /// wasn't renewed within the [OUTDATED_TIMEOUT_MILLIS]. Returns the removed clients.
+  pub fn check_outdated_at(&mut self, now_millis: i64) -> Vec<ClientID> {
+    let client_id = self.doc.client_id();
+    let renewed_state = match (self.states.get(&client_id), self.meta.get(&client_id)) {
+      (Some(state), Some(meta))
+        if now_millis - meta.last_updated >= OUTDATED_TIMEOUT_MILLIS / 2 =>
+      {
+        Some(state.clone())
+      },
+      _ => None,
+    };
+    if let Some(state) = renewed_state {
+      self.set_local_state(state);
+    }
+
+    let outdated = self
+      .meta
+      .iter()
+      .filter(|(id, meta)| {
+        **id != client_id
+          && now_millis - meta.last_updated >= OUTDATED_TIMEOUT_MILLIS
+          && self.states.contains_key(id)
+      })
+      .map(|(id, _)| *id)
+      .collect::<Vec<_>>();
+    if !outdated.is_empty() {
+      for id in outdated.iter() {
+        // Keep the clock of the remote client, so its next state is accepted.
+        self.states.remove(id);
+      }
+      if let Some(eh) = self.on_update.as_ref() {
+        let e = Event::new(vec![], vec![], outdated.clone());
+        for cb in eh.callbacks() {
+          cb(self, &e);
+        }
+      }
+    }
+    outdated
+  }
+
   fn update_meta(&mut self, client_id: ClientID) {
-    let now = chrono::Utc::now().timestamp();
+    let now = chrono::Utc::now().timestamp_millis();
     match self.meta.entry(client_id) {
       Entry::Occupied(mut e) => {
         let clock = e.get().clock + 1;
@@ -213,7 +269,7 @@ impl Awareness {
   /// If current instance has an observer channel (see: [Awareness::with_observer]), applied
   /// changes will also be emitted as events.
   pub fn apply_update(&mut self, update: AwarenessUpdate) -> Result<(), Error> {
-    let now = chrono::Utc::now().timestamp();
+    let now = chrono::Utc::now().timestamp_millis();
 
     let mut added = Vec::new();
     let mut updated = Vec::new();
@@ -388,6 +444,7 @@ pub enum Error {
 #[derive(Debug, Clone, PartialEq, Eq)]
 struct MetaClientState {
   clock: u32,
+  /// Timestamp in milliseconds.
   last_updated: i64,
 }
 
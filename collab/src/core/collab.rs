@@ -1,36 +1,53 @@
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 use std::ops::{Deref, DerefMut};
 use std::panic;
 use std::panic::AssertUnwindSafe;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::time::Duration;
 use std::vec::IntoIter;
 
-use parking_lot::{Mutex, RwLock};
+use parking_lot::{Mutex, MutexGuard, RwLock};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use serde_json::json;
 
+use tokio::sync::broadcast;
 use tokio_stream::wrappers::WatchStream;
-use tracing::{error, trace};
-use yrs::block::Prelim;
+use tracing::{error, trace, warn};
+use yrs::block::{ClientID, Prelim};
 use yrs::types::map::MapEvent;
 use yrs::types::{ToJson, Value};
 use yrs::updates::decoder::Decode;
 
 use yrs::{
   Any, ArrayPrelim, ArrayRef, Doc, Map, MapPrelim, MapRef, Observable, OffsetKind, Options,
-  ReadTxn, Subscription, Transact, Transaction, TransactionMut, UndoManager, Update,
-  UpdateSubscription,
+  ReadTxn, Subscription, Transact, Transaction, TransactionMut, Update, UpdateSubscription,
 };
 
+use crate::core::access_control::{AccessGuard, AccessPolicy, AccessViolationReceiver};
 use crate::core::awareness::{
   gen_awareness_update_message, Awareness, AwarenessUpdateSubscription, Event,
 };
 use crate::core::collab_plugin::{CollabPlugin, CollabPluginType, EncodedCollab};
 use crate::core::collab_state::{InitState, SnapshotState, State, SyncState};
+use crate::core::compaction::{
+  compact_doc, CompactedCollab, CompactionState, COMPACTION_EPOCH, SUPERSEDED_BY_EPOCH,
+};
 use crate::core::map_wrapper::{CustomMapRef, MapRefWrapper};
+use crate::core::migration::{read_schema_version, CollabMigrator, MigrationReport};
 use crate::core::origin::{CollabClient, CollabOrigin};
+use crate::core::plugin_pipeline::{AsyncCollabPlugin, PluginFlush, PluginPipeline};
+use crate::core::read_only::{ReadOnlyGuard, ReadOnlyMode};
+use crate::core::subdoc::{
+  get_subdoc, make_subdoc, subdoc_ids, ParentPlugins, SubdocPluginFactory, SUBDOCS_SECTION,
+};
 use crate::core::transaction::{DocTransactionExtension, TransactionRetry};
+use crate::core::transaction_hook::{TransactionHook, TransactionHooks};
+use crate::core::undo::{
+  ScopedUndoManager, UndoConfig, UndoEvent, UndoEventKind, DEFAULT_UNDO_SCOPE,
+};
 use crate::core::value::YrsValueExtension;
 use crate::error::CollabError;
 use crate::preclude::{ArrayRefWrapper, JsonValue, MapRefExtension};
@@ -76,14 +93,37 @@ pub struct Collab {
 
   meta: MapRef,
 
+  /// The child documents. See [crate::core::subdoc].
+  subdocs: MapRef,
+  loaded_subdocs: Mutex<HashMap<String, MutexCollab>>,
+  /// Builds the plugins of the child documents.
+  subdoc_plugin_factory: Option<SubdocPluginFactory>,
+
   /// A list of plugins that are used to extend the functionality of the [Collab].
   plugins: Plugins,
 
+  /// Delivers the updates to the [AsyncCollabPlugin]s in order.
+  async_plugins: PluginPipeline,
+
+  /// Enforces the [AccessPolicy] of the [Collab].
+  access_guard: Arc<AccessGuard>,
+
+  /// Enforces the [ReadOnlyMode] of the [Collab].
+  read_only: Arc<ReadOnlyGuard>,
+
+  /// Runs the [TransactionHook]s before the local transactions commit.
+  transaction_hooks: Arc<TransactionHooks>,
+
+  /// Runs the pending migrations when the [Collab] is initialized.
+  migrator: Option<CollabMigrator>,
+  migration_report: Option<MigrationReport>,
+
   state: Arc<State>,
 
-  /// The [UndoManager] is used to undo and redo changes. By default, the [UndoManager]
-  /// is disabled. To enable it, call [Collab::enable_undo_manager].
-  undo_manager: Mutex<Option<UndoManager>>,
+  /// The undo managers of the [Collab], by scope. By default, there is none. To enable the
+  /// default one, call [Collab::enable_undo_redo].
+  undo_managers: Mutex<HashMap<String, ScopedUndoManager>>,
+  undo_event_sender: broadcast::Sender<UndoEvent>,
   update_subscription: RwLock<Option<UpdateSubscription>>,
   awareness_subscription: RwLock<Option<AwarenessUpdateSubscription>>,
   after_txn_subscription: RwLock<Option<AfterTransactionSubscription>>,
@@ -97,14 +137,20 @@ impl Drop for Collab {
 }
 
 pub fn make_yrs_doc() -> Doc {
-  Doc::with_options(Options {
+  Doc::with_options(yrs_doc_options())
+}
+
+fn yrs_doc_options() -> Options {
+  Options {
     skip_gc: true,
     offset_kind: OffsetKind::Utf16,
     ..Options::default()
-  })
+  }
 }
 
 impl Collab {
+  /// Only the first [CollabPluginType::CloudStorage] plugin is kept, see [Plugins::new]. Use
+  /// [CollabBuilder] or [Collab::add_plugins] to get an error for a duplicate instead.
   pub fn new<T: AsRef<str>>(
     uid: i64,
     object_id: T,
@@ -122,42 +168,88 @@ impl Collab {
     plugins: Vec<Box<dyn CollabPlugin>>,
   ) -> Result<Self, CollabError> {
     let collab = Self::new_with_origin(origin, object_id, plugins);
-    if !collab_doc_state.is_empty() {
-      let mut txn = collab.origin_transact_mut();
-      let decoded_update = Update::decode_v1(&collab_doc_state)?;
+    collab.apply_doc_state(&collab_doc_state)?;
+    Ok(collab)
+  }
+
+  fn apply_doc_state(&self, doc_state: &[u8]) -> Result<(), CollabError> {
+    if !doc_state.is_empty() {
+      let mut txn = self.origin_transact_mut()?;
+      let decoded_update = Update::decode_v1(doc_state)?;
       txn.try_apply_update(decoded_update)?;
     }
-    Ok(collab)
+    Ok(())
   }
 
+  /// See [Collab::new].
   pub fn new_with_origin<T: AsRef<str>>(
     origin: CollabOrigin,
     object_id: T,
     plugins: Vec<Box<dyn CollabPlugin>>,
+  ) -> Collab {
+    Self::new_with_yrs_doc(origin, object_id, make_yrs_doc(), Plugins::new(plugins))
+  }
+
+  fn new_with_yrs_doc<T: AsRef<str>>(
+    origin: CollabOrigin,
+    object_id: T,
+    doc: Doc,
+    plugins: Plugins,
   ) -> Collab {
     let object_id = object_id.as_ref().to_string();
-    let doc = make_yrs_doc();
     let data = doc.get_or_insert_map(DATA_SECTION);
     let meta = doc.get_or_insert_map(META_SECTION);
-    let undo_manager = Mutex::new(None);
-    let plugins = Plugins::new(plugins);
+    let subdocs = doc.get_or_insert_map(SUBDOCS_SECTION);
+    let undo_managers = Mutex::new(HashMap::new());
+    let async_plugins = PluginPipeline::new(&object_id, origin.clone());
+    let access_guard = Arc::new(AccessGuard::new(&object_id, origin.clone()));
+    let read_only = Arc::new(ReadOnlyGuard::new(&object_id));
+    let transaction_hooks = Arc::new(TransactionHooks::new(
+      &object_id,
+      origin.clone(),
+      vec![
+        (DATA_SECTION, data.clone()),
+        (META_SECTION, meta.clone()),
+        (SUBDOCS_SECTION, subdocs.clone()),
+      ],
+    ));
     let state = Arc::new(State::new(&object_id));
     let awareness = Awareness::new(doc.clone());
     let mut this = Self {
       origin,
       object_id,
       doc,
-      undo_manager,
+      undo_managers,
+      undo_event_sender: broadcast::channel(100).0,
       awareness,
       data,
       meta,
+      subdocs,
+      loaded_subdocs: Default::default(),
+      subdoc_plugin_factory: None,
       plugins,
+      async_plugins,
+      access_guard,
+      read_only,
+      transaction_hooks,
+      migrator: None,
+      migration_report: None,
       state,
       update_subscription: Default::default(),
       after_txn_subscription: Default::default(),
       awareness_subscription: Default::default(),
       index_json_sender: tokio::sync::broadcast::channel(100).0,
     };
+    for plugin in this.plugins.to_vec() {
+      if let Some(pipeline_plugin) = plugin.pipeline_plugin() {
+        if let Err(err) = this.async_plugins.add_plugin(pipeline_plugin) {
+          error!(
+            "{} failed to add a pipeline plugin: {}",
+            this.object_id, err
+          );
+        }
+      }
+    }
     this.emit_awareness_state();
     this
   }
@@ -179,6 +271,12 @@ impl Collab {
     WatchStream::new(self.state.snapshot_state_notifier.subscribe())
   }
 
+  /// The stream yields [CompactionState::Superseded] when the [Collab] was compacted by this or
+  /// another device. See [Collab::compact].
+  pub fn subscribe_compaction_state(&self) -> WatchStream<CompactionState> {
+    WatchStream::new(self.state.compaction_state_notifier.subscribe())
+  }
+
   pub fn clean_awareness_state(&mut self) {
     self.awareness.clean_local_state();
   }
@@ -215,25 +313,118 @@ impl Collab {
     &mut self.awareness
   }
 
-  /// Add a plugin to the [Collab]. The plugin's callbacks will be called in the order they are added.
-  pub fn add_plugin(&mut self, plugin: Box<dyn CollabPlugin>) {
-    self.add_plugins(vec![plugin]);
+  /// Add a plugin to the [Collab] with the [DEFAULT_PLUGIN_PRIORITY]. Plugins with the same
+  /// priority are called in the order they are added. The [CollabPlugin::pipeline_plugin] of the
+  /// plugin is added to the plugin pipeline.
+  ///
+  /// Returns an error if the plugin is a [CollabPluginType::CloudStorage] plugin and the [Collab]
+  /// already has one, or if it has a pipeline plugin and there is no async runtime. A plugin that
+  /// is added after [Collab::initialize] won't receive [CollabPlugin::init], its
+  /// [CollabPlugin::did_init] is called right away.
+  pub fn add_plugin(&mut self, plugin: Box<dyn CollabPlugin>) -> Result<PluginId, CollabError> {
+    self.add_plugin_with_priority(plugin, DEFAULT_PLUGIN_PRIORITY)
+  }
+
+  /// Same as [Collab::add_plugin], but the plugin's callbacks are called before the callbacks of
+  /// the plugins with a lower priority.
+  pub fn add_plugin_with_priority(
+    &mut self,
+    plugin: Box<dyn CollabPlugin>,
+    priority: i32,
+  ) -> Result<PluginId, CollabError> {
+    let plugin_id = self.plugins.add(plugin, priority)?;
+    self.add_pipeline_plugin(plugin_id)?;
+    self.did_add_plugin(plugin_id);
+    Ok(plugin_id)
+  }
+
+  /// Same as [Collab::add_plugin_with_priority], but takes the plugin without boxing it.
+  pub fn add_typed_plugin<T: CollabPlugin>(
+    &mut self,
+    plugin: T,
+    priority: i32,
+  ) -> Result<PluginId, CollabError> {
+    let plugin_id = self.plugins.add_typed(plugin, priority)?;
+    self.add_pipeline_plugin(plugin_id)?;
+    self.did_add_plugin(plugin_id);
+    Ok(plugin_id)
+  }
+
+  /// Add plugins to the [Collab]. Either all the plugins are added or none of them.
+  pub fn add_plugins(
+    &mut self,
+    plugins: Vec<Box<dyn CollabPlugin>>,
+  ) -> Result<Vec<PluginId>, CollabError> {
+    let cloud_plugins = plugins
+      .iter()
+      .filter(|plugin| plugin.plugin_type() == CollabPluginType::CloudStorage)
+      .count();
+    if cloud_plugins > 1 || (cloud_plugins == 1 && self.plugins.has_cloud_storage_plugin()) {
+      return Err(CollabError::DuplicateCloudStoragePlugin);
+    }
+
+    plugins
+      .into_iter()
+      .map(|plugin| self.add_plugin(plugin))
+      .collect()
   }
 
-  /// Add plugins to the [Collab]. The plugin's callbacks will be called in the order they are added.
-  pub fn add_plugins(&mut self, plugins: Vec<Box<dyn CollabPlugin>>) {
-    let mut write_guard = self.plugins.write();
+  /// Removes the plugin with the given id. The plugin won't receive any callbacks after it is
+  /// removed. Returns the removed plugin.
+  pub fn remove_plugin(&mut self, plugin_id: PluginId) -> Option<Arc<dyn CollabPlugin>> {
+    self.plugins.remove(plugin_id)
+  }
 
-    for plugin in plugins {
-      if plugin.plugin_type() == CollabPluginType::CloudStorage {
-        let is_exist = write_guard
-          .iter()
-          .find(|plugin| plugin.plugin_type() == CollabPluginType::CloudStorage);
-        if is_exist.is_some() {
-          tracing::error!("Only one cloud storage plugin can be added to a collab instance.");
-        }
+  /// Changes the priority of the plugin with the given id. Returns false if the plugin doesn't
+  /// exist.
+  pub fn set_plugin_priority(&self, plugin_id: PluginId, priority: i32) -> bool {
+    self.plugins.set_priority(plugin_id, priority)
+  }
+
+  /// Returns the first plugin of type `T`, in the order their callbacks are called.
+  pub fn get_plugin<T: CollabPlugin>(&self) -> Option<Arc<T>> {
+    self.plugins.get::<T>()
+  }
+
+  /// Returns the ids of the plugins with the given [CollabPluginType], in the order their
+  /// callbacks are called.
+  pub fn get_plugin_ids(&self, plugin_type: &CollabPluginType) -> Vec<PluginId> {
+    self.plugins.ids_of_type(plugin_type)
+  }
+
+  /// Adds a plugin whose callbacks run on the plugin pipeline of this [Collab]. It receives the
+  /// updates made after this call. Must be called within an async runtime.
+  pub fn add_async_plugin<T: AsyncCollabPlugin>(&self, plugin: T) -> Result<(), CollabError> {
+    self.async_plugins.add_plugin(Arc::new(plugin))
+  }
+
+  /// Returns the queue that feeds the [AsyncCollabPlugin]s. Writers that produce a lot of updates
+  /// can await [PluginPipeline::ready] to apply backpressure.
+  pub fn async_plugins(&self) -> &PluginPipeline {
+    &self.async_plugins
+  }
+
+  /// Removes the plugin again if its pipeline plugin can't be added.
+  fn add_pipeline_plugin(&self, plugin_id: PluginId) -> Result<(), CollabError> {
+    let pipeline_plugin = self
+      .plugins
+      .get_by_id(plugin_id)
+      .and_then(|plugin| plugin.pipeline_plugin());
+    if let Some(pipeline_plugin) = pipeline_plugin {
+      if let Err(err) = self.async_plugins.add_plugin(pipeline_plugin) {
+        self.plugins.remove(plugin_id);
+        return Err(err);
       }
-      write_guard.push(plugin);
+    }
+    Ok(())
+  }
+
+  fn did_add_plugin(&self, plugin_id: PluginId) {
+    if !matches!(self.state.get(), InitState::Initialized) {
+      return;
+    }
+    if let Some(plugin) = self.plugins.get_by_id(plugin_id) {
+      plugin.did_init(self, &self.object_id, self.get_last_sync_at());
     }
   }
 
@@ -251,17 +442,12 @@ impl Collab {
 
     self.state.set_init_state(InitState::Loading);
     {
-      for plugin in self.plugins.read().iter() {
+      for plugin in self.plugins.to_vec() {
         plugin.init(&self.object_id, &self.origin, &self.doc);
       }
     }
 
-    let (update_subscription, after_txn_subscription) = observe_doc(
-      &self.doc,
-      self.object_id.clone(),
-      self.plugins.clone(),
-      self.origin.clone(),
-    );
+    let (update_subscription, after_txn_subscription) = observe_doc(self);
 
     let awareness_subscription = observe_awareness(
       &mut self.awareness,
@@ -278,10 +464,10 @@ impl Collab {
     {
       self
         .plugins
-        .read()
-        .iter()
-        .for_each(|plugin| plugin.did_init(self, &self.object_id, last_sync_at));
+        .each(|plugin| plugin.did_init(self, &self.object_id, last_sync_at));
     }
+    self.refresh_compaction_state();
+    self.run_migrations_on_init();
     self.state.set_init_state(InitState::Initialized);
   }
 
@@ -293,17 +479,12 @@ impl Collab {
 
     self.state.set_init_state(InitState::Loading);
     {
-      for plugin in self.plugins.read().iter() {
+      for plugin in self.plugins.to_vec() {
         plugin.init(&self.object_id, &self.origin, &self.doc).await;
       }
     }
 
-    let (update_subscription, after_txn_subscription) = observe_doc(
-      &self.doc,
-      self.object_id.clone(),
-      self.plugins.clone(),
-      self.origin.clone(),
-    );
+    let (update_subscription, after_txn_subscription) = observe_doc(self);
 
     let awareness_subscription = observe_awareness(
       &mut self.awareness,
@@ -320,10 +501,10 @@ impl Collab {
     {
       self
         .plugins
-        .read()
-        .iter()
-        .for_each(|plugin| plugin.did_init(&self, &self.object_id, last_sync_at));
+        .each(|plugin| plugin.did_init(self, &self.object_id, last_sync_at));
     }
+    self.refresh_compaction_state();
+    self.run_migrations_on_init();
     self.state.set_init_state(InitState::Initialized);
   }
 
@@ -332,6 +513,7 @@ impl Collab {
       Ok(mut txn) => {
         self.set_last_sync_at_with_txn(&mut txn, last_sync_at);
       },
+      Err(CollabError::ReadOnly(_)) => {},
       Err(_) => {
         error!("Fail to set last sync at");
       },
@@ -359,22 +541,254 @@ impl Collab {
     self.state.set_snapshot_state(snapshot_state);
   }
 
-  pub fn reset(&self) {
+  /// Returns [CompactionState::Superseded] if the `meta` section holds the superseded mark, or if
+  /// a peer at a newer epoch was seen by [Collab::check_remote_epoch].
+  pub fn get_compaction_state(&self) -> CompactionState {
+    match self.state.get_compaction_state() {
+      superseded @ CompactionState::Superseded { .. } => superseded,
+      CompactionState::Current { .. } => CompactionState::from_meta(&self.transact(), &self.meta),
+    }
+  }
+
+  /// Returns the epoch of the history stored in the [Collab]. See [crate::core::compaction].
+  pub fn get_compaction_epoch(&self) -> i64 {
     self
-      .plugins
-      .read()
-      .iter()
-      .for_each(|plugin| plugin.reset(&self.object_id));
+      .meta
+      .get_i64_with_txn(&self.transact(), COMPACTION_EPOCH)
+      .unwrap_or(0)
+  }
+
+  /// Checks the compaction epoch of a remote peer before its updates are applied. The updates of
+  /// a peer at an older epoch would merge the old history into the compacted document, so they
+  /// are rejected with [CollabError::StaleEpoch]. A peer at a newer epoch means that the [Collab]
+  /// was compacted on another device: it's marked as superseded and
+  /// [CollabError::Superseded] is returned.
+  pub fn check_remote_epoch(&self, remote_epoch: i64) -> Result<(), CollabError> {
+    let epoch = match self.get_compaction_state() {
+      CompactionState::Superseded { epoch } => return Err(CollabError::Superseded(epoch)),
+      CompactionState::Current { epoch } => epoch,
+    };
+    match remote_epoch.cmp(&epoch) {
+      std::cmp::Ordering::Equal => Ok(()),
+      std::cmp::Ordering::Less => Err(CollabError::StaleEpoch {
+        remote: remote_epoch,
+        epoch,
+      }),
+      std::cmp::Ordering::Greater => {
+        // Keep the mark in the document, so it survives a reload. A read-only collab only
+        // keeps it in memory.
+        let _ = self.try_with_origin_transact_mut(|txn| {
+          self
+            .meta
+            .insert_i64_with_txn(txn, SUPERSEDED_BY_EPOCH, remote_epoch);
+        });
+        self
+          .state
+          .set_compaction_state(CompactionState::Superseded {
+            epoch: remote_epoch,
+          });
+        Err(CollabError::Superseded(remote_epoch))
+      },
+    }
+  }
+
+  /// Copies the current content of the [Collab] into a new document without history and
+  /// tombstones, and returns it with its epoch. The [Collab] itself is marked as superseded by
+  /// that epoch and the mark is sent to the plugins like any other local update, so the devices
+  /// that hold the old history learn that they must replace it with the compacted document.
+  ///
+  /// The [Collab] should be dropped after the compacted document is persisted.
+  pub fn compact(&self) -> Result<CompactedCollab, CollabError> {
+    let epoch = match self.get_compaction_state() {
+      CompactionState::Superseded { epoch } => return Err(CollabError::Superseded(epoch)),
+      CompactionState::Current { epoch } => epoch + 1,
+    };
+    let encoded_collab = compact_doc(
+      &self.transact(),
+      &self.data,
+      &self.meta,
+      &self.subdocs,
+      epoch,
+    );
+    self.try_with_origin_transact_mut(|txn| {
+      self
+        .meta
+        .insert_i64_with_txn(txn, SUPERSEDED_BY_EPOCH, epoch);
+    })?;
+    self.refresh_compaction_state();
+    Ok(CompactedCollab {
+      epoch,
+      encoded_collab,
+    })
+  }
+
+  /// Returns the schema version of the [Collab]. See [CollabMigrator].
+  pub fn get_schema_version(&self) -> u32 {
+    read_schema_version(&self.transact(), &self.meta)
+  }
+
+  /// Sets the migrator that runs when the [Collab] is initialized.
+  pub fn set_migrator(&mut self, migrator: CollabMigrator) {
+    self.migrator = Some(migrator);
+  }
+
+  /// Runs the migrations that the [Collab] hasn't applied yet.
+  pub fn run_migrations(&self, migrator: &CollabMigrator) -> Result<MigrationReport, CollabError> {
+    migrator.run(self)
+  }
+
+  /// Returns the migrations that ran when the [Collab] was initialized.
+  pub fn get_migration_report(&self) -> Option<&MigrationReport> {
+    self.migration_report.as_ref()
+  }
+
+  /// Sets the factory that builds the plugins of each child document when it's loaded. The
+  /// children loaded before keep their plugins.
+  pub fn set_subdoc_plugin_factory<F>(&mut self, factory: F)
+  where
+    F: Fn(&str) -> Vec<Box<dyn CollabPlugin>> + Send + Sync + 'static,
+  {
+    self.subdoc_plugin_factory = Some(Arc::new(factory));
+  }
+
+  /// Adds an empty child document with the given object id and returns it loaded. See
+  /// [crate::core::subdoc].
+  #[cfg(not(feature = "async-plugin"))]
+  pub fn create_subdoc(&self, object_id: &str) -> Result<MutexCollab, CollabError> {
+    self.insert_subdoc(object_id)?;
+    self.load_subdoc(object_id)
+  }
+
+  #[cfg(feature = "async-plugin")]
+  pub async fn create_subdoc(&self, object_id: &str) -> Result<MutexCollab, CollabError> {
+    self.insert_subdoc(object_id)?;
+    self.load_subdoc(object_id).await
+  }
+
+  /// Loads the child document with the given object id. The child is initialized through the
+  /// plugins of this [Collab] and with the plugins built by the [SubdocPluginFactory] the first
+  /// time it's loaded, the following calls return the same instance until [Collab::unload_subdoc]
+  /// is called.
+  #[cfg(not(feature = "async-plugin"))]
+  pub fn load_subdoc(&self, object_id: &str) -> Result<MutexCollab, CollabError> {
+    if let Some(child) = self.loaded_subdocs.lock().get(object_id) {
+      return Ok(child.clone());
+    }
+    let child = self.open_subdoc(object_id)?;
+    child.lock().initialize();
+    Ok(self.cache_subdoc(object_id, child))
+  }
+
+  #[cfg(feature = "async-plugin")]
+  pub async fn load_subdoc(&self, object_id: &str) -> Result<MutexCollab, CollabError> {
+    if let Some(child) = self.loaded_subdocs.lock().get(object_id) {
+      return Ok(child.clone());
+    }
+    let child = self.open_subdoc(object_id)?;
+    {
+      let mut collab = child.lock();
+      collab.initialize().await;
+    }
+    Ok(self.cache_subdoc(object_id, child))
+  }
+
+  /// Drops the loaded child document. Its content stays in the parent's storage and can be
+  /// loaded again.
+  pub fn unload_subdoc(&self, object_id: &str) -> Option<MutexCollab> {
+    self.loaded_subdocs.lock().remove(object_id)
+  }
+
+  /// Removes the child document from this [Collab]. Returns false if there is no such child.
+  pub fn remove_subdoc(&self, object_id: &str) -> bool {
+    if self.is_read_only() {
+      return false;
+    }
+    self.unload_subdoc(object_id);
+    self
+      .try_with_origin_transact_mut(|txn| self.subdocs.remove(txn, object_id))
+      .map_or(false, |value| value.is_some())
+  }
+
+  /// Returns the object ids of the child documents, loaded or not.
+  pub fn get_subdoc_ids(&self) -> Vec<String> {
+    subdoc_ids(&self.transact(), &self.subdocs)
+  }
+
+  pub fn is_subdoc_loaded(&self, object_id: &str) -> bool {
+    self.loaded_subdocs.lock().contains_key(object_id)
+  }
+
+  fn insert_subdoc(&self, object_id: &str) -> Result<(), CollabError> {
+    if get_subdoc(&self.transact(), &self.subdocs, object_id).is_some() {
+      return Err(CollabError::SubdocAlreadyExists(object_id.to_string()));
+    }
+    self.try_with_origin_transact_mut(|txn| {
+      self.subdocs.insert(txn, object_id, make_subdoc(object_id));
+    })
+  }
+
+  /// Marks the child document as loaded and wraps it into a [Collab] that is routed through the
+  /// plugins of this [Collab], plus the ones built by the [SubdocPluginFactory]. The child shares
+  /// the [ReadOnlyMode] and the [SubdocPluginFactory] of this [Collab].
+  fn open_subdoc(&self, object_id: &str) -> Result<MutexCollab, CollabError> {
+    let doc = get_subdoc(&self.transact(), &self.subdocs, object_id)
+      .ok_or_else(|| CollabError::SubdocNotFound(object_id.to_string()))?;
+    let plugins = Plugins::default();
+    // Restores the child before its own plugins are initialized.
+    plugins.add_typed(ParentPlugins(self.plugins.clone()), i32::MAX)?;
+    if let Some(factory) = &self.subdoc_plugin_factory {
+      for plugin in factory(object_id) {
+        plugins.add(plugin, DEFAULT_PLUGIN_PRIORITY)?;
+      }
+    }
+    // Loading only flags the child as loaded, the content of this collab doesn't change. So it
+    // is allowed when this collab is read-only.
+    doc.load(&mut TransactionRetry::new(&self.doc).get_write_txn_with(self.origin.clone()));
+    let mut child = Collab::new_with_yrs_doc(self.origin.clone(), object_id, doc, plugins);
+    child.subdoc_plugin_factory = self.subdoc_plugin_factory.clone();
+    child.set_read_only_mode(self.get_read_only_mode());
+    Ok(MutexCollab::from_collab(child))
+  }
+
+  /// Returns the cached child if another call loaded it in the meantime.
+  fn cache_subdoc(&self, object_id: &str, child: MutexCollab) -> MutexCollab {
+    self
+      .loaded_subdocs
+      .lock()
+      .entry(object_id.to_string())
+      .or_insert(child)
+      .clone()
+  }
+
+  fn run_migrations_on_init(&mut self) {
+    if let Some(migrator) = self.migrator.as_ref() {
+      match migrator.run(self) {
+        Ok(report) => self.migration_report = Some(report),
+        Err(err) => error!("{} failed to migrate: {}", self.object_id, err),
+      }
+    }
+  }
+
+  fn refresh_compaction_state(&self) {
+    self.state.set_compaction_state(self.get_compaction_state());
+  }
+
+  pub fn reset(&self) {
+    self.plugins.each(|plugin| plugin.reset(&self.object_id));
+    self.async_plugins.push_reset();
   }
 
   /// Make a full update with the current state of the [Collab].
-  /// It invokes the [CollabPlugin::flush] method of each plugin.
-  pub fn flush(&self) {
+  /// It invokes the [CollabPlugin::flush] method of each plugin, and queues a flush for the
+  /// [AsyncCollabPlugin]s. The returned [PluginFlush] resolves when the [AsyncCollabPlugin]s have
+  /// handled it. Release the lock of the [MutexCollab] before awaiting it.
+  pub fn flush(&self) -> PluginFlush {
     self
       .plugins
-      .read()
-      .iter()
-      .for_each(|plugin| plugin.flush(&self.object_id, &self.doc));
+      .each(|plugin| plugin.flush(&self.object_id, &self.doc));
+    self
+      .async_plugins
+      .push_flush(|| self.doc.get_encoded_collab_v1())
   }
 
   pub fn observe_data<F>(&mut self, f: F) -> MapSubscription
@@ -400,8 +814,15 @@ impl Collab {
     self.data.get(txn, key)
   }
 
-  pub fn insert<V: Prelim>(&self, key: &str, value: V) -> V::Return {
-    self.with_origin_transact_mut(|txn| self.insert_with_txn(txn, key, value))
+  /// Logs a warning and returns [None] if the [Collab] is read-only.
+  pub fn insert<V: Prelim>(&self, key: &str, value: V) -> Option<V::Return> {
+    match self.with_origin_transact_mut(|txn| self.insert_with_txn(txn, key, value)) {
+      Ok(value) => Some(value),
+      Err(err) => {
+        warn!("failed to insert {}: {}", key, err);
+        None
+      },
+    }
   }
 
   pub fn insert_with_txn<V: Prelim>(
@@ -421,13 +842,16 @@ impl Collab {
       self.get_map_with_txn(&txn, path).map(|m| m.into_inner())
     };
 
-    self.with_origin_transact_mut(|txn| {
+    let result = self.try_with_origin_transact_mut(|txn| {
       if map.is_none() {
         map = Some(self.data.insert(txn, key, MapPrelim::<Any>::new()));
       }
       let value = serde_json::to_value(&value).unwrap();
       insert_json_value_to_map_ref(key, &value, map.unwrap(), txn);
     });
+    if let Err(err) = result {
+      warn!("[{}]: failed to insert {}: {}", self.object_id, key, err);
+    }
   }
 
   pub fn get_json_with_path<T: DeserializeOwned>(&self, path: impl Into<Path>) -> Option<T> {
@@ -526,11 +950,15 @@ impl Collab {
     map_ref?.get(txn, &last)
   }
 
+  /// Returns None if the [Collab] is read-only.
   pub fn remove(&mut self, key: &str) -> Option<Value> {
-    let mut txn = self.origin_transact_mut();
-    self.data.remove(&mut txn, key)
+    self
+      .try_with_origin_transact_mut(|txn| self.data.remove(txn, key))
+      .ok()
+      .flatten()
   }
 
+  /// Returns None if the [Collab] is read-only.
   pub fn remove_with_path<P: Into<Path>>(&mut self, path: P) -> Option<Value> {
     let path = path.into();
     if path.is_empty() {
@@ -538,7 +966,10 @@ impl Collab {
     }
     let len = path.len();
     if len == 1 {
-      self.with_origin_transact_mut(|txn| self.data.remove(txn, &path[0]))
+      self
+        .try_with_origin_transact_mut(|txn| self.data.remove(txn, &path[0]))
+        .ok()
+        .flatten()
     } else {
       let txn = self.transact();
       let mut iter = path.into_iter();
@@ -557,7 +988,10 @@ impl Collab {
       drop(txn);
 
       let map_ref = map_ref?;
-      self.with_origin_transact_mut(|txn| map_ref.remove(txn, &remove_path))
+      self
+        .try_with_origin_transact_mut(|txn| map_ref.remove(txn, &remove_path))
+        .ok()
+        .flatten()
     }
   }
 
@@ -575,25 +1009,65 @@ impl Collab {
     serde_json::to_value(&self.data.to_json(&txn)).unwrap()
   }
 
+  /// Enables the undo manager of the [DEFAULT_UNDO_SCOPE]. It tracks the local changes of the
+  /// whole data section.
   pub fn enable_undo_redo(&mut self) {
-    if self.undo_manager.lock().is_some() {
+    if self.undo_managers.lock().contains_key(DEFAULT_UNDO_SCOPE) {
       tracing::warn!("Undo manager already enabled");
       return;
     }
     // a frequent case includes establishing a new transaction for every user key stroke. Meanwhile
     // we may decide to use different granularity of undo/redo actions. These are grouped together
     // on time-based ranges (configurable in undo::Options, which is 500ms by default).
-    let mut undo_manager =
-      UndoManager::with_options(&self.doc, &self.data, yrs::undo::Options::default());
-    undo_manager.include_origin(self.origin.clone());
-    *self.undo_manager.lock() = Some(undo_manager);
+    if let Err(err) = self.enable_scoped_undo_redo(DEFAULT_UNDO_SCOPE, UndoConfig::default()) {
+      error!("Failed to enable undo manager: {}", err);
+    }
+  }
+
+  /// Creates an undo manager for the scope. It replaces the undo manager of the scope if one
+  /// exists. The path of the [UndoConfig] must point to an existing map, array or text.
+  pub fn enable_scoped_undo_redo(
+    &self,
+    scope: &str,
+    config: UndoConfig,
+  ) -> Result<(), CollabError> {
+    let undo_manager = ScopedUndoManager::new(&self.doc, &self.data, scope, config, &self.origin)?;
+    self
+      .undo_managers
+      .lock()
+      .insert(scope.to_string(), undo_manager);
+    Ok(())
+  }
+
+  /// Removes the undo manager of the scope. Returns false if the scope had none.
+  pub fn disable_scoped_undo_redo(&self, scope: &str) -> bool {
+    self.undo_managers.lock().remove(scope).is_some()
   }
 
   /// Undo the previous change.
   /// Returns true if the undo was successful, false if there was nothing to undo. If the
   /// UndoManager is not enabled, returns false.
   pub fn can_undo(&self) -> bool {
-    match &*self.undo_manager.lock() {
+    self.can_undo_scope(DEFAULT_UNDO_SCOPE)
+  }
+
+  /// Redo the previous change.
+  /// Returns true if the redo was successful, false if there was nothing to redo. If the
+  /// UndoManager is not enabled, returns false.
+  pub fn can_redo(&self) -> bool {
+    self.can_redo_scope(DEFAULT_UNDO_SCOPE)
+  }
+
+  pub fn undo(&mut self) -> Result<bool, CollabError> {
+    Ok(self.undo_scope(DEFAULT_UNDO_SCOPE)?.is_some())
+  }
+
+  pub fn redo(&mut self) -> Result<bool, CollabError> {
+    Ok(self.redo_scope(DEFAULT_UNDO_SCOPE)?.is_some())
+  }
+
+  pub fn can_undo_scope(&self, scope: &str) -> bool {
+    match self.undo_managers.lock().get(scope) {
       None => {
         tracing::warn!("Undo manager not enabled, should enable_undo_redo first");
         false
@@ -602,11 +1076,8 @@ impl Collab {
     }
   }
 
-  /// Redo the previous change.
-  /// Returns true if the redo was successful, false if there was nothing to redo. If the
-  /// UndoManager is not enabled, returns false.
-  pub fn can_redo(&self) -> bool {
-    match &*self.undo_manager.lock() {
+  pub fn can_redo_scope(&self, scope: &str) -> bool {
+    match self.undo_managers.lock().get(scope) {
       None => {
         tracing::warn!("Undo manager not enabled, should enable_undo_redo first");
         false
@@ -615,20 +1086,52 @@ impl Collab {
     }
   }
 
-  pub fn undo(&mut self) -> Result<bool, CollabError> {
-    match &mut *self.undo_manager.lock() {
+  /// Undoes the last change of the scope. Returns None if there was nothing to undo. The
+  /// returned [UndoEvent] is also sent to the subscribers of [Collab::subscribe_undo_event].
+  pub fn undo_scope(&self, scope: &str) -> Result<Option<UndoEvent>, CollabError> {
+    self.apply_undo(scope, UndoEventKind::Undo)
+  }
+
+  /// Redoes the last undone change of the scope. See [Collab::undo_scope].
+  pub fn redo_scope(&self, scope: &str) -> Result<Option<UndoEvent>, CollabError> {
+    self.apply_undo(scope, UndoEventKind::Redo)
+  }
+
+  /// Marks a boundary in the undo stack of the scope: the next change is undone separately from
+  /// the previous ones, even if it's made within the capture timeout.
+  pub fn stop_capturing(&self, scope: &str) -> Result<(), CollabError> {
+    match self.undo_managers.lock().get_mut(scope) {
       None => Err(CollabError::UndoManagerNotEnabled),
-      Some(mgr) => mgr.undo().map_err(|e| CollabError::Internal(Box::new(e))),
+      Some(mgr) => {
+        mgr.stop_capturing();
+        Ok(())
+      },
     }
   }
 
-  pub fn redo(&mut self) -> Result<bool, CollabError> {
-    match &mut *self.undo_manager.lock() {
+  /// Removes all the changes from the undo and redo stacks of the scope.
+  pub fn clear_undo_stack(&self, scope: &str) -> Result<(), CollabError> {
+    match self.undo_managers.lock().get_mut(scope) {
       None => Err(CollabError::UndoManagerNotEnabled),
-      Some(mgr) => mgr.redo().map_err(|e| CollabError::Internal(Box::new(e))),
+      Some(mgr) => mgr.clear(),
     }
   }
 
+  pub fn subscribe_undo_event(&self) -> broadcast::Receiver<UndoEvent> {
+    self.undo_event_sender.subscribe()
+  }
+
+  fn apply_undo(&self, scope: &str, kind: UndoEventKind) -> Result<Option<UndoEvent>, CollabError> {
+    let event = match self.undo_managers.lock().get_mut(scope) {
+      None => return Err(CollabError::UndoManagerNotEnabled),
+      Some(mgr) => mgr.apply(kind)?,
+    };
+    if let Some(event) = &event {
+      let _ = self.undo_event_sender.send(event.clone());
+    }
+    Ok(event)
+  }
+
   pub fn transact(&self) -> Transaction {
     TransactionRetry::new(&self.doc).get_read_txn()
   }
@@ -644,14 +1147,20 @@ impl Collab {
     TransactionRetry::new(&self.doc).try_get_write_txn()
   }
 
+  /// Returns [CollabError::ReadOnly] if the [Collab] is read-only.
   pub fn try_origin_transaction_mut(&self) -> Result<TransactionMut, CollabError> {
+    self.read_only.check_local_change()?;
     TransactionRetry::new(&self.doc).try_get_write_txn_with(self.origin.clone())
   }
 
   /// Returns a transaction that can mutate the document. This transaction will carry the
-  /// origin of the current user.
-  pub fn origin_transact_mut(&self) -> TransactionMut {
-    TransactionRetry::new(&self.doc).get_write_txn_with(self.origin.clone())
+  /// origin of the current user. Prefer [Collab::with_origin_transact_mut], which runs the
+  /// [TransactionHook]s.
+  ///
+  /// Returns [CollabError::ReadOnly] if the [Collab] is read-only.
+  pub fn origin_transact_mut(&self) -> Result<TransactionMut, CollabError> {
+    self.read_only.check_local_change()?;
+    Ok(TransactionRetry::new(&self.doc).get_write_txn_with(self.origin.clone()))
   }
 
   /// Returns a transaction that can mutate the document. This transaction will carry the
@@ -659,26 +1168,142 @@ impl Collab {
   ///
   /// If applying the remote update, please use the `transact_mut` of `doc`. Ot
   /// update will send to remote that the remote already has.
-  pub fn with_origin_transact_mut<F, T>(&self, f: F) -> T
+  ///
+  /// The transactions aborted by a [TransactionHook] are reverted once they commit. Use
+  /// [Collab::try_with_origin_transact_mut] to get the error of the hook.
+  ///
+  /// Returns [CollabError::ReadOnly] without running `f` if the [Collab] is read-only, see
+  /// [crate::core::read_only].
+  pub fn with_origin_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
   where
     F: FnOnce(&mut TransactionMut) -> T,
   {
-    let mut txn = TransactionRetry::new(&self.doc).get_write_txn_with(self.origin.clone());
-    let ret = f(&mut txn);
-    drop(txn);
-    ret
+    self.read_only.check_local_change()?;
+    Ok(self.local_transact_mut(f).0)
+  }
+
+  /// Like [Collab::with_origin_transact_mut], but also returns the error of the
+  /// [TransactionHook] that aborted the transaction.
+  pub fn try_with_origin_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    self.read_only.check_local_change()?;
+    let (ret, result) = self.local_transact_mut(f);
+    result.map(|_| ret)
+  }
+
+  fn local_transact_mut<F, T>(&self, f: F) -> (T, Result<(), CollabError>)
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    local_transact_mut(
+      &self.object_id,
+      &self.origin,
+      &self.doc,
+      &self.plugins,
+      &self.access_guard,
+      &self.transaction_hooks,
+      f,
+    )
+  }
+
+  /// Adds a hook that runs before each local transaction of the [Collab] and its wrappers
+  /// commits. The hooks run in the order they were added. See [crate::core::transaction_hook].
+  pub fn add_transaction_hook<H>(&self, hook: H)
+  where
+    H: TransactionHook,
+  {
+    self.transaction_hooks.add(Arc::new(hook), &self.doc);
+  }
+
+  /// Sets the [ReadOnlyMode] of the [Collab], see [crate::core::read_only].
+  pub fn set_read_only_mode(&self, mode: ReadOnlyMode) {
+    self.read_only.set_mode(mode);
+  }
+
+  pub fn get_read_only_mode(&self) -> ReadOnlyMode {
+    self.read_only.mode()
+  }
+
+  pub fn is_read_only(&self) -> bool {
+    !self.read_only.mode().allows_local_changes()
+  }
+
+  /// Sets the [AccessPolicy] of the [Collab]. Local transactions that break the policy are
+  /// reverted once they are committed. Transactions that are not created by the [Collab] or its
+  /// wrappers are checked with the next transaction, or by calling [Collab::enforce_access_policy].
+  pub fn set_access_policy(&self, policy: AccessPolicy) {
+    self
+      .access_guard
+      .set_policy(policy, &self.doc, &self.data, &self.meta);
+  }
+
+  pub fn get_access_policy(&self) -> AccessPolicy {
+    self.access_guard.policy()
+  }
+
+  /// Reverts the local changes made since the last check if one of them broke the
+  /// [AccessPolicy].
+  pub fn enforce_access_policy(&self) {
+    self.access_guard.enforce();
+  }
+
+  /// Returns a receiver of the changes that were rejected or reverted because of the
+  /// [AccessPolicy].
+  pub fn subscribe_access_violation(&self) -> AccessViolationReceiver {
+    self.access_guard.subscribe()
+  }
+
+  /// Applies a v1 encoded update that was made by the given origin. The update is rejected with
+  /// [CollabError::AccessDenied] if it breaks the [AccessPolicy], with
+  /// [CollabError::Superseded] if the [Collab] was compacted, and with [CollabError::ReadOnly] if
+  /// the [Collab] is frozen.
+  pub fn apply_remote_update(
+    &self,
+    origin: &CollabOrigin,
+    update: &[u8],
+  ) -> Result<(), CollabError> {
+    if let CompactionState::Superseded { epoch } = self.get_compaction_state() {
+      return Err(CollabError::Superseded(epoch));
+    }
+    self.read_only.check_remote_update()?;
+    self.access_guard.check_remote_update(origin, update)?;
+    let update = Update::decode_v1(update)?;
+    {
+      let mut txn = TransactionRetry::new(&self.doc).try_get_write_txn_with(origin.clone())?;
+      txn.try_apply_update(update)?;
+    }
+    self.refresh_compaction_state();
+    Ok(())
   }
 
   fn map_wrapper_with(&self, map_ref: MapRef) -> MapRefWrapper {
     MapRefWrapper::new(
       map_ref,
-      CollabContext::new(self.origin.clone(), self.plugins.clone(), self.doc.clone()),
+      CollabContext::new(
+        self.object_id.clone(),
+        self.origin.clone(),
+        self.plugins.clone(),
+        self.doc.clone(),
+        self.access_guard.clone(),
+        self.read_only.clone(),
+        self.transaction_hooks.clone(),
+      ),
     )
   }
   fn array_wrapper_with(&self, array_ref: ArrayRef) -> ArrayRefWrapper {
     ArrayRefWrapper::new(
       array_ref,
-      CollabContext::new(self.origin.clone(), self.plugins.clone(), self.doc.clone()),
+      CollabContext::new(
+        self.object_id.clone(),
+        self.origin.clone(),
+        self.plugins.clone(),
+        self.doc.clone(),
+        self.access_guard.clone(),
+        self.read_only.clone(),
+        self.transaction_hooks.clone(),
+      ),
     )
   }
 }
@@ -691,10 +1316,7 @@ fn observe_awareness(
 ) -> AwarenessUpdateSubscription {
   awareness.on_update(move |awareness, event| {
     if let Ok(update) = gen_awareness_update_message(awareness, event) {
-      plugins
-        .read()
-        .iter()
-        .for_each(|plugin| plugin.receive_local_state(&origin, &oid, event, &update));
+      plugins.each(|plugin| plugin.receive_local_state(&origin, &oid, event, &update));
     }
   })
 }
@@ -702,23 +1324,31 @@ fn observe_awareness(
 /// Observe a document for updates.
 /// Use the uid and the device_id to verify that the update is local or remote.
 /// If the update is local, the plugins will be notified.
-fn observe_doc(
-  doc: &Doc,
-  oid: String,
-  plugins: Plugins,
-  local_origin: CollabOrigin,
-) -> (UpdateSubscription, AfterTransactionSubscription) {
+fn observe_doc(collab: &Collab) -> (UpdateSubscription, AfterTransactionSubscription) {
+  let oid = collab.object_id.clone();
+  let plugins = collab.plugins.clone();
+  let async_plugins = collab.async_plugins.clone();
+  let local_origin = collab.origin.clone();
+  let meta = collab.meta.clone();
+  let state = collab.state.clone();
+  let transaction_hooks = collab.transaction_hooks.clone();
   let cloned_oid = oid.clone();
   let cloned_plugins = plugins.clone();
-  let update_sub = doc
+  let cloned_transaction_hooks = transaction_hooks.clone();
+  let update_sub = collab
+    .doc
     .observe_update_v1(move |txn, event| {
+      // The plugins only receive the redacted update of an aborted transaction.
+      let update = cloned_transaction_hooks.plugin_update(&event.update);
+      // The revert of an aborted transaction is sent like the transaction itself.
+      let remote_origin = CollabOrigin::from(txn);
+      let is_local = remote_origin == local_origin || cloned_transaction_hooks.is_revert(txn);
       // If the origin of the txn is none, it means that the update is coming from a remote source.
-      cloned_plugins.read().iter().for_each(|plugin| {
-        plugin.receive_update(&cloned_oid, txn, &event.update);
+      cloned_plugins.each(|plugin| {
+        plugin.receive_update(&cloned_oid, txn, &update);
 
-        let remote_origin = CollabOrigin::from(txn);
-        if remote_origin == local_origin {
-          plugin.receive_local_update(&local_origin, &cloned_oid, &event.update);
+        if is_local {
+          plugin.receive_local_update(&local_origin, &cloned_oid, &update);
         } else {
           tracing::trace!(
             "[Client]: {} did apply remote {} update",
@@ -727,15 +1357,22 @@ fn observe_doc(
           );
         }
       });
+
+      async_plugins.push_update(&update, is_local);
     })
     .unwrap();
 
-  let after_txn_sub = doc
+  let after_txn_sub = collab
+    .doc
     .observe_after_transaction(move |txn| {
-      plugins
-        .read()
-        .iter()
-        .for_each(|plugin| plugin.after_transaction(&oid, txn));
+      if !transaction_hooks.is_aborting() {
+        plugins.each(|plugin| plugin.after_transaction(&oid, txn));
+      }
+      // The superseded mark can arrive with any transaction, including the ones the plugins
+      // open on the document.
+      if !state.get_compaction_state().is_superseded() {
+        state.set_compaction_state(CompactionState::from_meta(txn, &meta));
+      }
     })
     .unwrap();
 
@@ -753,9 +1390,18 @@ impl Display for Collab {
 pub struct CollabBuilder {
   uid: i64,
   device_id: String,
-  plugins: Vec<Box<dyn CollabPlugin>>,
+  plugins: Plugins,
+  async_plugins: Vec<Arc<dyn AsyncCollabPlugin>>,
+  plugin_queue_capacity: Option<usize>,
+  access_policy: Option<AccessPolicy>,
+  read_only_mode: ReadOnlyMode,
+  transaction_hooks: Vec<Arc<dyn TransactionHook>>,
+  migrator: Option<CollabMigrator>,
+  subdoc_plugin_factory: Option<SubdocPluginFactory>,
   object_id: String,
   doc_state: CollabDocState,
+  client_id: Option<ClientID>,
+  error: Option<CollabError>,
 }
 
 /// The raw data of a collab document. It is a list of updates. Each of them can be parsed by
@@ -767,10 +1413,19 @@ impl CollabBuilder {
     let object_id = object_id.as_ref();
     Self {
       uid,
-      plugins: vec![],
+      plugins: Plugins::default(),
+      async_plugins: vec![],
+      plugin_queue_capacity: None,
+      access_policy: None,
+      read_only_mode: ReadOnlyMode::Writable,
+      transaction_hooks: vec![],
+      migrator: None,
+      subdoc_plugin_factory: None,
       object_id: object_id.to_string(),
       device_id: "".to_string(),
       doc_state: vec![],
+      client_id: None,
+      error: None,
     }
   }
 
@@ -782,11 +1437,78 @@ impl CollabBuilder {
     self
   }
 
-  pub fn with_plugin<T>(mut self, plugin: T) -> Self
+  pub fn with_plugin<T>(self, plugin: T) -> Self
   where
     T: CollabPlugin + 'static,
   {
-    self.plugins.push(Box::new(plugin));
+    self.with_plugin_priority(plugin, DEFAULT_PLUGIN_PRIORITY)
+  }
+
+  /// Adds a plugin with the given priority, and its [CollabPlugin::pipeline_plugin] if it has
+  /// one. See [Collab::add_plugin_with_priority].
+  pub fn with_plugin_priority<T>(mut self, plugin: T, priority: i32) -> Self
+  where
+    T: CollabPlugin + 'static,
+  {
+    let pipeline_plugin = plugin.pipeline_plugin();
+    match self.plugins.add_typed(plugin, priority) {
+      Ok(_) => self.async_plugins.extend(pipeline_plugin),
+      Err(err) => self.error = Some(err),
+    }
+    self
+  }
+
+  /// Adds a plugin that runs on the plugin pipeline. See [Collab::add_async_plugin].
+  pub fn with_async_plugin<T>(mut self, plugin: T) -> Self
+  where
+    T: AsyncCollabPlugin,
+  {
+    self.async_plugins.push(Arc::new(plugin));
+    self
+  }
+
+  /// The number of queued events above which the plugin pipeline reports backpressure.
+  /// Defaults to [DEFAULT_PIPELINE_CAPACITY](crate::core::plugin_pipeline::DEFAULT_PIPELINE_CAPACITY).
+  pub fn with_plugin_queue_capacity(mut self, capacity: usize) -> Self {
+    self.plugin_queue_capacity = Some(capacity);
+    self
+  }
+
+  /// See [Collab::set_access_policy].
+  pub fn with_access_policy(mut self, policy: AccessPolicy) -> Self {
+    self.access_policy = Some(policy);
+    self
+  }
+
+  /// See [Collab::set_read_only_mode]. The doc state and the data loaded by the plugins are
+  /// still applied.
+  pub fn with_read_only_mode(mut self, mode: ReadOnlyMode) -> Self {
+    self.read_only_mode = mode;
+    self
+  }
+
+  /// See [Collab::add_transaction_hook].
+  pub fn with_transaction_hook<H>(mut self, hook: H) -> Self
+  where
+    H: TransactionHook,
+  {
+    self.transaction_hooks.push(Arc::new(hook));
+    self
+  }
+
+  /// The pending migrations run when the collab is initialized, after the plugins loaded its
+  /// data.
+  pub fn with_migrator(mut self, migrator: CollabMigrator) -> Self {
+    self.migrator = Some(migrator);
+    self
+  }
+
+  /// See [Collab::set_subdoc_plugin_factory].
+  pub fn with_subdoc_plugin_factory<F>(mut self, factory: F) -> Self
+  where
+    F: Fn(&str) -> Vec<Box<dyn CollabPlugin>> + Send + Sync + 'static,
+  {
+    self.subdoc_plugin_factory = Some(Arc::new(factory));
     self
   }
 
@@ -795,26 +1517,80 @@ impl CollabBuilder {
     self
   }
 
+  /// Sets the client id of the underlying [Doc] instead of a random one. Two docs editing the same
+  /// collab must never share a client id.
+  pub fn with_client_id(mut self, client_id: ClientID) -> Self {
+    self.client_id = Some(client_id);
+    self
+  }
+
+  /// Returns an error if more than one [CollabPluginType::CloudStorage] plugin was added, or if
+  /// the plugin pipeline is used without an async runtime.
   pub fn build(self) -> Result<MutexCollab, CollabError> {
+    if let Some(err) = self.error {
+      return Err(err);
+    }
     let origin = CollabOrigin::Client(CollabClient::new(self.uid, self.device_id));
-    MutexCollab::new_with_doc_state(origin, &self.object_id, self.doc_state, self.plugins)
+    let mut options = yrs_doc_options();
+    if let Some(client_id) = self.client_id {
+      options.client_id = client_id;
+    }
+    let mut collab = Collab::new_with_yrs_doc(
+      origin,
+      &self.object_id,
+      Doc::with_options(options),
+      Plugins::default(),
+    );
+    collab.apply_doc_state(&self.doc_state)?;
+    collab.plugins = self.plugins;
+    if let Some(capacity) = self.plugin_queue_capacity {
+      collab.async_plugins.set_capacity(capacity);
+    }
+    for plugin in self.async_plugins {
+      collab.async_plugins.add_plugin(plugin)?;
+    }
+    if let Some(policy) = self.access_policy {
+      collab.set_access_policy(policy);
+    }
+    collab.set_read_only_mode(self.read_only_mode);
+    for hook in self.transaction_hooks {
+      collab.transaction_hooks.add(hook, &collab.doc);
+    }
+    collab.migrator = self.migrator;
+    collab.subdoc_plugin_factory = self.subdoc_plugin_factory;
+    Ok(MutexCollab::from_collab(collab))
   }
 }
 
 #[derive(Clone)]
 pub struct CollabContext {
+  object_id: String,
   origin: CollabOrigin,
   doc: Doc,
-  #[allow(dead_code)]
   plugins: Plugins,
+  access_guard: Arc<AccessGuard>,
+  read_only: Arc<ReadOnlyGuard>,
+  transaction_hooks: Arc<TransactionHooks>,
 }
 
 impl CollabContext {
-  fn new(origin: CollabOrigin, plugins: Plugins, doc: Doc) -> Self {
+  fn new(
+    object_id: String,
+    origin: CollabOrigin,
+    plugins: Plugins,
+    doc: Doc,
+    access_guard: Arc<AccessGuard>,
+    read_only: Arc<ReadOnlyGuard>,
+    transaction_hooks: Arc<TransactionHooks>,
+  ) -> Self {
     Self {
+      object_id,
       origin,
       plugins,
       doc,
+      access_guard,
+      read_only,
+      transaction_hooks,
     }
   }
 
@@ -822,15 +1598,70 @@ impl CollabContext {
     TransactionRetry::new(&self.doc).get_read_txn()
   }
 
-  pub fn with_transact_mut<F, T>(&self, f: F) -> T
+  /// See [Collab::with_origin_transact_mut].
+  pub fn with_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
   where
     F: FnOnce(&mut TransactionMut) -> T,
   {
-    let mut txn = TransactionRetry::new(&self.doc).get_write_txn_with(self.origin.clone());
-    let ret = f(&mut txn);
-    drop(txn);
-    ret
+    self.read_only.check_local_change()?;
+    Ok(self.local_transact_mut(f).0)
   }
+
+  /// See [Collab::try_with_origin_transact_mut].
+  pub fn try_with_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    self.read_only.check_local_change()?;
+    let (ret, result) = self.local_transact_mut(f);
+    result.map(|_| ret)
+  }
+
+  fn local_transact_mut<F, T>(&self, f: F) -> (T, Result<(), CollabError>)
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    local_transact_mut(
+      &self.object_id,
+      &self.origin,
+      &self.doc,
+      &self.plugins,
+      &self.access_guard,
+      &self.transaction_hooks,
+      f,
+    )
+  }
+
+  pub fn is_read_only(&self) -> bool {
+    !self.read_only.mode().allows_local_changes()
+  }
+}
+
+/// Runs a transaction with the local origin. The caller checks the [ReadOnlyMode] first.
+/// Returns the error of the [TransactionHook] that aborted the transaction, if any.
+fn local_transact_mut<F, T>(
+  object_id: &str,
+  origin: &CollabOrigin,
+  doc: &Doc,
+  plugins: &Plugins,
+  access_guard: &AccessGuard,
+  transaction_hooks: &TransactionHooks,
+  f: F,
+) -> (T, Result<(), CollabError>)
+where
+  F: FnOnce(&mut TransactionMut) -> T,
+{
+  let stopwatch = plugins.is_timed().then(Stopwatch::start);
+  let mut txn = TransactionRetry::new(doc).get_write_txn_with(origin.clone());
+  let ret = f(&mut txn);
+  let result = transaction_hooks.before_commit(&mut txn);
+  drop(txn);
+  transaction_hooks.after_commit(&result);
+  if let Some(elapsed) = stopwatch.and_then(|stopwatch| stopwatch.elapsed()) {
+    plugins.each(|plugin| plugin.did_transact(object_id, elapsed));
+  }
+  access_guard.enforce();
+  (ret, result)
 }
 
 #[derive(Clone)]
@@ -875,20 +1706,165 @@ impl DerefMut for Path {
   }
 }
 
+pub type PluginId = u64;
+
+/// Plugins with a higher priority receive the callbacks first.
+pub const DEFAULT_PLUGIN_PRIORITY: i32 = 0;
+
+struct PluginEntry {
+  id: PluginId,
+  priority: i32,
+  plugin: Arc<dyn CollabPlugin>,
+}
+
+/// The plugins of a [Collab], ordered by priority. Plugins with the same priority are kept in
+/// the order they were added.
 #[derive(Default, Clone)]
-pub struct Plugins(Arc<RwLock<Vec<Box<dyn CollabPlugin>>>>);
+pub struct Plugins {
+  entries: Arc<RwLock<Vec<PluginEntry>>>,
+  next_id: Arc<AtomicU64>,
+  /// The number of plugins that return true from [CollabPlugin::measures_timing].
+  timed_count: Arc<AtomicUsize>,
+}
 
 impl Plugins {
+  /// Creates the plugins with the [DEFAULT_PLUGIN_PRIORITY]. Only the first
+  /// [CollabPluginType::CloudStorage] plugin is kept, the others are logged and dropped.
   pub fn new(plugins: Vec<Box<dyn CollabPlugin>>) -> Plugins {
-    Self(Arc::new(RwLock::new(plugins)))
+    let this = Self::default();
+    for plugin in plugins {
+      if let Err(err) = this.add(plugin, DEFAULT_PLUGIN_PRIORITY) {
+        error!("Ignore plugin: {}", err);
+      }
+    }
+    this
+  }
+
+  pub fn add(&self, plugin: Box<dyn CollabPlugin>, priority: i32) -> Result<PluginId, CollabError> {
+    self.insert(Arc::from(plugin), priority)
   }
-}
 
-impl Deref for Plugins {
-  type Target = Arc<RwLock<Vec<Box<dyn CollabPlugin>>>>;
+  pub fn add_typed<T: CollabPlugin>(
+    &self,
+    plugin: T,
+    priority: i32,
+  ) -> Result<PluginId, CollabError> {
+    self.insert(Arc::new(plugin), priority)
+  }
 
-  fn deref(&self) -> &Self::Target {
-    &self.0
+  fn insert(&self, plugin: Arc<dyn CollabPlugin>, priority: i32) -> Result<PluginId, CollabError> {
+    let mut entries = self.entries.write();
+    if plugin.plugin_type() == CollabPluginType::CloudStorage
+      && entries
+        .iter()
+        .any(|entry| entry.plugin.plugin_type() == CollabPluginType::CloudStorage)
+    {
+      return Err(CollabError::DuplicateCloudStoragePlugin);
+    }
+
+    let id = self.next_id.fetch_add(1, Ordering::SeqCst);
+    if plugin.measures_timing() {
+      self.timed_count.fetch_add(1, Ordering::SeqCst);
+    }
+    let index = entries.partition_point(|entry| entry.priority >= priority);
+    entries.insert(
+      index,
+      PluginEntry {
+        id,
+        priority,
+        plugin,
+      },
+    );
+    Ok(id)
+  }
+
+  pub fn remove(&self, id: PluginId) -> Option<Arc<dyn CollabPlugin>> {
+    let mut entries = self.entries.write();
+    let index = entries.iter().position(|entry| entry.id == id)?;
+    let plugin = entries.remove(index).plugin;
+    if plugin.measures_timing() {
+      self.timed_count.fetch_sub(1, Ordering::SeqCst);
+    }
+    Some(plugin)
+  }
+
+  pub fn set_priority(&self, id: PluginId, priority: i32) -> bool {
+    let mut entries = self.entries.write();
+    match entries.iter().position(|entry| entry.id == id) {
+      None => false,
+      Some(index) => {
+        let mut entry = entries.remove(index);
+        entry.priority = priority;
+        let index = entries.partition_point(|entry| entry.priority >= priority);
+        entries.insert(index, entry);
+        true
+      },
+    }
+  }
+
+  pub fn get<T: CollabPlugin>(&self) -> Option<Arc<T>> {
+    self
+      .entries
+      .read()
+      .iter()
+      .find_map(|entry| entry.plugin.clone().into_any().downcast::<T>().ok())
+  }
+
+  pub fn get_by_id(&self, id: PluginId) -> Option<Arc<dyn CollabPlugin>> {
+    self
+      .entries
+      .read()
+      .iter()
+      .find(|entry| entry.id == id)
+      .map(|entry| entry.plugin.clone())
+  }
+
+  pub fn ids_of_type(&self, plugin_type: &CollabPluginType) -> Vec<PluginId> {
+    self
+      .entries
+      .read()
+      .iter()
+      .filter(|entry| &entry.plugin.plugin_type() == plugin_type)
+      .map(|entry| entry.id)
+      .collect()
+  }
+
+  pub fn has_cloud_storage_plugin(&self) -> bool {
+    !self.ids_of_type(&CollabPluginType::CloudStorage).is_empty()
+  }
+
+  /// Returns the plugins in the order their callbacks are called.
+  pub fn to_vec(&self) -> Vec<Arc<dyn CollabPlugin>> {
+    self
+      .entries
+      .read()
+      .iter()
+      .map(|entry| entry.plugin.clone())
+      .collect()
+  }
+
+  /// Calls `f` for each plugin in priority order. The lock is released before calling `f`, so a
+  /// plugin can add or remove plugins in its callbacks.
+  pub fn each<F>(&self, mut f: F)
+  where
+    F: FnMut(&dyn CollabPlugin),
+  {
+    for plugin in self.to_vec() {
+      f(plugin.as_ref());
+    }
+  }
+
+  /// Returns true if one of the plugins wants the timings of the collab.
+  pub fn is_timed(&self) -> bool {
+    self.timed_count.load(Ordering::Relaxed) > 0
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.read().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.read().is_empty()
   }
 }
 
@@ -897,6 +1873,7 @@ impl Deref for Plugins {
 pub struct MutexCollab(Arc<Mutex<Collab>>);
 
 impl MutexCollab {
+  /// See [Collab::new].
   pub fn new(origin: CollabOrigin, object_id: &str, plugins: Vec<Box<dyn CollabPlugin>>) -> Self {
     let collab = Collab::new_with_origin(origin, object_id, plugins);
     #[allow(clippy::arc_with_non_send_sync)]
@@ -919,6 +1896,70 @@ impl MutexCollab {
     MutexCollab(Arc::new(Mutex::new(collab)))
   }
 
+  pub fn is_read_only(&self) -> bool {
+    self.lock().is_read_only()
+  }
+
+  pub fn set_read_only_mode(&self, mode: ReadOnlyMode) {
+    self.lock().set_read_only_mode(mode)
+  }
+
+  pub fn downgrade(&self) -> WeakMutexCollab {
+    WeakMutexCollab(Arc::downgrade(&self.0))
+  }
+
+  /// Locks the collab. The time spent waiting for the lock is reported to
+  /// [CollabPlugin::did_acquire_lock] if one of the plugins [CollabPlugin::measures_timing].
+  pub fn lock(&self) -> MutexGuard<'_, Collab> {
+    // The plugins can only be read under the lock, so an uncontended lock skips the stopwatch.
+    if let Some(collab) = self.0.try_lock() {
+      if collab.plugins.is_timed() && cfg!(not(target_arch = "wasm32")) {
+        collab
+          .plugins
+          .each(|plugin| plugin.did_acquire_lock(&collab.object_id, Duration::ZERO));
+      }
+      return collab;
+    }
+    let stopwatch = Stopwatch::start();
+    let collab = self.0.lock();
+    if !collab.plugins.is_timed() {
+      return collab;
+    }
+    if let Some(waited) = stopwatch.elapsed() {
+      collab
+        .plugins
+        .each(|plugin| plugin.did_acquire_lock(&collab.object_id, waited));
+    }
+    collab
+  }
+
+  /// Calls [Awareness::check_outdated] periodically until the collab is dropped. It renews the
+  /// local awareness state and removes the clients that went away without saying goodbye.
+  #[cfg(not(target_arch = "wasm32"))]
+  pub fn start_awareness_heartbeat(&self) -> Result<(), CollabError> {
+    let handle = tokio::runtime::Handle::try_current().map_err(|_| {
+      CollabError::Internal("The awareness heartbeat requires a tokio runtime".into())
+    })?;
+    let weak_collab = self.downgrade();
+    handle.spawn(async move {
+      let mut interval =
+        tokio::time::interval(crate::core::awareness::AWARENESS_HEARTBEAT_INTERVAL);
+      loop {
+        interval.tick().await;
+        match weak_collab.upgrade() {
+          None => break,
+          Some(collab) => {
+            let removed = collab.lock().get_mut_awareness().check_outdated();
+            if !removed.is_empty() {
+              trace!("Remove outdated awareness clients: {:?}", removed);
+            }
+          },
+        }
+      }
+    });
+    Ok(())
+  }
+
   /// Returns the doc state and the state vector.
   pub fn encode_collab_v1(&self) -> EncodedCollab {
     let collab = self.0.lock();
@@ -935,6 +1976,19 @@ impl MutexCollab {
   }
 }
 
+#[derive(Clone)]
+pub struct WeakMutexCollab(Weak<Mutex<Collab>>);
+
+impl WeakMutexCollab {
+  pub fn upgrade(&self) -> Option<MutexCollab> {
+    self.0.upgrade().map(MutexCollab)
+  }
+}
+
+unsafe impl Sync for WeakMutexCollab {}
+
+unsafe impl Send for WeakMutexCollab {}
+
 impl Deref for MutexCollab {
   type Target = Arc<Mutex<Collab>>;
   fn deref(&self) -> &Self::Target {
@@ -946,6 +2000,37 @@ unsafe impl Sync for MutexCollab {}
 
 unsafe impl Send for MutexCollab {}
 
+/// Measures the time reported to the plugins. [std::time::Instant] panics on wasm, so nothing is
+/// measured there.
+struct Stopwatch {
+  #[cfg(not(target_arch = "wasm32"))]
+  start: std::time::Instant,
+}
+
+impl Stopwatch {
+  #[cfg(not(target_arch = "wasm32"))]
+  fn start() -> Self {
+    Self {
+      start: std::time::Instant::now(),
+    }
+  }
+
+  #[cfg(target_arch = "wasm32")]
+  fn start() -> Self {
+    Self {}
+  }
+
+  #[cfg(not(target_arch = "wasm32"))]
+  fn elapsed(&self) -> Option<Duration> {
+    Some(self.start.elapsed())
+  }
+
+  #[cfg(target_arch = "wasm32")]
+  fn elapsed(&self) -> Option<Duration> {
+    None
+  }
+}
+
 // Extension trait for `TransactionMut`
 pub trait TransactionMutExt<'doc> {
   /// Applies an update to the document. If the update is invalid, it will return an error.
@@ -0,0 +1,339 @@
+//! A self-describing binary container for [EncodedCollab].
+//!
+//! Layout of a version 1 envelope. All integers are big-endian.
+//!
+//! ```text
+//! magic            4 bytes   "AFEC"
+//! format_version   u8        ENVELOPE_FORMAT_VERSION
+//! flags            u8        FLAG_ZSTD | FLAG_COLLAB_TYPE
+//! collab_type      i32       only meaningful when FLAG_COLLAB_TYPE is set
+//! encoder_version  u8        EncoderVersion of the payload
+//! extension_len    u16
+//! extension        extension_len bytes, reserved for future fields
+//! payload_len      u32
+//! raw_len          u32       length of the uncompressed payload, at most MAX_RAW_PAYLOAD_LEN
+//! payload          payload_len bytes, zstd compressed when FLAG_ZSTD is set
+//! checksum         u32       CRC32 of every preceding byte
+//! ```
+//!
+//! The uncompressed payload is `state_vector_len (u32) | state_vector | doc_state`.
+//! Decoders must skip the extension bytes they don't understand, so new fields can be added
+//! without bumping the format version.
+
+use bytes::Bytes;
+
+use crate::core::collab_plugin::{EncodedCollab, EncoderVersion};
+
+pub const ENVELOPE_MAGIC: [u8; 4] = *b"AFEC";
+pub const ENVELOPE_FORMAT_VERSION: u8 = 1;
+
+const FLAG_ZSTD: u8 = 1;
+const FLAG_COLLAB_TYPE: u8 = 1 << 1;
+const KNOWN_FLAGS: u8 = FLAG_ZSTD | FLAG_COLLAB_TYPE;
+
+const HEADER_LEN: usize = 4 + 1 + 1 + 4 + 1 + 2;
+const CHECKSUM_LEN: usize = 4;
+
+/// The largest uncompressed payload an envelope can hold. Decoding never allocates more than
+/// the `raw_len` of the header, so a small compressed payload can't exhaust the memory.
+pub const MAX_RAW_PAYLOAD_LEN: usize = 1 << 30;
+
+/// Payloads smaller than this are stored uncompressed even if compression is requested, because
+/// zstd's frame overhead outweighs the gain.
+const MIN_COMPRESS_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum EnvelopeCompression {
+  #[default]
+  None,
+  /// Only available on native targets. Encoding or decoding a compressed envelope on wasm
+  /// returns [EncodedCollabError::CompressionUnsupported].
+  Zstd { level: i32 },
+}
+
+/// Options used by [EncodedCollab::encode_to_envelope].
+#[derive(Debug, Clone, Default)]
+pub struct EnvelopeOptions {
+  /// The value of the `CollabType` of the encoded object, see `CollabType::value` in
+  /// `collab-entity`.
+  pub collab_type: Option<i32>,
+  pub compression: EnvelopeCompression,
+}
+
+impl EnvelopeOptions {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn with_collab_type(mut self, collab_type: i32) -> Self {
+    self.collab_type = Some(collab_type);
+    self
+  }
+
+  pub fn with_compression(mut self, compression: EnvelopeCompression) -> Self {
+    self.compression = compression;
+    self
+  }
+}
+
+/// The header of a decoded envelope.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct EnvelopeHeader {
+  pub format_version: u8,
+  pub collab_type: Option<i32>,
+  pub compressed: bool,
+  pub encoder_version: EncoderVersion,
+  /// Extension bytes written by newer encoders. Empty for version 1 envelopes.
+  pub extension: Bytes,
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum EncodedCollabError {
+  #[error("Encoded collab is truncated: expected at least {expected} bytes, got {actual}")]
+  Truncated { expected: usize, actual: usize },
+
+  #[error("Encoded collab checksum mismatch: expected {expected:#010x}, got {actual:#010x}")]
+  ChecksumMismatch { expected: u32, actual: u32 },
+
+  #[error("Unsupported encoded collab format version: {0}")]
+  UnsupportedFormatVersion(u8),
+
+  #[error("Unknown encoded collab flags: {0:#04x}")]
+  UnknownFlags(u8),
+
+  #[error("Unknown encoder version: {0}")]
+  UnknownEncoderVersion(u8),
+
+  #[error("Invalid encoded collab payload: {0}")]
+  InvalidPayload(String),
+
+  #[error("Compression failed: {0}")]
+  Compression(String),
+
+  #[error("Zstd compression is not supported on this platform")]
+  CompressionUnsupported,
+
+  #[error(transparent)]
+  Bincode(#[from] bincode::Error),
+}
+
+/// [EncodedCollab::decode_from_bytes] used to return a [bincode::Error], so the callers that
+/// propagate it with `?` keep compiling.
+impl From<EncodedCollabError> for bincode::Error {
+  fn from(err: EncodedCollabError) -> Self {
+    match err {
+      EncodedCollabError::Bincode(err) => err,
+      err => Box::new(bincode::ErrorKind::Custom(err.to_string())),
+    }
+  }
+}
+
+/// Returns true if the bytes start with the envelope magic. Blobs without the magic are
+/// decoded as the legacy bincode format.
+pub fn is_envelope(data: &[u8]) -> bool {
+  data.len() >= ENVELOPE_MAGIC.len() && data[..ENVELOPE_MAGIC.len()] == ENVELOPE_MAGIC
+}
+
+pub(crate) fn encode_envelope(
+  encoded_collab: &EncodedCollab,
+  options: &EnvelopeOptions,
+) -> Result<Vec<u8>, EncodedCollabError> {
+  let mut payload =
+    Vec::with_capacity(4 + encoded_collab.state_vector.len() + encoded_collab.doc_state.len());
+  payload.extend_from_slice(&(encoded_collab.state_vector.len() as u32).to_be_bytes());
+  payload.extend_from_slice(&encoded_collab.state_vector);
+  payload.extend_from_slice(&encoded_collab.doc_state);
+
+  if payload.len() > MAX_RAW_PAYLOAD_LEN {
+    return Err(EncodedCollabError::InvalidPayload(format!(
+      "payload of {} bytes exceeds the limit of {} bytes",
+      payload.len(),
+      MAX_RAW_PAYLOAD_LEN
+    )));
+  }
+  let raw_len = payload.len() as u32;
+
+  let mut flags = 0;
+  if let EnvelopeCompression::Zstd { level } = options.compression {
+    if payload.len() >= MIN_COMPRESS_LEN {
+      payload = compress(&payload, level)?;
+      flags |= FLAG_ZSTD;
+    }
+  }
+  if options.collab_type.is_some() {
+    flags |= FLAG_COLLAB_TYPE;
+  }
+
+  let mut data = Vec::with_capacity(HEADER_LEN + 8 + payload.len() + CHECKSUM_LEN);
+  data.extend_from_slice(&ENVELOPE_MAGIC);
+  data.push(ENVELOPE_FORMAT_VERSION);
+  data.push(flags);
+  data.extend_from_slice(&options.collab_type.unwrap_or_default().to_be_bytes());
+  data.push(encoded_collab.version.clone() as u8);
+  data.extend_from_slice(&0u16.to_be_bytes());
+  data.extend_from_slice(&(payload.len() as u32).to_be_bytes());
+  data.extend_from_slice(&raw_len.to_be_bytes());
+  data.extend_from_slice(&payload);
+
+  let checksum = crc32fast::hash(&data);
+  data.extend_from_slice(&checksum.to_be_bytes());
+  Ok(data)
+}
+
+pub(crate) fn decode_envelope(
+  data: &[u8],
+) -> Result<(EnvelopeHeader, EncodedCollab), EncodedCollabError> {
+  let mut reader = Reader::new(data);
+  let _magic = reader.read_bytes(ENVELOPE_MAGIC.len())?;
+  // Check the version before anything else. A newer format might use a different layout.
+  let format_version = reader.read_u8()?;
+  if format_version != ENVELOPE_FORMAT_VERSION {
+    return Err(EncodedCollabError::UnsupportedFormatVersion(format_version));
+  }
+  let flags = reader.read_u8()?;
+  let collab_type = i32::from_be_bytes(reader.read_array()?);
+  let encoder_version = reader.read_u8()?;
+  let extension_len = u16::from_be_bytes(reader.read_array()?);
+  let extension = Bytes::copy_from_slice(reader.read_bytes(extension_len as usize)?);
+  let payload_len = u32::from_be_bytes(reader.read_array()?);
+  let raw_len = u32::from_be_bytes(reader.read_array()?) as usize;
+  let payload = reader.read_bytes(payload_len as usize)?;
+
+  let body_len = reader.offset;
+  let expected = u32::from_be_bytes(reader.read_array()?);
+  if !reader.is_empty() {
+    return Err(EncodedCollabError::InvalidPayload(format!(
+      "{} unexpected trailing bytes",
+      reader.remaining()
+    )));
+  }
+  let actual = crc32fast::hash(&data[..body_len]);
+  if expected != actual {
+    return Err(EncodedCollabError::ChecksumMismatch { expected, actual });
+  }
+
+  if flags & !KNOWN_FLAGS != 0 {
+    return Err(EncodedCollabError::UnknownFlags(flags));
+  }
+  let encoder_version = match encoder_version {
+    0 => EncoderVersion::V1,
+    1 => EncoderVersion::V2,
+    other => return Err(EncodedCollabError::UnknownEncoderVersion(other)),
+  };
+  if raw_len > MAX_RAW_PAYLOAD_LEN {
+    return Err(EncodedCollabError::InvalidPayload(format!(
+      "payload of {} bytes exceeds the limit of {} bytes",
+      raw_len, MAX_RAW_PAYLOAD_LEN
+    )));
+  }
+
+  let compressed = flags & FLAG_ZSTD != 0;
+  let payload = if compressed {
+    decompress(payload, raw_len)?
+  } else {
+    payload.to_vec()
+  };
+  if payload.len() != raw_len {
+    return Err(EncodedCollabError::InvalidPayload(format!(
+      "expected a payload of {} bytes, got {}",
+      raw_len,
+      payload.len()
+    )));
+  }
+
+  let mut reader = Reader::new(&payload);
+  let state_vector_len = u32::from_be_bytes(reader.read_array()?);
+  let state_vector = Bytes::copy_from_slice(reader.read_bytes(state_vector_len as usize)?);
+  let doc_state_len = reader.remaining();
+  let doc_state = Bytes::copy_from_slice(reader.read_bytes(doc_state_len)?);
+
+  let header = EnvelopeHeader {
+    format_version,
+    collab_type: (flags & FLAG_COLLAB_TYPE != 0).then_some(collab_type),
+    compressed,
+    encoder_version: encoder_version.clone(),
+    extension,
+  };
+  let encoded_collab = EncodedCollab {
+    state_vector,
+    doc_state,
+    version: encoder_version,
+  };
+  Ok((header, encoded_collab))
+}
+
+fn ensure_len(data: &[u8], expected: usize) -> Result<(), EncodedCollabError> {
+  if data.len() < expected {
+    return Err(EncodedCollabError::Truncated {
+      expected,
+      actual: data.len(),
+    });
+  }
+  Ok(())
+}
+
+struct Reader<'a> {
+  data: &'a [u8],
+  offset: usize,
+}
+
+impl<'a> Reader<'a> {
+  fn new(data: &'a [u8]) -> Self {
+    Self { data, offset: 0 }
+  }
+
+  fn remaining(&self) -> usize {
+    self.data.len() - self.offset
+  }
+
+  fn is_empty(&self) -> bool {
+    self.remaining() == 0
+  }
+
+  fn read_u8(&mut self) -> Result<u8, EncodedCollabError> {
+    Ok(self.read_bytes(1)?[0])
+  }
+
+  fn read_array<const N: usize>(&mut self) -> Result<[u8; N], EncodedCollabError> {
+    let bytes = self.read_bytes(N)?;
+    Ok(bytes.try_into().unwrap())
+  }
+
+  fn read_bytes(&mut self, len: usize) -> Result<&'a [u8], EncodedCollabError> {
+    ensure_len(self.data, self.offset + len)?;
+    let bytes = &self.data[self.offset..self.offset + len];
+    self.offset += len;
+    Ok(bytes)
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn compress(data: &[u8], level: i32) -> Result<Vec<u8>, EncodedCollabError> {
+  zstd::bulk::compress(data, level).map_err(|e| EncodedCollabError::Compression(e.to_string()))
+}
+
+/// Decompresses at most `raw_len + 1` bytes, so the caller can tell that the payload is larger
+/// than announced without decompressing all of it.
+#[cfg(not(target_arch = "wasm32"))]
+fn decompress(data: &[u8], raw_len: usize) -> Result<Vec<u8>, EncodedCollabError> {
+  use std::io::Read;
+
+  let decoder = zstd::stream::read::Decoder::new(data)
+    .map_err(|e| EncodedCollabError::Compression(e.to_string()))?;
+  let mut payload = vec![];
+  decoder
+    .take(raw_len as u64 + 1)
+    .read_to_end(&mut payload)
+    .map_err(|e| EncodedCollabError::Compression(e.to_string()))?;
+  Ok(payload)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn compress(_data: &[u8], _level: i32) -> Result<Vec<u8>, EncodedCollabError> {
+  Err(EncodedCollabError::CompressionUnsupported)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn decompress(_data: &[u8], _raw_len: usize) -> Result<Vec<u8>, EncodedCollabError> {
+  Err(EncodedCollabError::CompressionUnsupported)
+}
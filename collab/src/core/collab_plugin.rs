@@ -1,14 +1,22 @@
 use crate::core::awareness::{AwarenessUpdate, Event};
+use crate::core::collab_envelope::{
+  decode_envelope, encode_envelope, is_envelope, EncodedCollabError, EnvelopeHeader,
+  EnvelopeOptions,
+};
 use async_trait::async_trait;
 use bytes::Bytes;
 use serde::{Deserialize, Serialize};
 use serde_repr::*;
+use std::any::Any;
+use std::sync::Arc;
+use std::time::Duration;
 use yrs::{Doc, TransactionMut};
 
 use crate::core::origin::CollabOrigin;
+use crate::core::plugin_pipeline::AsyncCollabPlugin;
 use crate::preclude::Collab;
 
-#[derive(Debug, Eq, PartialEq)]
+#[derive(Debug, Clone, Copy, Hash, Eq, PartialEq)]
 pub enum CollabPluginType {
   /// The plugin is used for sync data with a remote storage. Only one plugin of this type can be
   /// used per document.
@@ -17,8 +25,20 @@ pub enum CollabPluginType {
   Other,
 }
 
+/// Gives access to the concrete type of a [CollabPlugin], so it can be looked up with
+/// [Collab::get_plugin] however it was added. Implemented for every type.
+pub trait PluginAny {
+  fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync>;
+}
+
+impl<T: Send + Sync + 'static> PluginAny for T {
+  fn into_any(self: Arc<Self>) -> Arc<dyn Any + Send + Sync> {
+    self
+  }
+}
+
 #[async_trait]
-pub trait CollabPlugin: Send + Sync + 'static {
+pub trait CollabPlugin: PluginAny + Send + Sync + 'static {
   /// Called when the plugin is initialized.
   /// The will apply the updates to the current [TransactionMut] which will restore the state of
   /// the document.
@@ -39,6 +59,14 @@ pub trait CollabPlugin: Send + Sync + 'static {
   /// We use the [CollabOrigin] to know if the update comes from the local user or from a remote
   fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, _update: &[u8]) {}
 
+  /// Called when a child document of the collab is loaded, before the plugins of the child are
+  /// initialized. Like [CollabPlugin::init], it can restore the state of the child. See
+  /// [crate::core::subdoc].
+  fn init_subdoc(&self, _object_id: &str, _origin: &CollabOrigin, _doc: &Doc) {}
+
+  /// Called with each update of a loaded child document of the collab, local or remote.
+  fn receive_subdoc_update(&self, _object_id: &str, _update: &[u8]) {}
+
   fn receive_local_state(
     &self,
     origin: &CollabOrigin,
@@ -50,6 +78,21 @@ pub trait CollabPlugin: Send + Sync + 'static {
   /// Called after each [TransactionMut]
   fn after_transaction(&self, _object_id: &str, _txn: &mut TransactionMut) {}
 
+  /// Returns true if the plugin wants [CollabPlugin::did_transact] and
+  /// [CollabPlugin::did_acquire_lock]. Nothing is measured unless one of the plugins of the
+  /// collab returns true, so the timings cost nothing when they aren't used.
+  fn measures_timing(&self) -> bool {
+    false
+  }
+
+  /// Called after a [TransactionMut] is committed, with the time spent in the transaction
+  /// including the commit. Not called on wasm, where the time can't be measured.
+  fn did_transact(&self, _object_id: &str, _elapsed: Duration) {}
+
+  /// Called when the lock of a [crate::core::collab::MutexCollab] is acquired, with the time
+  /// spent waiting for it. Not called on wasm, where the time can't be measured.
+  fn did_acquire_lock(&self, _object_id: &str, _waited: Duration) {}
+
   /// Returns the type of the plugin.
   fn plugin_type(&self) -> CollabPluginType {
     CollabPluginType::Other
@@ -62,6 +105,13 @@ pub trait CollabPlugin: Send + Sync + 'static {
   /// Flush the data to the storage. It will remove all existing updates and insert the state vector
   /// and doc_state.
   fn flush(&self, _object_id: &str, _doc: &Doc) {}
+
+  /// Returns the part of the plugin that runs on the plugin pipeline, if it has one. It is
+  /// registered together with the plugin, so a plugin that writes its updates on the pipeline
+  /// can't be added without it.
+  fn pipeline_plugin(&self) -> Option<Arc<dyn AsyncCollabPlugin>> {
+    None
+  }
 }
 
 /// Implement the [CollabPlugin] trait for Box<T> and Arc<T> where T implements CollabPlugin.
@@ -91,6 +141,14 @@ where
   fn receive_local_update(&self, origin: &CollabOrigin, object_id: &str, update: &[u8]) {
     (**self).receive_local_update(origin, object_id, update)
   }
+
+  fn init_subdoc(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
+    (**self).init_subdoc(object_id, origin, doc)
+  }
+
+  fn receive_subdoc_update(&self, object_id: &str, update: &[u8]) {
+    (**self).receive_subdoc_update(object_id, update)
+  }
   fn receive_local_state(
     &self,
     origin: &CollabOrigin,
@@ -104,6 +162,19 @@ where
   fn after_transaction(&self, object_id: &str, txn: &mut TransactionMut) {
     (**self).after_transaction(object_id, txn)
   }
+
+  fn measures_timing(&self) -> bool {
+    (**self).measures_timing()
+  }
+
+  fn did_transact(&self, object_id: &str, elapsed: Duration) {
+    (**self).did_transact(object_id, elapsed)
+  }
+
+  fn did_acquire_lock(&self, object_id: &str, waited: Duration) {
+    (**self).did_acquire_lock(object_id, waited)
+  }
+
   fn plugin_type(&self) -> CollabPluginType {
     (**self).plugin_type()
   }
@@ -115,6 +186,10 @@ where
   fn flush(&self, object_id: &str, doc: &Doc) {
     (**self).flush(object_id, doc)
   }
+
+  fn pipeline_plugin(&self) -> Option<Arc<dyn AsyncCollabPlugin>> {
+    (**self).pipeline_plugin()
+  }
 }
 
 #[derive(Serialize, Deserialize, Debug, Clone, Eq, PartialEq)]
@@ -150,11 +225,41 @@ impl EncodedCollab {
     }
   }
 
+  /// Encodes with the legacy bincode format. Prefer [EncodedCollab::encode_to_envelope], which
+  /// can be validated and carries the type of the collab. Use this only when the reader
+  /// doesn't understand the envelope yet.
   pub fn encode_to_bytes(&self) -> Result<Vec<u8>, bincode::Error> {
     bincode::serialize(self)
   }
 
-  pub fn decode_from_bytes(encoded: &[u8]) -> Result<EncodedCollab, bincode::Error> {
+  /// Encodes into the versioned and checksummed envelope. See [crate::core::collab_envelope].
+  pub fn encode_to_envelope(
+    &self,
+    options: &EnvelopeOptions,
+  ) -> Result<Vec<u8>, EncodedCollabError> {
+    encode_envelope(self, options)
+  }
+
+  /// Decodes either an envelope or the legacy bincode format.
+  pub fn decode_from_bytes(encoded: &[u8]) -> Result<EncodedCollab, EncodedCollabError> {
+    let (_, encoded_collab) = Self::decode_with_header(encoded)?;
+    Ok(encoded_collab)
+  }
+
+  /// Same as [EncodedCollab::decode_from_bytes], but also returns the [EnvelopeHeader]. The
+  /// header is [None] for blobs in the legacy bincode format.
+  pub fn decode_with_header(
+    encoded: &[u8],
+  ) -> Result<(Option<EnvelopeHeader>, EncodedCollab), EncodedCollabError> {
+    if is_envelope(encoded) {
+      let (header, encoded_collab) = decode_envelope(encoded)?;
+      return Ok((Some(header), encoded_collab));
+    }
+    let encoded_collab = Self::decode_from_legacy_bytes(encoded)?;
+    Ok((None, encoded_collab))
+  }
+
+  fn decode_from_legacy_bytes(encoded: &[u8]) -> Result<EncodedCollab, bincode::Error> {
     // The deserialize_encoded_collab function first tries to deserialize the data as EncodedCollab.
     // If it fails (presumably because the data was serialized with EncodedCollabV0), it then tries to deserialize as EncodedCollabV0.
     // After successfully deserializing as EncodedCollabV0, it constructs a new EncodedCollab object with the data from
@@ -182,6 +287,7 @@ pub struct EncodedCollabV0 {
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::core::collab_envelope::EnvelopeCompression;
   #[test]
   fn old_encoded_collab_decoded_into_new_encoded_collab() {
     let old_encoded_collab = EncodedCollabV0 {
@@ -220,4 +326,76 @@ mod tests {
       new_encoded_collab.state_vector
     );
   }
+
+  #[test]
+  fn encoded_collab_envelope_round_trip() {
+    let encoded_collab = EncodedCollab::new_v1(vec![1, 2, 3], vec![7; 1024]);
+    for compression in [
+      EnvelopeCompression::None,
+      EnvelopeCompression::Zstd { level: 3 },
+    ] {
+      let options = EnvelopeOptions::new()
+        .with_collab_type(3)
+        .with_compression(compression);
+      let bytes = encoded_collab.encode_to_envelope(&options).unwrap();
+      let (header, decoded) = EncodedCollab::decode_with_header(&bytes).unwrap();
+      let header = header.unwrap();
+      assert_eq!(header.collab_type, Some(3));
+      assert_eq!(header.compressed, compression != EnvelopeCompression::None);
+      assert_eq!(decoded, encoded_collab);
+    }
+  }
+
+  #[test]
+  fn corrupted_envelope_is_rejected() {
+    let encoded_collab = EncodedCollab::new_v1(vec![1, 2, 3], vec![4, 5, 6]);
+    let mut bytes = encoded_collab
+      .encode_to_envelope(&EnvelopeOptions::new())
+      .unwrap();
+    let index = bytes.len() - 6;
+    bytes[index] ^= 0xff;
+    assert!(matches!(
+      EncodedCollab::decode_from_bytes(&bytes),
+      Err(EncodedCollabError::ChecksumMismatch { .. })
+    ));
+  }
+
+  #[test]
+  fn truncated_envelope_is_rejected() {
+    let encoded_collab = EncodedCollab::new_v1(vec![1, 2, 3], vec![4, 5, 6]);
+    let bytes = encoded_collab
+      .encode_to_envelope(&EnvelopeOptions::new())
+      .unwrap();
+    for len in [6, bytes.len() - 1] {
+      assert!(matches!(
+        EncodedCollab::decode_from_bytes(&bytes[..len]),
+        Err(EncodedCollabError::Truncated { .. })
+      ));
+    }
+  }
+
+  #[test]
+  fn payload_larger_than_announced_is_rejected() {
+    let encoded_collab = EncodedCollab::new_v1(vec![1, 2, 3], vec![7; 4096]);
+    let options = EnvelopeOptions::new().with_compression(EnvelopeCompression::Zstd { level: 3 });
+    let mut bytes = encoded_collab.encode_to_envelope(&options).unwrap();
+    // Shrink the announced raw_len and fix the checksum, as a crafted envelope would.
+    bytes[17..21].copy_from_slice(&16u32.to_be_bytes());
+    let body_len = bytes.len() - 4;
+    let checksum = crc32fast::hash(&bytes[..body_len]);
+    bytes[body_len..].copy_from_slice(&checksum.to_be_bytes());
+    assert!(matches!(
+      EncodedCollab::decode_from_bytes(&bytes),
+      Err(EncodedCollabError::InvalidPayload(_))
+    ));
+  }
+
+  #[test]
+  fn legacy_encoded_collab_has_no_header() {
+    let encoded_collab = EncodedCollab::new_v2(vec![1, 2, 3], vec![4, 5, 6]);
+    let bytes = encoded_collab.encode_to_bytes().unwrap();
+    let (header, decoded) = EncodedCollab::decode_with_header(&bytes).unwrap();
+    assert!(header.is_none());
+    assert_eq!(decoded, encoded_collab);
+  }
 }
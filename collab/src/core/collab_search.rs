@@ -1 +1,647 @@
+//! A local full-text search over the [IndexContent] of folders, documents and databases.
+//!
+//! The [SearchIndex] is an inverted index from terms to documents. Latin text is split into
+//! lowercased words. CJK text has no word boundaries, so each CJK character is a term, and so is
+//! each pair of adjacent CJK characters. A query matches a document if every term of the query
+//! matches the document, either exactly, as a prefix of a term of the document, or within the
+//! allowed edit distance. The terms within the edit distance are only looked for among the terms
+//! that share enough pairs of adjacent characters with the term of the query.
+//!
+//! The index can be persisted as a whole with [SearchIndex::encode], or document by document with
+//! [SearchIndex::postings] and restored with [SearchIndex::from_postings].
 
+use std::cmp::Ordering;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock, RwLockReadGuard};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::core::collab::{IndexContent, IndexContentReceiver};
+use crate::preclude::JsonValue;
+
+/// The fields of an [IndexContent] that identify the indexed object.
+const ID_FIELDS: [&str; 4] = ["id", "page_id", "row_id", "object_id"];
+/// The fields of an [IndexContent] that are indexed as the title.
+const TITLE_FIELDS: [&str; 2] = ["name", "title"];
+/// The fields of an [IndexContent] that are indexed as the body. Nested values are indexed too.
+const BODY_FIELDS: [&str; 4] = ["text", "content", "desc", "cells"];
+
+/// A term of the title counts as much as this many terms of the body.
+const TITLE_BOOST: f32 = 3.0;
+/// The saturation of the term frequency, as in BM25.
+const TF_SATURATION: f32 = 1.2;
+const PREFIX_MATCH_WEIGHT: f32 = 0.7;
+const FUZZY_MATCH_WEIGHT: f32 = 0.5;
+
+/// The text of an indexed object.
+#[derive(Debug, Clone, Eq, PartialEq, Serialize, Deserialize)]
+pub struct IndexedDocument {
+  pub id: String,
+  pub title: String,
+  pub body: String,
+}
+
+impl IndexedDocument {
+  pub fn new(id: impl ToString, title: impl ToString, body: impl ToString) -> Self {
+    Self {
+      id: id.to_string(),
+      title: title.to_string(),
+      body: body.to_string(),
+    }
+  }
+
+  /// Extracts the document from the json of an [IndexContent::Create] or
+  /// [IndexContent::Update]. Returns None if the json has no id.
+  pub fn from_json(value: &JsonValue) -> Option<Self> {
+    let object = value.as_object()?;
+    let id = ID_FIELDS
+      .iter()
+      .find_map(|field| object.get(*field)?.as_str())?;
+    let title = TITLE_FIELDS
+      .iter()
+      .find_map(|field| object.get(*field)?.as_str())
+      .unwrap_or_default();
+    let mut body = vec![];
+    for field in BODY_FIELDS {
+      if let Some(value) = object.get(field) {
+        collect_strings(value, &mut body);
+      }
+    }
+    Some(Self::new(id, title, body.join("\n")))
+  }
+
+  /// Returns the distinct terms of the title and the body.
+  pub fn terms(&self) -> BTreeSet<String> {
+    tokenize(&self.title)
+      .into_iter()
+      .chain(tokenize(&self.body))
+      .collect()
+  }
+}
+
+fn collect_strings<'a>(value: &'a JsonValue, strings: &mut Vec<&'a str>) {
+  match value {
+    JsonValue::String(s) if !s.is_empty() => strings.push(s),
+    JsonValue::Array(values) => values.iter().for_each(|v| collect_strings(v, strings)),
+    JsonValue::Object(map) => map.values().for_each(|v| collect_strings(v, strings)),
+    _ => {},
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct SearchQuery {
+  text: String,
+  prefix: bool,
+  max_distance: usize,
+  limit: usize,
+  snippet_len: usize,
+}
+
+impl SearchQuery {
+  /// By default, the terms of the query also match the terms they are a prefix of, and the
+  /// first 20 results are returned.
+  pub fn new(text: impl ToString) -> Self {
+    Self {
+      text: text.to_string(),
+      prefix: true,
+      max_distance: 0,
+      limit: 20,
+      snippet_len: 80,
+    }
+  }
+
+  pub fn with_prefix(mut self, prefix: bool) -> Self {
+    self.prefix = prefix;
+    self
+  }
+
+  /// Allows the Latin terms of the query that are at least 3 characters long to match the terms
+  /// within the given edit distance.
+  pub fn with_fuzzy(mut self, max_distance: usize) -> Self {
+    self.max_distance = max_distance;
+    self
+  }
+
+  pub fn with_limit(mut self, limit: usize) -> Self {
+    self.limit = limit;
+    self
+  }
+
+  /// The number of characters of the snippets.
+  pub fn with_snippet_len(mut self, snippet_len: usize) -> Self {
+    self.snippet_len = snippet_len;
+    self
+  }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchResult {
+  pub id: String,
+  pub title: String,
+  /// The part of the body around the first match, or the start of the title if only the title
+  /// matches.
+  pub snippet: String,
+  pub score: f32,
+}
+
+/// The number of times a term occurs in the title and in the body of a document.
+#[derive(Debug, Default, Clone, Copy, Eq, PartialEq, Serialize, Deserialize)]
+pub struct TermFrequency {
+  pub title: u32,
+  pub body: u32,
+}
+
+impl TermFrequency {
+  fn score(&self) -> f32 {
+    let tf = self.title as f32 * TITLE_BOOST + self.body as f32;
+    tf * (TF_SATURATION + 1.0) / (tf + TF_SATURATION)
+  }
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SearchIndex {
+  documents: HashMap<String, IndexedDocument>,
+  /// The terms are sorted, so the terms that start with a prefix are a range.
+  terms: BTreeMap<String, HashMap<String, TermFrequency>>,
+  /// The Latin terms by the pairs of adjacent characters of `^term$`, see
+  /// [SearchIndex::fuzzy_candidates].
+  pairs: HashMap<String, HashSet<String>>,
+}
+
+impl SearchIndex {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn len(&self) -> usize {
+    self.documents.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.documents.is_empty()
+  }
+
+  pub fn get(&self, id: &str) -> Option<&IndexedDocument> {
+    self.documents.get(id)
+  }
+
+  pub fn documents(&self) -> impl Iterator<Item = &IndexedDocument> {
+    self.documents.values()
+  }
+
+  /// Applies an [IndexContent] message. Returns false if the message didn't change the index.
+  pub fn apply(&mut self, content: &IndexContent) -> bool {
+    !self.apply_changes(content).is_empty()
+  }
+
+  /// Applies an [IndexContent] message and returns the ids of the documents it changed.
+  fn apply_changes(&mut self, content: &IndexContent) -> Vec<String> {
+    match content {
+      IndexContent::Create(value) | IndexContent::Update(value) => {
+        match IndexedDocument::from_json(value) {
+          None => vec![],
+          Some(document) => {
+            let id = document.id.clone();
+            self.upsert(document);
+            vec![id]
+          },
+        }
+      },
+      IndexContent::Delete(ids) => ids.iter().filter(|id| self.remove(id)).cloned().collect(),
+    }
+  }
+
+  /// Adds the document, or replaces the document with the same id.
+  pub fn upsert(&mut self, document: IndexedDocument) {
+    self.remove(&document.id);
+    for term in tokenize(&document.title) {
+      self.posting(term, &document.id).title += 1;
+    }
+    for term in tokenize(&document.body) {
+      self.posting(term, &document.id).body += 1;
+    }
+    self.documents.insert(document.id.clone(), document);
+  }
+
+  pub fn remove(&mut self, id: &str) -> bool {
+    let document = match self.documents.remove(id) {
+      None => return false,
+      Some(document) => document,
+    };
+    for term in document.terms() {
+      if let Some(postings) = self.terms.get_mut(&term) {
+        postings.remove(id);
+        if postings.is_empty() {
+          self.terms.remove(&term);
+          self.remove_pairs(&term);
+        }
+      }
+    }
+    true
+  }
+
+  pub fn clear(&mut self) {
+    self.documents.clear();
+    self.terms.clear();
+    self.pairs.clear();
+  }
+
+  /// Returns the terms of the document with their frequency, sorted by term.
+  pub fn postings(&self, id: &str) -> Vec<(String, TermFrequency)> {
+    let document = match self.documents.get(id) {
+      None => return vec![],
+      Some(document) => document,
+    };
+    document
+      .terms()
+      .into_iter()
+      .filter_map(|term| {
+        let frequency = *self.terms.get(&term)?.get(id)?;
+        Some((term, frequency))
+      })
+      .collect()
+  }
+
+  /// Restores an index from its documents and the `(term, document id, frequency)` postings
+  /// returned by [SearchIndex::postings]. The postings of unknown documents are ignored.
+  pub fn from_postings(
+    documents: Vec<IndexedDocument>,
+    postings: Vec<(String, String, TermFrequency)>,
+  ) -> Self {
+    let mut index = Self::new();
+    for document in documents {
+      index.documents.insert(document.id.clone(), document);
+    }
+    for (term, id, frequency) in postings {
+      if index.documents.contains_key(&id) {
+        *index.posting(term, &id) = frequency;
+      }
+    }
+    index
+  }
+
+  /// Returns the documents that match every term of the query, best match first.
+  pub fn search(&self, query: &SearchQuery) -> Vec<SearchResult> {
+    let mut query_terms = tokenize(&query.text);
+    query_terms.sort();
+    query_terms.dedup();
+    if query_terms.is_empty() {
+      return vec![];
+    }
+
+    // The score and the matched terms of each document.
+    let mut matches: Option<HashMap<&str, (f32, Vec<&str>)>> = None;
+    for query_term in query_terms.iter() {
+      let mut term_matches: HashMap<&str, (f32, &str)> = HashMap::new();
+      for (term, weight) in self.matching_terms(query_term, query) {
+        let postings = &self.terms[term];
+        let idf = self.idf(postings.len());
+        for (id, frequency) in postings {
+          let score = idf * weight * frequency.score();
+          let entry = term_matches.entry(id.as_str()).or_insert((0.0, term));
+          if score > entry.0 {
+            *entry = (score, term);
+          }
+        }
+      }
+      matches = Some(match matches {
+        None => term_matches
+          .into_iter()
+          .map(|(id, (score, term))| (id, (score, vec![term])))
+          .collect(),
+        Some(mut matches) => {
+          matches.retain(|id, _| term_matches.contains_key(id));
+          for (id, (score, terms)) in matches.iter_mut() {
+            let (term_score, term) = term_matches[id];
+            *score += term_score;
+            terms.push(term);
+          }
+          matches
+        },
+      });
+    }
+
+    let mut results = matches
+      .unwrap_or_default()
+      .into_iter()
+      .map(|(id, (score, terms))| {
+        let document = &self.documents[id];
+        SearchResult {
+          id: document.id.clone(),
+          title: document.title.clone(),
+          snippet: make_snippet(document, &terms, query.snippet_len),
+          score,
+        }
+      })
+      .collect::<Vec<_>>();
+    results.sort_by(|a, b| {
+      b.score
+        .partial_cmp(&a.score)
+        .unwrap_or(Ordering::Equal)
+        .then_with(|| a.id.cmp(&b.id))
+    });
+    results.truncate(query.limit);
+    results
+  }
+
+  /// Encodes the documents of the index. The terms are rebuilt by [SearchIndex::decode].
+  pub fn encode(&self) -> Result<Vec<u8>, bincode::Error> {
+    let mut documents = self.documents.values().collect::<Vec<_>>();
+    documents.sort_by(|a, b| a.id.cmp(&b.id));
+    bincode::serialize(&documents)
+  }
+
+  pub fn decode(data: &[u8]) -> Result<Self, bincode::Error> {
+    let documents: Vec<IndexedDocument> = bincode::deserialize(data)?;
+    let mut index = Self::new();
+    for document in documents {
+      index.upsert(document);
+    }
+    Ok(index)
+  }
+
+  fn posting(&mut self, term: String, id: &str) -> &mut TermFrequency {
+    if !self.terms.contains_key(&term) && !is_cjk_term(&term) {
+      for pair in char_pairs(&term) {
+        self.pairs.entry(pair).or_default().insert(term.clone());
+      }
+    }
+    self
+      .terms
+      .entry(term)
+      .or_default()
+      .entry(id.to_string())
+      .or_default()
+  }
+
+  fn remove_pairs(&mut self, term: &str) {
+    for pair in char_pairs(term) {
+      if let Some(terms) = self.pairs.get_mut(&pair) {
+        terms.remove(term);
+        if terms.is_empty() {
+          self.pairs.remove(&pair);
+        }
+      }
+    }
+  }
+
+  /// Returns the terms that may be within the edit distance of the term, sorted. An edit changes
+  /// at most two pairs of adjacent characters, so such a term shares all but `2 * max_distance`
+  /// of the pairs of the term. At least one shared pair is required, so the shortest terms with
+  /// the largest distances may be missed.
+  fn fuzzy_candidates(&self, query_term: &str, max_distance: usize) -> Vec<&str> {
+    let pairs = char_pairs(query_term);
+    let min_shared = pairs.len().saturating_sub(2 * max_distance).max(1);
+    let mut shared = HashMap::<&str, usize>::new();
+    for pair in pairs {
+      for term in self.pairs.get(&pair).into_iter().flatten() {
+        *shared.entry(term.as_str()).or_default() += 1;
+      }
+    }
+    let mut candidates = shared
+      .into_iter()
+      .filter(|(_, count)| *count >= min_shared)
+      .map(|(term, _)| term)
+      .collect::<Vec<_>>();
+    candidates.sort_unstable();
+    candidates
+  }
+
+  fn idf(&self, document_frequency: usize) -> f32 {
+    let n = self.documents.len() as f32;
+    let df = document_frequency as f32;
+    ((n - df + 0.5) / (df + 0.5) + 1.0).ln()
+  }
+
+  /// Returns the terms of the index that match the term of the query, with the weight of the
+  /// match.
+  fn matching_terms<'a>(&'a self, query_term: &str, query: &SearchQuery) -> Vec<(&'a str, f32)> {
+    let mut terms = vec![];
+    if let Some((term, _)) = self.terms.get_key_value(query_term) {
+      terms.push((term.as_str(), 1.0));
+    }
+    if is_cjk_term(query_term) {
+      return terms;
+    }
+    if query.prefix {
+      terms.extend(
+        self
+          .terms
+          .range::<str, _>((
+            std::ops::Bound::Excluded(query_term),
+            std::ops::Bound::Unbounded,
+          ))
+          .take_while(|(term, _)| term.starts_with(query_term))
+          .map(|(term, _)| (term.as_str(), PREFIX_MATCH_WEIGHT)),
+      );
+    }
+    let len = query_term.chars().count();
+    if query.max_distance > 0 && len >= 3 {
+      let matched = terms.iter().map(|(term, _)| *term).collect::<HashSet<_>>();
+      terms.extend(
+        self
+          .fuzzy_candidates(query_term, query.max_distance)
+          .into_iter()
+          .filter(|term| !matched.contains(term))
+          .filter(|term| term.chars().count().abs_diff(len) <= query.max_distance)
+          .filter(|term| edit_distance(query_term, term) <= query.max_distance)
+          .map(|term| (term, FUZZY_MATCH_WEIGHT)),
+      );
+    }
+    terms
+  }
+}
+
+/// Splits the text into lowercased Latin words, CJK characters and pairs of adjacent CJK
+/// characters.
+pub fn tokenize(text: &str) -> Vec<String> {
+  let mut terms = vec![];
+  let mut word = String::new();
+  let mut prev_cjk = None;
+  for c in text.chars() {
+    if is_cjk(c) {
+      if !word.is_empty() {
+        terms.push(std::mem::take(&mut word));
+      }
+      terms.push(c.to_string());
+      if let Some(prev) = prev_cjk {
+        terms.push(format!("{}{}", prev, c));
+      }
+      prev_cjk = Some(c);
+    } else {
+      prev_cjk = None;
+      if c.is_alphanumeric() {
+        word.extend(c.to_lowercase());
+      } else if !word.is_empty() {
+        terms.push(std::mem::take(&mut word));
+      }
+    }
+  }
+  if !word.is_empty() {
+    terms.push(word);
+  }
+  terms
+}
+
+fn is_cjk(c: char) -> bool {
+  matches!(c as u32,
+    0x3040..=0x30FF // Hiragana and Katakana
+    | 0x3400..=0x4DBF // CJK Unified Ideographs Extension A
+    | 0x4E00..=0x9FFF // CJK Unified Ideographs
+    | 0xAC00..=0xD7AF // Hangul Syllables
+    | 0xF900..=0xFAFF // CJK Compatibility Ideographs
+    | 0x20000..=0x2A6DF // CJK Unified Ideographs Extension B
+  )
+}
+
+/// The distinct pairs of adjacent characters of `^term$`.
+fn char_pairs(term: &str) -> HashSet<String> {
+  let chars = std::iter::once('^')
+    .chain(term.chars())
+    .chain(std::iter::once('$'))
+    .collect::<Vec<_>>();
+  chars.windows(2).map(|pair| pair.iter().collect()).collect()
+}
+
+fn is_cjk_term(term: &str) -> bool {
+  term.chars().next().map(is_cjk).unwrap_or(false)
+}
+
+/// The Levenshtein distance between the two strings.
+fn edit_distance(a: &str, b: &str) -> usize {
+  let b = b.chars().collect::<Vec<_>>();
+  let mut prev_row = (0..=b.len()).collect::<Vec<_>>();
+  for (i, ca) in a.chars().enumerate() {
+    let mut row = vec![i + 1; b.len() + 1];
+    for (j, cb) in b.iter().enumerate() {
+      let substitution = prev_row[j] + usize::from(ca != *cb);
+      row[j + 1] = substitution.min(prev_row[j + 1] + 1).min(row[j] + 1);
+    }
+    prev_row = row;
+  }
+  prev_row[b.len()]
+}
+
+fn make_snippet(document: &IndexedDocument, terms: &[&str], len: usize) -> String {
+  let text = if document.body.is_empty() {
+    &document.title
+  } else {
+    &document.body
+  };
+  let chars = text.chars().collect::<Vec<_>>();
+  let lowercase = chars
+    .iter()
+    .map(|c| c.to_lowercase().next().unwrap_or(*c))
+    .collect::<Vec<_>>();
+  let position = terms
+    .iter()
+    .filter_map(|term| find_chars(&lowercase, term))
+    .min();
+  let start = position.map(|p| p.saturating_sub(len / 4)).unwrap_or(0);
+  let end = (start + len).min(chars.len());
+  let mut snippet = String::new();
+  if start > 0 {
+    snippet.push('…');
+  }
+  snippet.extend(&chars[start..end]);
+  if end < chars.len() {
+    snippet.push('…');
+  }
+  snippet
+}
+
+fn find_chars(haystack: &[char], needle: &str) -> Option<usize> {
+  let needle = needle.chars().collect::<Vec<_>>();
+  if needle.is_empty() || needle.len() > haystack.len() {
+    return None;
+  }
+  haystack
+    .windows(needle.len())
+    .position(|window| window == needle.as_slice())
+}
+
+/// A [SearchIndex] that can be shared and fed by [Collab::subscribe_index_content].
+///
+/// [Collab::subscribe_index_content]: crate::core::collab::Collab::subscribe_index_content
+#[derive(Clone, Default)]
+pub struct CollabSearch {
+  index: Arc<RwLock<SearchIndex>>,
+  /// The ids of the documents that changed since the last call to [CollabSearch::take_changes].
+  changes: Arc<Mutex<HashSet<String>>>,
+}
+
+impl CollabSearch {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  pub fn from_index(index: SearchIndex) -> Self {
+    Self {
+      index: Arc::new(RwLock::new(index)),
+      changes: Default::default(),
+    }
+  }
+
+  pub fn search(&self, query: &SearchQuery) -> Vec<SearchResult> {
+    self.index.read().search(query)
+  }
+
+  pub fn apply(&self, content: &IndexContent) {
+    let ids = self.index.write().apply_changes(content);
+    self.changes.lock().extend(ids);
+  }
+
+  pub fn upsert(&self, document: IndexedDocument) {
+    let id = document.id.clone();
+    self.index.write().upsert(document);
+    self.changes.lock().insert(id);
+  }
+
+  /// Applies the messages of the receiver until all the senders are dropped. Spawn it to keep
+  /// the index up to date. Messages that were missed because the receiver lagged are logged:
+  /// the missed objects must be indexed again with [CollabSearch::upsert].
+  pub async fn consume(&self, mut receiver: IndexContentReceiver) {
+    loop {
+      match receiver.recv().await {
+        Ok(content) => self.apply(&content),
+        Err(RecvError::Lagged(count)) => {
+          tracing::warn!("Search index missed {} index content messages", count);
+        },
+        Err(RecvError::Closed) => break,
+      }
+    }
+  }
+
+  /// Returns the sorted ids of the documents that were added, updated or removed since the last
+  /// call. Use it to persist only the changed documents.
+  pub fn take_changes(&self) -> Vec<String> {
+    let mut ids = std::mem::take(&mut *self.changes.lock())
+      .into_iter()
+      .collect::<Vec<_>>();
+    ids.sort();
+    ids
+  }
+
+  /// Marks the documents as changed again, e.g. when saving them failed.
+  pub fn mark_changed(&self, ids: impl IntoIterator<Item = String>) {
+    self.changes.lock().extend(ids);
+  }
+
+  pub fn read_index(&self) -> RwLockReadGuard<'_, SearchIndex> {
+    self.index.read()
+  }
+
+  pub fn encode(&self) -> Result<Vec<u8>, bincode::Error> {
+    self.index.read().encode()
+  }
+
+  pub fn len(&self) -> usize {
+    self.index.read().len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.index.read().is_empty()
+  }
+}
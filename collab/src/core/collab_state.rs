@@ -3,6 +3,8 @@ use std::sync::Arc;
 use parking_lot::RwLock;
 use tokio::sync::watch;
 
+use crate::core::compaction::CompactionState;
+
 #[derive(Clone, Debug)]
 pub enum InitState {
   /// The [Collab] is not initialized yet. Call [Collab::initialize] to initialize
@@ -66,21 +68,26 @@ pub struct State {
   init_state: Arc<RwLock<InitState>>,
   sync_state: Arc<RwLock<SyncState>>,
   snapshot_state: Arc<RwLock<SnapshotState>>,
+  compaction_state: Arc<RwLock<CompactionState>>,
   pub(crate) sync_state_notifier: Arc<watch::Sender<SyncState>>,
   pub(crate) snapshot_state_notifier: Arc<watch::Sender<SnapshotState>>,
+  pub(crate) compaction_state_notifier: Arc<watch::Sender<CompactionState>>,
 }
 
 impl State {
   pub fn new(object_id: &str) -> Self {
     let (sync_state_notifier, _) = watch::channel(SyncState::InitSyncBegin);
     let (snapshot_state_notifier, _) = watch::channel(SnapshotState::WaitingForSnapshot);
+    let (compaction_state_notifier, _) = watch::channel(CompactionState::Current { epoch: 0 });
     Self {
       object_id: object_id.to_string(),
       init_state: Arc::new(RwLock::new(InitState::Uninitialized)),
       sync_state: Arc::new(RwLock::new(SyncState::InitSyncBegin)),
       snapshot_state: Arc::new(RwLock::new(SnapshotState::WaitingForSnapshot)),
+      compaction_state: Arc::new(RwLock::new(CompactionState::Current { epoch: 0 })),
       sync_state_notifier: Arc::new(sync_state_notifier),
       snapshot_state_notifier: Arc::new(snapshot_state_notifier),
+      compaction_state_notifier: Arc::new(compaction_state_notifier),
     }
   }
 
@@ -118,4 +125,22 @@ impl State {
       let _ = self.snapshot_state_notifier.send(new_state);
     }
   }
+
+  pub fn get_compaction_state(&self) -> CompactionState {
+    self.compaction_state.read().clone()
+  }
+
+  pub fn set_compaction_state(&self, new_state: CompactionState) {
+    let old_state = self.compaction_state.read().clone();
+    if old_state != new_state {
+      tracing::debug!(
+        "{} compaction state {:?} => {:?}",
+        self.object_id,
+        old_state,
+        new_state
+      );
+      *self.compaction_state.write() = new_state.clone();
+      let _ = self.compaction_state_notifier.send(new_state);
+    }
+  }
 }
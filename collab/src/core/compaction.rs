@@ -0,0 +1,156 @@
+//! Squashes the history of a [Collab].
+//!
+//! A [Collab] never collects its garbage, so every deleted item stays in the document as a
+//! tombstone. [Collab::compact] copies the current content into a fresh document that has a new
+//! client id and no history, and bumps the compaction epoch stored in the `meta` section.
+//!
+//! The compacted document doesn't share any item with the old one, so the two can't be merged.
+//! The old document is marked as superseded by the new epoch. The mark is a regular update, so
+//! it reaches every device that still holds the old history, and those devices must replace
+//! their state with the compacted one. See [CompactionState].
+
+use tracing::warn;
+use yrs::types::text::YChange;
+use yrs::types::Value;
+use yrs::{
+  Any, Array, ArrayPrelim, ArrayRef, Doc, Map, MapPrelim, MapRef, OffsetKind, Options, ReadTxn,
+  Text, TextPrelim, TextRef, Transact, TransactionMut,
+};
+
+use crate::core::collab::{DATA_SECTION, META_SECTION};
+use crate::core::collab_plugin::EncodedCollab;
+use crate::core::subdoc::{make_subdoc, SUBDOCS_SECTION};
+use crate::core::transaction::DocTransactionExtension;
+use crate::preclude::MapRefExtension;
+
+/// The epoch of the history that is stored in the document. Starts at 0 and is increased by
+/// every compaction.
+pub const COMPACTION_EPOCH: &str = "compaction_epoch";
+
+/// Set in the old document when it's compacted. Its value is the epoch of the compacted
+/// document.
+pub const SUPERSEDED_BY_EPOCH: &str = "superseded_by_epoch";
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum CompactionState {
+  /// The document holds the latest history.
+  Current { epoch: i64 },
+  /// The document was compacted into the given epoch. Its state must be replaced by the
+  /// compacted state instead of being merged with it.
+  Superseded { epoch: i64 },
+}
+
+impl CompactionState {
+  pub fn from_meta<T: ReadTxn>(txn: &T, meta: &MapRef) -> Self {
+    let epoch = meta.get_i64_with_txn(txn, COMPACTION_EPOCH).unwrap_or(0);
+    match meta.get_i64_with_txn(txn, SUPERSEDED_BY_EPOCH) {
+      Some(superseded_by) if superseded_by > epoch => CompactionState::Superseded {
+        epoch: superseded_by,
+      },
+      _ => CompactionState::Current { epoch },
+    }
+  }
+
+  pub fn is_superseded(&self) -> bool {
+    matches!(self, CompactionState::Superseded { .. })
+  }
+}
+
+/// The result of [Collab::compact].
+#[derive(Debug, Clone)]
+pub struct CompactedCollab {
+  pub epoch: i64,
+  pub encoded_collab: EncodedCollab,
+}
+
+/// Copies the `data`, `meta` and `subdocs` sections of the document into a new garbage collected
+/// document that starts at the given epoch. Only the references to the child documents are
+/// copied, their content isn't part of the parent.
+pub(crate) fn compact_doc<T: ReadTxn>(
+  txn: &T,
+  data: &MapRef,
+  meta: &MapRef,
+  subdocs: &MapRef,
+  epoch: i64,
+) -> EncodedCollab {
+  let new_doc = Doc::with_options(Options {
+    skip_gc: false,
+    offset_kind: OffsetKind::Utf16,
+    ..Options::default()
+  });
+  let new_data = new_doc.get_or_insert_map(DATA_SECTION);
+  let new_meta = new_doc.get_or_insert_map(META_SECTION);
+  let new_subdocs = new_doc.get_or_insert_map(SUBDOCS_SECTION);
+  {
+    let mut new_txn = new_doc.transact_mut();
+    copy_map(txn, data, &mut new_txn, &new_data);
+    copy_map(txn, meta, &mut new_txn, &new_meta);
+    copy_map(txn, subdocs, &mut new_txn, &new_subdocs);
+    new_meta.remove(&mut new_txn, SUPERSEDED_BY_EPOCH);
+    new_meta.insert_i64_with_txn(&mut new_txn, COMPACTION_EPOCH, epoch);
+  }
+  new_doc.get_encoded_collab_v1()
+}
+
+fn copy_map<T: ReadTxn>(txn: &T, src: &MapRef, new_txn: &mut TransactionMut, dst: &MapRef) {
+  for (key, value) in src.iter(txn) {
+    match value {
+      Value::Any(any) => {
+        dst.insert(new_txn, key, any);
+      },
+      Value::YMap(map) => {
+        let new_map = dst.insert(new_txn, key, MapPrelim::<Any>::new());
+        copy_map(txn, &map, new_txn, &new_map);
+      },
+      Value::YArray(array) => {
+        let new_array = dst.insert(new_txn, key, ArrayPrelim::<Vec<Any>, Any>::from(vec![]));
+        copy_array(txn, &array, new_txn, &new_array);
+      },
+      Value::YText(text) => {
+        let new_text = dst.insert(new_txn, key, TextPrelim::new(""));
+        copy_text(txn, &text, new_txn, &new_text);
+      },
+      Value::YDoc(doc) => {
+        dst.insert(new_txn, key, make_subdoc(doc.guid()));
+      },
+      other => warn!("Compaction skips unsupported value {:?} of {}", other, key),
+    }
+  }
+}
+
+fn copy_array<T: ReadTxn>(txn: &T, src: &ArrayRef, new_txn: &mut TransactionMut, dst: &ArrayRef) {
+  for value in src.iter(txn) {
+    match value {
+      Value::Any(any) => {
+        dst.push_back(new_txn, any);
+      },
+      Value::YMap(map) => {
+        let new_map = dst.push_back(new_txn, MapPrelim::<Any>::new());
+        copy_map(txn, &map, new_txn, &new_map);
+      },
+      Value::YArray(array) => {
+        let new_array = dst.push_back(new_txn, ArrayPrelim::<Vec<Any>, Any>::from(vec![]));
+        copy_array(txn, &array, new_txn, &new_array);
+      },
+      Value::YText(text) => {
+        let new_text = dst.push_back(new_txn, TextPrelim::new(""));
+        copy_text(txn, &text, new_txn, &new_text);
+      },
+      other => warn!("Compaction skips unsupported array value {:?}", other),
+    }
+  }
+}
+
+fn copy_text<T: ReadTxn>(txn: &T, src: &TextRef, new_txn: &mut TransactionMut, dst: &TextRef) {
+  for chunk in src.diff(txn, YChange::identity) {
+    let index = dst.len(new_txn);
+    let attrs = chunk.attributes.map(|attrs| *attrs).unwrap_or_default();
+    match chunk.insert {
+      Value::Any(Any::String(s)) => dst.insert_with_attributes(new_txn, index, &s, attrs),
+      Value::Any(any) => {
+        dst.insert_embed_with_attributes(new_txn, index, any, attrs);
+      },
+      other => warn!("Compaction skips unsupported text embed {:?}", other),
+    }
+  }
+}
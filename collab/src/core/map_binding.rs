@@ -0,0 +1,284 @@
+//! Typed bindings between structs and [MapRef]s.
+//!
+//! `#[derive(Collab)]` from `collab-derive` implements [MapBinding] for a struct, which stores
+//! each field under its own key so concurrent edits of different fields merge. The generated code
+//! reads and writes the fields with the functions of this module:
+//!
+//! * `String`, `i64`, `f64` and `bool` are stored as [Any] values, see [AnyValue].
+//! * `Vec<V>` and `HashMap<String, V>` of those types are stored as arrays and maps of values.
+//! * Fields marked with `#[collab(map)]` are nested [MapBinding]s, stored as nested maps. A
+//!   `Vec` of them is stored as an array of maps.
+//! * Any other type is stored as a json string.
+//!
+//! Only the changed values are written, and writing a nested map or a map of values updates the
+//! map that is already stored under the key, so the concurrent edits of different fields of a
+//! nested struct merge too. A collection is only read
+//! if all of its items can be read.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use yrs::types::Value;
+use yrs::{Any, Array, ArrayPrelim, Map, MapPrelim, MapRef, ReadTxn, TransactionMut};
+
+/// A struct that is stored field by field in a [MapRef].
+pub trait MapBinding: Sized {
+  /// Reads the struct from the map. Returns None if a required field is missing.
+  fn from_map_ref<T: ReadTxn>(txn: &T, map_ref: &MapRef) -> Option<Self>;
+
+  /// Writes every field of the struct into the map. Optional fields that are None are removed.
+  fn fill_map_ref(self, txn: &mut TransactionMut, map_ref: &MapRef);
+}
+
+/// A value that is stored as an [Any].
+pub trait AnyValue: Sized {
+  fn into_any(self) -> Any;
+
+  fn from_any(any: Any) -> Option<Self>;
+}
+
+impl AnyValue for String {
+  fn into_any(self) -> Any {
+    Any::String(Arc::from(self))
+  }
+
+  fn from_any(any: Any) -> Option<Self> {
+    match any {
+      Any::String(value) => Some(value.to_string()),
+      _ => None,
+    }
+  }
+}
+
+impl AnyValue for i64 {
+  fn into_any(self) -> Any {
+    Any::BigInt(self)
+  }
+
+  fn from_any(any: Any) -> Option<Self> {
+    match any {
+      Any::BigInt(value) => Some(value),
+      Any::Number(value) if value.fract() == 0.0 => Some(value as i64),
+      _ => None,
+    }
+  }
+}
+
+impl AnyValue for f64 {
+  fn into_any(self) -> Any {
+    Any::Number(self)
+  }
+
+  fn from_any(any: Any) -> Option<Self> {
+    match any {
+      Any::Number(value) => Some(value),
+      Any::BigInt(value) => Some(value as f64),
+      _ => None,
+    }
+  }
+}
+
+impl AnyValue for bool {
+  fn into_any(self) -> Any {
+    Any::Bool(self)
+  }
+
+  fn from_any(any: Any) -> Option<Self> {
+    match any {
+      Any::Bool(value) => Some(value),
+      _ => None,
+    }
+  }
+}
+
+pub fn read_value<V: AnyValue, T: ReadTxn>(txn: &T, map_ref: &MapRef, key: &str) -> Option<V> {
+  match map_ref.get(txn, key)? {
+    Value::Any(any) => V::from_any(any),
+    _ => None,
+  }
+}
+
+/// Does nothing if the stored value is equal, so rewriting a struct only touches its changed
+/// fields.
+pub fn write_value<V: AnyValue>(txn: &mut TransactionMut, map_ref: &MapRef, key: &str, value: V) {
+  let value = value.into_any();
+  if !matches!(map_ref.get(txn, key), Some(Value::Any(any)) if any == value) {
+    map_ref.insert(txn, key, value);
+  }
+}
+
+/// Returns None if the array is missing or if one of its values has another type.
+pub fn read_array<V: AnyValue, T: ReadTxn>(txn: &T, map_ref: &MapRef, key: &str) -> Option<Vec<V>> {
+  match map_ref.get(txn, key)? {
+    Value::YArray(array) => array
+      .iter(txn)
+      .map(|value| match value {
+        Value::Any(any) => V::from_any(any),
+        _ => None,
+      })
+      .collect(),
+    _ => None,
+  }
+}
+
+/// The items of an array have no key, so the array is replaced unless it's unchanged.
+pub fn write_array<V: AnyValue>(
+  txn: &mut TransactionMut,
+  map_ref: &MapRef,
+  key: &str,
+  values: Vec<V>,
+) {
+  let values = values
+    .into_iter()
+    .map(AnyValue::into_any)
+    .collect::<Vec<_>>();
+  if let Some(Value::YArray(array)) = map_ref.get(txn, key) {
+    let unchanged = array.len(txn) as usize == values.len()
+      && array
+        .iter(txn)
+        .zip(values.iter())
+        .all(|(value, new_value)| matches!(value, Value::Any(any) if &any == new_value));
+    if unchanged {
+      return;
+    }
+  }
+  map_ref.insert(txn, key, ArrayPrelim::<Vec<Any>, Any>::from(values));
+}
+
+/// Returns None if the map is missing or if one of its values has another type.
+pub fn read_map_values<V: AnyValue, T: ReadTxn>(
+  txn: &T,
+  map_ref: &MapRef,
+  key: &str,
+) -> Option<HashMap<String, V>> {
+  match map_ref.get(txn, key)? {
+    Value::YMap(map) => map
+      .iter(txn)
+      .map(|(key, value)| match value {
+        Value::Any(any) => V::from_any(any).map(|value| (key.to_string(), value)),
+        _ => None,
+      })
+      .collect(),
+    _ => None,
+  }
+}
+
+/// Only the changed values of the stored map are written, and the keys that are not in `values`
+/// are removed.
+pub fn write_map_values<V: AnyValue>(
+  txn: &mut TransactionMut,
+  map_ref: &MapRef,
+  key: &str,
+  values: HashMap<String, V>,
+) {
+  let map = get_or_insert_map(txn, map_ref, key);
+  let removed_keys = map
+    .keys(txn)
+    .filter(|key| !values.contains_key(*key))
+    .map(|key| key.to_string())
+    .collect::<Vec<_>>();
+  for key in removed_keys {
+    map.remove(txn, &key);
+  }
+  for (key, value) in values {
+    let value = value.into_any();
+    if !matches!(map.get(txn, &key), Some(Value::Any(any)) if any == value) {
+      map.insert(txn, key, value);
+    }
+  }
+}
+
+pub fn read_nested<B: MapBinding, T: ReadTxn>(txn: &T, map_ref: &MapRef, key: &str) -> Option<B> {
+  match map_ref.get(txn, key)? {
+    Value::YMap(map) => B::from_map_ref(txn, &map),
+    _ => None,
+  }
+}
+
+pub fn write_nested<B: MapBinding>(
+  txn: &mut TransactionMut,
+  map_ref: &MapRef,
+  key: &str,
+  value: B,
+) {
+  let map = get_or_insert_map(txn, map_ref, key);
+  value.fill_map_ref(txn, &map);
+}
+
+/// Returns None if the array is missing or if one of its items can't be read.
+pub fn read_nested_array<B: MapBinding, T: ReadTxn>(
+  txn: &T,
+  map_ref: &MapRef,
+  key: &str,
+) -> Option<Vec<B>> {
+  match map_ref.get(txn, key)? {
+    Value::YArray(array) => array
+      .iter(txn)
+      .map(|value| match value {
+        Value::YMap(map) => B::from_map_ref(txn, &map),
+        _ => None,
+      })
+      .collect(),
+    _ => None,
+  }
+}
+
+/// The item at each index is written into the map that is already stored at that index. The
+/// items past the end of `values` are removed.
+pub fn write_nested_array<B: MapBinding>(
+  txn: &mut TransactionMut,
+  map_ref: &MapRef,
+  key: &str,
+  values: Vec<B>,
+) {
+  let array = match map_ref.get(txn, key) {
+    Some(Value::YArray(array)) => array,
+    _ => map_ref.insert(txn, key, ArrayPrelim::<Vec<Any>, Any>::from(vec![])),
+  };
+  let len = values.len() as u32;
+  let stored_len = array.len(txn);
+  if stored_len > len {
+    array.remove_range(txn, len, stored_len - len);
+  }
+  for (index, value) in (0..).zip(values) {
+    let map = match array.get(txn, index) {
+      Some(Value::YMap(map)) => map,
+      Some(_) => {
+        array.remove(txn, index);
+        array.insert(txn, index, MapPrelim::<Any>::new())
+      },
+      None => array.push_back(txn, MapPrelim::<Any>::new()),
+    };
+    value.fill_map_ref(txn, &map);
+  }
+}
+
+/// Returns the map stored under the key, or inserts an empty one if there is none.
+fn get_or_insert_map(txn: &mut TransactionMut, map_ref: &MapRef, key: &str) -> MapRef {
+  match map_ref.get(txn, key) {
+    Some(Value::YMap(map)) => map,
+    _ => map_ref.insert(txn, key, MapPrelim::<Any>::new()),
+  }
+}
+
+pub fn read_json<V: DeserializeOwned, T: ReadTxn>(
+  txn: &T,
+  map_ref: &MapRef,
+  key: &str,
+) -> Option<V> {
+  let json = read_value::<String, T>(txn, map_ref, key)?;
+  serde_json::from_str(&json).ok()
+}
+
+pub fn write_json<V: Serialize>(txn: &mut TransactionMut, map_ref: &MapRef, key: &str, value: V) {
+  match serde_json::to_string(&value) {
+    Ok(json) => write_value(txn, map_ref, key, json),
+    Err(err) => tracing::error!("Failed to serialize the value of {}: {}", key, err),
+  }
+}
+
+pub fn remove_key(txn: &mut TransactionMut, map_ref: &MapRef, key: &str) {
+  map_ref.remove(txn, key);
+}
@@ -12,6 +12,7 @@ use yrs::{
 use crate::core::array_wrapper::ArrayRefWrapper;
 use crate::core::text_wrapper::TextRefWrapper;
 use crate::core::value::YrsValueExtension;
+use crate::error::CollabError;
 use crate::preclude::*;
 use crate::util::any_to_json_value;
 
@@ -43,10 +44,14 @@ impl MapRefWrapper {
     self.map_ref
   }
 
+  /// Logs an error if the collab is read-only.
   pub fn insert<V: Prelim>(&self, key: &str, value: V) {
-    self.collab_ctx.with_transact_mut(|txn| {
+    let result = self.collab_ctx.try_with_transact_mut(|txn| {
       self.map_ref.insert(txn, key, value);
-    })
+    });
+    if let Err(err) = result {
+      tracing::warn!("failed to insert {}: {}", key, err);
+    }
   }
 
   pub fn insert_with_txn<V: Prelim>(&self, txn: &mut TransactionMut, key: &str, value: V) {
@@ -59,12 +64,21 @@ impl MapRefWrapper {
     TextRefWrapper::new(text_ref, self.collab_ctx.clone())
   }
 
-  pub fn insert_array<V: Prelim>(&self, key: &str, values: Vec<V>) -> ArrayRefWrapper {
-    self.with_transact_mut(|txn| self.insert_array_with_txn(txn, key, values))
+  /// Logs an error and returns [None] if the collab is read-only.
+  pub fn insert_array<V: Prelim>(&self, key: &str, values: Vec<V>) -> Option<ArrayRefWrapper> {
+    let result = self.with_transact_mut(|txn| self.insert_array_with_txn(txn, key, values));
+    if let Err(err) = &result {
+      tracing::warn!("failed to insert array {}: {}", key, err);
+    }
+    result.ok()
   }
 
+  /// Logs an error if the collab is read-only.
   pub fn insert_map<T: Into<MapPrelim<Any>>>(&self, key: &str, value: T) {
-    self.with_transact_mut(|txn| self.insert_map_with_txn(txn, key, value));
+    let result = self.try_with_transact_mut(|txn| self.insert_map_with_txn(txn, key, value));
+    if let Err(err) = result {
+      tracing::warn!("failed to insert map {}: {}", key, err);
+    }
   }
 
   pub fn insert_map_with_txn<T: Into<MapPrelim<Any>>>(
@@ -153,11 +167,15 @@ impl MapRefWrapper {
     Some(TextRefWrapper::new(text_ref, self.collab_ctx.clone()))
   }
 
+  /// Logs an error if the collab is read-only.
   pub fn insert_json<T: Serialize>(&self, key: &str, value: T) {
     let value = serde_json::to_value(&value).unwrap();
-    self.collab_ctx.with_transact_mut(|txn| {
+    let result = self.collab_ctx.try_with_transact_mut(|txn| {
       insert_json_value_to_map_ref(key, &value, self.map_ref.clone(), txn);
     });
+    if let Err(err) = result {
+      tracing::warn!("failed to insert json {}: {}", key, err);
+    }
   }
 
   pub fn insert_json_with_txn<T: Serialize>(&self, txn: &mut TransactionMut, key: &str, value: T) {
@@ -181,13 +199,24 @@ impl MapRefWrapper {
     self.collab_ctx.transact()
   }
 
-  pub fn with_transact_mut<F, T>(&self, f: F) -> T
+  pub fn with_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
   where
     F: FnOnce(&mut TransactionMut) -> T,
   {
     self.collab_ctx.with_transact_mut(f)
   }
 
+  pub fn try_with_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    self.collab_ctx.try_with_transact_mut(f)
+  }
+
+  pub fn is_read_only(&self) -> bool {
+    self.collab_ctx.is_read_only()
+  }
+
   pub fn to_json_str(&self) -> String {
     let txn = self.collab_ctx.transact();
     let value = self.map_ref.to_json(&txn);
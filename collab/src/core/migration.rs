@@ -0,0 +1,221 @@
+//! Versioned migrations of the schema of a [Collab].
+//!
+//! Each [Migration] has a version. The versions that were applied are recorded in the `meta`
+//! section, one key per version, so the record of two devices that migrate the same document at
+//! the same time is the union of both records. A [CollabMigrator] runs the migrations whose
+//! version is greater than the recorded one, in ascending order.
+//!
+//! Migrations run on a copy of the document. The changes of a migration are applied to the
+//! [Collab] in one local transaction once it succeeds, so a failing migration doesn't change the
+//! [Collab] at all.
+//!
+//! Migrations must be idempotent: when two devices migrate concurrently, both run the same
+//! migration and their changes are merged. Prefer writing to map keys over pushing to arrays.
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, Map, MapRef, ReadTxn, StateVector, Transact, TransactionMut, Update};
+
+use crate::core::collab::{make_yrs_doc, Collab, TransactionMutExt, DATA_SECTION, META_SECTION};
+use crate::error::CollabError;
+use crate::preclude::MapRefExtension;
+
+/// The prefix of the `meta` keys that record the applied versions, e.g. `schema_version:2`.
+pub const SCHEMA_VERSION_PREFIX: &str = "schema_version:";
+
+pub type MigrationFn =
+  Box<dyn Fn(&MigrationDoc, &mut TransactionMut) -> Result<(), CollabError> + Send + Sync>;
+
+/// The copy of the document that a [Migration] changes.
+pub struct MigrationDoc {
+  doc: Doc,
+  pub data: MapRef,
+  pub meta: MapRef,
+}
+
+impl MigrationDoc {
+  fn copy_of(collab: &Collab) -> Result<Self, CollabError> {
+    let doc_state = collab
+      .transact()
+      .encode_state_as_update_v1(&StateVector::default());
+    let doc = make_yrs_doc();
+    doc
+      .transact_mut()
+      .try_apply_update(Update::decode_v1(&doc_state)?)?;
+    let data = doc.get_or_insert_map(DATA_SECTION);
+    let meta = doc.get_or_insert_map(META_SECTION);
+    Ok(Self { doc, data, meta })
+  }
+
+  /// Runs the migration and returns its changes as a v1 encoded update.
+  fn migrate(&self, migration: &Migration) -> Result<Vec<u8>, CollabError> {
+    let sv = self.doc.transact().state_vector();
+    {
+      let mut txn = self.doc.transact_mut();
+      (migration.migrate)(self, &mut txn)?;
+      record_schema_version(&mut txn, &self.meta, migration.version);
+    }
+    Ok(self.doc.transact().encode_state_as_update_v1(&sv))
+  }
+}
+
+pub struct Migration {
+  version: u32,
+  name: String,
+  migrate: MigrationFn,
+}
+
+impl Migration {
+  /// The version must be greater than 0. The collab is at this version after the migration.
+  pub fn new<F>(version: u32, name: impl ToString, migrate: F) -> Self
+  where
+    F: Fn(&MigrationDoc, &mut TransactionMut) -> Result<(), CollabError> + Send + Sync + 'static,
+  {
+    debug_assert!(
+      version > 0,
+      "The version of a migration must be greater than 0"
+    );
+    Self {
+      version,
+      name: name.to_string(),
+      migrate: Box::new(migrate),
+    }
+  }
+
+  pub fn version(&self) -> u32 {
+    self.version
+  }
+
+  pub fn name(&self) -> &str {
+    &self.name
+  }
+}
+
+impl Debug for Migration {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    f.debug_struct("Migration")
+      .field("version", &self.version)
+      .field("name", &self.name)
+      .finish()
+  }
+}
+
+/// An ordered list of [Migration]s.
+#[derive(Debug, Clone, Default)]
+pub struct CollabMigrator {
+  migrations: Vec<Arc<Migration>>,
+}
+
+impl CollabMigrator {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// Adds the migration. A migration with the same version is replaced.
+  pub fn with_migration(mut self, migration: Migration) -> Self {
+    match self
+      .migrations
+      .binary_search_by_key(&migration.version, |m| m.version)
+    {
+      Ok(index) => self.migrations[index] = Arc::new(migration),
+      Err(index) => self.migrations.insert(index, Arc::new(migration)),
+    }
+    self
+  }
+
+  /// Returns the version of the last migration, or 0 if there is none.
+  pub fn latest_version(&self) -> u32 {
+    self.migrations.last().map(|m| m.version).unwrap_or(0)
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.migrations.is_empty()
+  }
+
+  /// Runs the migrations that the collab hasn't applied yet on a copy of the document. The
+  /// changes of each migration, including the record of its version, are applied to the collab
+  /// in their own transaction. If a migration fails, none of its changes are applied, the
+  /// following migrations are not run and the collab stays at the version of the last
+  /// successful one.
+  pub fn run(&self, collab: &Collab) -> Result<MigrationReport, CollabError> {
+    let from_version = collab.get_schema_version();
+    let mut report = MigrationReport {
+      from_version,
+      to_version: from_version,
+      applied: vec![],
+    };
+    let mut pending = self
+      .migrations
+      .iter()
+      .filter(|migration| migration.version > from_version)
+      .peekable();
+    let doc = match pending.peek() {
+      None => return Ok(report),
+      Some(first) => MigrationDoc::copy_of(collab).map_err(|err| migration_failed(first, err))?,
+    };
+    for migration in pending {
+      let update = doc
+        .migrate(migration)
+        .map_err(|err| migration_failed(migration, err))?;
+      collab
+        .try_with_origin_transact_mut(|txn| txn.try_apply_update(Update::decode_v1(&update)?))
+        .and_then(|result| result)
+        .map_err(|err| migration_failed(migration, err))?;
+      tracing::debug!(
+        "{} did migrate to version {}: {}",
+        collab.object_id,
+        migration.version,
+        migration.name
+      );
+      report.to_version = migration.version;
+      report.applied.push(AppliedMigration {
+        version: migration.version,
+        name: migration.name.clone(),
+      });
+    }
+    Ok(report)
+  }
+}
+
+fn migration_failed(migration: &Migration, err: CollabError) -> CollabError {
+  CollabError::MigrationFailed {
+    version: migration.version,
+    reason: err.to_string(),
+  }
+}
+
+#[derive(Debug, Clone, Default, Eq, PartialEq)]
+pub struct MigrationReport {
+  pub from_version: u32,
+  pub to_version: u32,
+  /// The migrations that were run, in the order they were run.
+  pub applied: Vec<AppliedMigration>,
+}
+
+impl MigrationReport {
+  pub fn is_empty(&self) -> bool {
+    self.applied.is_empty()
+  }
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct AppliedMigration {
+  pub version: u32,
+  pub name: String,
+}
+
+/// Returns the greatest version that is recorded in the `meta` section, or 0.
+pub fn read_schema_version<T: ReadTxn>(txn: &T, meta: &MapRef) -> u32 {
+  meta
+    .keys(txn)
+    .filter_map(|key| key.strip_prefix(SCHEMA_VERSION_PREFIX)?.parse::<u32>().ok())
+    .max()
+    .unwrap_or(0)
+}
+
+fn record_schema_version(txn: &mut TransactionMut, meta: &MapRef, version: u32) {
+  let key = format!("{}{}", SCHEMA_VERSION_PREFIX, version);
+  meta.insert_i64_with_txn(txn, &key, chrono::Utc::now().timestamp());
+}
@@ -1,14 +1,27 @@
+pub mod access_control;
 pub mod any_array;
 pub mod any_map;
 pub mod array_wrapper;
 pub mod awareness;
 pub mod collab;
+pub mod collab_envelope;
 pub mod collab_plugin;
-mod collab_search;
+pub mod collab_search;
 mod collab_serde;
 pub mod collab_state;
+pub mod compaction;
+pub mod map_binding;
 pub mod map_wrapper;
+pub mod migration;
 pub mod origin;
+pub mod plugin_pipeline;
+pub mod presence;
+pub mod read_only;
+pub mod subdoc;
 pub mod text_wrapper;
 pub mod transaction;
+pub mod transaction_hook;
+pub mod undo;
+mod update_blocks;
+pub mod updates;
 pub mod value;
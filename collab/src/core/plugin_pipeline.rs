@@ -0,0 +1,393 @@
+//! Ordered, asynchronous delivery of document events to [AsyncCollabPlugin]s.
+//!
+//! The Yrs observers can't await, so the events are pushed into a per-collab queue that is
+//! drained by a single worker task. The worker awaits each plugin before it takes the next
+//! event, which keeps the updates in the order they were applied to the document.
+//!
+//! The queue is a channel bounded by the capacity of the pipeline. The observers can't wait for
+//! room in the channel, so the events that don't fit are merged into the last overflowing event
+//! of the same kind: consecutive updates become a single update and consecutive flushes a single
+//! flush. The memory used by the queue is bounded by the size of the changes instead of their
+//! number. Once the number of queued events reaches the capacity,
+//! [PluginPipeline::is_backpressured] returns true and writers should await
+//! [PluginPipeline::ready] before making more changes.
+
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use bytes::Bytes;
+use parking_lot::{Mutex, RwLock};
+use tokio::sync::mpsc::error::{TryRecvError, TrySendError};
+use tokio::sync::{mpsc, oneshot, Notify};
+use tracing::{error, trace};
+
+use crate::core::collab_plugin::EncodedCollab;
+use crate::core::origin::CollabOrigin;
+use crate::core::updates::merge_updates_v1;
+use crate::error::CollabError;
+
+pub const DEFAULT_PIPELINE_CAPACITY: usize = 1024;
+
+/// A plugin whose callbacks are awaited one after another, in the order the events happened.
+/// Use it instead of [CollabPlugin](crate::core::collab_plugin::CollabPlugin) when the plugin
+/// needs to do IO for each update.
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait AsyncCollabPlugin: Send + Sync + 'static {
+  /// Called for every update that is applied to the document.
+  async fn receive_update(&self, _object_id: &str, _update: &[u8]) {}
+
+  /// Called after [AsyncCollabPlugin::receive_update] if the update was made by the local
+  /// origin.
+  async fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, _update: &[u8]) {}
+
+  /// See [CollabPlugin::reset](crate::core::collab_plugin::CollabPlugin::reset).
+  async fn reset(&self, _object_id: &str) {}
+
+  /// Called by [Collab::flush](crate::core::collab::Collab::flush) with the state of the
+  /// document at the time of the call.
+  async fn flush(&self, _object_id: &str, _encoded_collab: &EncodedCollab) {}
+}
+
+enum PipelineEvent {
+  Update {
+    update: Bytes,
+    /// The part of the update that was made by the local origin.
+    local_update: Option<Bytes>,
+  },
+  Reset,
+  Flush {
+    encoded_collab: EncodedCollab,
+    notify: Vec<oneshot::Sender<()>>,
+  },
+}
+
+impl PipelineEvent {
+  /// Merges `next` into this event if they are of the same kind. Otherwise returns `next`.
+  fn merge(&mut self, next: PipelineEvent) -> Option<PipelineEvent> {
+    match (self, next) {
+      (
+        PipelineEvent::Update {
+          update,
+          local_update,
+        },
+        PipelineEvent::Update {
+          update: next_update,
+          local_update: next_local_update,
+        },
+      ) => {
+        let merged_local_update = match (local_update.as_ref(), next_local_update.as_ref()) {
+          (Some(local_update), Some(next_local_update)) => {
+            merge_updates_v1(&[local_update, next_local_update]).map(|update| Some(update.into()))
+          },
+          _ => Ok(local_update.clone().or(next_local_update.clone())),
+        };
+        match (
+          merge_updates_v1(&[&*update, &next_update]),
+          merged_local_update,
+        ) {
+          (Ok(merged_update), Ok(merged_local_update)) => {
+            *update = merged_update.into();
+            *local_update = merged_local_update;
+            None
+          },
+          (Err(err), _) | (_, Err(err)) => {
+            error!("Failed to merge the queued updates: {}", err);
+            Some(PipelineEvent::Update {
+              update: next_update,
+              local_update: next_local_update,
+            })
+          },
+        }
+      },
+      (PipelineEvent::Reset, PipelineEvent::Reset) => None,
+      (
+        PipelineEvent::Flush {
+          encoded_collab,
+          notify,
+        },
+        PipelineEvent::Flush {
+          encoded_collab: next_encoded_collab,
+          notify: next_notify,
+        },
+      ) => {
+        *encoded_collab = next_encoded_collab;
+        notify.extend(next_notify);
+        None
+      },
+      (_, next) => Some(next),
+    }
+  }
+}
+
+/// The queue of a [Collab](crate::core::collab::Collab) that feeds its [AsyncCollabPlugin]s.
+/// Cloning the pipeline returns a handle to the same queue.
+#[derive(Clone)]
+pub struct PluginPipeline {
+  inner: Arc<PipelineInner>,
+}
+
+struct PipelineInner {
+  object_id: String,
+  origin: CollabOrigin,
+  plugins: RwLock<Vec<Arc<dyn AsyncCollabPlugin>>>,
+  /// Created with the worker when the first plugin is added.
+  sender: Mutex<Option<mpsc::Sender<PipelineEvent>>>,
+  /// The events that didn't fit in the channel, in order. The worker takes them once the
+  /// channel is empty.
+  overflow: Mutex<Vec<PipelineEvent>>,
+  capacity: AtomicUsize,
+  pending: AtomicUsize,
+  drained: Notify,
+}
+
+impl PluginPipeline {
+  pub fn new(object_id: &str, origin: CollabOrigin) -> Self {
+    Self {
+      inner: Arc::new(PipelineInner {
+        object_id: object_id.to_string(),
+        origin,
+        plugins: Default::default(),
+        sender: Default::default(),
+        overflow: Default::default(),
+        capacity: AtomicUsize::new(DEFAULT_PIPELINE_CAPACITY),
+        pending: AtomicUsize::new(0),
+        drained: Notify::new(),
+      }),
+    }
+  }
+
+  /// Sets the number of queued events above which [PluginPipeline::is_backpressured] returns
+  /// true. The channel is created with the capacity when the first plugin is added, so the
+  /// events are merged once the initial capacity is reached even if it is raised afterwards.
+  pub fn set_capacity(&self, capacity: usize) {
+    self.inner.capacity.store(capacity.max(1), Ordering::SeqCst);
+    self.inner.drained.notify_waiters();
+  }
+
+  pub fn capacity(&self) -> usize {
+    self.inner.capacity.load(Ordering::SeqCst)
+  }
+
+  /// Adds a plugin to the pipeline. The plugin receives the events that are queued after this
+  /// call. The first call spawns the worker, so it must be made within an async runtime.
+  pub fn add_plugin(&self, plugin: Arc<dyn AsyncCollabPlugin>) -> Result<(), CollabError> {
+    self.start_worker()?;
+    self.inner.plugins.write().push(plugin);
+    Ok(())
+  }
+
+  pub fn has_plugins(&self) -> bool {
+    !self.inner.plugins.read().is_empty()
+  }
+
+  /// The number of events that were queued but not yet delivered to all the plugins. Merged
+  /// events count once.
+  pub fn pending(&self) -> usize {
+    self.inner.pending.load(Ordering::SeqCst)
+  }
+
+  pub fn is_backpressured(&self) -> bool {
+    self.pending() >= self.capacity()
+  }
+
+  /// Resolves once the number of pending events is below the capacity of the pipeline.
+  pub async fn ready(&self) {
+    loop {
+      // Create the future before checking the condition, otherwise a wakeup that happens in
+      // between would be missed.
+      let drained = self.inner.drained.notified();
+      if !self.is_backpressured() {
+        return;
+      }
+      drained.await;
+    }
+  }
+
+  pub(crate) fn push_update(&self, update: &[u8], is_local: bool) {
+    if self.has_plugins() {
+      let update = Bytes::copy_from_slice(update);
+      self.send(PipelineEvent::Update {
+        local_update: is_local.then(|| update.clone()),
+        update,
+      });
+    }
+  }
+
+  pub(crate) fn push_reset(&self) {
+    if self.has_plugins() {
+      self.send(PipelineEvent::Reset);
+    }
+  }
+
+  pub(crate) fn push_flush<F>(&self, encode: F) -> PluginFlush
+  where
+    F: FnOnce() -> EncodedCollab,
+  {
+    if !self.has_plugins() {
+      return PluginFlush { receiver: None };
+    }
+
+    let (notify, receiver) = oneshot::channel();
+    self.send(PipelineEvent::Flush {
+      encoded_collab: encode(),
+      notify: vec![notify],
+    });
+    PluginFlush {
+      receiver: Some(receiver),
+    }
+  }
+
+  fn send(&self, mut event: PipelineEvent) {
+    let sender = self.inner.sender.lock();
+    let sender = match sender.as_ref() {
+      None => return,
+      Some(sender) => sender,
+    };
+    let mut overflow = self.inner.overflow.lock();
+    // Once an event overflowed, the next ones must follow it to keep the order.
+    if overflow.is_empty() {
+      match sender.try_send(event) {
+        Ok(()) => {
+          self.inner.pending.fetch_add(1, Ordering::SeqCst);
+          return;
+        },
+        Err(TrySendError::Closed(_)) => return,
+        Err(TrySendError::Full(full)) => event = full,
+      }
+    }
+    let unmerged = match overflow.last_mut() {
+      None => Some(event),
+      Some(last) => last.merge(event),
+    };
+    if let Some(event) = unmerged {
+      overflow.push(event);
+      self.inner.pending.fetch_add(1, Ordering::SeqCst);
+    }
+  }
+
+  fn start_worker(&self) -> Result<(), CollabError> {
+    let mut sender = self.inner.sender.lock();
+    if sender.is_some() {
+      return Ok(());
+    }
+
+    let (tx, rx) = mpsc::channel(self.capacity());
+    spawn_worker(run_pipeline(Arc::downgrade(&self.inner), rx))?;
+    *sender = Some(tx);
+    Ok(())
+  }
+}
+
+/// Resolves when all the [AsyncCollabPlugin]s have handled a flush, and every event that was
+/// queued before it. Dropping it doesn't cancel the flush.
+pub struct PluginFlush {
+  receiver: Option<oneshot::Receiver<()>>,
+}
+
+impl Future for PluginFlush {
+  type Output = ();
+
+  fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+    match self.receiver.as_mut() {
+      None => Poll::Ready(()),
+      // An error means the worker is gone, there is nothing left to wait for.
+      Some(receiver) => Pin::new(receiver).poll(cx).map(|_| ()),
+    }
+  }
+}
+
+/// The worker only holds a weak reference to the pipeline, it stops when the collab that owns the
+/// pipeline is dropped.
+async fn run_pipeline(inner: Weak<PipelineInner>, mut receiver: mpsc::Receiver<PipelineEvent>) {
+  loop {
+    let events = match receiver.try_recv() {
+      Ok(event) => vec![event],
+      Err(TryRecvError::Disconnected) => break,
+      // The overflowing events are newer than the ones in the channel.
+      Err(TryRecvError::Empty) => {
+        let overflow = match inner.upgrade() {
+          None => break,
+          Some(inner) => std::mem::take(&mut *inner.overflow.lock()),
+        };
+        if !overflow.is_empty() {
+          overflow
+        } else {
+          match receiver.recv().await {
+            None => break,
+            Some(event) => vec![event],
+          }
+        }
+      },
+    };
+    let inner = match inner.upgrade() {
+      None => break,
+      Some(inner) => inner,
+    };
+    for event in events {
+      deliver(&inner, event).await;
+      inner.pending.fetch_sub(1, Ordering::SeqCst);
+      inner.drained.notify_waiters();
+    }
+  }
+  trace!("Plugin pipeline stopped");
+}
+
+async fn deliver(inner: &PipelineInner, event: PipelineEvent) {
+  let plugins = inner.plugins.read().clone();
+  match event {
+    PipelineEvent::Update {
+      update,
+      local_update,
+    } => {
+      for plugin in plugins.iter() {
+        plugin.receive_update(&inner.object_id, &update).await;
+        if let Some(local_update) = &local_update {
+          plugin
+            .receive_local_update(&inner.origin, &inner.object_id, local_update)
+            .await;
+        }
+      }
+    },
+    PipelineEvent::Reset => {
+      for plugin in plugins.iter() {
+        plugin.reset(&inner.object_id).await;
+      }
+    },
+    PipelineEvent::Flush {
+      encoded_collab,
+      notify,
+    } => {
+      for plugin in plugins.iter() {
+        plugin.flush(&inner.object_id, &encoded_collab).await;
+      }
+      for notify in notify {
+        let _ = notify.send(());
+      }
+    },
+  }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn spawn_worker<F>(future: F) -> Result<(), CollabError>
+where
+  F: Future<Output = ()> + Send + 'static,
+{
+  let handle = tokio::runtime::Handle::try_current()
+    .map_err(|_| CollabError::Internal("The plugin pipeline requires a tokio runtime".into()))?;
+  handle.spawn(future);
+  Ok(())
+}
+
+#[cfg(target_arch = "wasm32")]
+fn spawn_worker<F>(future: F) -> Result<(), CollabError>
+where
+  F: Future<Output = ()> + 'static,
+{
+  wasm_bindgen_futures::spawn_local(future);
+  Ok(())
+}
@@ -0,0 +1,181 @@
+//! A typed schema for the [Awareness] state of a client.
+//!
+//! Every client publishes a [Presence] that contains who the user is, the object the user is
+//! looking at and the selection in a text. Selections are stored as encoded [StickyIndex]es, so
+//! they keep pointing at the same characters while other clients edit the text.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use yrs::block::ClientID;
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{Assoc, IndexedSequence, ReadTxn, StickyIndex, TextRef, TransactionMut};
+
+use crate::core::awareness::Awareness;
+use crate::error::CollabError;
+
+/// The colors that are assigned to users that didn't pick one.
+const PRESENCE_COLORS: [&str; 8] = [
+  "#e8384f", "#fd612c", "#fd9a00", "#62d26f", "#4ecbc4", "#20aaea", "#4186e0", "#aa62e3",
+];
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Presence {
+  pub uid: i64,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub user: Option<PresenceUser>,
+  /// The id of the object the user is currently looking at.
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub viewing: Option<String>,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub selection: Option<PresenceSelection>,
+}
+
+impl Presence {
+  pub fn new(uid: i64) -> Self {
+    Self {
+      uid,
+      ..Default::default()
+    }
+  }
+
+  pub fn with_user(mut self, user: PresenceUser) -> Self {
+    self.user = Some(user);
+    self
+  }
+
+  pub fn with_viewing<T: ToString>(mut self, object_id: T) -> Self {
+    self.viewing = Some(object_id.to_string());
+    self
+  }
+
+  pub fn with_selection(mut self, selection: Option<PresenceSelection>) -> Self {
+    self.selection = selection;
+    self
+  }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceUser {
+  pub name: String,
+  /// A CSS color, e.g. `#e8384f`.
+  pub color: String,
+  #[serde(default, skip_serializing_if = "Option::is_none")]
+  pub avatar: Option<String>,
+}
+
+impl PresenceUser {
+  /// Creates a user with a color that is derived from the uid, so the user has the same color on
+  /// every client.
+  pub fn new<T: ToString>(uid: i64, name: T) -> Self {
+    let color = PRESENCE_COLORS[uid.unsigned_abs() as usize % PRESENCE_COLORS.len()];
+    Self {
+      name: name.to_string(),
+      color: color.to_string(),
+      avatar: None,
+    }
+  }
+
+  pub fn with_color<T: ToString>(mut self, color: T) -> Self {
+    self.color = color.to_string();
+    self
+  }
+
+  pub fn with_avatar<T: ToString>(mut self, avatar: T) -> Self {
+    self.avatar = Some(avatar.to_string());
+    self
+  }
+}
+
+/// A selection in a [TextRef]. The selection is a cursor if the anchor and the head are at the
+/// same position.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PresenceSelection {
+  /// Identifies the text in the object, for example the id of a block.
+  pub text_id: String,
+  /// The v1 encoded [StickyIndex] of the position where the selection started.
+  anchor: Vec<u8>,
+  /// The v1 encoded [StickyIndex] of the position where the selection ends.
+  head: Vec<u8>,
+}
+
+impl PresenceSelection {
+  /// Returns None if one of the indexes is out of the bounds of the text.
+  pub fn new<T: ToString>(
+    txn: &mut TransactionMut,
+    text_id: T,
+    text: &TextRef,
+    anchor: u32,
+    head: u32,
+  ) -> Option<Self> {
+    let anchor = sticky_index(txn, text, anchor)?;
+    let head = sticky_index(txn, text, head)?;
+    Some(Self {
+      text_id: text_id.to_string(),
+      anchor: anchor.encode_v1(),
+      head: head.encode_v1(),
+    })
+  }
+
+  pub fn cursor<T: ToString>(
+    txn: &mut TransactionMut,
+    text_id: T,
+    text: &TextRef,
+    index: u32,
+  ) -> Option<Self> {
+    Self::new(txn, text_id, text, index, index)
+  }
+
+  /// Returns the current indexes of the anchor and the head. Returns None if the text that
+  /// contains the selection doesn't exist in the document.
+  pub fn resolve<T: ReadTxn>(&self, txn: &T) -> Option<(u32, u32)> {
+    let anchor = StickyIndex::decode_v1(&self.anchor).ok()?.get_offset(txn)?;
+    let head = StickyIndex::decode_v1(&self.head).ok()?.get_offset(txn)?;
+    Some((anchor.index, head.index))
+  }
+
+  pub fn is_cursor(&self) -> bool {
+    self.anchor == self.head
+  }
+}
+
+/// Sticks the index to the character after it, or to the character before it at the end of the
+/// text, where there is no character after it.
+fn sticky_index(txn: &mut TransactionMut, text: &TextRef, index: u32) -> Option<StickyIndex> {
+  text
+    .sticky_index(txn, index, Assoc::After)
+    .or_else(|| text.sticky_index(txn, index, Assoc::Before))
+}
+
+impl Awareness {
+  /// Sets the [Presence] of the local client.
+  pub fn set_local_presence(&mut self, presence: &Presence) -> Result<(), CollabError> {
+    let json = serde_json::to_value(presence)?;
+    self.set_local_state(json);
+    Ok(())
+  }
+
+  pub fn get_local_presence(&self) -> Option<Presence> {
+    self.get_presence(self.client_id())
+  }
+
+  /// Returns the [Presence] of the client. Returns None if the client has no state or its state
+  /// doesn't follow the [Presence] schema.
+  pub fn get_presence(&self, client_id: ClientID) -> Option<Presence> {
+    let state = self.get_states().get(&client_id)?;
+    serde_json::from_value(state.clone()).ok()
+  }
+
+  /// Returns the [Presence] of every client whose state follows the [Presence] schema.
+  pub fn get_presences(&self) -> HashMap<ClientID, Presence> {
+    self
+      .get_states()
+      .iter()
+      .filter_map(|(client_id, state)| {
+        let presence = serde_json::from_value(state.clone()).ok()?;
+        Some((*client_id, presence))
+      })
+      .collect()
+  }
+}
@@ -0,0 +1,81 @@
+//! Read-only mode of a [Collab](crate::core::collab::Collab).
+//!
+//! Collabs that are opened for previews, published pages or indexing must never write. In
+//! [ReadOnlyMode::ReadOnly] the local changes are rejected while the remote updates are still
+//! applied, and in [ReadOnlyMode::Frozen] the remote updates are rejected too.
+//!
+//! The entry points that open a write transaction, like `Collab::with_origin_transact_mut` and
+//! the `with_transact_mut` methods of the wrappers, return [CollabError::ReadOnly] without
+//! opening it. The setters that don't return a result, like `Collab::insert`, the inserts of the
+//! map wrapper or the setters of `DatabaseRow`, log a warning and do nothing.
+//!
+//! The data loaded by the plugins in `CollabPlugin::init` is still applied in every mode, since
+//! it is the content of the collab. Everything received afterwards must go through
+//! `Collab::apply_remote_update`, which rejects the updates of a frozen collab.
+
+use parking_lot::RwLock;
+
+use crate::error::CollabError;
+
+#[derive(Debug, Clone, Copy, Default, Eq, PartialEq)]
+pub enum ReadOnlyMode {
+  /// Local changes and remote updates are applied.
+  #[default]
+  Writable,
+  /// Local changes are rejected, remote updates are applied.
+  ReadOnly,
+  /// Local changes and remote updates are rejected.
+  Frozen,
+}
+
+impl ReadOnlyMode {
+  pub fn allows_local_changes(&self) -> bool {
+    matches!(self, ReadOnlyMode::Writable)
+  }
+
+  /// Only the updates applied with `Collab::apply_remote_update` are checked, see the
+  /// [module](self) documentation.
+  pub fn allows_remote_updates(&self) -> bool {
+    !matches!(self, ReadOnlyMode::Frozen)
+  }
+}
+
+/// Enforces the [ReadOnlyMode] of a collab. It is shared with the wrappers created by the
+/// collab, so their transactions are checked too.
+pub(crate) struct ReadOnlyGuard {
+  object_id: String,
+  mode: RwLock<ReadOnlyMode>,
+}
+
+impl ReadOnlyGuard {
+  pub(crate) fn new(object_id: &str) -> Self {
+    Self {
+      object_id: object_id.to_string(),
+      mode: Default::default(),
+    }
+  }
+
+  pub(crate) fn mode(&self) -> ReadOnlyMode {
+    *self.mode.read()
+  }
+
+  pub(crate) fn set_mode(&self, mode: ReadOnlyMode) {
+    *self.mode.write() = mode;
+  }
+
+  pub(crate) fn check_local_change(&self) -> Result<(), CollabError> {
+    if self.mode().allows_local_changes() {
+      Ok(())
+    } else {
+      Err(CollabError::ReadOnly(self.object_id.clone()))
+    }
+  }
+
+  pub(crate) fn check_remote_update(&self) -> Result<(), CollabError> {
+    if self.mode().allows_remote_updates() {
+      Ok(())
+    } else {
+      Err(CollabError::ReadOnly(self.object_id.clone()))
+    }
+  }
+}
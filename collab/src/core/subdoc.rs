@@ -0,0 +1,110 @@
+//! Child documents embedded in a [Collab].
+//!
+//! The children are yrs subdocuments stored in the `subdocs` section of the parent, keyed by
+//! their object id. The parent only stores a reference to each child: the content of a child is
+//! a separate document that is loaded on demand with [Collab::load_subdoc].
+//!
+//! The updates of a loaded child are routed through the plugins of the parent: when the child is
+//! loaded, the parent's plugins restore it in [CollabPlugin::init_subdoc], then they receive each
+//! of its updates in [CollabPlugin::receive_subdoc_update]. Plugins usually keep state for a single
+//! object, such as its sync state or its pending updates, so the child doesn't call their other
+//! methods. A plugin that ignores these two methods doesn't see the children, for example the
+//! disk plugin persists them while the sync plugins don't sync them.
+//!
+//! A loaded child is a regular [Collab]. The plugins that must handle the child as their own
+//! object are built for its object id by the [SubdocPluginFactory] of the parent.
+//!
+//! [Collab]: crate::core::collab::Collab
+//! [Collab::load_subdoc]: crate::core::collab::Collab::load_subdoc
+
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use yrs::types::Value;
+use yrs::{Doc, Map, MapRef, OffsetKind, Options, ReadTxn, TransactionMut};
+
+use crate::core::awareness::{AwarenessUpdate, Event};
+use crate::core::collab::Plugins;
+use crate::core::collab_plugin::CollabPlugin;
+use crate::core::origin::CollabOrigin;
+
+/// Builds the plugins of a child document, given its object id. See
+/// [Collab::set_subdoc_plugin_factory].
+///
+/// [Collab::set_subdoc_plugin_factory]: crate::core::collab::Collab::set_subdoc_plugin_factory
+pub type SubdocPluginFactory = Arc<dyn Fn(&str) -> Vec<Box<dyn CollabPlugin>> + Send + Sync>;
+
+/// The root map of the parent that holds the child documents.
+pub const SUBDOCS_SECTION: &str = "subdocs";
+
+/// Returns a new child document. The child isn't loaded until [Collab::load_subdoc] is called.
+///
+/// [Collab::load_subdoc]: crate::core::collab::Collab::load_subdoc
+pub(crate) fn make_subdoc(object_id: &str) -> Doc {
+  Doc::with_options(Options {
+    guid: object_id.into(),
+    skip_gc: true,
+    offset_kind: OffsetKind::Utf16,
+    should_load: false,
+    ..Options::default()
+  })
+}
+
+/// Returns the child document with the given object id.
+pub(crate) fn get_subdoc<T: ReadTxn>(txn: &T, subdocs: &MapRef, object_id: &str) -> Option<Doc> {
+  match subdocs.get(txn, object_id)? {
+    Value::YDoc(doc) => Some(doc),
+    _ => None,
+  }
+}
+
+/// Returns the object ids of the child documents.
+pub(crate) fn subdoc_ids<T: ReadTxn>(txn: &T, subdocs: &MapRef) -> Vec<String> {
+  subdocs
+    .iter(txn)
+    .filter(|(_, value)| matches!(value, Value::YDoc(_)))
+    .map(|(key, _)| key.to_string())
+    .collect()
+}
+
+/// The plugin of a loaded child that routes it through the plugins of its parent.
+pub(crate) struct ParentPlugins(pub(crate) Plugins);
+
+#[async_trait]
+impl CollabPlugin for ParentPlugins {
+  #[cfg(not(feature = "async-plugin"))]
+  fn init(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
+    self.init_subdoc(object_id, origin, doc);
+  }
+
+  #[cfg(feature = "async-plugin")]
+  async fn init(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
+    self.init_subdoc(object_id, origin, doc);
+  }
+
+  fn receive_update(&self, object_id: &str, _txn: &TransactionMut, update: &[u8]) {
+    self.receive_subdoc_update(object_id, update);
+  }
+
+  /// The children of the child are routed to the parent too.
+  fn init_subdoc(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
+    self
+      .0
+      .each(|plugin| plugin.init_subdoc(object_id, origin, doc));
+  }
+
+  fn receive_subdoc_update(&self, object_id: &str, update: &[u8]) {
+    self
+      .0
+      .each(|plugin| plugin.receive_subdoc_update(object_id, update));
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+}
@@ -1,3 +1,4 @@
+use crate::error::CollabError;
 use crate::preclude::{CollabContext, YrsDelta};
 use std::ops::{Deref, DerefMut};
 use std::sync::Arc;
@@ -24,13 +25,20 @@ impl TextRefWrapper {
     self.collab_ctx.transact()
   }
 
-  pub fn with_transact_mut<F, T>(&self, f: F) -> T
+  pub fn with_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
   where
     F: FnOnce(&mut TransactionMut) -> T,
   {
     self.collab_ctx.with_transact_mut(f)
   }
 
+  pub fn try_with_transact_mut<F, T>(&self, f: F) -> Result<T, CollabError>
+  where
+    F: FnOnce(&mut TransactionMut) -> T,
+  {
+    self.collab_ctx.try_with_transact_mut(f)
+  }
+
   pub fn get_delta_with_txn<T: ReadTxn>(&self, txn: &T) -> Vec<Delta> {
     let changes = self.text_ref.diff(txn, YChange::identity);
     let mut deltas = vec![];
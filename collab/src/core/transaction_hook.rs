@@ -0,0 +1,448 @@
+//! Hooks that run before a local transaction of a [Collab](crate::core::collab::Collab) commits.
+//!
+//! A [TransactionHook] sees the [PendingChanges] of the transaction and can:
+//! * accept them by returning `Ok(())`,
+//! * amend them by writing through the transaction, for example to stamp the time and the user
+//!   of the last edit, see [LastEditedStamp],
+//! * abort the transaction by returning an error, usually [CollabError::TransactionAborted].
+//!
+//! A yrs transaction can't be rolled back, so an aborted transaction still commits and is
+//! reverted right after. The plugins don't see what an aborted transaction inserted: they receive
+//! its update with the content of the items replaced by deleted content of the same length, then
+//! the revert, which restores what the transaction deleted. Both are delivered as local updates,
+//! so the peers don't miss any clock of the local client. If the update can't be redacted, it is
+//! delivered unredacted rather than dropped. Like any deleted content, the inserted content stays
+//! in the local document, so it is still part of the full state that this client encodes. The
+//! `try_` entry points, like `Collab::try_with_origin_transact_mut`, return the error of the
+//! hook. The other ones only log it.
+//!
+//! Hooks are opt-in: a collab without hooks doesn't index its items or record its transactions.
+//! The hooks only run for the transactions created by the collab and its wrappers. The pending
+//! changes are read from the update of the transaction. To locate them, the items of the document
+//! are indexed when the first hook is added, and each local transaction is recorded so it can be
+//! reverted. Prefer writing derived values inline when a single code path makes the change.
+
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use parking_lot::{Mutex, RwLock};
+use yrs::types::Value;
+use yrs::{Doc, Map, MapRef, Origin, ReadTxn, TransactionMut, UndoManager, UpdateSubscription};
+
+use crate::core::origin::CollabOrigin;
+use crate::core::update_blocks::{redact_update, ItemIndex};
+use crate::error::CollabError;
+use crate::preclude::MapRefExtension;
+
+pub trait TransactionHook: Send + Sync + 'static {
+  /// Called with the transaction before it commits. Returning an error aborts the transaction.
+  ///
+  /// The changes made by the hook are part of the transaction, but they are not added to
+  /// `changes` and are not seen by the following hooks.
+  fn before_commit(
+    &self,
+    txn: &mut TransactionMut,
+    changes: &PendingChanges,
+  ) -> Result<(), CollabError>;
+}
+
+impl<F> TransactionHook for F
+where
+  F: Fn(&mut TransactionMut, &PendingChanges) -> Result<(), CollabError> + Send + Sync + 'static,
+{
+  fn before_commit(
+    &self,
+    txn: &mut TransactionMut,
+    changes: &PendingChanges,
+  ) -> Result<(), CollabError> {
+    self(txn, changes)
+  }
+}
+
+/// The changes of a transaction that is about to commit.
+pub struct PendingChanges {
+  origin: CollabOrigin,
+  /// The changed paths, starting with the section name. For maps, the changed keys are part of
+  /// the path.
+  paths: Vec<Vec<String>>,
+  roots: Vec<(&'static str, MapRef)>,
+}
+
+impl PendingChanges {
+  pub fn origin(&self) -> &CollabOrigin {
+    &self.origin
+  }
+
+  pub fn paths(&self) -> &[Vec<String>] {
+    &self.paths
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.paths.is_empty()
+  }
+
+  /// Returns true if the value at `path`, one of its children or one of its ancestors changed.
+  pub fn touches<P>(&self, path: P) -> bool
+  where
+    P: IntoIterator,
+    P::Item: AsRef<str>,
+  {
+    let path = path
+      .into_iter()
+      .map(|segment| segment.as_ref().to_string())
+      .collect::<Vec<_>>();
+    self
+      .paths
+      .iter()
+      .any(|changed| is_prefix(changed, &path) || is_prefix(&path, changed))
+  }
+
+  /// Returns the keys of the children of `path` that changed, in order. For example, the ids of
+  /// the changed views when `path` is the map of the views.
+  pub fn changed_keys<P>(&self, path: P) -> Vec<String>
+  where
+    P: IntoIterator,
+    P::Item: AsRef<str>,
+  {
+    let path = path
+      .into_iter()
+      .map(|segment| segment.as_ref().to_string())
+      .collect::<Vec<_>>();
+    self
+      .paths
+      .iter()
+      .filter(|changed| changed.len() > path.len() && is_prefix(&path, changed))
+      .map(|changed| changed[path.len()].clone())
+      .collect::<BTreeSet<_>>()
+      .into_iter()
+      .collect()
+  }
+
+  /// Returns the map at `path`, starting with the section name, if it exists.
+  pub fn map_at<T, P>(&self, txn: &T, path: P) -> Option<MapRef>
+  where
+    T: ReadTxn,
+    P: IntoIterator,
+    P::Item: AsRef<str>,
+  {
+    let mut path = path.into_iter();
+    let section = path.next()?;
+    let mut map = self
+      .roots
+      .iter()
+      .find(|(name, _)| *name == section.as_ref())
+      .map(|(_, root)| root.clone())?;
+    for key in path {
+      match map.get(txn, key.as_ref())? {
+        Value::YMap(child) => map = child,
+        _ => return None,
+      }
+    }
+    Some(map)
+  }
+}
+
+fn is_prefix(prefix: &[String], path: &[String]) -> bool {
+  prefix.len() <= path.len() && prefix.iter().zip(path).all(|(a, b)| a == b)
+}
+
+/// Stamps the time and the user of the last edit on the children of a map. When anything below
+/// one of the children changes, the child gets the current timestamp, in seconds, and the uid of
+/// the origin of the transaction. Inserting, replacing or removing a child doesn't stamp it, and
+/// the stamps written by the transaction itself are kept.
+///
+/// ```ignore
+/// // Stamps `last_edited_time` and `last_edited_by` on the changed views of a folder.
+/// collab.add_transaction_hook(LastEditedStamp::new(["data", "folder", "views"]));
+/// ```
+#[derive(Debug, Clone)]
+pub struct LastEditedStamp {
+  path: Vec<String>,
+  /// True if the children of the map at `path` are stamped, false if the map itself is.
+  children: bool,
+  time_key: String,
+  user_key: Option<String>,
+}
+
+impl LastEditedStamp {
+  pub fn new<P>(path: P) -> Self
+  where
+    P: IntoIterator,
+    P::Item: ToString,
+  {
+    Self {
+      path: path.into_iter().map(|s| s.to_string()).collect(),
+      children: true,
+      time_key: "last_edited_time".to_string(),
+      user_key: Some("last_edited_by".to_string()),
+    }
+  }
+
+  /// Stamps the map at `path` itself when anything below it changes.
+  pub fn for_map<P>(path: P) -> Self
+  where
+    P: IntoIterator,
+    P::Item: ToString,
+  {
+    Self {
+      children: false,
+      ..Self::new(path)
+    }
+  }
+
+  pub fn with_time_key(mut self, key: impl ToString) -> Self {
+    self.time_key = key.to_string();
+    self
+  }
+
+  pub fn with_user_key(mut self, key: impl ToString) -> Self {
+    self.user_key = Some(key.to_string());
+    self
+  }
+
+  /// Only stamps the time of the last edit.
+  pub fn without_user_key(mut self) -> Self {
+    self.user_key = None;
+    self
+  }
+
+  /// The length of the paths of the stamped maps.
+  fn depth(&self) -> usize {
+    self.path.len() + usize::from(self.children)
+  }
+
+  /// Changing only the stamp itself doesn't count as an edit.
+  fn is_stamp(&self, path: &[String]) -> bool {
+    path.len() == self.depth() + 1
+      && (path[self.depth()] == self.time_key
+        || Some(&path[self.depth()]) == self.user_key.as_ref())
+  }
+}
+
+impl TransactionHook for LastEditedStamp {
+  fn before_commit(
+    &self,
+    txn: &mut TransactionMut,
+    changes: &PendingChanges,
+  ) -> Result<(), CollabError> {
+    let depth = self.depth();
+    let (written, edited): (Vec<_>, Vec<_>) = changes
+      .paths()
+      .iter()
+      .filter(|path| path.len() > depth && is_prefix(&self.path, path))
+      .partition(|path| self.is_stamp(path));
+    let targets = edited
+      .into_iter()
+      .map(|path| &path[..depth])
+      .collect::<BTreeSet<_>>();
+    if targets.is_empty() {
+      return Ok(());
+    }
+
+    let written = written.into_iter().collect::<BTreeSet<_>>();
+    let is_written = |target: &[String], key: &String| {
+      written
+        .iter()
+        .any(|path| path.starts_with(target) && path[depth] == *key)
+    };
+    let now = chrono::Utc::now().timestamp();
+    let uid = changes.origin().client_user_id();
+    for target in targets {
+      let map = match changes.map_at(&*txn, target) {
+        None => continue,
+        Some(map) => map,
+      };
+      if !is_written(target, &self.time_key) {
+        map.insert_i64_with_txn(txn, &self.time_key, now);
+      }
+      if let (Some(user_key), Some(uid)) = (&self.user_key, uid) {
+        if !is_written(target, user_key) {
+          map.insert_i64_with_txn(txn, user_key, uid);
+        }
+      }
+    }
+    Ok(())
+  }
+}
+
+/// Runs the [TransactionHook]s of a collab. It is shared with the wrappers created by the
+/// collab, so their transactions run the hooks too.
+pub(crate) struct TransactionHooks {
+  object_id: String,
+  local_origin: CollabOrigin,
+  hooks: RwLock<Vec<Arc<dyn TransactionHook>>>,
+  roots: Vec<(&'static str, MapRef)>,
+  /// The locations of the items of the document, to find the changes of a transaction. Only
+  /// filled once a hook was added.
+  index: Arc<Mutex<ItemIndex>>,
+  /// True from the moment a hook aborts a transaction until its update is delivered, or until it
+  /// committed if it has no update.
+  aborting: AtomicBool,
+  /// The origin of the transactions that revert the aborted ones.
+  revert_origin: RwLock<Option<Origin>>,
+  /// Only set once a hook was added.
+  observers: Mutex<Option<HookObservers>>,
+}
+
+/// The yrs handles owned by the [TransactionHooks].
+struct HookObservers {
+  /// Records the local transactions, so the aborted ones can be reverted.
+  undo_manager: UndoManager,
+  /// Keeps the [ItemIndex] up to date.
+  _update_subscription: Option<UpdateSubscription>,
+}
+
+// SAFETY: yrs doesn't mark the roots, the undo manager and the update subscription as Send or
+// Sync. The roots point into the document of the collab that owns the hooks, so they live as
+// long as it. The undo manager keeps its options in `Rc`s that it never clones, and its `Cell`
+// flags are only touched by `undo` and by its document observer. The hooks are only used by the
+// transactions of the collab and by `after_commit`, which all run on the thread that holds the
+// collab, so these handles are never used from two threads at once. That is the same guarantee
+// that makes `MutexCollab` Send and Sync.
+unsafe impl Send for TransactionHooks {}
+unsafe impl Sync for TransactionHooks {}
+
+impl TransactionHooks {
+  pub(crate) fn new(
+    object_id: &str,
+    local_origin: CollabOrigin,
+    roots: Vec<(&'static str, MapRef)>,
+  ) -> Self {
+    Self {
+      object_id: object_id.to_string(),
+      local_origin,
+      hooks: Default::default(),
+      roots,
+      index: Default::default(),
+      aborting: AtomicBool::new(false),
+      revert_origin: Default::default(),
+      observers: Mutex::new(None),
+    }
+  }
+
+  pub(crate) fn add(&self, hook: Arc<dyn TransactionHook>, doc: &Doc) {
+    let mut observers = self.observers.lock();
+    if observers.is_none() {
+      let options = yrs::undo::Options {
+        capture_timeout_millis: 0,
+        ..Default::default()
+      };
+      let mut undo_manager = UndoManager::with_options(doc, &self.roots[0].1, options);
+      for (_, root) in &self.roots[1..] {
+        undo_manager.expand_scope(root);
+      }
+      undo_manager.include_origin(self.local_origin.clone());
+      *self.revert_origin.write() = Some(undo_manager.as_origin());
+      *observers = Some(HookObservers {
+        undo_manager,
+        _update_subscription: ItemIndex::track(&self.index, doc, &self.object_id),
+      });
+    }
+    self.hooks.write().push(hook);
+  }
+
+  pub(crate) fn is_empty(&self) -> bool {
+    self.hooks.read().is_empty()
+  }
+
+  /// Runs the hooks with the changes made by the transaction. Stops at the first hook that aborts
+  /// the transaction.
+  pub(crate) fn before_commit(&self, txn: &mut TransactionMut) -> Result<(), CollabError> {
+    if self.is_empty() {
+      return Ok(());
+    }
+    let result = self.pending_changes(txn).and_then(|changes| {
+      if changes.is_empty() {
+        return Ok(());
+      }
+      let hooks = self.hooks.read().clone();
+      hooks
+        .iter()
+        .try_for_each(|hook| hook.before_commit(txn, &changes))
+    });
+    if result.is_err() {
+      self.aborting.store(true, Ordering::SeqCst);
+    }
+    result
+  }
+
+  /// Reverts the committed transaction if one of the hooks aborted it. Called after each local
+  /// transaction.
+  pub(crate) fn after_commit(&self, result: &Result<(), CollabError>) {
+    self.aborting.store(false, Ordering::SeqCst);
+    let mut observers = self.observers.lock();
+    let undo_manager = match observers.as_mut() {
+      None => return,
+      Some(observers) => &mut observers.undo_manager,
+    };
+
+    if let Err(err) = result {
+      // Each local transaction is a separate undo step, the last one is the aborted transaction.
+      if let Err(err) = undo_manager.undo() {
+        tracing::error!(
+          "[{}]: failed to revert local change: {}",
+          self.object_id,
+          err
+        );
+      }
+      tracing::warn!("[{}]: abort local change: {}", self.object_id, err);
+    }
+    if let Err(err) = undo_manager.clear() {
+      tracing::error!("Failed to clear the transaction hook history: {}", err);
+    }
+  }
+
+  /// Returns true while an aborted transaction commits.
+  pub(crate) fn is_aborting(&self) -> bool {
+    self.aborting.load(Ordering::SeqCst)
+  }
+
+  /// Returns true if the transaction reverts an aborted transaction.
+  pub(crate) fn is_revert(&self, txn: &TransactionMut) -> bool {
+    let revert_origin = self.revert_origin.read();
+    revert_origin.is_some() && txn.origin() == revert_origin.as_ref()
+  }
+
+  /// Returns the update of a transaction as the plugins receive it. Called once for each update.
+  /// The content of an aborted transaction is redacted. If that fails, the update is returned as
+  /// it is: the peers need it to receive the later updates of this client, and the revert that
+  /// follows removes its content again.
+  pub(crate) fn plugin_update<'a>(&self, update: &'a [u8]) -> Cow<'a, [u8]> {
+    if !self.aborting.swap(false, Ordering::SeqCst) {
+      return Cow::Borrowed(update);
+    }
+    match redact_update(update) {
+      Ok(update) => Cow::Owned(update),
+      Err(err) => {
+        tracing::error!(
+          "[{}]: failed to redact aborted change, send it unredacted: {}",
+          self.object_id,
+          err
+        );
+        Cow::Borrowed(update)
+      },
+    }
+  }
+
+  /// Reads the paths changed by the transaction from its update, without applying it.
+  fn pending_changes(&self, txn: &TransactionMut) -> Result<PendingChanges, CollabError> {
+    let paths = self
+      .index
+      .lock()
+      .changed_paths(&txn.encode_update_v1())?
+      .into_iter()
+      .filter(|path| {
+        path.first().map_or(false, |root| {
+          self.roots.iter().any(|(name, _)| name == root)
+        })
+      })
+      .collect();
+    Ok(PendingChanges {
+      origin: CollabOrigin::from(txn),
+      paths,
+      roots: self.roots.clone(),
+    })
+  }
+}
@@ -0,0 +1,249 @@
+//! Undo managers that are scoped to a part of the `data` section of a [Collab].
+//!
+//! A [Collab] can have several undo managers, each identified by a scope id. Each of them only
+//! tracks the changes made under its path by its tracked origins, so undoing a cell edit of a
+//! database doesn't revert the changes of other users or of other cells.
+//!
+//! [Collab]: crate::core::collab::Collab
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use yrs::types::{DeepObservable, Delta, Event, PathSegment, Value};
+use yrs::{ArrayRef, Doc, Map, MapRef, ReadTxn, TextRef, Transact, UndoManager};
+
+use crate::core::access_control::touched_paths;
+use crate::core::collab::{Path, DATA_SECTION};
+use crate::core::origin::CollabOrigin;
+use crate::error::CollabError;
+
+/// The scope of the undo manager created by `Collab::enable_undo_redo`.
+pub const DEFAULT_UNDO_SCOPE: &str = "default";
+
+/// Changes made within this time are undone together, unless
+/// `Collab::stop_capturing` is called between them.
+pub const DEFAULT_CAPTURE_TIMEOUT: Duration = Duration::from_millis(500);
+
+#[derive(Debug, Clone)]
+pub struct UndoConfig {
+  path: Vec<String>,
+  tracked_origins: Vec<CollabOrigin>,
+  capture_timeout: Duration,
+}
+
+impl Default for UndoConfig {
+  fn default() -> Self {
+    Self {
+      path: vec![],
+      tracked_origins: vec![],
+      capture_timeout: DEFAULT_CAPTURE_TIMEOUT,
+    }
+  }
+}
+
+impl UndoConfig {
+  pub fn new() -> Self {
+    Self::default()
+  }
+
+  /// The path of the map, array or text to track, relative to the `data` section. An empty path
+  /// tracks the whole section.
+  pub fn with_path(mut self, path: impl Into<Path>) -> Self {
+    self.path = path.into().to_vec();
+    self
+  }
+
+  /// Only the changes of the tracked origins can be undone. If no origin is added, the changes
+  /// of the local origin of the collab are tracked.
+  pub fn with_tracked_origin(mut self, origin: CollabOrigin) -> Self {
+    self.tracked_origins.push(origin);
+    self
+  }
+
+  pub fn with_capture_timeout(mut self, capture_timeout: Duration) -> Self {
+    self.capture_timeout = capture_timeout;
+    self
+  }
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum UndoEventKind {
+  Undo,
+  Redo,
+}
+
+/// Describes the changes made by an undo or a redo.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct UndoEvent {
+  pub scope: String,
+  pub kind: UndoEventKind,
+  /// The changed paths, starting with the section name. For maps the changed keys are part of
+  /// the path.
+  pub changed_paths: Vec<Vec<String>>,
+  /// For each changed text, the position after the last change. Useful to restore the cursor.
+  pub text_cursors: Vec<TextCursor>,
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct TextCursor {
+  pub path: Vec<String>,
+  pub index: u32,
+}
+
+pub(crate) struct ScopedUndoManager {
+  scope: String,
+  undo_manager: UndoManager,
+  data: MapRef,
+}
+
+impl ScopedUndoManager {
+  pub(crate) fn new(
+    doc: &Doc,
+    data: &MapRef,
+    scope: &str,
+    config: UndoConfig,
+    local_origin: &CollabOrigin,
+  ) -> Result<Self, CollabError> {
+    let options = yrs::undo::Options {
+      capture_timeout_millis: config.capture_timeout.as_millis() as u64,
+      ..Default::default()
+    };
+    // The undo manager observes the doc, which fails while a transaction is open.
+    let scope_ref = resolve_scope(&doc.transact(), data, &config.path);
+    let mut undo_manager = match scope_ref {
+      Some(UndoScopeRef::Map(map)) => UndoManager::with_options(doc, &map, options),
+      Some(UndoScopeRef::Array(array)) => UndoManager::with_options(doc, &array, options),
+      Some(UndoScopeRef::Text(text)) => UndoManager::with_options(doc, &text, options),
+      None => return Err(CollabError::InvalidUndoScope(config.path.join("/"))),
+    };
+    if config.tracked_origins.is_empty() {
+      undo_manager.include_origin(local_origin.clone());
+    } else {
+      for origin in config.tracked_origins {
+        undo_manager.include_origin(origin);
+      }
+    }
+    Ok(Self {
+      scope: scope.to_string(),
+      undo_manager,
+      data: data.clone(),
+    })
+  }
+
+  pub(crate) fn can_undo(&self) -> bool {
+    self.undo_manager.can_undo()
+  }
+
+  pub(crate) fn can_redo(&self) -> bool {
+    self.undo_manager.can_redo()
+  }
+
+  /// Starts a new stack item: the next change isn't merged with the previous one, even if it's
+  /// made within the capture timeout.
+  pub(crate) fn stop_capturing(&mut self) {
+    self.undo_manager.reset();
+  }
+
+  pub(crate) fn clear(&mut self) -> Result<(), CollabError> {
+    self
+      .undo_manager
+      .clear()
+      .map_err(|e| CollabError::Internal(Box::new(e)))
+  }
+
+  /// Returns None if there was nothing to undo or redo.
+  pub(crate) fn apply(&mut self, kind: UndoEventKind) -> Result<Option<UndoEvent>, CollabError> {
+    let changes: Arc<Mutex<UndoChanges>> = Default::default();
+    let cloned_changes = changes.clone();
+    let subscription = self.data.observe_deep(move |txn, events| {
+      let mut changes = cloned_changes.lock();
+      changes
+        .paths
+        .extend(touched_paths(DATA_SECTION, txn, events));
+      for event in events.iter() {
+        if let Event::Text(text_event) = event {
+          let mut path = vec![DATA_SECTION.to_string()];
+          path.extend(text_event.path().iter().map(|segment| match segment {
+            PathSegment::Key(key) => key.to_string(),
+            PathSegment::Index(index) => index.to_string(),
+          }));
+          if let Some(index) = cursor_after(text_event.delta(txn)) {
+            changes.text_cursors.push(TextCursor { path, index });
+          }
+        }
+      }
+    });
+    let result = match kind {
+      UndoEventKind::Undo => self.undo_manager.undo(),
+      UndoEventKind::Redo => self.undo_manager.redo(),
+    };
+    drop(subscription);
+
+    let applied = result.map_err(|e| CollabError::Internal(Box::new(e)))?;
+    if !applied {
+      return Ok(None);
+    }
+    let UndoChanges {
+      paths: changed_paths,
+      text_cursors,
+    } = std::mem::take(&mut *changes.lock());
+    Ok(Some(UndoEvent {
+      scope: self.scope.clone(),
+      kind,
+      changed_paths,
+      text_cursors,
+    }))
+  }
+}
+
+/// The changes made by an undo or a redo, see [UndoEvent].
+#[derive(Default)]
+struct UndoChanges {
+  paths: Vec<Vec<String>>,
+  text_cursors: Vec<TextCursor>,
+}
+
+enum UndoScopeRef {
+  Map(MapRef),
+  Array(ArrayRef),
+  Text(TextRef),
+}
+
+fn resolve_scope<T: ReadTxn>(txn: &T, data: &MapRef, path: &[String]) -> Option<UndoScopeRef> {
+  let mut scope = UndoScopeRef::Map(data.clone());
+  for key in path {
+    let map = match scope {
+      UndoScopeRef::Map(map) => map,
+      _ => return None,
+    };
+    scope = match map.get(txn, key)? {
+      Value::YMap(map) => UndoScopeRef::Map(map),
+      Value::YArray(array) => UndoScopeRef::Array(array),
+      Value::YText(text) => UndoScopeRef::Text(text),
+      _ => return None,
+    };
+  }
+  Some(scope)
+}
+
+/// Returns the index after the last insertion or deletion of the text delta.
+fn cursor_after(delta: &[Delta]) -> Option<u32> {
+  let mut index = 0;
+  let mut cursor = None;
+  for d in delta {
+    match d {
+      Delta::Retain(len, _) => index += len,
+      Delta::Inserted(Value::Any(yrs::Any::String(s)), _) => {
+        index += s.encode_utf16().count() as u32;
+        cursor = Some(index);
+      },
+      Delta::Inserted(_, _) => {
+        index += 1;
+        cursor = Some(index);
+      },
+      Delta::Deleted(_) => cursor = Some(index),
+    }
+  }
+  cursor
+}
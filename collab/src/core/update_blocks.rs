@@ -0,0 +1,418 @@
+//! Reads the blocks of v1 encoded updates.
+//!
+//! yrs doesn't expose the blocks of a decoded [Update](yrs::Update), so the updates are read
+//! following the layout written by yrs. The [ItemIndex] finds the paths changed by an update from
+//! its blocks alone, without applying it, and [redact_update] strips the content of an update.
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::ops::Range;
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use yrs::block::{
+  ClientID, ItemContent, BLOCK_GC_REF_NUMBER, BLOCK_ITEM_DELETED_REF_NUMBER, BLOCK_SKIP_REF_NUMBER,
+  HAS_ORIGIN, HAS_PARENT_SUB, HAS_RIGHT_ORIGIN,
+};
+use yrs::encoding::read::Read;
+use yrs::encoding::write::Write;
+use yrs::updates::decoder::{Decode, Decoder, DecoderV1};
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::{DeleteSet, Doc, OffsetKind, ReadTxn, StateVector, Transact, UpdateSubscription, ID};
+
+use crate::error::CollabError;
+
+/// Guards against cyclic parent references in a malformed update.
+const MAX_PATH_DEPTH: usize = 64;
+
+/// The type that holds an item.
+#[derive(Debug, Clone, Eq, PartialEq)]
+enum ParentRef {
+  /// A root type of the document, for example the data section.
+  Root(Arc<str>),
+  /// The type stored in the item with the given id.
+  Type(ID),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+struct ItemLocation {
+  parent: ParentRef,
+  /// The key of the item if the parent is a map.
+  key: Option<Arc<str>>,
+}
+
+/// An item block read from a v1 encoded update.
+struct DecodedItem {
+  id: ID,
+  len: u32,
+  origin: Option<ID>,
+  right_origin: Option<ID>,
+  /// Only encoded if the item has neither an origin nor a right origin. Otherwise the item has
+  /// the location of its neighbours.
+  location: Option<ItemLocation>,
+}
+
+/// Maps the ids of the items of a document to their [ItemLocation], so the paths changed by a
+/// remote update can be found from its blocks alone. Consecutive items of a client with the same
+/// location share one entry.
+#[derive(Default)]
+pub(crate) struct ItemIndex {
+  clients: HashMap<ClientID, BTreeMap<u32, (u32, ItemLocation)>>,
+}
+
+impl ItemIndex {
+  /// Indexes the document and keeps the index up to date while the returned subscription is
+  /// alive.
+  pub(crate) fn track(
+    index: &Arc<Mutex<ItemIndex>>,
+    doc: &Doc,
+    object_id: &str,
+  ) -> Option<UpdateSubscription> {
+    // Subscribe before reading the state, so no update is missed in between. Adding an item
+    // twice is a no-op.
+    let cloned_index = index.clone();
+    let cloned_object_id = object_id.to_string();
+    let subscription = doc
+      .observe_update_v1(move |_, event| {
+        if let Err(err) = cloned_index.lock().add_update(&event.update) {
+          tracing::error!(
+            "[{}]: failed to index the update: {}",
+            cloned_object_id,
+            err
+          );
+        }
+      })
+      .map_err(|err| tracing::error!("[{}]: failed to observe updates: {}", object_id, err))
+      .ok()?;
+    let doc_state = doc
+      .transact()
+      .encode_state_as_update_v1(&StateVector::default());
+    if let Err(err) = index.lock().add_update(&doc_state) {
+      tracing::error!("[{}]: failed to index the document: {}", object_id, err);
+    }
+    Some(subscription)
+  }
+
+  fn get(&self, id: &ID) -> Option<&ItemLocation> {
+    let (start, (len, location)) = self
+      .clients
+      .get(&id.client)?
+      .range(..=id.clock)
+      .next_back()?;
+    (id.clock < start + len).then_some(location)
+  }
+
+  fn insert(&mut self, id: ID, len: u32, location: ItemLocation) {
+    if self.get(&id).is_some() {
+      return;
+    }
+    let items = self.clients.entry(id.client).or_default();
+    if let Some((start, (prev_len, prev_location))) = items.range_mut(..id.clock).next_back() {
+      if start + *prev_len == id.clock && *prev_location == location {
+        *prev_len += len;
+        return;
+      }
+    }
+    items.insert(id.clock, (len, location));
+  }
+
+  /// Returns the locations of the items of the client in the given clock range.
+  fn in_range<'a>(
+    &'a self,
+    client: &ClientID,
+    range: &Range<u32>,
+  ) -> impl Iterator<Item = &'a ItemLocation> + 'a {
+    let (start, end) = (range.start, range.end);
+    self
+      .clients
+      .get(client)
+      .into_iter()
+      .flat_map(move |items| items.range(..end).rev())
+      .take_while(move |(clock, (len, _))| *clock + *len > start)
+      .map(|(_, (_, location))| location)
+  }
+
+  /// Adds the items of an update that was applied to the document.
+  pub(crate) fn add_update(&mut self, update: &[u8]) -> Result<(), yrs::encoding::read::Error> {
+    let (items, _) = decode_items(update)?;
+    let unresolved = self.resolve(None, items);
+    if !unresolved.is_empty() {
+      tracing::warn!(
+        "{} items of the update have no known location",
+        unresolved.len()
+      );
+    }
+    Ok(())
+  }
+
+  /// Adds the items that can be located through their own parent info, this index or `base`.
+  /// The neighbours of an item may be part of the same update, so this runs until no more items
+  /// can be located. Returns the remaining items.
+  fn resolve(&mut self, base: Option<&ItemIndex>, mut items: Vec<DecodedItem>) -> Vec<DecodedItem> {
+    loop {
+      let remaining = items.len();
+      items.retain(|item| {
+        let location = item.location.clone().or_else(|| {
+          [item.origin, item.right_origin]
+            .iter()
+            .flatten()
+            .find_map(|id| self.get(id).or_else(|| base.and_then(|base| base.get(id))))
+            .cloned()
+        });
+        match location {
+          Some(location) => {
+            self.insert(item.id, item.len, location);
+            false
+          },
+          None => true,
+        }
+      });
+      if items.is_empty() || items.len() == remaining {
+        return items;
+      }
+    }
+  }
+
+  /// Returns the path of the location, starting with the name of the root type. Returns an empty
+  /// path if one of the parents is unknown. If `collapse` is true, a location inside a type of
+  /// `added` has the path of the outermost such type.
+  fn path(&self, added: &ItemIndex, location: &ItemLocation, collapse: bool) -> Vec<String> {
+    let mut path = vec![];
+    let mut location = location;
+    for _ in 0..MAX_PATH_DEPTH {
+      if let Some(key) = &location.key {
+        path.push(key.to_string());
+      }
+      match &location.parent {
+        ParentRef::Root(name) => {
+          path.push(name.to_string());
+          path.reverse();
+          return path;
+        },
+        ParentRef::Type(id) => match added.get(id) {
+          Some(parent) => {
+            if collapse {
+              path.clear();
+            }
+            location = parent;
+          },
+          None => match self.get(id) {
+            Some(parent) => location = parent,
+            None => break,
+          },
+        },
+      }
+    }
+    vec![]
+  }
+
+  /// Returns the paths that the update inserts into or deletes from. The elements of an array
+  /// have the path of the array. Items that can't be located, for example because the update
+  /// depends on changes the document hasn't seen yet, are reported with an empty path.
+  pub(crate) fn touched_paths(&self, update: &[u8]) -> Result<BTreeSet<Vec<String>>, CollabError> {
+    self.paths(update, false)
+  }
+
+  /// Returns the paths changed by the update, the way the deep observers of the root types would
+  /// report them: the changes made inside a type that the update inserts are part of that type,
+  /// so only its path is returned.
+  pub(crate) fn changed_paths(&self, update: &[u8]) -> Result<BTreeSet<Vec<String>>, CollabError> {
+    self.paths(update, true)
+  }
+
+  fn paths(&self, update: &[u8], collapse: bool) -> Result<BTreeSet<Vec<String>>, CollabError> {
+    let (items, delete_set) = decode_items(update)?;
+    let mut added = ItemIndex::default();
+    let unresolved = added.resolve(Some(self), items);
+
+    let mut paths = BTreeSet::new();
+    if !unresolved.is_empty() {
+      paths.insert(vec![]);
+    }
+    for items in added.clients.values() {
+      for (_, location) in items.values() {
+        paths.insert(self.path(&added, location, collapse));
+      }
+    }
+    for (client, ranges) in delete_set.iter() {
+      for range in ranges.iter() {
+        for location in self
+          .in_range(client, range)
+          .chain(added.in_range(client, range))
+        {
+          paths.insert(self.path(&added, location, collapse));
+        }
+      }
+    }
+    Ok(paths)
+  }
+}
+
+/// Reads the item blocks and the delete set of a v1 encoded update. Follows the layout written
+/// by yrs, which doesn't expose the blocks of a decoded [Update](yrs::Update).
+fn decode_items(
+  update: &[u8],
+) -> Result<(Vec<DecodedItem>, DeleteSet), yrs::encoding::read::Error> {
+  let mut decoder = DecoderV1::from(update);
+  let mut items = vec![];
+  let clients_len: u32 = decoder.read_var()?;
+  for _ in 0..clients_len {
+    let blocks_len: u32 = decoder.read_var()?;
+    let client = decoder.read_client()?;
+    let mut clock: u32 = decoder.read_var()?;
+    for _ in 0..blocks_len {
+      let len = match decoder.read_info()? {
+        BLOCK_SKIP_REF_NUMBER => decoder.read_var()?,
+        BLOCK_GC_REF_NUMBER => decoder.read_len()?,
+        info => {
+          let origin = if info & HAS_ORIGIN != 0 {
+            Some(decoder.read_left_id()?)
+          } else {
+            None
+          };
+          let right_origin = if info & HAS_RIGHT_ORIGIN != 0 {
+            Some(decoder.read_right_id()?)
+          } else {
+            None
+          };
+          let location = if origin.is_none() && right_origin.is_none() {
+            let parent = if decoder.read_parent_info()? {
+              ParentRef::Root(decoder.read_string()?.into())
+            } else {
+              ParentRef::Type(decoder.read_left_id()?)
+            };
+            let key = if info & HAS_PARENT_SUB != 0 {
+              Some(decoder.read_string()?.into())
+            } else {
+              None
+            };
+            Some(ItemLocation { parent, key })
+          } else {
+            None
+          };
+          let len = ItemContent::decode(&mut decoder, info)?.len(OffsetKind::Utf16);
+          items.push(DecodedItem {
+            id: ID::new(client, clock),
+            len,
+            origin,
+            right_origin,
+            location,
+          });
+          len
+        },
+      };
+      clock += len;
+    }
+  }
+  let delete_set = DeleteSet::decode(&mut decoder)?;
+  Ok((items, delete_set))
+}
+
+/// Returns the update with the content of its items replaced by deleted content of the same
+/// length, and with the items added to its delete set. The items inside a type that the update
+/// inserts become garbage collected blocks, since their parent has no content anymore. Applying
+/// it advances the clocks of the clients like the original update, without revealing what the
+/// update inserted.
+pub(crate) fn redact_update(update: &[u8]) -> Result<Vec<u8>, yrs::encoding::read::Error> {
+  let mut decoder = DecoderV1::from(update);
+  let mut encoder = EncoderV1::new();
+  // The ids of the items of the update, and of the ones inside a type inserted by the update.
+  let mut added = DeleteSet::new();
+  let mut nested = DeleteSet::new();
+  let mut redacted = DeleteSet::new();
+  let clients_len: u32 = decoder.read_var()?;
+  encoder.write_var(clients_len);
+  for _ in 0..clients_len {
+    let blocks_len: u32 = decoder.read_var()?;
+    let client = decoder.read_client()?;
+    let mut clock: u32 = decoder.read_var()?;
+    encoder.write_var(blocks_len);
+    encoder.write_client(client);
+    encoder.write_var(clock);
+    for _ in 0..blocks_len {
+      let id = ID::new(client, clock);
+      let len = match decoder.read_info()? {
+        BLOCK_SKIP_REF_NUMBER => {
+          let len: u32 = decoder.read_var()?;
+          encoder.write_info(BLOCK_SKIP_REF_NUMBER);
+          encoder.write_var(len);
+          len
+        },
+        BLOCK_GC_REF_NUMBER => {
+          let len = decoder.read_len()?;
+          encoder.write_info(BLOCK_GC_REF_NUMBER);
+          encoder.write_len(len);
+          len
+        },
+        info => {
+          let origin = if info & HAS_ORIGIN != 0 {
+            Some(decoder.read_left_id()?)
+          } else {
+            None
+          };
+          let right_origin = if info & HAS_RIGHT_ORIGIN != 0 {
+            Some(decoder.read_right_id()?)
+          } else {
+            None
+          };
+          let mut parent = None;
+          let mut parent_sub = None;
+          if origin.is_none() && right_origin.is_none() {
+            parent = Some(if decoder.read_parent_info()? {
+              ParentRef::Root(decoder.read_string()?.into())
+            } else {
+              ParentRef::Type(decoder.read_left_id()?)
+            });
+            if info & HAS_PARENT_SUB != 0 {
+              parent_sub = Some(decoder.read_string()?.to_string());
+            }
+          }
+          let len = ItemContent::decode(&mut decoder, info)?.len(OffsetKind::Utf16);
+
+          // An item has the parent of its neighbours.
+          let is_nested = match (&parent, origin.or(right_origin)) {
+            (Some(ParentRef::Type(parent)), _) => added.is_deleted(parent),
+            (_, Some(neighbour)) => nested.is_deleted(&neighbour),
+            _ => false,
+          };
+          added.insert(id, len);
+          if is_nested {
+            nested.insert(id, len);
+            encoder.write_info(BLOCK_GC_REF_NUMBER);
+            encoder.write_len(len);
+          } else {
+            // Keep the flags, replace the kind of content.
+            encoder.write_info((info & !0b0001_1111) | BLOCK_ITEM_DELETED_REF_NUMBER);
+            if let Some(origin) = &origin {
+              encoder.write_left_id(origin);
+            }
+            if let Some(right_origin) = &right_origin {
+              encoder.write_right_id(right_origin);
+            }
+            match &parent {
+              Some(ParentRef::Root(name)) => {
+                encoder.write_parent_info(true);
+                encoder.write_string(name);
+              },
+              Some(ParentRef::Type(parent)) => {
+                encoder.write_parent_info(false);
+                encoder.write_left_id(parent);
+              },
+              None => {},
+            }
+            if let Some(parent_sub) = &parent_sub {
+              encoder.write_string(parent_sub);
+            }
+            encoder.write_len(len);
+            redacted.insert(id, len);
+          }
+          len
+        },
+      };
+      clock += len;
+    }
+  }
+  let mut delete_set = DeleteSet::decode(&mut decoder)?;
+  delete_set.merge(redacted);
+  delete_set.encode(&mut encoder);
+  Ok(encoder.to_vec())
+}
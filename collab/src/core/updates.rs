@@ -0,0 +1,159 @@
+use std::panic;
+use std::panic::AssertUnwindSafe;
+
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{StateVector, Update};
+
+use crate::core::collab_plugin::{EncodedCollab, EncoderVersion};
+use crate::error::CollabError;
+
+/// Merges a list of v1 encoded updates into a single v1 encoded update. The updates don't need
+/// to be in causal order.
+pub fn merge_updates_v1<T: AsRef<[u8]>>(updates: &[T]) -> Result<Vec<u8>, CollabError> {
+  let updates = updates
+    .iter()
+    .map(|update| update.as_ref())
+    .collect::<Vec<&[u8]>>();
+  catch_yrs_panic(|| yrs::merge_updates_v1(&updates))
+}
+
+/// Merges a list of v2 encoded updates into a single v2 encoded update. The updates don't need
+/// to be in causal order.
+pub fn merge_updates_v2<T: AsRef<[u8]>>(updates: &[T]) -> Result<Vec<u8>, CollabError> {
+  let updates = updates
+    .iter()
+    .map(|update| update.as_ref())
+    .collect::<Vec<&[u8]>>();
+  catch_yrs_panic(|| yrs::merge_updates_v2(&updates))
+}
+
+/// Merges a list of updates that were encoded with the given [EncoderVersion].
+pub fn merge_updates<T: AsRef<[u8]>>(
+  updates: &[T],
+  version: &EncoderVersion,
+) -> Result<Vec<u8>, CollabError> {
+  match version {
+    EncoderVersion::V1 => merge_updates_v1(updates),
+    EncoderVersion::V2 => merge_updates_v2(updates),
+  }
+}
+
+/// Re-encodes a v1 update with the v2 encoding.
+pub fn convert_update_v1_to_v2(update: &[u8]) -> Result<Vec<u8>, CollabError> {
+  let update = Update::decode_v1(update)?;
+  catch_yrs_panic(|| Ok(update.encode_v2()))
+}
+
+/// Re-encodes a v2 update with the v1 encoding.
+pub fn convert_update_v2_to_v1(update: &[u8]) -> Result<Vec<u8>, CollabError> {
+  let update = Update::decode_v2(update)?;
+  catch_yrs_panic(|| Ok(update.encode_v1()))
+}
+
+/// Re-encodes a v1 state vector with the v2 encoding.
+pub fn convert_state_vector_v1_to_v2(state_vector: &[u8]) -> Result<Vec<u8>, CollabError> {
+  Ok(StateVector::decode_v1(state_vector)?.encode_v2())
+}
+
+/// Re-encodes a v2 state vector with the v1 encoding.
+pub fn convert_state_vector_v2_to_v1(state_vector: &[u8]) -> Result<Vec<u8>, CollabError> {
+  Ok(StateVector::decode_v2(state_vector)?.encode_v1())
+}
+
+/// Returns the part of the v1 encoded `update` that is missing on a peer whose state is described
+/// by the v1 encoded `state_vector`.
+pub fn diff_update_v1(update: &[u8], state_vector: &[u8]) -> Result<Vec<u8>, CollabError> {
+  catch_yrs_panic(|| yrs::diff_updates_v1(update, state_vector))
+}
+
+/// Returns the part of the v2 encoded `update` that is missing on a peer whose state is described
+/// by the v2 encoded `state_vector`.
+pub fn diff_update_v2(update: &[u8], state_vector: &[u8]) -> Result<Vec<u8>, CollabError> {
+  catch_yrs_panic(|| yrs::diff_updates_v2(update, state_vector))
+}
+
+/// Computes the state vector that is covered by the v1 encoded `update`.
+pub fn state_vector_from_update_v1(update: &[u8]) -> Result<Vec<u8>, CollabError> {
+  catch_yrs_panic(|| yrs::encode_state_vector_from_update_v1(update))
+}
+
+/// Computes the state vector that is covered by the v2 encoded `update`.
+pub fn state_vector_from_update_v2(update: &[u8]) -> Result<Vec<u8>, CollabError> {
+  catch_yrs_panic(|| yrs::encode_state_vector_from_update_v2(update))
+}
+
+/// Compacts a stored doc state and the updates that were appended after it into a single
+/// [EncodedCollab]. All the inputs must be encoded with the given [EncoderVersion].
+///
+/// The returned [EncodedCollab] is equivalent to loading the doc state and the updates into a
+/// [yrs::Doc] and encoding it again, without the cost of building the document.
+pub fn compact_updates<T: AsRef<[u8]>>(
+  doc_state: &[u8],
+  updates: &[T],
+  version: EncoderVersion,
+) -> Result<EncodedCollab, CollabError> {
+  let mut all_updates = Vec::with_capacity(updates.len() + 1);
+  all_updates.push(doc_state);
+  all_updates.extend(updates.iter().map(|update| update.as_ref()));
+
+  let doc_state = merge_updates(&all_updates, &version)?;
+  let encoded_collab = match version {
+    EncoderVersion::V1 => {
+      let state_vector = state_vector_from_update_v1(&doc_state)?;
+      EncodedCollab::new_v1(state_vector, doc_state)
+    },
+    EncoderVersion::V2 => {
+      let state_vector = state_vector_from_update_v2(&doc_state)?;
+      EncodedCollab::new_v2(state_vector, doc_state)
+    },
+  };
+  Ok(encoded_collab)
+}
+
+impl EncodedCollab {
+  /// Returns the [EncodedCollab] encoded with the v1 encoding. Returns itself if it is already
+  /// encoded with v1.
+  pub fn into_v1(self) -> Result<EncodedCollab, CollabError> {
+    match self.version {
+      EncoderVersion::V1 => Ok(self),
+      EncoderVersion::V2 => Ok(EncodedCollab::new_v1(
+        convert_state_vector_v2_to_v1(&self.state_vector)?,
+        convert_update_v2_to_v1(&self.doc_state)?,
+      )),
+    }
+  }
+
+  /// Returns the [EncodedCollab] encoded with the v2 encoding. Returns itself if it is already
+  /// encoded with v2.
+  pub fn into_v2(self) -> Result<EncodedCollab, CollabError> {
+    match self.version {
+      EncoderVersion::V2 => Ok(self),
+      EncoderVersion::V1 => Ok(EncodedCollab::new_v2(
+        convert_state_vector_v1_to_v2(&self.state_vector)?,
+        convert_update_v1_to_v2(&self.doc_state)?,
+      )),
+    }
+  }
+
+  /// Returns the update that a remote peer with the given state vector is missing. The state
+  /// vector and the returned update use the same encoding as this [EncodedCollab].
+  pub fn diff(&self, remote_state_vector: &[u8]) -> Result<Vec<u8>, CollabError> {
+    match self.version {
+      EncoderVersion::V1 => diff_update_v1(&self.doc_state, remote_state_vector),
+      EncoderVersion::V2 => diff_update_v2(&self.doc_state, remote_state_vector),
+    }
+  }
+}
+
+/// Yrs panics instead of returning an error for some malformed inputs. Turn those panics into
+/// [CollabError::YrsTransactionError] so a corrupted update can't take down the caller.
+fn catch_yrs_panic<F, T>(f: F) -> Result<T, CollabError>
+where
+  F: FnOnce() -> Result<T, yrs::encoding::read::Error>,
+{
+  match panic::catch_unwind(AssertUnwindSafe(f)) {
+    Ok(result) => Ok(result?),
+    Err(e) => Err(CollabError::YrsTransactionError(format!("{:?}", e))),
+  }
+}
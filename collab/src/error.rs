@@ -15,6 +15,36 @@ pub enum CollabError {
   #[error("UndoManager is not enabled")]
   UndoManagerNotEnabled,
 
+  #[error("The undo scope {0} is not a map, array or text")]
+  InvalidUndoScope(String),
+
+  #[error("Only one cloud storage plugin can be added to a collab instance")]
+  DuplicateCloudStoragePlugin,
+
+  #[error("Access denied: {0}")]
+  AccessDenied(crate::core::access_control::AccessViolation),
+
+  #[error("The collab {0} is read-only")]
+  ReadOnly(String),
+
+  #[error("The transaction was aborted: {0}")]
+  TransactionAborted(String),
+
+  #[error("The collab was compacted into epoch {0}, its state must be replaced")]
+  Superseded(i64),
+
+  #[error("The remote peer is at compaction epoch {remote}, the collab is at epoch {epoch}")]
+  StaleEpoch { remote: i64, epoch: i64 },
+
+  #[error("The subdocument {0} doesn't exist")]
+  SubdocNotFound(String),
+
+  #[error("The subdocument {0} already exists")]
+  SubdocAlreadyExists(String),
+
+  #[error("Migration to version {version} failed: {reason}")]
+  MigrationFailed { version: u32, reason: String },
+
   #[error(transparent)]
   DecodeUpdate(#[from] yrs::encoding::read::Error),
 
@@ -16,6 +16,9 @@ macro_rules! if_wasm {
 
 pub mod core;
 pub mod error;
+#[cfg(feature = "simulation")]
+pub mod simulation;
+pub mod sync_protocol;
 pub mod util;
 
 pub mod preclude {
@@ -0,0 +1,553 @@
+//! A deterministic simulation of several clients editing the same collab.
+//!
+//! Each client is a [MutexCollab] with its own [CollabOrigin], wrapped in a [SimulationModel]
+//! such as a document, a folder or a database. The [Simulation] applies random operations to the
+//! clients and delivers their updates over a simulated network that interleaves the links between
+//! the clients, reorders and duplicates messages and partitions clients. Once the network is healed, all the
+//! clients must hold the same content and satisfy the invariants of their model.
+//!
+//! Everything is driven by a [SimulationRng] created from a single seed, so a failing run can be
+//! replayed with the seed printed in the [SimulationFailure]. The tests run a fixed list of seeds,
+//! so they are reproducible. Set the `COLLAB_SIMULATION_SEED` environment variable to run a given
+//! seed instead, see [simulation_seeds].
+//!
+//! With the `async-plugin` feature, [crate::core::collab::Collab::initialize] is async. The
+//! clients only have an internal plugin whose `init` doesn't wait, so the simulation completes
+//! their initialization with a single poll and stays synchronous.
+
+use std::collections::HashSet;
+use std::fmt::{Display, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use parking_lot::Mutex;
+use yrs::{ReadTxn, TransactionMut};
+
+use crate::core::awareness::{AwarenessUpdate, Event};
+use crate::core::collab::{CollabBuilder, MutexCollab};
+use crate::core::collab_plugin::CollabPlugin;
+use crate::core::origin::{CollabClient, CollabOrigin};
+use crate::preclude::JsonValue;
+
+pub const SIMULATION_SEED_ENV: &str = "COLLAB_SIMULATION_SEED";
+
+/// The number of operations kept in [SimulationFailure::trace].
+const TRACE_LEN: usize = 50;
+
+/// Returns the seeds a test runs: the seed set in [SIMULATION_SEED_ENV] if any, otherwise the
+/// seeds from 1 to `count`.
+pub fn simulation_seeds(count: u64) -> Vec<u64> {
+  match std::env::var(SIMULATION_SEED_ENV)
+    .ok()
+    .and_then(|seed| seed.parse().ok())
+  {
+    Some(seed) => vec![seed],
+    None => (1..=count).collect(),
+  }
+}
+
+/// A small pseudo random generator (SplitMix64). It doesn't depend on an external crate, so the
+/// same seed replays the same simulation on every platform and version.
+#[derive(Debug, Clone)]
+pub struct SimulationRng {
+  state: u64,
+}
+
+impl SimulationRng {
+  pub fn new(seed: u64) -> Self {
+    Self { state: seed }
+  }
+
+  pub fn next_u64(&mut self) -> u64 {
+    self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+    let mut z = self.state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+  }
+
+  /// Returns a number in `0..n`. Panics if `n` is 0.
+  pub fn below(&mut self, n: usize) -> usize {
+    assert!(n > 0, "the range must not be empty");
+    (self.next_u64() % n as u64) as usize
+  }
+
+  /// Returns true with the given probability.
+  pub fn chance(&mut self, probability: f64) -> bool {
+    probability > 0.0 && ((self.next_u64() >> 11) as f64 / (1u64 << 53) as f64) < probability
+  }
+
+  pub fn choose<'a, T>(&mut self, items: &'a [T]) -> Option<&'a T> {
+    if items.is_empty() {
+      None
+    } else {
+      Some(&items[self.below(items.len())])
+    }
+  }
+
+  /// Returns a lowercase string of the given length.
+  pub fn string(&mut self, len: usize) -> String {
+    (0..len)
+      .map(|_| (b'a' + self.below(26) as u8) as char)
+      .collect()
+  }
+}
+
+/// The content of a collab as seen by one client, e.g. a document or a folder.
+pub trait SimulationModel {
+  /// Applies a random local operation and returns a description of it for the trace.
+  fn apply_random_operation(&mut self, rng: &mut SimulationRng) -> String;
+
+  /// Checks the invariants of the model. Called on every client once they converged.
+  fn check_invariants(&self) -> Result<(), String> {
+    Ok(())
+  }
+}
+
+/// How the simulated network treats the updates.
+#[derive(Debug, Clone)]
+pub struct SimulationConfig {
+  pub object_id: String,
+  pub client_count: usize,
+  /// The probability that a step applies an operation, instead of delivering an update.
+  pub operation_probability: f64,
+  /// The probability that an update is sent twice.
+  pub duplicate_probability: f64,
+  /// The probability that a delivery picks any message of a link instead of the oldest one, so
+  /// the receiver gets updates whose dependencies are still in flight.
+  pub reorder_probability: f64,
+  /// The probability that a step partitions a connected client from the others.
+  pub partition_probability: f64,
+  /// The probability that a step reconnects a partitioned client.
+  pub heal_probability: f64,
+}
+
+impl Default for SimulationConfig {
+  fn default() -> Self {
+    Self {
+      object_id: "simulation".to_string(),
+      client_count: 3,
+      operation_probability: 0.5,
+      duplicate_probability: 0.1,
+      reorder_probability: 0.2,
+      partition_probability: 0.05,
+      heal_probability: 0.1,
+    }
+  }
+}
+
+impl SimulationConfig {
+  pub fn with_client_count(mut self, client_count: usize) -> Self {
+    self.client_count = client_count;
+    self
+  }
+
+  pub fn with_operation_probability(mut self, probability: f64) -> Self {
+    self.operation_probability = probability;
+    self
+  }
+
+  pub fn with_duplicate_probability(mut self, probability: f64) -> Self {
+    self.duplicate_probability = probability;
+    self
+  }
+
+  pub fn with_reorder_probability(mut self, probability: f64) -> Self {
+    self.reorder_probability = probability;
+    self
+  }
+
+  pub fn with_partition_probability(mut self, probability: f64) -> Self {
+    self.partition_probability = probability;
+    self
+  }
+
+  pub fn with_heal_probability(mut self, probability: f64) -> Self {
+    self.heal_probability = probability;
+    self
+  }
+}
+
+#[derive(Debug, Clone)]
+pub struct SimulationFailure {
+  pub seed: u64,
+  pub step: usize,
+  pub reason: String,
+  /// The last operations and network events before the failure.
+  pub trace: Vec<String>,
+}
+
+impl Display for SimulationFailure {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    writeln!(
+      f,
+      "simulation failed at step {}: {}. Replay it with {}={}",
+      self.step, self.reason, SIMULATION_SEED_ENV, self.seed
+    )?;
+    for event in &self.trace {
+      writeln!(f, "  {}", event)?;
+    }
+    Ok(())
+  }
+}
+
+impl std::error::Error for SimulationFailure {}
+
+/// Collects the updates of a client, except the ones delivered by the [Simulation].
+#[derive(Clone, Default)]
+struct Outbox {
+  updates: Arc<Mutex<Vec<Vec<u8>>>>,
+  paused: Arc<AtomicBool>,
+}
+
+impl CollabPlugin for Outbox {
+  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, update: &[u8]) {
+    if !self.paused.load(Ordering::SeqCst) {
+      self.updates.lock().push(update.to_vec());
+    }
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+}
+
+#[cfg(not(feature = "async-plugin"))]
+fn initialize_client(collab: &MutexCollab) -> bool {
+  collab.lock().initialize();
+  true
+}
+
+/// The [Outbox] is the only plugin of the client and doesn't wait in `init`, so the
+/// initialization completes on the first poll.
+#[cfg(feature = "async-plugin")]
+fn initialize_client(collab: &MutexCollab) -> bool {
+  futures_util::FutureExt::now_or_never(collab.lock().initialize()).is_some()
+}
+
+pub struct SimulationClient<M> {
+  pub origin: CollabOrigin,
+  pub collab: MutexCollab,
+  pub model: M,
+  outbox: Outbox,
+  partitioned: bool,
+}
+
+struct Message {
+  from: usize,
+  to: usize,
+  update: Vec<u8>,
+}
+
+pub struct Simulation<M> {
+  seed: u64,
+  rng: SimulationRng,
+  config: SimulationConfig,
+  clients: Vec<SimulationClient<M>>,
+  in_flight: Vec<Message>,
+  trace: Vec<String>,
+  step: usize,
+}
+
+impl<M: SimulationModel> Simulation<M> {
+  /// Creates the clients. `create` builds the model of the first client, which writes the
+  /// initial content. The other clients start from that content and `open` builds their model.
+  pub fn new<C, O>(
+    seed: u64,
+    config: SimulationConfig,
+    create: C,
+    open: O,
+  ) -> Result<Self, SimulationFailure>
+  where
+    C: FnOnce(i64, MutexCollab) -> M,
+    O: Fn(i64, MutexCollab) -> M,
+  {
+    let mut this = Self {
+      seed,
+      rng: SimulationRng::new(seed),
+      config,
+      clients: vec![],
+      in_flight: vec![],
+      trace: vec![],
+      step: 0,
+    };
+
+    let (collab, outbox) = this.build_collab(1, vec![])?;
+    let model = create(1, collab.clone());
+    let doc_state = collab.lock().encode_collab_v1().doc_state.to_vec();
+    // The other clients already have the initial content.
+    outbox.updates.lock().clear();
+    this.push_client(1, collab, model, outbox);
+
+    for uid in 2..=this.config.client_count as i64 {
+      let (collab, outbox) = this.build_collab(uid, doc_state.clone())?;
+      let model = open(uid, collab.clone());
+      outbox.updates.lock().clear();
+      this.push_client(uid, collab, model, outbox);
+    }
+    Ok(this)
+  }
+
+  pub fn seed(&self) -> u64 {
+    self.seed
+  }
+
+  pub fn clients(&self) -> &[SimulationClient<M>] {
+    &self.clients
+  }
+
+  /// Runs the given number of random steps, then heals the network, delivers all the updates and
+  /// checks that the clients converged.
+  pub fn run(&mut self, steps: usize) -> Result<(), SimulationFailure> {
+    for _ in 0..steps {
+      self.step += 1;
+      self.random_step()?;
+    }
+    self.settle()?;
+    self.check_convergence()
+  }
+
+  /// Like [Simulation::run], but panics with the seed and the trace on failure.
+  pub fn assert_run(&mut self, steps: usize) {
+    if let Err(failure) = self.run(steps) {
+      panic!("{}", failure);
+    }
+  }
+
+  /// Reconnects every client and delivers all the pending updates, in random order. Then, like
+  /// the sync protocol does when a client reconnects, each client sends the others the updates
+  /// missing from their state vectors.
+  pub fn settle(&mut self) -> Result<(), SimulationFailure> {
+    for client in self.clients.iter_mut() {
+      client.partitioned = false;
+    }
+    self.record("heal all".to_string());
+    loop {
+      self.collect_outboxes();
+      if self.in_flight.is_empty() {
+        break;
+      }
+      self.deliver_random()?;
+    }
+
+    for to in 0..self.clients.len() {
+      for from in 0..self.clients.len() {
+        if from != to {
+          self.sync(from, to)?;
+        }
+      }
+    }
+    self.collect_outboxes();
+    self.in_flight.clear();
+    Ok(())
+  }
+
+  pub fn check_convergence(&self) -> Result<(), SimulationFailure> {
+    let first = &self.clients[0];
+    let expected_json = first.collab.to_json_value();
+    let expected_sv = first.collab.lock().transact().state_vector();
+    for (index, client) in self.clients.iter().enumerate() {
+      if index > 0 {
+        let sv = client.collab.lock().transact().state_vector();
+        if sv != expected_sv {
+          return Err(self.failure(format!(
+            "{} has the state vector {:?}, {} has {:?}",
+            client.origin, sv, first.origin, expected_sv
+          )));
+        }
+        let json = client.collab.to_json_value();
+        if json != expected_json {
+          return Err(self.failure(format!(
+            "{} diverged from {}: {} != {}",
+            client.origin,
+            first.origin,
+            json_to_string(&json),
+            json_to_string(&expected_json)
+          )));
+        }
+      }
+      if let Err(reason) = client.model.check_invariants() {
+        return Err(self.failure(format!("{} broke an invariant: {}", client.origin, reason)));
+      }
+    }
+    Ok(())
+  }
+
+  fn build_collab(
+    &self,
+    uid: i64,
+    doc_state: Vec<u8>,
+  ) -> Result<(MutexCollab, Outbox), SimulationFailure> {
+    let outbox = Outbox::default();
+    // yrs orders concurrent edits by client id, so a replay needs the same client ids.
+    let collab = CollabBuilder::new(uid, &self.config.object_id)
+      .with_device_id(uid.to_string())
+      .with_client_id(uid as u64)
+      .with_doc_state(doc_state)
+      .with_plugin(outbox.clone())
+      .build()
+      .map_err(|err| self.failure(format!("failed to build client {}: {}", uid, err)))?;
+    if !initialize_client(&collab) {
+      return Err(self.failure(format!("client {} did not initialize", uid)));
+    }
+    Ok((collab, outbox))
+  }
+
+  fn push_client(&mut self, uid: i64, collab: MutexCollab, model: M, outbox: Outbox) {
+    self.clients.push(SimulationClient {
+      origin: CollabOrigin::Client(CollabClient::new(uid, uid.to_string())),
+      collab,
+      model,
+      outbox,
+      partitioned: false,
+    });
+  }
+
+  fn random_step(&mut self) -> Result<(), SimulationFailure> {
+    if self.rng.chance(self.config.partition_probability) {
+      let index = self.rng.below(self.clients.len());
+      if !self.clients[index].partitioned {
+        self.clients[index].partitioned = true;
+        let event = format!("partition {}", self.clients[index].origin);
+        self.record(event);
+        return Ok(());
+      }
+    }
+    if self.rng.chance(self.config.heal_probability) {
+      let partitioned = (0..self.clients.len())
+        .filter(|index| self.clients[*index].partitioned)
+        .collect::<Vec<_>>();
+      if let Some(index) = self.rng.choose(&partitioned).copied() {
+        self.clients[index].partitioned = false;
+        let event = format!("heal {}", self.clients[index].origin);
+        self.record(event);
+        return Ok(());
+      }
+    }
+
+    self.collect_outboxes();
+    if self.in_flight.is_empty() || self.rng.chance(self.config.operation_probability) {
+      let index = self.rng.below(self.clients.len());
+      let client = &mut self.clients[index];
+      let operation = client.model.apply_random_operation(&mut self.rng);
+      let event = format!("{}: {}", client.origin, operation);
+      self.record(event);
+      Ok(())
+    } else {
+      self.deliver_random()
+    }
+  }
+
+  /// Turns the new updates of each client into one message per peer.
+  fn collect_outboxes(&mut self) {
+    for from in 0..self.clients.len() {
+      let updates = std::mem::take(&mut *self.clients[from].outbox.updates.lock());
+      for update in updates {
+        for to in 0..self.clients.len() {
+          if to == from {
+            continue;
+          }
+          if self.rng.chance(self.config.duplicate_probability) {
+            self.in_flight.push(Message {
+              from,
+              to,
+              update: update.clone(),
+            });
+          }
+          self.in_flight.push(Message {
+            from,
+            to,
+            update: update.clone(),
+          });
+        }
+      }
+    }
+  }
+
+  /// Delivers a random message whose sender and receiver are connected. Like the real transports,
+  /// each link usually delivers its messages in the order they were sent, while the links
+  /// interleave randomly. With [SimulationConfig::reorder_probability], any message of a link can
+  /// be delivered first. The messages of the partitioned clients stay in flight.
+  fn deliver_random(&mut self) -> Result<(), SimulationFailure> {
+    let reorder = self.rng.chance(self.config.reorder_probability);
+    let mut links = HashSet::new();
+    let deliverable = self
+      .in_flight
+      .iter()
+      .enumerate()
+      .filter(|(_, message)| reorder || links.insert((message.from, message.to)))
+      .filter(|(_, message)| {
+        !self.clients[message.from].partitioned && !self.clients[message.to].partitioned
+      })
+      .map(|(index, _)| index)
+      .collect::<Vec<_>>();
+    let index = match self.rng.choose(&deliverable) {
+      None => return Ok(()),
+      Some(index) => *index,
+    };
+
+    let message = self.in_flight.remove(index);
+    self.apply(message.from, message.to, &message.update, "deliver")
+  }
+
+  /// Sends the client `to` the updates of the client `from` that are missing from its state
+  /// vector.
+  fn sync(&mut self, from: usize, to: usize) -> Result<(), SimulationFailure> {
+    let state_vector = self.clients[to].collab.lock().transact().state_vector();
+    let update = self.clients[from]
+      .collab
+      .lock()
+      .transact()
+      .encode_state_as_update_v1(&state_vector);
+    self.apply(from, to, &update, "sync")
+  }
+
+  fn apply(
+    &mut self,
+    from: usize,
+    to: usize,
+    update: &[u8],
+    action: &str,
+  ) -> Result<(), SimulationFailure> {
+    let from = self.clients[from].origin.clone();
+    let to = &self.clients[to];
+    to.outbox.paused.store(true, Ordering::SeqCst);
+    let result = to.collab.lock().apply_remote_update(&from, update);
+    to.outbox.paused.store(false, Ordering::SeqCst);
+    let event = format!(
+      "{} {} bytes from {} to {}",
+      action,
+      update.len(),
+      from,
+      to.origin
+    );
+    self.record(event);
+    result.map_err(|err| self.failure(format!("failed to apply update: {}", err)))
+  }
+
+  fn record(&mut self, event: String) {
+    tracing::trace!("[simulation {}] {}", self.seed, event);
+    if self.trace.len() == TRACE_LEN {
+      self.trace.remove(0);
+    }
+    self.trace.push(event);
+  }
+
+  fn failure(&self, reason: String) -> SimulationFailure {
+    SimulationFailure {
+      seed: self.seed,
+      step: self.step,
+      reason,
+      trace: self.trace.clone(),
+    }
+  }
+}
+
+fn json_to_string(json: &JsonValue) -> String {
+  serde_json::to_string(json).unwrap_or_default()
+}
@@ -0,0 +1,22 @@
+use crate::error::CollabError;
+
+#[derive(Debug, thiserror::Error)]
+pub enum SyncProtocolError {
+  #[error(transparent)]
+  Decode(#[from] yrs::encoding::read::Error),
+
+  #[error("Permission denied: {0}")]
+  PermissionDenied(String),
+
+  #[error(transparent)]
+  Collab(#[from] CollabError),
+
+  #[error(transparent)]
+  Awareness(#[from] crate::core::awareness::Error),
+
+  #[error("Transport failure: {0}")]
+  Transport(String),
+
+  #[error("The connection is closed")]
+  Closed,
+}
@@ -0,0 +1,60 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use futures_util::{Sink, Stream};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+
+use crate::sync_protocol::SyncProtocolError;
+
+/// One end of an in-memory connection. The frames sent to one end are received by the other
+/// end. Useful to sync two collabs in the same process, for example in tests.
+pub struct MemoryTransport {
+  sender: UnboundedSender<Vec<u8>>,
+  receiver: UnboundedReceiver<Vec<u8>>,
+}
+
+/// Returns the two ends of an in-memory connection.
+pub fn memory_duplex() -> (MemoryTransport, MemoryTransport) {
+  let (a_sender, b_receiver) = unbounded_channel();
+  let (b_sender, a_receiver) = unbounded_channel();
+  let a = MemoryTransport {
+    sender: a_sender,
+    receiver: a_receiver,
+  };
+  let b = MemoryTransport {
+    sender: b_sender,
+    receiver: b_receiver,
+  };
+  (a, b)
+}
+
+impl Sink<Vec<u8>> for MemoryTransport {
+  type Error = SyncProtocolError;
+
+  fn poll_ready(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn start_send(self: Pin<&mut Self>, item: Vec<u8>) -> Result<(), Self::Error> {
+    self
+      .sender
+      .send(item)
+      .map_err(|_| SyncProtocolError::Closed)
+  }
+
+  fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+
+  fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+    Poll::Ready(Ok(()))
+  }
+}
+
+impl Stream for MemoryTransport {
+  type Item = Result<Vec<u8>, SyncProtocolError>;
+
+  fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+    self.receiver.poll_recv(cx).map(|frame| frame.map(Ok))
+  }
+}
@@ -0,0 +1,181 @@
+use std::fmt::{Display, Formatter};
+
+use yrs::encoding::read::Cursor;
+use yrs::updates::decoder::{Decode, Decoder, DecoderV1};
+use yrs::updates::encoder::{Encode, Encoder, EncoderV1};
+use yrs::StateVector;
+
+use crate::core::awareness::AwarenessUpdate;
+use crate::sync_protocol::SyncProtocolError;
+
+pub const MSG_SYNC: u8 = 0;
+pub const MSG_AWARENESS: u8 = 1;
+pub const MSG_AUTH: u8 = 2;
+pub const MSG_QUERY_AWARENESS: u8 = 3;
+/// Not part of y-protocols. Other Yjs clients pass it through as a [Message::Custom].
+pub const MSG_COMPACTION_EPOCH: u8 = 4;
+
+pub const MSG_SYNC_STEP_1: u8 = 0;
+pub const MSG_SYNC_STEP_2: u8 = 1;
+pub const MSG_SYNC_UPDATE: u8 = 2;
+
+const PERMISSION_DENIED: u8 = 0;
+const PERMISSION_GRANTED: u8 = 1;
+
+/// A message of the Yjs sync protocol. The binary layout is the same as the one used by
+/// y-protocols, so the messages can be exchanged with any Yjs client.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum Message {
+  Sync(SyncMessage),
+  /// The reason is None when the permission is granted.
+  Auth(Option<String>),
+  AwarenessQuery,
+  Awareness(AwarenessUpdate),
+  /// The compaction epoch of the sender, sent before its [SyncMessage::SyncStep1]. Peers at
+  /// different epochs can't merge their histories, see [crate::core::compaction].
+  CompactionEpoch(i64),
+  /// A message type that is not part of the protocol. The payload is passed through as is.
+  Custom(u8, Vec<u8>),
+}
+
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub enum SyncMessage {
+  /// Sends the state vector of the sender. The receiver replies with the updates the sender is
+  /// missing.
+  SyncStep1(StateVector),
+  /// The reply to [SyncMessage::SyncStep1]. Contains a v1 encoded update.
+  SyncStep2(Vec<u8>),
+  /// A v1 encoded update made after the handshake.
+  Update(Vec<u8>),
+}
+
+impl Encode for Message {
+  fn encode<E: Encoder>(&self, encoder: &mut E) {
+    match self {
+      Message::Sync(msg) => {
+        encoder.write_var(MSG_SYNC);
+        msg.encode(encoder);
+      },
+      Message::Auth(reason) => {
+        encoder.write_var(MSG_AUTH);
+        match reason {
+          None => encoder.write_var(PERMISSION_GRANTED),
+          Some(reason) => {
+            encoder.write_var(PERMISSION_DENIED);
+            encoder.write_string(reason);
+          },
+        }
+      },
+      Message::AwarenessQuery => {
+        encoder.write_var(MSG_QUERY_AWARENESS);
+      },
+      Message::Awareness(update) => {
+        encoder.write_var(MSG_AWARENESS);
+        encoder.write_buf(update.encode_v1());
+      },
+      Message::CompactionEpoch(epoch) => {
+        encoder.write_var(MSG_COMPACTION_EPOCH);
+        encoder.write_var(*epoch);
+      },
+      Message::Custom(tag, payload) => {
+        encoder.write_var(*tag);
+        encoder.write_buf(payload);
+      },
+    }
+  }
+}
+
+impl Decode for Message {
+  fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, yrs::encoding::read::Error> {
+    let tag: u8 = decoder.read_var()?;
+    match tag {
+      MSG_SYNC => Ok(Message::Sync(SyncMessage::decode(decoder)?)),
+      MSG_AWARENESS => {
+        let data = decoder.read_buf()?;
+        Ok(Message::Awareness(AwarenessUpdate::decode_v1(data)?))
+      },
+      MSG_AUTH => {
+        let permission: u8 = decoder.read_var()?;
+        if permission == PERMISSION_DENIED {
+          Ok(Message::Auth(Some(decoder.read_string()?.to_string())))
+        } else {
+          Ok(Message::Auth(None))
+        }
+      },
+      MSG_QUERY_AWARENESS => Ok(Message::AwarenessQuery),
+      MSG_COMPACTION_EPOCH => Ok(Message::CompactionEpoch(decoder.read_var()?)),
+      tag => Ok(Message::Custom(tag, decoder.read_buf()?.to_vec())),
+    }
+  }
+}
+
+impl Encode for SyncMessage {
+  fn encode<E: Encoder>(&self, encoder: &mut E) {
+    match self {
+      SyncMessage::SyncStep1(sv) => {
+        encoder.write_var(MSG_SYNC_STEP_1);
+        encoder.write_buf(sv.encode_v1());
+      },
+      SyncMessage::SyncStep2(update) => {
+        encoder.write_var(MSG_SYNC_STEP_2);
+        encoder.write_buf(update);
+      },
+      SyncMessage::Update(update) => {
+        encoder.write_var(MSG_SYNC_UPDATE);
+        encoder.write_buf(update);
+      },
+    }
+  }
+}
+
+impl Decode for SyncMessage {
+  fn decode<D: Decoder>(decoder: &mut D) -> Result<Self, yrs::encoding::read::Error> {
+    let tag: u8 = decoder.read_var()?;
+    match tag {
+      MSG_SYNC_STEP_1 => {
+        let buf = decoder.read_buf()?;
+        Ok(SyncMessage::SyncStep1(StateVector::decode_v1(buf)?))
+      },
+      MSG_SYNC_STEP_2 => Ok(SyncMessage::SyncStep2(decoder.read_buf()?.to_vec())),
+      MSG_SYNC_UPDATE => Ok(SyncMessage::Update(decoder.read_buf()?.to_vec())),
+      _ => Err(yrs::encoding::read::Error::UnexpectedValue),
+    }
+  }
+}
+
+impl Display for Message {
+  fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Message::Sync(SyncMessage::SyncStep1(sv)) => write!(f, "SyncStep1({:?})", sv),
+      Message::Sync(SyncMessage::SyncStep2(update)) => {
+        write!(f, "SyncStep2({} bytes)", update.len())
+      },
+      Message::Sync(SyncMessage::Update(update)) => write!(f, "Update({} bytes)", update.len()),
+      Message::Auth(reason) => write!(f, "Auth({:?})", reason),
+      Message::AwarenessQuery => write!(f, "AwarenessQuery"),
+      Message::Awareness(update) => write!(f, "Awareness({})", update),
+      Message::CompactionEpoch(epoch) => write!(f, "CompactionEpoch({})", epoch),
+      Message::Custom(tag, payload) => write!(f, "Custom({}, {} bytes)", tag, payload.len()),
+    }
+  }
+}
+
+/// Decodes all the messages in the buffer. y-protocols allows to send several messages in one
+/// frame. The frame must end at a message boundary, a truncated message is a decode error.
+pub fn decode_messages(data: &[u8]) -> Result<Vec<Message>, SyncProtocolError> {
+  let mut decoder = DecoderV1::new(Cursor::new(data));
+  let mut messages = vec![];
+  while !decoder.read_to_end()?.is_empty() {
+    messages.push(Message::decode(&mut decoder)?);
+  }
+  Ok(messages)
+}
+
+/// Encodes the messages into a single frame. See [decode_messages].
+pub fn encode_messages(messages: &[Message]) -> Vec<u8> {
+  let mut encoder = EncoderV1::new();
+  for message in messages {
+    message.encode(&mut encoder);
+  }
+  encoder.to_vec()
+}
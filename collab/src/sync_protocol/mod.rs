@@ -0,0 +1,25 @@
+//! A transport-agnostic implementation of the Yjs sync protocol.
+//!
+//! The peers exchange their compaction epochs ([Message::CompactionEpoch]) and their state
+//! vectors ([SyncMessage::SyncStep1]), reply with the updates the other peer is missing
+//! ([SyncMessage::SyncStep2]) and then send every new update ([SyncMessage::Update]) and
+//! awareness change as they happen. Peers at different epochs don't exchange updates.
+//! [SyncProtocol] is the state machine of one peer and [run_sync_protocol] drives it over any
+//! [CollabConnect].
+
+use futures_util::{Sink, Stream};
+
+pub use error::*;
+pub use memory::*;
+pub use message::*;
+pub use protocol::*;
+
+mod error;
+mod memory;
+mod message;
+mod protocol;
+
+/// A bidirectional connection to a remote peer.
+pub trait CollabConnect<Item>: Sink<Item> + Stream {}
+
+impl<T, Item> CollabConnect<Item> for T where T: Sink<Item> + Stream {}
@@ -0,0 +1,246 @@
+use std::fmt::Display;
+
+use futures_util::future::ready;
+use futures_util::{stream, SinkExt, StreamExt};
+use tokio::sync::mpsc::{unbounded_channel, UnboundedSender};
+use tokio_stream::wrappers::UnboundedReceiverStream;
+use tracing::{error, trace};
+use yrs::{ReadTxn, StateVector, UpdateSubscription};
+
+use crate::core::awareness::{gen_awareness_update_message, AwarenessUpdateSubscription};
+use crate::core::collab::{Collab, MutexCollab};
+use crate::core::collab_state::SyncState;
+use crate::core::compaction::CompactionState;
+use crate::core::origin::CollabOrigin;
+use crate::error::CollabError;
+use crate::sync_protocol::{
+  decode_messages, encode_messages, CollabConnect, Message, SyncMessage, SyncProtocolError,
+};
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub enum SyncProtocolState {
+  /// The [SyncMessage::SyncStep2] of the remote peer hasn't been received yet.
+  Handshake,
+  /// The collab contains all the updates the remote peer had when the handshake started.
+  Synced,
+}
+
+/// The state machine of one peer of the sync protocol. It doesn't do any IO: the messages that
+/// must be sent to the remote peer are returned to the caller.
+pub struct SyncProtocol {
+  remote_origin: CollabOrigin,
+  state: SyncProtocolState,
+  /// Set by the [Message::CompactionEpoch] of the remote peer.
+  remote_epoch: Option<i64>,
+}
+
+impl SyncProtocol {
+  /// The updates of the remote peer are applied with the `remote_origin`, so they are checked
+  /// against the access policy of the collab and are not sent back to the remote peer.
+  pub fn new(remote_origin: CollabOrigin) -> Self {
+    Self {
+      remote_origin,
+      state: SyncProtocolState::Handshake,
+      remote_epoch: None,
+    }
+  }
+
+  pub fn remote_origin(&self) -> &CollabOrigin {
+    &self.remote_origin
+  }
+
+  pub fn state(&self) -> SyncProtocolState {
+    self.state
+  }
+
+  pub fn is_synced(&self) -> bool {
+    self.state == SyncProtocolState::Synced
+  }
+
+  /// Returns the messages that start the handshake: the compaction epoch and the state vector of
+  /// the collab, and the awareness states it knows about. A superseded collab can't sync.
+  pub fn start(&self, collab: &Collab) -> Result<Vec<Message>, SyncProtocolError> {
+    if let CompactionState::Superseded { epoch } = collab.get_compaction_state() {
+      return Err(CollabError::Superseded(epoch).into());
+    }
+    collab.set_sync_state(SyncState::InitSyncBegin);
+    let sv = collab.transact().state_vector();
+    let mut messages = vec![
+      Message::CompactionEpoch(collab.get_compaction_epoch()),
+      Message::Sync(SyncMessage::SyncStep1(sv)),
+    ];
+    let awareness = collab.get_awareness();
+    if !awareness.get_states().is_empty() {
+      messages.push(Message::Awareness(awareness.update()?));
+    }
+    Ok(messages)
+  }
+
+  /// Handles a message of the remote peer and returns the replies.
+  pub fn handle_message(
+    &mut self,
+    collab: &mut Collab,
+    message: Message,
+  ) -> Result<Vec<Message>, SyncProtocolError> {
+    trace!("[{}]: receive {}", self.remote_origin, message);
+    match message {
+      Message::CompactionEpoch(epoch) => {
+        collab.check_remote_epoch(epoch)?;
+        self.remote_epoch = Some(epoch);
+        Ok(vec![])
+      },
+      Message::Sync(SyncMessage::SyncStep1(sv)) => {
+        self.check_remote_epoch(collab)?;
+        let update = self.handle_sync_step1(collab, &sv);
+        Ok(vec![Message::Sync(SyncMessage::SyncStep2(update))])
+      },
+      Message::Sync(SyncMessage::SyncStep2(update)) => {
+        self.check_remote_epoch(collab)?;
+        collab.apply_remote_update(&self.remote_origin, &update)?;
+        if self.state == SyncProtocolState::Handshake {
+          self.state = SyncProtocolState::Synced;
+          collab.set_sync_state(SyncState::InitSyncEnd);
+        }
+        Ok(vec![])
+      },
+      Message::Sync(SyncMessage::Update(update)) => {
+        self.check_remote_epoch(collab)?;
+        collab.apply_remote_update(&self.remote_origin, &update)?;
+        Ok(vec![])
+      },
+      Message::Awareness(update) => {
+        collab.get_mut_awareness().apply_update(update)?;
+        Ok(vec![])
+      },
+      Message::AwarenessQuery => {
+        let update = collab.get_awareness().update()?;
+        Ok(vec![Message::Awareness(update)])
+      },
+      Message::Auth(Some(reason)) => Err(SyncProtocolError::PermissionDenied(reason)),
+      Message::Auth(None) => Ok(vec![]),
+      Message::Custom(tag, _) => {
+        trace!("[{}]: ignore custom message {}", self.remote_origin, tag);
+        Ok(vec![])
+      },
+    }
+  }
+
+  /// A remote peer that didn't send its epoch is a plain Yjs client, which never compacts, so it
+  /// counts as epoch 0.
+  fn check_remote_epoch(&self, collab: &Collab) -> Result<(), SyncProtocolError> {
+    collab.check_remote_epoch(self.remote_epoch.unwrap_or(0))?;
+    Ok(())
+  }
+
+  fn handle_sync_step1(&self, collab: &Collab, sv: &StateVector) -> Vec<u8> {
+    collab.transact().encode_state_as_update_v1(sv)
+  }
+}
+
+/// Runs the sync protocol over the connection until the remote peer closes it. The local
+/// updates and awareness changes of the collab are sent to the remote peer as they happen.
+///
+/// Each item of the connection is one frame of encoded [Message]s, see [encode_messages].
+pub async fn run_sync_protocol<C, E>(
+  collab: MutexCollab,
+  remote_origin: CollabOrigin,
+  connect: C,
+) -> Result<(), SyncProtocolError>
+where
+  C: CollabConnect<Vec<u8>, Item = Result<Vec<u8>, E>>,
+  <C as futures_util::Sink<Vec<u8>>>::Error: Display,
+  E: Display,
+{
+  let mut protocol = SyncProtocol::new(remote_origin);
+  let (tx, rx) = unbounded_channel();
+  let _subscriptions = LocalChangeSubscriptions::new(&collab, protocol.remote_origin(), tx);
+  let (mut sink, stream) = connect.split();
+
+  let messages = protocol.start(&collab.lock())?;
+  send_messages(&mut sink, &messages).await?;
+
+  let incoming = stream
+    .map(ProtocolEvent::Incoming)
+    .chain(stream::once(ready(ProtocolEvent::Closed)));
+  let outgoing = UnboundedReceiverStream::new(rx).map(ProtocolEvent::Outgoing);
+  let mut events = stream::select(incoming, outgoing);
+  while let Some(event) = events.next().await {
+    let replies = match event {
+      ProtocolEvent::Incoming(Ok(data)) => {
+        let mut replies = vec![];
+        for message in decode_messages(&data)? {
+          let mut collab = collab.lock();
+          replies.extend(protocol.handle_message(&mut collab, message)?);
+        }
+        replies
+      },
+      ProtocolEvent::Incoming(Err(err)) => {
+        return Err(SyncProtocolError::Transport(err.to_string()));
+      },
+      ProtocolEvent::Outgoing(message) => vec![message],
+      ProtocolEvent::Closed => break,
+    };
+    if !replies.is_empty() {
+      send_messages(&mut sink, &replies).await?;
+    }
+  }
+  Ok(())
+}
+
+async fn send_messages<S>(sink: &mut S, messages: &[Message]) -> Result<(), SyncProtocolError>
+where
+  S: futures_util::Sink<Vec<u8>> + Unpin,
+  S::Error: Display,
+{
+  sink
+    .send(encode_messages(messages))
+    .await
+    .map_err(|err| SyncProtocolError::Transport(err.to_string()))
+}
+
+enum ProtocolEvent<T> {
+  Incoming(T),
+  Outgoing(Message),
+  Closed,
+}
+
+/// Forwards the local updates and the awareness changes of the collab to the channel. The
+/// updates that were applied with the remote origin are not forwarded, so they don't go back to
+/// the peer they came from.
+struct LocalChangeSubscriptions {
+  #[allow(dead_code)]
+  update: Option<UpdateSubscription>,
+  #[allow(dead_code)]
+  awareness: AwarenessUpdateSubscription,
+}
+
+impl LocalChangeSubscriptions {
+  fn new(collab: &MutexCollab, remote_origin: &CollabOrigin, tx: UnboundedSender<Message>) -> Self {
+    let mut collab = collab.lock();
+    let remote_origin = remote_origin.clone();
+    let update_tx = tx.clone();
+    let update = collab
+      .get_doc()
+      .observe_update_v1(move |txn, event| {
+        if CollabOrigin::from(txn) != remote_origin {
+          let message = Message::Sync(SyncMessage::Update(event.update.clone()));
+          let _ = update_tx.send(message);
+        }
+      })
+      .map_err(|err| error!("Failed to observe the updates of the collab: {:?}", err))
+      .ok();
+    let awareness = collab.observe_awareness(move |awareness, event| {
+      match gen_awareness_update_message(awareness, event) {
+        Ok(update) => {
+          let _ = tx.send(Message::Awareness(update));
+        },
+        Err(err) => error!("Failed to encode the awareness update: {}", err),
+      }
+    });
+    Self { update, awareness }
+  }
+}
+
+unsafe impl Send for LocalChangeSubscriptions {}
+
+unsafe impl Sync for LocalChangeSubscriptions {}
@@ -0,0 +1,228 @@
+use collab::core::access_control::{AccessPolicy, ViolationAction, ViolationReason};
+use collab::core::collab::{CollabBuilder, MutexCollab, DATA_SECTION};
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::error::CollabError;
+use collab::preclude::MapRefExtension;
+use serde_json::json;
+use yrs::ReadTxn;
+
+fn make_collab(uid: i64, policy: AccessPolicy) -> MutexCollab {
+  let collab = CollabBuilder::new(uid, "1")
+    .with_device_id(uid.to_string())
+    .with_access_policy(policy)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  collab
+}
+
+/// Returns the update that makes `to` catch up with `from`.
+fn make_update(from: &MutexCollab, to: &MutexCollab) -> Vec<u8> {
+  let sv = to.lock().transact().state_vector();
+  from.lock().transact().encode_state_as_update_v1(&sv)
+}
+
+fn client_origin(uid: i64) -> CollabOrigin {
+  CollabOrigin::Client(CollabClient::new(uid, uid.to_string()))
+}
+
+#[tokio::test]
+async fn revert_local_change_of_read_only_user_test() {
+  let collab = make_collab(1, AccessPolicy::new());
+  collab.lock().insert("title", "hello");
+
+  collab
+    .lock()
+    .set_access_policy(AccessPolicy::new().with_read_only_user(1));
+  let mut rx = collab.lock().subscribe_access_violation();
+  collab.lock().insert("title", "world");
+  collab.lock().insert("name", "appflowy");
+
+  assert_eq!(
+    collab.to_json_value(),
+    json!({
+      "title": "hello"
+    })
+  );
+  let event = rx.recv().await.unwrap();
+  assert_eq!(event.action, ViolationAction::Reverted);
+  assert_eq!(event.violation.reason, ViolationReason::ReadOnly);
+}
+
+#[tokio::test]
+async fn reject_remote_update_of_protected_path_test() {
+  let policy = AccessPolicy::new().with_protected_path([DATA_SECTION, "fields"], [1]);
+  let collab = make_collab(1, policy);
+  let mut rx = collab.lock().subscribe_access_violation();
+
+  let remote = make_collab(2, AccessPolicy::new());
+  remote.lock().insert("fields", "f1");
+  remote.lock().insert("rows", "r1");
+  let update = make_update(&remote, &collab);
+
+  let result = collab
+    .lock()
+    .apply_remote_update(&client_origin(2), &update);
+  assert!(matches!(result, Err(CollabError::AccessDenied(_))));
+  assert_eq!(collab.to_json_value(), json!({}));
+
+  let event = rx.recv().await.unwrap();
+  assert_eq!(event.action, ViolationAction::Rejected);
+  assert_eq!(
+    event.violation.reason,
+    ViolationReason::ProtectedPath(vec!["data".to_string(), "fields".to_string()])
+  );
+
+  // The writer of the protected path and the server are allowed to change it.
+  collab
+    .lock()
+    .apply_remote_update(&client_origin(1), &update)
+    .unwrap();
+  assert_eq!(
+    collab.to_json_value(),
+    json!({
+      "fields": "f1",
+      "rows": "r1"
+    })
+  );
+  remote.lock().insert("fields", "f2");
+  let update = make_update(&remote, &collab);
+  collab
+    .lock()
+    .apply_remote_update(&CollabOrigin::Server, &update)
+    .unwrap();
+  assert_eq!(collab.to_json_value()["fields"], json!("f2"));
+}
+
+#[tokio::test]
+async fn allow_remote_update_outside_protected_path_test() {
+  let policy = AccessPolicy::new().with_protected_path([DATA_SECTION, "fields"], [1]);
+  let collab = make_collab(1, policy);
+  let remote = make_collab(2, AccessPolicy::new());
+  remote.lock().insert("rows", "r1");
+
+  let update = make_update(&remote, &collab);
+  collab
+    .lock()
+    .apply_remote_update(&client_origin(2), &update)
+    .unwrap();
+  assert_eq!(collab.to_json_value(), json!({ "rows": "r1" }));
+}
+
+#[tokio::test]
+async fn reject_remote_update_without_author_test() {
+  let policy = AccessPolicy::new().with_read_only_user(2);
+  let collab = make_collab(1, policy);
+  let remote = make_collab(3, AccessPolicy::new());
+  remote.lock().insert("rows", "r1");
+  let update = make_update(&remote, &collab);
+
+  // The update could be made by the read-only user.
+  let result = collab
+    .lock()
+    .apply_remote_update(&CollabOrigin::Empty, &update);
+  assert!(matches!(result, Err(CollabError::AccessDenied(_))));
+  assert_eq!(collab.to_json_value(), json!({}));
+
+  // The updates relayed by the server are trusted.
+  collab
+    .lock()
+    .apply_remote_update(&CollabOrigin::Server, &update)
+    .unwrap();
+  assert_eq!(collab.to_json_value(), json!({ "rows": "r1" }));
+}
+
+/// Returns a collab of the user that has the same state as `from`.
+fn make_synced_collab(uid: i64, from: &MutexCollab) -> MutexCollab {
+  let collab = make_collab(uid, AccessPolicy::new());
+  let update = make_update(from, &collab);
+  collab
+    .lock()
+    .apply_remote_update(&CollabOrigin::Server, &update)
+    .unwrap();
+  collab
+}
+
+#[tokio::test]
+async fn reject_remote_replacement_of_protected_path_test() {
+  let policy = AccessPolicy::new().with_protected_path([DATA_SECTION, "fields"], [1]);
+  let collab = make_collab(1, policy);
+  collab.lock().insert("fields", "f1");
+  collab.lock().insert("rows", "r1");
+  let remote = make_synced_collab(2, &collab);
+
+  // A replaced value doesn't carry its key in the update, the key is taken from the value it
+  // replaces.
+  remote.lock().insert("rows", "r2");
+  let update = make_update(&remote, &collab);
+  collab
+    .lock()
+    .apply_remote_update(&client_origin(2), &update)
+    .unwrap();
+
+  remote.lock().insert("fields", "f2");
+  let update = make_update(&remote, &collab);
+  let result = collab
+    .lock()
+    .apply_remote_update(&client_origin(2), &update);
+  assert!(matches!(result, Err(CollabError::AccessDenied(_))));
+  assert_eq!(
+    collab.to_json_value(),
+    json!({
+      "fields": "f1",
+      "rows": "r2"
+    })
+  );
+}
+
+#[tokio::test]
+async fn reject_remote_removal_of_protected_path_test() {
+  let policy = AccessPolicy::new().with_protected_path([DATA_SECTION, "fields"], [1]);
+  let collab = make_collab(1, policy);
+  collab.lock().insert("fields", "f1");
+  let remote = make_synced_collab(2, &collab);
+
+  remote.lock().remove("fields");
+  let update = make_update(&remote, &collab);
+  let result = collab
+    .lock()
+    .apply_remote_update(&client_origin(2), &update);
+  assert!(matches!(result, Err(CollabError::AccessDenied(_))));
+  assert_eq!(collab.to_json_value(), json!({ "fields": "f1" }));
+}
+
+#[tokio::test]
+async fn reject_remote_update_below_protected_path_test() {
+  let policy = AccessPolicy::new().with_protected_path([DATA_SECTION, "fields"], [1]);
+  let collab = make_collab(1, policy);
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let fields = collab.insert_map_with_txn(txn, "fields");
+        let f1 = fields.create_map_with_txn(txn, "f1");
+        f1.insert_str_with_txn(txn, "name", "first");
+      })
+      .unwrap();
+  }
+  let remote = make_synced_collab(2, &collab);
+  {
+    let remote = remote.lock();
+    remote
+      .with_origin_transact_mut(|txn| {
+        let f1 = remote.get_map_with_txn(txn, vec!["fields", "f1"]).unwrap();
+        f1.insert_str_with_txn(txn, "name", "renamed");
+      })
+      .unwrap();
+  }
+
+  let update = make_update(&remote, &collab);
+  let result = collab
+    .lock()
+    .apply_remote_update(&client_origin(2), &update);
+  assert!(matches!(result, Err(CollabError::AccessDenied(_))));
+  assert_eq!(
+    collab.to_json_value()["fields"]["f1"]["name"],
+    json!("first")
+  );
+}
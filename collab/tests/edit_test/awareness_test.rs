@@ -1,5 +1,6 @@
-use collab::core::awareness::gen_awareness_update_message;
-use collab::preclude::Collab;
+use collab::core::awareness::{gen_awareness_update_message, OUTDATED_TIMEOUT_MILLIS};
+use collab::core::presence::{Presence, PresenceSelection, PresenceUser};
+use collab::preclude::{Collab, Text, Transact};
 use serde_json::json;
 use std::collections::HashMap;
 use std::sync::mpsc;
@@ -88,3 +89,84 @@ async fn clean_awareness_state_sync_test() {
   let states = collab_b.get_awareness().get_states();
   assert_eq!(states.len(), 1);
 }
+
+#[tokio::test]
+async fn presence_sync_test() {
+  let mut collab_a = Collab::new(1, "1", "1", vec![]);
+  let (tx, rx) = mpsc::sync_channel(1);
+  let _update = collab_a.observe_awareness(move |awareness, event| {
+    let update = gen_awareness_update_message(awareness, event).unwrap();
+    tx.send(update).unwrap();
+  });
+
+  let presence = Presence::new(1)
+    .with_user(PresenceUser::new(1, "nathan"))
+    .with_viewing("doc_1");
+  collab_a
+    .get_mut_awareness()
+    .set_local_presence(&presence)
+    .unwrap();
+
+  let mut collab_b = Collab::new(2, "1", "2", vec![]);
+  collab_b
+    .get_mut_awareness()
+    .apply_update(rx.recv().unwrap())
+    .unwrap();
+  let client_id = collab_a.get_doc().client_id();
+  assert_eq!(
+    collab_b.get_awareness().get_presence(client_id).unwrap(),
+    presence
+  );
+  // The initial state of collab_b only contains the uid, which is a valid presence too.
+  assert_eq!(collab_b.get_awareness().get_presences().len(), 2);
+}
+
+#[tokio::test]
+async fn presence_selection_follows_text_edits_test() {
+  let collab = Collab::new(1, "1", "1", vec![]);
+  let text = collab.get_doc().get_or_insert_text("text");
+  let selection = {
+    let mut txn = collab.get_doc().transact_mut();
+    text.insert(&mut txn, 0, "hello world");
+    PresenceSelection::new(&mut txn, "text", &text, 6, 11).unwrap()
+  };
+  assert!(!selection.is_cursor());
+
+  text.insert(&mut collab.get_doc().transact_mut(), 0, ">> ");
+  let txn = collab.get_doc().transact();
+  assert_eq!(selection.resolve(&txn), Some((9, 14)));
+}
+
+#[tokio::test]
+async fn awareness_heartbeat_test() {
+  let mut collab_a = Collab::new(1, "1", "1", vec![]);
+  let (tx, rx) = mpsc::sync_channel(10);
+  let _update = collab_a.observe_awareness(move |awareness, event| {
+    let update = gen_awareness_update_message(awareness, event).unwrap();
+    tx.send(update).unwrap();
+  });
+  collab_a.emit_awareness_state();
+
+  let mut collab_b = Collab::new(2, "1", "2", vec![]);
+  collab_b
+    .get_mut_awareness()
+    .apply_update(rx.recv().unwrap())
+    .unwrap();
+  assert_eq!(collab_b.get_awareness().get_states().len(), 2);
+
+  // collab_a renews its state after half of the timeout
+  let now = chrono::Utc::now().timestamp_millis();
+  assert!(collab_a
+    .get_mut_awareness()
+    .check_outdated_at(now + OUTDATED_TIMEOUT_MILLIS / 2)
+    .is_empty());
+  assert!(rx.try_recv().is_ok());
+
+  // collab_b removes collab_a once the timeout elapsed, but keeps its own state
+  let removed = collab_b
+    .get_mut_awareness()
+    .check_outdated_at(now + OUTDATED_TIMEOUT_MILLIS + 1);
+  assert_eq!(removed, vec![collab_a.get_doc().client_id()]);
+  assert_eq!(collab_b.get_awareness().get_states().len(), 1);
+  assert!(collab_b.get_awareness().get_local_state().is_some());
+}
@@ -0,0 +1,197 @@
+use collab::core::collab::{CollabBuilder, MutexCollab};
+use collab::core::compaction::CompactionState;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::error::CollabError;
+use collab::preclude::{Collab, ReadTxn, StateVector, Text, Transact, Update};
+use collab::sync_protocol::{SyncProtocol, SyncProtocolError};
+use serde_json::json;
+use tokio_stream::StreamExt;
+use yrs::updates::decoder::Decode;
+
+fn make_collab(uid: i64) -> MutexCollab {
+  let collab = CollabBuilder::new(uid, "1")
+    .with_device_id(uid.to_string())
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  collab
+}
+
+fn client_origin(uid: i64) -> CollabOrigin {
+  CollabOrigin::Client(CollabClient::new(uid, uid.to_string()))
+}
+
+#[tokio::test]
+async fn compact_collab_test() {
+  let collab = make_collab(1);
+  for i in 0..100 {
+    collab
+      .lock()
+      .insert(&format!("key_{}", i), "a deleted value");
+    collab.lock().remove(&format!("key_{}", i));
+  }
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let map = collab.insert_map_with_txn(txn, "document");
+        map.insert_with_txn(txn, "name", "appflowy");
+        let text = map.insert_text_with_txn(txn, "text");
+        text.insert(txn, 0, "hello world");
+        text.remove_range(txn, 0, 6);
+      })
+      .unwrap();
+  }
+  let old_size = collab.encode_collab_v1().doc_state.len();
+
+  let compacted = collab.lock().compact().unwrap();
+  assert_eq!(compacted.epoch, 1);
+  assert!(compacted.encoded_collab.doc_state.len() < old_size);
+  assert_eq!(
+    collab.lock().get_compaction_state(),
+    CompactionState::Superseded { epoch: 1 }
+  );
+
+  let new_collab = Collab::new_with_doc_state(
+    client_origin(1),
+    "1",
+    compacted.encoded_collab.doc_state.to_vec(),
+    vec![],
+  )
+  .unwrap();
+  assert_eq!(
+    new_collab.get_compaction_state(),
+    CompactionState::Current { epoch: 1 }
+  );
+  assert_eq!(
+    new_collab.to_json_value(),
+    json!({
+      "document": {
+        "name": "appflowy",
+        "text": "world"
+      }
+    })
+  );
+
+  // The compacted document can be compacted again.
+  let compacted = new_collab.compact().unwrap();
+  assert_eq!(compacted.epoch, 2);
+}
+
+#[tokio::test]
+async fn superseded_collab_rejects_remote_update_test() {
+  let collab_1 = make_collab(1);
+  let collab_2 = make_collab(2);
+  collab_1.lock().insert("title", "hello");
+  let update = collab_1
+    .lock()
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  collab_2
+    .lock()
+    .apply_remote_update(&client_origin(1), &update)
+    .unwrap();
+
+  // Device 1 compacts the collab. The mark reaches device 2 with the next update.
+  let sv = collab_2.lock().transact().state_vector();
+  collab_1.lock().compact().unwrap();
+  let update = collab_1.lock().transact().encode_state_as_update_v1(&sv);
+  collab_2
+    .lock()
+    .apply_remote_update(&client_origin(1), &update)
+    .unwrap();
+  assert!(collab_2.lock().get_compaction_state().is_superseded());
+
+  collab_1.lock().insert("title", "world");
+  let update = collab_1
+    .lock()
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  let result = collab_2
+    .lock()
+    .apply_remote_update(&client_origin(1), &update);
+  assert!(matches!(result, Err(CollabError::Superseded(1))));
+}
+
+#[tokio::test]
+async fn superseded_mark_applied_outside_collab_test() {
+  let collab_1 = make_collab(1);
+  let collab_2 = make_collab(2);
+  let mut states = collab_2.lock().subscribe_compaction_state();
+  assert_eq!(
+    states.next().await.unwrap(),
+    CompactionState::Current { epoch: 0 }
+  );
+
+  // Plugins apply the updates they receive directly to the document.
+  collab_1.lock().compact().unwrap();
+  let update = collab_1
+    .lock()
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  collab_2
+    .lock()
+    .get_doc()
+    .transact_mut()
+    .apply_update(Update::decode_v1(&update).unwrap());
+  assert_eq!(
+    states.next().await.unwrap(),
+    CompactionState::Superseded { epoch: 1 }
+  );
+}
+
+#[test]
+fn sync_protocol_rejects_peer_at_other_epoch_test() {
+  let collab_1 = make_collab(1);
+  let collab_2 = make_collab(2);
+  collab_1.lock().insert("title", "hello");
+  let update = collab_1
+    .lock()
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  collab_2
+    .lock()
+    .apply_remote_update(&client_origin(1), &update)
+    .unwrap();
+
+  // Device 2 goes offline before the superseded mark reaches it.
+  let compacted = collab_1.lock().compact().unwrap();
+  let mut new_collab = Collab::new_with_doc_state(
+    client_origin(1),
+    "1",
+    compacted.encoded_collab.doc_state.to_vec(),
+    vec![],
+  )
+  .unwrap();
+  let mut protocol_1 = SyncProtocol::new(client_origin(2));
+  let mut protocol_2 = SyncProtocol::new(client_origin(1));
+  let to_2 = protocol_1.start(&new_collab).unwrap();
+  let to_1 = protocol_2.start(&collab_2.lock()).unwrap();
+
+  // The stale history of device 2 is not merged into the compacted document.
+  let result = to_1.into_iter().try_for_each(|message| {
+    protocol_1
+      .handle_message(&mut new_collab, message)
+      .map(|_| ())
+  });
+  assert!(matches!(
+    result,
+    Err(SyncProtocolError::Collab(CollabError::StaleEpoch {
+      remote: 0,
+      epoch: 1
+    }))
+  ));
+  assert_eq!(new_collab.to_json_value(), json!({ "title": "hello" }));
+
+  // Device 2 learns that its collab was compacted.
+  let result = to_2.into_iter().try_for_each(|message| {
+    protocol_2
+      .handle_message(&mut collab_2.lock(), message)
+      .map(|_| ())
+  });
+  assert!(matches!(
+    result,
+    Err(SyncProtocolError::Collab(CollabError::Superseded(1)))
+  ));
+  assert!(collab_2.lock().get_compaction_state().is_superseded());
+}
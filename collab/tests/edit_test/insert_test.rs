@@ -1,4 +1,3 @@
-use std::sync::Arc;
 use std::time::Duration;
 
 use collab::core::any_map::{AnyMap, AnyMapExtension};
@@ -98,7 +97,7 @@ async fn remove_value() {
 #[tokio::test]
 async fn retry_write_txn_success_test() {
   setup_log();
-  let collab = Arc::new(Collab::new(1, "1", "1", vec![]));
+  let collab = Collab::new(1, "1", "1", vec![]);
   let doc = collab.get_doc().clone();
   let txn = TransactionRetry::new(&doc).get_write_txn_with(CollabOrigin::Empty);
 
@@ -121,7 +120,7 @@ async fn retry_write_txn_success_test() {
 #[should_panic]
 async fn retry_write_txn_fail_test() {
   setup_log();
-  let collab = Arc::new(Collab::new(1, "1", "1", vec![]));
+  let collab = Collab::new(1, "1", "1", vec![]);
   let doc = collab.get_doc().clone();
   let _txn = TransactionRetry::new(&doc).get_write_txn_with(CollabOrigin::Empty);
 
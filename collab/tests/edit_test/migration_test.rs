@@ -0,0 +1,125 @@
+use collab::core::collab::{CollabBuilder, MutexCollab};
+use collab::core::migration::{CollabMigrator, Migration};
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::error::CollabError;
+use collab::preclude::{MapRefExtension, ReadTxn};
+use serde_json::json;
+
+fn make_collab(uid: i64, migrator: Option<CollabMigrator>) -> MutexCollab {
+  let mut builder = CollabBuilder::new(uid, "1").with_device_id(uid.to_string());
+  if let Some(migrator) = migrator {
+    builder = builder.with_migrator(migrator);
+  }
+  let collab = builder.build().unwrap();
+  collab.lock().initialize();
+  collab
+}
+
+fn sync(from: &MutexCollab, to: &MutexCollab, from_uid: i64) {
+  let sv = to.lock().transact().state_vector();
+  let update = from.lock().transact().encode_state_as_update_v1(&sv);
+  let origin = CollabOrigin::Client(CollabClient::new(from_uid, from_uid.to_string()));
+  to.lock().apply_remote_update(&origin, &update).unwrap();
+}
+
+/// Copies the `title` to the `name` and adds a `views` map.
+fn test_migrator() -> CollabMigrator {
+  CollabMigrator::new()
+    .with_migration(Migration::new(2, "add views", |doc, txn| {
+      doc.data.create_map_if_not_exist_with_txn(txn, "views");
+      Ok(())
+    }))
+    .with_migration(Migration::new(1, "copy title", |doc, txn| {
+      if doc.data.get_str_with_txn(txn, "name").is_none() {
+        if let Some(title) = doc.data.get_str_with_txn(txn, "title") {
+          doc.data.insert_str_with_txn(txn, "name", title);
+        }
+      }
+      Ok(())
+    }))
+}
+
+#[tokio::test]
+async fn run_migrations_in_order_test() {
+  let collab = make_collab(1, None);
+  collab.lock().insert("title", "hello");
+  assert_eq!(collab.lock().get_schema_version(), 0);
+
+  let migrator = test_migrator();
+  assert_eq!(migrator.latest_version(), 2);
+  let report = collab.lock().run_migrations(&migrator).unwrap();
+  assert_eq!(report.from_version, 0);
+  assert_eq!(report.to_version, 2);
+  let names = report
+    .applied
+    .iter()
+    .map(|m| m.name.as_str())
+    .collect::<Vec<_>>();
+  assert_eq!(names, vec!["copy title", "add views"]);
+  assert_eq!(
+    collab.to_json_value(),
+    json!({
+      "name": "hello",
+      "title": "hello",
+      "views": {}
+    })
+  );
+
+  // Migrations that were applied don't run again.
+  let report = collab.lock().run_migrations(&migrator).unwrap();
+  assert!(report.is_empty());
+  assert_eq!(report.to_version, 2);
+}
+
+#[tokio::test]
+async fn run_migrations_when_collab_opens_test() {
+  let collab = make_collab(1, Some(test_migrator()));
+  let collab = collab.lock();
+  let report = collab.get_migration_report().unwrap();
+  assert_eq!(report.applied.len(), 2);
+  assert_eq!(collab.get_schema_version(), 2);
+}
+
+#[tokio::test]
+async fn concurrent_migrations_test() {
+  let collab_1 = make_collab(1, None);
+  collab_1.lock().insert("title", "hello");
+  let collab_2 = make_collab(2, None);
+  sync(&collab_1, &collab_2, 1);
+
+  // Both devices migrate the same document before they see each other's changes.
+  let migrator = test_migrator();
+  collab_1.lock().run_migrations(&migrator).unwrap();
+  collab_2.lock().run_migrations(&migrator).unwrap();
+  sync(&collab_1, &collab_2, 1);
+  sync(&collab_2, &collab_1, 2);
+
+  for collab in [&collab_1, &collab_2] {
+    assert_eq!(collab.lock().get_schema_version(), 2);
+    assert_eq!(
+      collab.to_json_value(),
+      json!({ "name": "hello", "title": "hello", "views": {} })
+    );
+    assert!(collab.lock().run_migrations(&migrator).unwrap().is_empty());
+  }
+}
+
+#[tokio::test]
+async fn failed_migration_stops_the_runner_test() {
+  let collab = make_collab(1, None);
+  let migrator = CollabMigrator::new()
+    .with_migration(Migration::new(1, "ok", |_, _| Ok(())))
+    .with_migration(Migration::new(2, "fail", |doc, txn| {
+      doc.data.insert_str_with_txn(txn, "title", "half done");
+      Err(CollabError::UnexpectedEmpty)
+    }))
+    .with_migration(Migration::new(3, "never", |_, _| Ok(())));
+  let result = collab.lock().run_migrations(&migrator);
+  assert!(matches!(
+    result,
+    Err(CollabError::MigrationFailed { version: 2, .. })
+  ));
+  assert_eq!(collab.lock().get_schema_version(), 1);
+  // The changes of the failed migration are not applied.
+  assert_eq!(collab.to_json_value(), json!({}));
+}
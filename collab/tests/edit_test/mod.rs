@@ -1,5 +1,17 @@
+mod access_control_test;
 mod awareness_test;
+mod compaction_test;
 mod insert_test;
+mod migration_test;
 mod observer_test;
+mod plugin_pipeline_test;
+mod plugin_test;
+mod read_only_test;
 mod restore_test;
+mod search_test;
 mod state_vec_test;
+mod subdoc_test;
+mod sync_protocol_test;
+mod transaction_hook_test;
+mod undo_test;
+mod updates_test;
@@ -0,0 +1,224 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use collab::core::awareness::{AwarenessUpdate, Event};
+use collab::core::collab::{CollabBuilder, DATA_SECTION};
+use collab::core::collab_plugin::{CollabPlugin, EncodedCollab};
+use collab::core::origin::CollabOrigin;
+use collab::core::plugin_pipeline::AsyncCollabPlugin;
+use parking_lot::Mutex;
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, Map, Transact, Update};
+
+type EventLog = Arc<Mutex<Vec<String>>>;
+
+struct SlowPlugin {
+  delay: Duration,
+  events: EventLog,
+}
+
+impl SlowPlugin {
+  fn new(delay_ms: u64, events: &EventLog) -> Self {
+    Self {
+      delay: Duration::from_millis(delay_ms),
+      events: events.clone(),
+    }
+  }
+}
+
+#[async_trait]
+impl AsyncCollabPlugin for SlowPlugin {
+  async fn receive_update(&self, _object_id: &str, update: &[u8]) {
+    tokio::time::sleep(self.delay).await;
+    self.events.lock().push(format!("update:{}", update.len()));
+  }
+
+  async fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, _update: &[u8]) {
+    self.events.lock().push("local".to_string());
+  }
+
+  async fn flush(&self, _object_id: &str, _encoded_collab: &EncodedCollab) {
+    tokio::time::sleep(self.delay).await;
+    self.events.lock().push("flush".to_string());
+  }
+}
+
+#[tokio::test]
+async fn async_plugin_receive_updates_in_order_test() {
+  let events = EventLog::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_async_plugin(SlowPlugin::new(20, &events))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  collab.lock().insert("1", "a");
+  collab.lock().insert("2", "bb");
+  collab.lock().insert("3", "ccc");
+  assert_eq!(collab.lock().async_plugins().pending(), 3);
+
+  let flush = collab.lock().flush();
+  flush.await;
+
+  let events = events.lock().clone();
+  assert_eq!(events.len(), 7);
+  assert_eq!(events.last().unwrap(), "flush");
+  for pair in events[..6].chunks(2) {
+    assert!(pair[0].starts_with("update:"));
+    assert_eq!(pair[1], "local");
+  }
+  assert_eq!(collab.lock().async_plugins().pending(), 0);
+}
+
+#[tokio::test]
+async fn flush_without_async_plugin_test() {
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  collab.lock().insert("1", "a");
+
+  let flush = collab.lock().flush();
+  flush.await;
+  assert_eq!(collab.lock().async_plugins().pending(), 0);
+}
+
+#[tokio::test]
+async fn async_plugin_backpressure_test() {
+  let events = EventLog::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_async_plugin(SlowPlugin::new(10, &events))
+    .with_plugin_queue_capacity(2)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  let pipeline = collab.lock().async_plugins().clone();
+  collab.lock().insert("1", "a");
+  assert!(!pipeline.is_backpressured());
+  collab.lock().insert("2", "b");
+  assert!(pipeline.is_backpressured());
+
+  pipeline.ready().await;
+  assert!(pipeline.pending() < 2);
+  assert!(!events.lock().is_empty());
+}
+
+struct RecordUpdatesPlugin {
+  updates: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+#[async_trait]
+impl AsyncCollabPlugin for RecordUpdatesPlugin {
+  async fn receive_update(&self, _object_id: &str, update: &[u8]) {
+    self.updates.lock().push(update.to_vec());
+  }
+}
+
+#[tokio::test]
+async fn overflowing_updates_are_merged_test() {
+  let updates = Arc::new(Mutex::new(vec![]));
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_async_plugin(RecordUpdatesPlugin {
+      updates: updates.clone(),
+    })
+    .with_plugin_queue_capacity(2)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  for i in 0..20 {
+    collab.lock().insert(&i.to_string(), i);
+  }
+  // Two updates in the channel and the rest merged into one.
+  assert_eq!(collab.lock().async_plugins().pending(), 3);
+
+  let flush = collab.lock().flush();
+  flush.await;
+  let updates = updates.lock().clone();
+  assert_eq!(updates.len(), 3);
+
+  let doc = Doc::new();
+  {
+    let mut txn = doc.transact_mut();
+    for update in updates {
+      txn.apply_update(Update::decode_v1(&update).unwrap());
+    }
+  }
+  let map = doc.get_or_insert_map(DATA_SECTION);
+  let txn = doc.transact();
+  assert_eq!(map.len(&txn), 20);
+  assert!((0..20).all(|i| map.contains_key(&txn, &i.to_string())));
+}
+
+/// A plugin that writes its updates on the plugin pipeline.
+struct PipelinePlugin {
+  updates: Arc<Mutex<Vec<Vec<u8>>>>,
+}
+
+impl CollabPlugin for PipelinePlugin {
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+
+  fn pipeline_plugin(&self) -> Option<Arc<dyn AsyncCollabPlugin>> {
+    Some(Arc::new(RecordUpdatesPlugin {
+      updates: self.updates.clone(),
+    }))
+  }
+}
+
+#[tokio::test]
+async fn pipeline_plugin_is_added_with_its_plugin_test() {
+  let built_updates = Arc::new(Mutex::new(vec![]));
+  let built = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(PipelinePlugin {
+      updates: built_updates.clone(),
+    })
+    .build()
+    .unwrap();
+  built.lock().initialize();
+
+  let added_updates = Arc::new(Mutex::new(vec![]));
+  let added = CollabBuilder::new(1, "2")
+    .with_device_id("1")
+    .build()
+    .unwrap();
+  added
+    .lock()
+    .add_plugin(Box::new(PipelinePlugin {
+      updates: added_updates.clone(),
+    }))
+    .unwrap();
+  added.lock().initialize();
+
+  for collab in [&built, &added] {
+    collab.lock().insert("1", "a");
+    let flush = collab.lock().flush();
+    flush.await;
+  }
+  assert_eq!(built_updates.lock().len(), 1);
+  assert_eq!(added_updates.lock().len(), 1);
+}
+
+#[test]
+fn pipeline_plugin_without_runtime_test() {
+  let result = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(PipelinePlugin {
+      updates: Default::default(),
+    })
+    .build();
+  assert!(result.is_err());
+}
@@ -0,0 +1,177 @@
+use std::sync::Arc;
+
+use collab::core::awareness::{AwarenessUpdate, Event};
+use collab::core::collab::{CollabBuilder, MutexCollab, DEFAULT_PLUGIN_PRIORITY};
+use collab::core::collab_plugin::CollabPluginType;
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::CollabPlugin;
+use parking_lot::Mutex;
+use yrs::TransactionMut;
+
+type CallLog = Arc<Mutex<Vec<String>>>;
+
+struct RecordPlugin {
+  name: String,
+  plugin_type: CollabPluginType,
+  calls: CallLog,
+}
+
+impl RecordPlugin {
+  fn new(name: &str, calls: &CallLog) -> Self {
+    Self {
+      name: name.to_string(),
+      plugin_type: CollabPluginType::Other,
+      calls: calls.clone(),
+    }
+  }
+
+  fn cloud(name: &str, calls: &CallLog) -> Self {
+    Self {
+      plugin_type: CollabPluginType::CloudStorage,
+      ..Self::new(name, calls)
+    }
+  }
+}
+
+impl CollabPlugin for RecordPlugin {
+  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, _update: &[u8]) {
+    self.calls.lock().push(self.name.clone());
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+
+  fn plugin_type(&self) -> CollabPluginType {
+    self.plugin_type
+  }
+}
+
+#[tokio::test]
+async fn plugin_priority_order_test() {
+  let calls = CallLog::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(RecordPlugin::new("a", &calls))
+    .with_plugin_priority(RecordPlugin::new("b", &calls), 10)
+    .with_plugin(RecordPlugin::new("c", &calls))
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  collab.lock().insert("1", "a");
+  assert_eq!(*calls.lock(), vec!["b", "a", "c"]);
+}
+
+#[tokio::test]
+async fn remove_plugin_test() {
+  let calls = CallLog::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+
+  let plugin_id = collab
+    .lock()
+    .add_plugin(Box::new(RecordPlugin::new("a", &calls)))
+    .unwrap();
+  collab.lock().insert("1", "a");
+  assert!(collab.lock().remove_plugin(plugin_id).is_some());
+  assert!(collab.lock().remove_plugin(plugin_id).is_none());
+  collab.lock().insert("2", "b");
+
+  assert_eq!(*calls.lock(), vec!["a"]);
+}
+
+#[tokio::test]
+async fn get_plugin_by_type_test() {
+  let calls = CallLog::default();
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(RecordPlugin::cloud("cloud", &calls))
+    .build()
+    .unwrap();
+
+  let plugin = collab.lock().get_plugin::<RecordPlugin>().unwrap();
+  assert_eq!(plugin.name, "cloud");
+
+  let cloud_ids = collab
+    .lock()
+    .get_plugin_ids(&CollabPluginType::CloudStorage);
+  assert_eq!(cloud_ids.len(), 1);
+  assert!(collab
+    .lock()
+    .get_plugin_ids(&CollabPluginType::Other)
+    .is_empty());
+}
+
+#[tokio::test]
+async fn reject_second_cloud_storage_plugin_test() {
+  let calls = CallLog::default();
+  let result = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(RecordPlugin::cloud("cloud_1", &calls))
+    .with_plugin(RecordPlugin::cloud("cloud_2", &calls))
+    .build();
+  assert!(matches!(
+    result,
+    Err(CollabError::DuplicateCloudStoragePlugin)
+  ));
+
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .build()
+    .unwrap();
+  let plugin_id = collab
+    .lock()
+    .add_typed_plugin(
+      RecordPlugin::cloud("cloud_1", &calls),
+      DEFAULT_PLUGIN_PRIORITY,
+    )
+    .unwrap();
+  let result = collab
+    .lock()
+    .add_plugin(Box::new(RecordPlugin::cloud("cloud_2", &calls)));
+  assert!(matches!(
+    result,
+    Err(CollabError::DuplicateCloudStoragePlugin)
+  ));
+
+  // Swapping the cloud plugin is allowed once the previous one is removed.
+  collab.lock().remove_plugin(plugin_id).unwrap();
+  collab
+    .lock()
+    .add_plugin(Box::new(RecordPlugin::cloud("cloud_2", &calls)))
+    .unwrap();
+  // A boxed plugin can be looked up by its type too.
+  let plugin = collab.lock().get_plugin::<RecordPlugin>().unwrap();
+  assert_eq!(plugin.name, "cloud_2");
+
+  let result = collab.lock().add_plugins(vec![
+    Box::new(RecordPlugin::new("local", &calls)),
+    Box::new(RecordPlugin::cloud("cloud_3", &calls)),
+  ]);
+  assert!(matches!(
+    result,
+    Err(CollabError::DuplicateCloudStoragePlugin)
+  ));
+
+  // The constructors keep the first cloud plugin.
+  let collab = MutexCollab::new(
+    CollabOrigin::Empty,
+    "1",
+    vec![
+      Box::new(RecordPlugin::cloud("cloud_1", &calls)),
+      Box::new(RecordPlugin::cloud("cloud_2", &calls)),
+    ],
+  );
+  let plugin = collab.lock().get_plugin::<RecordPlugin>().unwrap();
+  assert_eq!(plugin.name, "cloud_1");
+}
@@ -0,0 +1,197 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use collab::core::awareness::{AwarenessUpdate, Event};
+use collab::core::collab::{CollabBuilder, MutexCollab};
+use collab::core::collab_plugin::CollabPlugin;
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::core::read_only::ReadOnlyMode;
+use collab::error::CollabError;
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+use yrs::{Doc, ReadTxn, Transact, TransactionMut, Update};
+
+#[derive(Clone, Default)]
+struct UpdateCounter(Arc<AtomicUsize>);
+
+impl UpdateCounter {
+  fn count(&self) -> usize {
+    self.0.load(Ordering::SeqCst)
+  }
+}
+
+impl CollabPlugin for UpdateCounter {
+  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, _update: &[u8]) {
+    self.0.fetch_add(1, Ordering::SeqCst);
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+}
+
+fn make_collab(uid: i64, mode: ReadOnlyMode, counter: UpdateCounter) -> MutexCollab {
+  let collab = CollabBuilder::new(uid, "1")
+    .with_device_id(uid.to_string())
+    .with_plugin(counter)
+    .with_read_only_mode(mode)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  collab
+}
+
+fn make_update(from: &MutexCollab, to: &MutexCollab) -> Vec<u8> {
+  let sv = to.lock().transact().state_vector();
+  from.lock().transact().encode_state_as_update_v1(&sv)
+}
+
+fn client_origin(uid: i64) -> CollabOrigin {
+  CollabOrigin::Client(CollabClient::new(uid, uid.to_string()))
+}
+
+#[test]
+fn reject_local_change_test() {
+  let counter = UpdateCounter::default();
+  let collab = make_collab(1, ReadOnlyMode::ReadOnly, counter.clone());
+  assert!(collab.is_read_only());
+
+  let result = collab
+    .lock()
+    .try_with_origin_transact_mut(|_txn| unreachable!());
+  assert!(matches!(result, Err(CollabError::ReadOnly(_))));
+  assert!(matches!(
+    collab.lock().try_origin_transaction_mut(),
+    Err(CollabError::ReadOnly(_))
+  ));
+  assert_eq!(counter.count(), 0);
+}
+
+#[test]
+fn read_only_wrappers_do_not_write_test() {
+  let counter = UpdateCounter::default();
+  let collab = make_collab(1, ReadOnlyMode::Writable, counter.clone());
+  collab.lock().insert("title", "hello");
+  let map = {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| collab.insert_map_with_txn(txn, "map"))
+      .unwrap()
+  };
+  let count = counter.count();
+
+  collab.set_read_only_mode(ReadOnlyMode::ReadOnly);
+  assert!(map.is_read_only());
+  let result = map.try_with_transact_mut(|_txn| unreachable!());
+  assert!(matches!(result, Err(CollabError::ReadOnly(_))));
+  map.insert("name", "appflowy");
+  assert!(collab.lock().remove("title").is_none());
+
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "title": "hello", "map": {} })
+  );
+  assert_eq!(counter.count(), count);
+}
+
+#[test]
+fn write_transaction_fails_when_read_only_test() {
+  let collab = make_collab(1, ReadOnlyMode::ReadOnly, UpdateCounter::default());
+  let result = collab
+    .lock()
+    .with_origin_transact_mut(|_txn| unreachable!());
+  assert!(matches!(result, Err(CollabError::ReadOnly(_))));
+  assert!(collab.lock().insert("title", "hello").is_none());
+  assert_eq!(collab.to_json_value(), json!({}));
+}
+
+#[test]
+fn apply_remote_update_test() {
+  let counter = UpdateCounter::default();
+  let collab = make_collab(1, ReadOnlyMode::ReadOnly, counter.clone());
+  let remote = make_collab(2, ReadOnlyMode::Writable, UpdateCounter::default());
+  remote.lock().insert("title", "hello");
+
+  let update = make_update(&remote, &collab);
+  collab
+    .lock()
+    .apply_remote_update(&client_origin(2), &update)
+    .unwrap();
+  assert_eq!(collab.to_json_value(), json!({ "title": "hello" }));
+  assert_eq!(counter.count(), 1);
+
+  collab.set_read_only_mode(ReadOnlyMode::Frozen);
+  remote.lock().insert("name", "appflowy");
+  let update = make_update(&remote, &collab);
+  let result = collab
+    .lock()
+    .apply_remote_update(&client_origin(2), &update);
+  assert!(matches!(result, Err(CollabError::ReadOnly(_))));
+  assert_eq!(collab.to_json_value(), json!({ "title": "hello" }));
+}
+
+#[test]
+fn read_only_collab_can_become_writable_test() {
+  let collab = make_collab(1, ReadOnlyMode::Writable, UpdateCounter::default());
+  collab.lock().insert("title", "hello");
+  collab.set_read_only_mode(ReadOnlyMode::ReadOnly);
+  let result = collab
+    .lock()
+    .try_with_origin_transact_mut(|_txn| unreachable!());
+  assert!(matches!(result, Err(CollabError::ReadOnly(_))));
+
+  // Nothing was written in the meantime, so the collab can be edited again.
+  collab.set_read_only_mode(ReadOnlyMode::Writable);
+  collab.lock().insert("title", "world");
+  assert_eq!(collab.to_json_value(), json!({ "title": "world" }));
+}
+
+/// Loads the given update in `init`, like a storage plugin.
+struct LoadPlugin(Vec<u8>);
+
+impl CollabPlugin for LoadPlugin {
+  fn init(&self, _object_id: &str, _origin: &CollabOrigin, doc: &Doc) {
+    let mut txn = doc.transact_mut();
+    txn.apply_update(Update::decode_v1(&self.0).unwrap());
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+}
+
+#[test]
+fn frozen_collab_loads_plugin_data_test() {
+  let remote = make_collab(2, ReadOnlyMode::Writable, UpdateCounter::default());
+  remote.lock().insert("title", "hello");
+  let update = remote
+    .lock()
+    .transact()
+    .encode_state_as_update_v1(&Default::default());
+
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .with_plugin(LoadPlugin(update))
+    .with_read_only_mode(ReadOnlyMode::Frozen)
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  assert_eq!(collab.to_json_value(), json!({ "title": "hello" }));
+
+  remote.lock().insert("name", "appflowy");
+  let update = make_update(&remote, &collab);
+  let result = collab
+    .lock()
+    .apply_remote_update(&client_origin(2), &update);
+  assert!(matches!(result, Err(CollabError::ReadOnly(_))));
+}
@@ -74,9 +74,12 @@ async fn apply_same_update_multiple_time() {
 
   // It's ok to apply the updates that were already applied
   let doc_state = update_cache.get_doc_state().unwrap();
-  restored_collab.lock().with_origin_transact_mut(|txn| {
-    txn.apply_update(Update::decode_v1(&doc_state).unwrap());
-  });
+  restored_collab
+    .lock()
+    .with_origin_transact_mut(|txn| {
+      txn.apply_update(Update::decode_v1(&doc_state).unwrap());
+    })
+    .unwrap();
 
   assert_json_diff::assert_json_eq!(collab.lock().to_json(), restored_collab.lock().to_json(),);
 }
@@ -97,16 +100,20 @@ async fn root_change_test() {
 
   {
     let collab_1_guard = collab_1.lock();
-    collab_1_guard.with_origin_transact_mut(|txn| {
-      collab_1_guard.insert_map_with_txn(txn, "map");
-    });
+    collab_1_guard
+      .with_origin_transact_mut(|txn| {
+        collab_1_guard.insert_map_with_txn(txn, "map");
+      })
+      .unwrap();
     drop(collab_1_guard);
   }
   {
     let collab_2_guard = collab_2.lock();
-    collab_2_guard.with_origin_transact_mut(|txn| {
-      collab_2_guard.insert_map_with_txn(txn, "map");
-    });
+    collab_2_guard
+      .with_origin_transact_mut(|txn| {
+        collab_2_guard.insert_map_with_txn(txn, "map");
+      })
+      .unwrap();
     drop(collab_2_guard);
   }
 
@@ -116,10 +123,12 @@ async fn root_change_test() {
     let map_2 = collab_guard.get_map_with_txn(&txn, vec!["map"]).unwrap();
     drop(txn);
 
-    collab_guard.with_origin_transact_mut(|txn| {
-      map_2.insert_with_txn(txn, "1", "a");
-      map_2.insert_with_txn(txn, "2", "b");
-    });
+    collab_guard
+      .with_origin_transact_mut(|txn| {
+        map_2.insert_with_txn(txn, "1", "a");
+        map_2.insert_with_txn(txn, "2", "b");
+      })
+      .unwrap();
     map_2
   };
 
@@ -132,10 +141,12 @@ async fn root_change_test() {
 
   let map_1 = {
     let collab_1_guard = collab_1.lock();
-    collab_1_guard.with_origin_transact_mut(|txn| {
-      let update = Update::decode_v1(&sv_1_update).unwrap();
-      txn.apply_update(update);
-    });
+    collab_1_guard
+      .with_origin_transact_mut(|txn| {
+        let update = Update::decode_v1(&sv_1_update).unwrap();
+        txn.apply_update(update);
+      })
+      .unwrap();
 
     let txn = collab_1_guard.transact();
     collab_1_guard.get_map_with_txn(&txn, vec!["map"]).unwrap()
@@ -0,0 +1,170 @@
+use collab::core::collab::IndexContent;
+use collab::core::collab_search::{
+  tokenize, CollabSearch, IndexedDocument, SearchIndex, SearchQuery,
+};
+use serde_json::json;
+
+fn make_index() -> SearchIndex {
+  let mut index = SearchIndex::new();
+  index.apply(&IndexContent::Create(json!({
+    "id": "view_1",
+    "parent_view_id": "workspace",
+    "name": "Meeting notes",
+    "layout": "Document",
+  })));
+  index.apply(&IndexContent::Create(json!({
+    "page_id": "view_2",
+    "text": "The roadmap for the next quarter includes collaboration features",
+  })));
+  index.apply(&IndexContent::Create(json!({
+    "id": "view_3",
+    "name": "项目计划",
+    "text": "我们的产品路线图",
+  })));
+  index
+}
+
+#[test]
+fn tokenize_latin_and_cjk_test() {
+  assert_eq!(tokenize("Hello, World!"), vec!["hello", "world"]);
+  assert_eq!(
+    tokenize("AppFlowy项目"),
+    vec!["appflowy", "项", "目", "项目"]
+  );
+}
+
+#[test]
+fn search_exact_and_prefix_test() {
+  let index = make_index();
+  let results = index.search(&SearchQuery::new("meeting"));
+  assert_eq!(results.len(), 1);
+  assert_eq!(results[0].id, "view_1");
+  assert_eq!(results[0].title, "Meeting notes");
+
+  let results = index.search(&SearchQuery::new("road"));
+  assert_eq!(results[0].id, "view_2");
+  assert!(index
+    .search(&SearchQuery::new("road").with_prefix(false))
+    .is_empty());
+}
+
+#[test]
+fn search_cjk_test() {
+  let index = make_index();
+  let results = index.search(&SearchQuery::new("路线"));
+  assert_eq!(results.len(), 1);
+  assert_eq!(results[0].id, "view_3");
+  assert!(results[0].snippet.contains("路线图"));
+  assert_eq!(index.search(&SearchQuery::new("计划"))[0].id, "view_3");
+}
+
+#[test]
+fn search_fuzzy_test() {
+  let index = make_index();
+  assert!(index.search(&SearchQuery::new("colaboration")).is_empty());
+  let results = index.search(&SearchQuery::new("colaboration").with_fuzzy(1));
+  assert_eq!(results[0].id, "view_2");
+  assert!(index
+    .search(&SearchQuery::new("roadtrip").with_fuzzy(2))
+    .is_empty());
+}
+
+#[test]
+fn search_fuzzy_after_update_test() {
+  let mut index = make_index();
+  index.upsert(IndexedDocument::new("view_2", "", "quarterly planning"));
+  assert!(index
+    .search(&SearchQuery::new("colaboration").with_fuzzy(1))
+    .is_empty());
+  let results = index.search(&SearchQuery::new("planing").with_fuzzy(1));
+  assert_eq!(results[0].id, "view_2");
+}
+
+#[test]
+fn search_ranking_and_snippet_test() {
+  let mut index = SearchIndex::new();
+  index.upsert(IndexedDocument::new("1", "Notes", "a note about rust"));
+  index.upsert(IndexedDocument::new("2", "Rust", "rust rust rust"));
+  let results = index.search(&SearchQuery::new("rust"));
+  assert_eq!(results[0].id, "2");
+  assert_eq!(results[1].id, "1");
+
+  let body = format!("{} rust {}", "a ".repeat(50), "b ".repeat(50));
+  index.upsert(IndexedDocument::new("3", "", body));
+  let results = index.search(&SearchQuery::new("rust").with_snippet_len(20));
+  let snippet = &results.iter().find(|r| r.id == "3").unwrap().snippet;
+  assert!(snippet.starts_with('…'));
+  assert!(snippet.ends_with('…'));
+  assert!(snippet.contains("rust"));
+}
+
+#[test]
+fn update_and_delete_test() {
+  let mut index = make_index();
+  index.apply(&IndexContent::Update(json!({
+    "id": "view_1",
+    "name": "Standup",
+  })));
+  assert!(index.search(&SearchQuery::new("meeting")).is_empty());
+  assert_eq!(index.search(&SearchQuery::new("standup"))[0].id, "view_1");
+
+  index.apply(&IndexContent::Delete(vec!["view_1".to_string()]));
+  assert!(index.search(&SearchQuery::new("standup")).is_empty());
+  assert_eq!(index.len(), 2);
+}
+
+#[test]
+fn encode_and_decode_index_test() {
+  let index = make_index();
+  let decoded = SearchIndex::decode(&index.encode().unwrap()).unwrap();
+  assert_eq!(decoded.len(), index.len());
+  assert_eq!(
+    decoded.search(&SearchQuery::new("roadmap")),
+    index.search(&SearchQuery::new("roadmap"))
+  );
+}
+
+#[test]
+fn restore_index_from_postings_test() {
+  let index = make_index();
+  let documents = index.documents().cloned().collect::<Vec<_>>();
+  let postings = documents
+    .iter()
+    .flat_map(|document| {
+      index
+        .postings(&document.id)
+        .into_iter()
+        .map(|(term, frequency)| (term, document.id.clone(), frequency))
+    })
+    .collect::<Vec<_>>();
+  let restored = SearchIndex::from_postings(documents, postings);
+  assert_eq!(restored.len(), index.len());
+  for query in ["roadmap", "路线", "meet"] {
+    assert_eq!(
+      restored.search(&SearchQuery::new(query)),
+      index.search(&SearchQuery::new(query))
+    );
+  }
+  assert_eq!(
+    restored
+      .search(&SearchQuery::new("colaboration").with_fuzzy(1))
+      .len(),
+    1
+  );
+}
+
+#[tokio::test]
+async fn consume_index_content_test() {
+  let search = CollabSearch::new();
+  let (tx, rx) = tokio::sync::broadcast::channel(10);
+  let cloned_search = search.clone();
+  let handle = tokio::spawn(async move { cloned_search.consume(rx).await });
+  tx.send(IndexContent::Create(json!({ "id": "1", "name": "Hello" })))
+    .unwrap();
+  drop(tx);
+  handle.await.unwrap();
+
+  assert_eq!(search.search(&SearchQuery::new("hello"))[0].id, "1");
+  assert_eq!(search.take_changes(), vec!["1".to_string()]);
+  assert!(search.take_changes().is_empty());
+}
@@ -0,0 +1,210 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use collab::core::awareness::{AwarenessUpdate, Event};
+use collab::core::collab::{CollabBuilder, MutexCollab};
+use collab::core::collab_plugin::CollabPlugin;
+use collab::core::origin::CollabOrigin;
+use collab::error::CollabError;
+use collab::preclude::{Doc, Transact, TransactionMut, Update};
+use parking_lot::Mutex;
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+
+/// Stores the updates of each object, like a storage plugin.
+#[derive(Clone, Default)]
+struct MemoryStoragePlugin(Arc<Mutex<HashMap<String, Vec<Vec<u8>>>>>);
+
+impl MemoryStoragePlugin {
+  fn object_ids(&self) -> Vec<String> {
+    let mut object_ids = self.0.lock().keys().cloned().collect::<Vec<_>>();
+    object_ids.sort();
+    object_ids
+  }
+
+  fn load(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
+    if let Some(updates) = self.0.lock().get(object_id) {
+      let mut txn = doc.transact_mut_with(origin.clone());
+      for update in updates {
+        txn.apply_update(Update::decode_v1(update).unwrap());
+      }
+    }
+  }
+
+  fn push(&self, object_id: &str, update: &[u8]) {
+    self
+      .0
+      .lock()
+      .entry(object_id.to_string())
+      .or_default()
+      .push(update.to_vec());
+  }
+}
+
+impl CollabPlugin for MemoryStoragePlugin {
+  fn init(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
+    self.load(object_id, origin, doc);
+  }
+
+  fn receive_update(&self, object_id: &str, _txn: &TransactionMut, update: &[u8]) {
+    self.push(object_id, update);
+  }
+
+  fn init_subdoc(&self, object_id: &str, origin: &CollabOrigin, doc: &Doc) {
+    self.load(object_id, origin, doc);
+  }
+
+  fn receive_subdoc_update(&self, object_id: &str, update: &[u8]) {
+    self.push(object_id, update);
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+}
+
+fn make_collab(object_id: &str, storage: &MemoryStoragePlugin) -> MutexCollab {
+  let collab = MutexCollab::new(
+    CollabOrigin::Empty,
+    object_id,
+    vec![Box::new(storage.clone())],
+  );
+  collab.lock().initialize();
+  collab
+}
+
+#[tokio::test]
+async fn create_subdoc_test() {
+  let storage = MemoryStoragePlugin::default();
+  let parent = make_collab("page", &storage);
+  let child = parent.lock().create_subdoc("row_document").unwrap();
+  child.lock().insert("text", "hello");
+
+  assert_eq!(parent.lock().get_subdoc_ids(), vec!["row_document"]);
+  assert!(parent.lock().is_subdoc_loaded("row_document"));
+  assert_eq!(child.to_json_value(), json!({ "text": "hello" }));
+  // The updates of the child go through the plugins of the parent.
+  assert_eq!(storage.object_ids(), vec!["page", "row_document"]);
+
+  let same_child = parent.lock().load_subdoc("row_document").unwrap();
+  assert!(Arc::ptr_eq(&*child, &*same_child));
+}
+
+#[tokio::test]
+async fn load_subdoc_lazily_test() {
+  let storage = MemoryStoragePlugin::default();
+  {
+    let parent = make_collab("page", &storage);
+    let child = parent.lock().create_subdoc("row_document").unwrap();
+    child.lock().insert("text", "hello");
+  }
+
+  let parent = make_collab("page", &storage);
+  assert_eq!(parent.lock().get_subdoc_ids(), vec!["row_document"]);
+  assert!(!parent.lock().is_subdoc_loaded("row_document"));
+
+  let child = parent.lock().load_subdoc("row_document").unwrap();
+  assert_eq!(child.to_json_value(), json!({ "text": "hello" }));
+
+  parent.lock().unload_subdoc("row_document");
+  assert!(!parent.lock().is_subdoc_loaded("row_document"));
+}
+
+#[tokio::test]
+async fn remove_subdoc_test() {
+  let storage = MemoryStoragePlugin::default();
+  let parent = make_collab("page", &storage);
+  parent.lock().create_subdoc("row_document").unwrap();
+  let result = parent.lock().create_subdoc("row_document");
+  assert!(matches!(result, Err(CollabError::SubdocAlreadyExists(_))));
+
+  assert!(parent.lock().remove_subdoc("row_document"));
+  assert!(parent.lock().get_subdoc_ids().is_empty());
+  assert!(matches!(
+    parent.lock().load_subdoc("row_document"),
+    Err(CollabError::SubdocNotFound(_))
+  ));
+}
+
+/// Remembers the object id it was built for.
+struct ObjectPlugin {
+  object_id: String,
+  updates: Arc<Mutex<Vec<String>>>,
+}
+
+impl CollabPlugin for ObjectPlugin {
+  fn receive_update(&self, object_id: &str, _txn: &TransactionMut, _update: &[u8]) {
+    assert_eq!(object_id, self.object_id);
+    self.updates.lock().push(self.object_id.clone());
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+}
+
+#[tokio::test]
+async fn subdoc_plugins_are_built_per_child_test() {
+  let updates = Arc::new(Mutex::new(vec![]));
+  let parent_updates = updates.clone();
+  let child_updates = updates.clone();
+  let parent = CollabBuilder::new(1, "page")
+    .with_device_id("1")
+    .with_plugin(ObjectPlugin {
+      object_id: "page".to_string(),
+      updates: parent_updates,
+    })
+    .with_subdoc_plugin_factory(move |object_id| {
+      vec![Box::new(ObjectPlugin {
+        object_id: object_id.to_string(),
+        updates: child_updates.clone(),
+      }) as Box<dyn CollabPlugin>]
+    })
+    .build()
+    .unwrap();
+  parent.lock().initialize();
+
+  let child_1 = parent.lock().create_subdoc("row_1").unwrap();
+  let child_2 = parent.lock().create_subdoc("row_2").unwrap();
+  updates.lock().clear();
+  child_1.lock().insert("text", "hello");
+  child_2.lock().insert("text", "world");
+  parent.lock().insert("title", "page");
+  assert_eq!(*updates.lock(), vec!["row_1", "row_2", "page"]);
+
+  // The children of a child get their own plugins too.
+  let grandchild = child_1.lock().create_subdoc("row_1_1").unwrap();
+  updates.lock().clear();
+  grandchild.lock().insert("text", "nested");
+  assert_eq!(*updates.lock(), vec!["row_1_1"]);
+}
+
+#[tokio::test]
+async fn nested_subdoc_is_routed_to_the_root_plugins_test() {
+  let storage = MemoryStoragePlugin::default();
+  {
+    let parent = make_collab("page", &storage);
+    let child = parent.lock().create_subdoc("row_document").unwrap();
+    let grandchild = child.lock().create_subdoc("nested_document").unwrap();
+    grandchild.lock().insert("text", "nested");
+  }
+  assert_eq!(
+    storage.object_ids(),
+    vec!["nested_document", "page", "row_document"]
+  );
+
+  let parent = make_collab("page", &storage);
+  let child = parent.lock().load_subdoc("row_document").unwrap();
+  let grandchild = child.lock().load_subdoc("nested_document").unwrap();
+  assert_eq!(grandchild.to_json_value(), json!({ "text": "nested" }));
+}
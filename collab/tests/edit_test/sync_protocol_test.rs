@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use collab::core::collab::{CollabBuilder, MutexCollab};
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::preclude::{ReadTxn, StateVector};
+use collab::sync_protocol::{
+  decode_messages, encode_messages, memory_duplex, run_sync_protocol, Message, SyncMessage,
+  SyncProtocol,
+};
+use serde_json::json;
+use tokio::time::sleep;
+
+fn make_collab(uid: i64) -> MutexCollab {
+  let collab = CollabBuilder::new(uid, "1")
+    .with_device_id(uid.to_string())
+    .build()
+    .unwrap();
+  collab.lock().initialize();
+  collab
+}
+
+fn client_origin(uid: i64) -> CollabOrigin {
+  CollabOrigin::Client(CollabClient::new(uid, uid.to_string()))
+}
+
+async fn wait_until<F: Fn() -> bool>(f: F) {
+  for _ in 0..50 {
+    if f() {
+      return;
+    }
+    sleep(Duration::from_millis(20)).await;
+  }
+  panic!("timeout");
+}
+
+#[test]
+fn encode_decode_messages_test() {
+  let collab = make_collab(1);
+  collab.lock().insert("name", "appflowy");
+  let update = collab
+    .lock()
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  let awareness_update = collab.lock().get_awareness().update().unwrap();
+  let messages = vec![
+    Message::Sync(SyncMessage::SyncStep1(StateVector::default())),
+    Message::Sync(SyncMessage::Update(update)),
+    Message::Awareness(awareness_update),
+    Message::AwarenessQuery,
+    Message::Auth(Some("expired token".to_string())),
+    Message::Custom(100, vec![1, 2, 3]),
+  ];
+  let data = encode_messages(&messages);
+  assert_eq!(decode_messages(&data).unwrap(), messages);
+}
+
+#[test]
+fn decode_truncated_messages_test() {
+  let messages = vec![
+    Message::Sync(SyncMessage::Update(vec![1, 2, 3, 4])),
+    Message::Custom(100, vec![1, 2, 3]),
+  ];
+  let data = encode_messages(&messages);
+  let boundary = encode_messages(&messages[..1]).len();
+  assert!(decode_messages(&[]).unwrap().is_empty());
+  assert_eq!(decode_messages(&data[..boundary]).unwrap(), messages[..1]);
+  for len in (1..data.len()).filter(|len| *len != boundary) {
+    assert!(
+      decode_messages(&data[..len]).is_err(),
+      "a frame truncated to {} bytes must not decode",
+      len
+    );
+  }
+}
+
+#[test]
+fn sync_protocol_handshake_test() {
+  let collab_1 = make_collab(1);
+  let collab_2 = make_collab(2);
+  collab_1.lock().insert("title", "hello");
+  collab_2.lock().insert("name", "appflowy");
+
+  let mut protocol_1 = SyncProtocol::new(client_origin(2));
+  let mut protocol_2 = SyncProtocol::new(client_origin(1));
+  let mut to_2 = protocol_1.start(&collab_1.lock()).unwrap();
+  let mut to_1 = protocol_2.start(&collab_2.lock()).unwrap();
+  while !to_1.is_empty() || !to_2.is_empty() {
+    let mut replies_to_2 = vec![];
+    for message in to_1.drain(..) {
+      replies_to_2.extend(
+        protocol_1
+          .handle_message(&mut collab_1.lock(), message)
+          .unwrap(),
+      );
+    }
+    for message in to_2.drain(..) {
+      to_1.extend(
+        protocol_2
+          .handle_message(&mut collab_2.lock(), message)
+          .unwrap(),
+      );
+    }
+    to_2 = replies_to_2;
+  }
+
+  assert!(protocol_1.is_synced());
+  assert!(protocol_2.is_synced());
+  let expected = json!({
+    "name": "appflowy",
+    "title": "hello"
+  });
+  assert_eq!(collab_1.to_json_value(), expected);
+  assert_eq!(collab_2.to_json_value(), expected);
+}
+
+#[tokio::test]
+async fn sync_two_collabs_over_memory_transport_test() {
+  let collab_1 = make_collab(1);
+  let collab_2 = make_collab(2);
+  collab_1.lock().insert("title", "hello");
+
+  let (conn_1, conn_2) = memory_duplex();
+  tokio::spawn(run_sync_protocol(
+    collab_1.clone(),
+    client_origin(2),
+    conn_1,
+  ));
+  tokio::spawn(run_sync_protocol(
+    collab_2.clone(),
+    client_origin(1),
+    conn_2,
+  ));
+  wait_until(|| collab_2.to_json_value() == json!({ "title": "hello" })).await;
+
+  // Changes made after the handshake are sent as they happen.
+  collab_2.lock().insert("name", "appflowy");
+  collab_1.lock().insert("title", "world");
+  let expected = json!({
+    "name": "appflowy",
+    "title": "world"
+  });
+  wait_until(|| collab_1.to_json_value() == expected && collab_2.to_json_value() == expected).await;
+
+  collab_1
+    .lock()
+    .get_mut_awareness()
+    .set_local_state(json!({ "uid": 1, "viewing": "doc" }));
+  let client_id = collab_1.lock().get_doc().client_id();
+  wait_until(|| {
+    collab_2.lock().get_awareness().get_states().get(&client_id)
+      == Some(&json!({ "uid": 1, "viewing": "doc" }))
+  })
+  .await;
+}
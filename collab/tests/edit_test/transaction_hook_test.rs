@@ -0,0 +1,307 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use collab::core::awareness::{AwarenessUpdate, Event};
+use collab::core::collab::{CollabBuilder, MutexCollab};
+use collab::core::collab_plugin::CollabPlugin;
+use collab::core::origin::CollabOrigin;
+use collab::core::transaction_hook::{LastEditedStamp, PendingChanges};
+use collab::error::CollabError;
+use collab::preclude::MapRefExtension;
+use parking_lot::Mutex;
+use serde_json::json;
+use yrs::updates::decoder::Decode;
+use yrs::{Transact, TransactionMut, Update};
+
+#[derive(Clone, Default)]
+struct UpdateCounter(Arc<AtomicUsize>);
+
+impl UpdateCounter {
+  fn count(&self) -> usize {
+    self.0.load(Ordering::SeqCst)
+  }
+}
+
+impl CollabPlugin for UpdateCounter {
+  fn receive_update(&self, _object_id: &str, _txn: &TransactionMut, _update: &[u8]) {
+    self.0.fetch_add(1, Ordering::SeqCst);
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+}
+
+/// Records the updates that would be sent to the peers.
+#[derive(Clone, Default)]
+struct LocalUpdates(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl CollabPlugin for LocalUpdates {
+  fn receive_local_update(&self, _origin: &CollabOrigin, _object_id: &str, update: &[u8]) {
+    self.0.lock().push(update.to_vec());
+  }
+
+  fn receive_local_state(
+    &self,
+    _origin: &CollabOrigin,
+    _object_id: &str,
+    _event: &Event,
+    _update: &AwarenessUpdate,
+  ) {
+  }
+}
+
+fn make_collab(builder: CollabBuilder) -> MutexCollab {
+  let collab = builder.with_device_id("1").build().unwrap();
+  collab.lock().initialize();
+  collab
+}
+
+fn reject_title_change(
+  _txn: &mut TransactionMut,
+  changes: &PendingChanges,
+) -> Result<(), CollabError> {
+  if changes.touches(["data", "title"]) {
+    return Err(CollabError::TransactionAborted(
+      "the title can't be changed".to_string(),
+    ));
+  }
+  Ok(())
+}
+
+#[test]
+fn stamp_last_edited_test() {
+  let counter = UpdateCounter::default();
+  let collab = make_collab(
+    CollabBuilder::new(1, "1")
+      .with_plugin(counter.clone())
+      .with_transaction_hook(LastEditedStamp::new(["data", "views"])),
+  );
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let views = collab.insert_map_with_txn(txn, "views");
+        let v1 = views.create_map_with_txn(txn, "v1");
+        v1.insert_str_with_txn(txn, "name", "first");
+        let v2 = views.create_map_with_txn(txn, "v2");
+        v2.insert_str_with_txn(txn, "name", "second");
+      })
+      .unwrap();
+  }
+  let count = counter.count();
+
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let v2 = collab.get_map_with_txn(txn, vec!["views", "v2"]).unwrap();
+        v2.insert_str_with_txn(txn, "name", "renamed");
+      })
+      .unwrap();
+  }
+  // The stamp is part of the same update as the edit.
+  assert_eq!(counter.count(), count + 1);
+
+  let json = collab.to_json_value();
+  assert_eq!(json["views"]["v2"]["name"], json!("renamed"));
+  assert_eq!(json["views"]["v2"]["last_edited_by"], json!(1));
+  assert!(json["views"]["v2"]["last_edited_time"].as_i64().unwrap() > 0);
+  assert_eq!(json["views"]["v1"], json!({ "name": "first" }));
+}
+
+#[test]
+fn stamp_keeps_explicit_values_test() {
+  let collab = make_collab(
+    CollabBuilder::new(1, "1").with_transaction_hook(LastEditedStamp::new(["data", "views"])),
+  );
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let views = collab.insert_map_with_txn(txn, "views");
+        views
+          .create_map_with_txn(txn, "v1")
+          .insert_str_with_txn(txn, "name", "first");
+      })
+      .unwrap();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let v1 = collab.get_map_with_txn(txn, vec!["views", "v1"]).unwrap();
+        v1.insert_str_with_txn(txn, "name", "renamed");
+        v1.insert_i64_with_txn(txn, "last_edited_time", 42);
+      })
+      .unwrap();
+  }
+  let json = collab.to_json_value();
+  assert_eq!(json["views"]["v1"]["last_edited_time"], json!(42));
+  assert_eq!(json["views"]["v1"]["last_edited_by"], json!(1));
+
+  // Replacing a view isn't an edit of the view.
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let views = collab.get_map_with_txn(txn, vec!["views"]).unwrap();
+        views
+          .create_map_with_txn(txn, "v1")
+          .insert_str_with_txn(txn, "name", "second");
+      })
+      .unwrap();
+  }
+  assert_eq!(
+    collab.to_json_value()["views"]["v1"],
+    json!({ "name": "second" })
+  );
+}
+
+#[test]
+fn stamp_map_test() {
+  let collab = make_collab(
+    CollabBuilder::new(1, "1").with_transaction_hook(
+      LastEditedStamp::for_map(["data", "row"])
+        .with_time_key("last_modified")
+        .without_user_key(),
+    ),
+  );
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let row = collab.insert_map_with_txn(txn, "row");
+        row.insert_str_with_txn(txn, "id", "r1");
+      })
+      .unwrap();
+  }
+  assert_eq!(collab.to_json_value()["row"], json!({ "id": "r1" }));
+
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let row = collab.get_map_with_txn(txn, vec!["row"]).unwrap();
+        row
+          .create_map_with_txn(txn, "cells")
+          .insert_str_with_txn(txn, "c1", "hello");
+      })
+      .unwrap();
+  }
+  let row = collab.to_json_value()["row"].clone();
+  assert!(row["last_modified"].as_i64().unwrap() > 0);
+  assert!(row.get("last_edited_by").is_none());
+}
+
+#[test]
+fn abort_transaction_test() {
+  let collab = make_collab(CollabBuilder::new(1, "1"));
+  collab.lock().insert("title", "hello");
+  collab.lock().add_transaction_hook(reject_title_change);
+
+  let result = {
+    let collab = collab.lock();
+    collab.try_with_origin_transact_mut(|txn| {
+      collab.insert_with_txn(txn, "title", "world");
+      collab.insert_with_txn(txn, "name", "appflowy");
+    })
+  };
+  assert!(matches!(result, Err(CollabError::TransactionAborted(_))));
+  assert_eq!(collab.to_json_value(), json!({ "title": "hello" }));
+
+  // The infallible variant reverts the transaction too.
+  collab.lock().insert("title", "world");
+  assert_eq!(collab.to_json_value(), json!({ "title": "hello" }));
+
+  // The other transactions are not affected.
+  collab.lock().insert("name", "appflowy");
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "title": "hello", "name": "appflowy" })
+  );
+}
+
+#[test]
+fn aborted_transaction_is_redacted_test() {
+  let updates = LocalUpdates::default();
+  let collab = make_collab(CollabBuilder::new(1, "1").with_plugin(updates.clone()));
+  collab.lock().insert("title", "hello");
+  collab.lock().add_transaction_hook(reject_title_change);
+
+  collab.lock().insert("title", "secret");
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        let title = collab.insert_map_with_txn(txn, "title");
+        title.insert_str_with_txn(txn, "text", "secret");
+      })
+      .unwrap();
+  }
+  collab.lock().insert("name", "appflowy");
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "title": "hello", "name": "appflowy" })
+  );
+
+  // The peers receive the aborted transaction without its content, and nothing is missing to
+  // apply the following updates.
+  let updates = updates.0.lock().clone();
+  assert_eq!(updates.len(), 6);
+  assert!(updates
+    .iter()
+    .all(|update| !update.windows(6).any(|bytes| bytes == b"secret")));
+  let peer = make_collab(CollabBuilder::new(2, "1"));
+  for update in updates {
+    let peer = peer.lock();
+    let mut txn = peer.get_doc().transact_mut();
+    txn.apply_update(Update::decode_v1(&update).unwrap());
+  }
+  assert_eq!(peer.to_json_value(), collab.to_json_value());
+}
+
+#[test]
+fn hooks_only_run_for_changes_test() {
+  let calls = Arc::new(AtomicUsize::new(0));
+  let cloned_calls = calls.clone();
+  let collab = make_collab(CollabBuilder::new(1, "1").with_transaction_hook(
+    move |_txn: &mut TransactionMut, changes: &PendingChanges| {
+      cloned_calls.fetch_add(1, Ordering::SeqCst);
+      assert_eq!(changes.changed_keys(["data"]), vec!["a", "b"]);
+      Ok(())
+    },
+  ));
+
+  collab.lock().with_origin_transact_mut(|_txn| {}).unwrap();
+  assert_eq!(calls.load(Ordering::SeqCst), 0);
+
+  {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| {
+        collab.insert_with_txn(txn, "b", "1");
+        collab.insert_with_txn(txn, "a", "2");
+      })
+      .unwrap();
+  }
+  assert_eq!(calls.load(Ordering::SeqCst), 1);
+}
+
+#[test]
+fn wrapper_transaction_runs_hooks_test() {
+  let collab = make_collab(CollabBuilder::new(1, "1"));
+  let map = {
+    let collab = collab.lock();
+    collab
+      .with_origin_transact_mut(|txn| collab.insert_map_with_txn(txn, "title"))
+      .unwrap()
+  };
+  collab.lock().add_transaction_hook(reject_title_change);
+
+  let result = map.try_with_transact_mut(|txn| map.insert_str_with_txn(txn, "name", "appflowy"));
+  assert!(matches!(result, Err(CollabError::TransactionAborted(_))));
+  assert_eq!(collab.to_json_value(), json!({ "title": {} }));
+}
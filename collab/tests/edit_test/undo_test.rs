@@ -0,0 +1,138 @@
+use std::time::Duration;
+
+use collab::core::origin::{CollabClient, CollabOrigin};
+use collab::core::undo::{TextCursor, UndoConfig, UndoEventKind};
+use collab::preclude::{Collab, GetString, MapRefWrapper, ReadTxn, StateVector, Text};
+use serde_json::json;
+
+fn make_collab(uid: i64) -> Collab {
+  let collab = Collab::new(uid, "1", uid.to_string(), vec![]);
+  collab
+    .with_origin_transact_mut(|txn| {
+      let cells = collab.insert_map_with_txn(txn, "cells");
+      cells.create_map_with_txn(txn, "a");
+      cells.create_map_with_txn(txn, "b");
+    })
+    .unwrap();
+  collab
+}
+
+#[tokio::test]
+async fn undo_scoped_to_path_test() {
+  let collab = make_collab(1);
+  collab
+    .enable_scoped_undo_redo("a", UndoConfig::new().with_path(vec!["cells", "a"]))
+    .unwrap();
+  collab
+    .enable_scoped_undo_redo("b", UndoConfig::new().with_path(vec!["cells", "b"]))
+    .unwrap();
+
+  let a = collab.get_map_with_path::<MapRefWrapper>(vec!["cells", "a"]);
+  let b = collab.get_map_with_path::<MapRefWrapper>(vec!["cells", "b"]);
+  a.unwrap().insert("value", "1");
+  b.unwrap().insert("value", "2");
+
+  let event = collab.undo_scope("a").unwrap().unwrap();
+  assert_eq!(event.kind, UndoEventKind::Undo);
+  assert_eq!(
+    event.changed_paths,
+    vec![vec!["data", "cells", "a", "value"]]
+  );
+  assert_eq!(
+    collab.to_json_value(),
+    json!({ "cells": { "a": {}, "b": { "value": "2" } } })
+  );
+  assert!(!collab.can_undo_scope("a"));
+  assert!(collab.can_undo_scope("b"));
+
+  let event = collab.redo_scope("a").unwrap().unwrap();
+  assert_eq!(event.kind, UndoEventKind::Redo);
+  assert_eq!(
+    collab.to_json_value()["cells"]["a"],
+    json!({ "value": "1" })
+  );
+}
+
+#[tokio::test]
+async fn undo_only_tracked_origin_test() {
+  let mut collab_1 = make_collab(1);
+  collab_1.enable_undo_redo();
+  let collab_2 = Collab::new(2, "1", "2", vec![]);
+  collab_2.insert("title", "remote");
+  let update = collab_2
+    .transact()
+    .encode_state_as_update_v1(&StateVector::default());
+  collab_1
+    .apply_remote_update(&CollabOrigin::Client(CollabClient::new(2, "2")), &update)
+    .unwrap();
+  collab_1.insert("name", "local");
+
+  assert!(collab_1.undo().unwrap());
+  assert_eq!(collab_1.to_json_value()["title"], json!("remote"));
+  assert!(collab_1.to_json_value().get("name").is_none());
+  assert!(!collab_1.undo().unwrap());
+}
+
+#[tokio::test]
+async fn stop_capturing_test() {
+  let collab = make_collab(1);
+  let config = UndoConfig::new().with_capture_timeout(Duration::from_secs(60));
+  collab.enable_scoped_undo_redo("data", config).unwrap();
+  collab.insert("1", "a");
+  collab.insert("2", "b");
+  collab.stop_capturing("data").unwrap();
+  collab.insert("3", "c");
+
+  collab.undo_scope("data").unwrap();
+  let json = collab.to_json_value();
+  assert_eq!(json["1"], json!("a"));
+  assert_eq!(json["2"], json!("b"));
+  assert!(json.get("3").is_none());
+
+  // The first two changes were captured together.
+  collab.undo_scope("data").unwrap();
+  let json = collab.to_json_value();
+  assert!(json.get("1").is_none());
+  assert!(json.get("2").is_none());
+}
+
+#[tokio::test]
+async fn undo_event_reports_text_cursor_test() {
+  let collab = make_collab(1);
+  let text = collab
+    .with_origin_transact_mut(|txn| {
+      let text = collab
+        .get_map_with_txn(txn, vec!["cells", "a"])
+        .unwrap()
+        .insert_text_with_txn(txn, "text");
+      text.insert(txn, 0, "hello");
+      text
+    })
+    .unwrap();
+  collab
+    .enable_scoped_undo_redo(
+      "text",
+      UndoConfig::new().with_path(vec!["cells", "a", "text"]),
+    )
+    .unwrap();
+  let mut rx = collab.subscribe_undo_event();
+  collab
+    .with_origin_transact_mut(|txn| text.insert(txn, 5, " world"))
+    .unwrap();
+
+  let event = collab.undo_scope("text").unwrap().unwrap();
+  assert_eq!(
+    event.text_cursors,
+    vec![TextCursor {
+      path: vec![
+        "data".to_string(),
+        "cells".to_string(),
+        "a".to_string(),
+        "text".to_string()
+      ],
+      index: 5,
+    }]
+  );
+  assert_eq!(rx.recv().await.unwrap(), event);
+  assert_eq!(text.get_string(&collab.transact()), "hello");
+}
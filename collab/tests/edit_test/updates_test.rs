@@ -0,0 +1,110 @@
+use collab::core::collab::{CollabBuilder, MutexCollab};
+use collab::core::collab_plugin::EncoderVersion;
+use collab::core::updates::{
+  compact_updates, convert_update_v1_to_v2, convert_update_v2_to_v1, merge_updates_v1,
+};
+use yrs::updates::decoder::Decode;
+use yrs::updates::encoder::Encode;
+use yrs::{ReadTxn, StateVector, Transact, Update};
+
+fn edit_collab(collab: &MutexCollab, key: &str, value: &str) -> Vec<u8> {
+  let sv = collab.lock().transact().state_vector();
+  collab.lock().insert(key, value);
+  collab.lock().transact().encode_state_as_update_v1(&sv)
+}
+
+fn restore_collab(doc_state: Vec<u8>) -> MutexCollab {
+  CollabBuilder::new(1, "1")
+    .with_device_id("2")
+    .with_doc_state(doc_state)
+    .build()
+    .unwrap()
+}
+
+#[tokio::test]
+async fn merge_updates_test() {
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .build()
+    .unwrap();
+  let updates = vec![
+    edit_collab(&collab, "1", "a"),
+    edit_collab(&collab, "2", "b"),
+    edit_collab(&collab, "1", "c"),
+  ];
+
+  let merged = merge_updates_v1(&updates).unwrap();
+  let restored = restore_collab(merged);
+  assert_eq!(collab.to_json_value(), restored.to_json_value());
+}
+
+#[tokio::test]
+async fn compact_doc_state_with_updates_test() {
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .build()
+    .unwrap();
+  edit_collab(&collab, "1", "a");
+  let doc_state = collab.encode_collab_v1().doc_state.to_vec();
+  let updates = vec![
+    edit_collab(&collab, "2", "b"),
+    edit_collab(&collab, "3", "c"),
+  ];
+
+  let encoded_collab = compact_updates(&doc_state, &updates, EncoderVersion::V1).unwrap();
+  assert_eq!(
+    encoded_collab.state_vector.to_vec(),
+    collab.lock().transact().state_vector().encode_v1()
+  );
+
+  let restored = restore_collab(encoded_collab.doc_state.to_vec());
+  assert_eq!(collab.to_json_value(), restored.to_json_value());
+}
+
+#[tokio::test]
+async fn convert_update_between_v1_and_v2_test() {
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .build()
+    .unwrap();
+  edit_collab(&collab, "1", "a");
+  edit_collab(&collab, "2", "b");
+
+  let encoded_v1 = collab.encode_collab_v1();
+  let update_v2 = convert_update_v1_to_v2(&encoded_v1.doc_state).unwrap();
+  assert!(Update::decode_v2(&update_v2).is_ok());
+
+  let update_v1 = convert_update_v2_to_v1(&update_v2).unwrap();
+  let restored = restore_collab(update_v1);
+  assert_eq!(collab.to_json_value(), restored.to_json_value());
+
+  let encoded_v2 = encoded_v1.clone().into_v2().unwrap();
+  assert_eq!(encoded_v2.version, EncoderVersion::V2);
+  assert_eq!(encoded_v2.into_v1().unwrap().version, EncoderVersion::V1);
+}
+
+#[tokio::test]
+async fn diff_encoded_collab_with_remote_state_vector_test() {
+  let collab = CollabBuilder::new(1, "1")
+    .with_device_id("1")
+    .build()
+    .unwrap();
+  edit_collab(&collab, "1", "a");
+  let remote = restore_collab(collab.encode_collab_v1().doc_state.to_vec());
+  edit_collab(&collab, "2", "b");
+
+  let remote_sv = remote.lock().transact().state_vector().encode_v1();
+  let diff = collab.encode_collab_v1().diff(&remote_sv).unwrap();
+  remote
+    .lock()
+    .get_doc()
+    .transact_mut()
+    .apply_update(Update::decode_v1(&diff).unwrap());
+  assert_eq!(collab.to_json_value(), remote.to_json_value());
+
+  let full_diff = collab
+    .encode_collab_v1()
+    .diff(&StateVector::default().encode_v1())
+    .unwrap();
+  assert!(full_diff.len() > diff.len());
+}
@@ -0,0 +1,68 @@
+use collab::core::collab::MutexCollab;
+use collab::preclude::MapRefExtension;
+use collab::simulation::{
+  simulation_seeds, Simulation, SimulationConfig, SimulationModel, SimulationRng,
+};
+
+const KEYS: [&str; 4] = ["a", "b", "c", "d"];
+
+struct MapModel {
+  collab: MutexCollab,
+}
+
+impl SimulationModel for MapModel {
+  fn apply_random_operation(&mut self, rng: &mut SimulationRng) -> String {
+    let key = *rng.choose(&KEYS).unwrap();
+    let mut collab = self.collab.lock();
+    match rng.below(3) {
+      0 => {
+        let value = rng.string(4);
+        collab.insert(key, value.clone());
+        format!("insert {}={}", key, value)
+      },
+      1 => {
+        collab
+          .with_origin_transact_mut(|txn| {
+            let map = collab.insert_map_with_txn_if_not_exist(txn, "nested");
+            map.insert_i64_with_txn(txn, key, rng.below(100) as i64);
+          })
+          .unwrap();
+        format!("insert nested {}", key)
+      },
+      _ => {
+        collab.remove(key);
+        format!("remove {}", key)
+      },
+    }
+  }
+}
+
+#[test]
+fn map_convergence_test() {
+  for seed in simulation_seeds(10) {
+    let mut simulation = Simulation::new(
+      seed,
+      SimulationConfig::default().with_client_count(4),
+      |_, collab| MapModel { collab },
+      |_, collab| MapModel { collab },
+    )
+    .unwrap();
+    simulation.assert_run(200);
+  }
+}
+
+#[test]
+fn same_seed_replays_the_same_simulation_test() {
+  let run = |seed| {
+    let mut simulation = Simulation::new(
+      seed,
+      SimulationConfig::default(),
+      |_, collab| MapModel { collab },
+      |_, collab| MapModel { collab },
+    )
+    .unwrap();
+    simulation.assert_run(100);
+    simulation.clients()[0].collab.to_json_value()
+  };
+  assert_eq!(run(42), run(42));
+}